@@ -0,0 +1,87 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+use std::{hint::black_box, sync::Arc};
+
+use backend::udp_server::peer_index::PeerIndex;
+use base64::prelude::*;
+use boringtun::{
+    noise::{rate_limiter::RateLimiter, Tunn, TunnResult},
+    x25519::{PublicKey, StaticSecret},
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand_core::{OsRng, TryRngCore};
+
+fn random_secret() -> StaticSecret {
+    let mut bytes = [0u8; 32];
+    OsRng.try_fill_bytes(&mut bytes).unwrap();
+    StaticSecret::from(bytes)
+}
+
+/// Creates an index with `size` chargers and a handshake initiation for the last one.
+fn setup(size: usize) -> (PeerIndex, Vec<u8>) {
+    let index = PeerIndex::new();
+    let mut last_public = None;
+    for _ in 0..size {
+        let secret = random_secret();
+        last_public = Some(PublicKey::from(&secret));
+        index
+            .insert(
+                uuid::Uuid::new_v4(),
+                BASE64_STANDARD.encode(secret.as_bytes()),
+            )
+            .unwrap();
+    }
+
+    let charger_private = random_secret();
+    let rate_limiter = Arc::new(RateLimiter::new(&PublicKey::from(&charger_private), 10));
+    let mut tunn = Tunn::new(
+        charger_private,
+        last_public.unwrap(),
+        None,
+        None,
+        0,
+        Some(rate_limiter),
+    );
+    let mut dst = vec![0u8; 2048];
+    let packet = match tunn.format_handshake_initiation(&mut dst, false) {
+        TunnResult::WriteToNetwork(packet) => packet.to_vec(),
+        _ => panic!("Failed to create handshake initiation"),
+    };
+
+    (index, packet)
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("peer_index_lookup");
+    group.sample_size(20);
+    for size in [10_000, 100_000] {
+        let (index, packet) = setup(size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &packet, |b, packet| {
+            b.iter(|| {
+                let matches = index.lookup(black_box(packet));
+                assert_eq!(matches.len(), 1);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lookup);
+criterion_main!(benches);
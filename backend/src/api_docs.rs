@@ -79,10 +79,12 @@ async fn main() {
     #[openapi(
         paths(
             routes::auth::login::login,
+            routes::auth::login_totp::login_totp,
             routes::auth::register::register,
             routes::auth::verify::verify,
             routes::auth::generate_salt::generate_salt,
             routes::auth::jwt_refresh::jwt_refresh,
+            routes::auth::jwks::jwks,
             routes::auth::get_login_salt::get_login_salt,
             routes::auth::recovery::recovery,
             routes::auth::start_recovery::start_recovery,
@@ -95,6 +97,11 @@ async fn main() {
             routes::charger::add_with_token::add_with_token,
             routes::charger::info::charger_info,
             routes::charger::get_devices::get_devices,
+            routes::charger::access_log::access_log,
+            routes::charger::command::send_command,
+            routes::charger::uptime::uptime,
+            routes::charger::offline_notification::get_offline_notification,
+            routes::charger::offline_notification::set_offline_notification,
             routes::grouping::create_grouping::create_grouping,
             routes::grouping::delete_grouping::delete_grouping,
             routes::grouping::edit_grouping::edit_grouping,
@@ -110,6 +117,16 @@ async fn main() {
             routes::user::create_authorization_token::create_authorization_token,
             routes::user::get_authorization_tokens::get_authorization_tokens,
             routes::user::delete_authorization_token::delete_authorization_token,
+            routes::user::create_webhook::create_webhook,
+            routes::user::get_webhooks::get_webhooks,
+            routes::user::delete_webhook::delete_webhook,
+            routes::user::sessions::get_sessions,
+            routes::user::sessions::revoke_session,
+            routes::user::totp::get_totp,
+            routes::user::totp::enroll_totp,
+            routes::user::totp::confirm_totp,
+            routes::user::totp::regenerate_backup_codes,
+            routes::user::totp::disable_totp,
             routes::user::delete::delete_user,
             routes::check_expiration::check_expiration,
             routes::management::management,
@@ -118,6 +135,8 @@ async fn main() {
         ),
         components(schemas(
             routes::auth::login::LoginSchema,
+            routes::auth::login::LoginResponseSchema,
+            routes::auth::login_totp::LoginTotpSchema,
             routes::auth::register::RegisterSchema,
             routes::auth::recovery::RecoverySchema,
             routes::auth::resend_verification::ResendSchema,
@@ -130,9 +149,23 @@ async fn main() {
             routes::charger::remove::DeleteChargerSchema,
             routes::charger::get_devices::ChargerStatus,
             routes::charger::get_devices::GetChargerSchema,
+            routes::charger::get_devices::ChargerTraffic,
+            routes::charger::get_devices::ChargerHealth,
+            udp_server::health::ConnectionHealth,
+            bandwidth::TrafficSnapshot,
             routes::charger::update_note::UpdateNoteSchema,
             routes::charger::info::ChargerInfo,
             routes::charger::info::ChargerInfoRequest,
+            routes::charger::access_log::AccessLogEntry,
+            routes::charger::access_log::AccessLogResponseSchema,
+            udp_server::commands::DeviceCommand,
+            routes::charger::command::SendCommandSchema,
+            routes::charger::command::CommandResultSchema,
+            udp_server::packet::NackReason,
+            udp_server::packet::DeviceStatus,
+            routes::charger::uptime::UptimeSchema,
+            routes::charger::uptime::OutageSchema,
+            routes::charger::offline_notification::OfflineNotificationSchema,
             routes::selfdestruct::SelfdestructSchema,
             routes::charger::get_key::GetWgKeysResponseSchema,
             routes::charger::add_with_token::AddChargerWithTokenSchema,
@@ -152,6 +185,20 @@ async fn main() {
             routes::user::create_authorization_token::CreateAuthorizationTokenSchema,
             routes::user::get_authorization_tokens::GetAuthorizationTokensResponseSchema,
             routes::user::delete_authorization_token::DeleteAuthorizationTokenSchema,
+            routes::user::create_webhook::CreateWebhookSchema,
+            routes::user::create_webhook::CreateWebhookResponseSchema,
+            routes::user::create_webhook::WebhookSchema,
+            routes::user::get_webhooks::GetWebhooksResponseSchema,
+            routes::user::delete_webhook::DeleteWebhookSchema,
+            routes::user::sessions::SessionSchema,
+            routes::user::sessions::GetSessionsResponseSchema,
+            routes::user::totp::TotpStatusSchema,
+            routes::user::totp::TotpEnrollResponseSchema,
+            routes::user::totp::TotpEnrollSchema,
+            routes::user::totp::TotpCodeSchema,
+            routes::user::totp::TotpChangeSchema,
+            routes::user::totp::BackupCodesSchema,
+            webhooks::WebhookEvent,
             routes::user::update_user::UpdateUserSchema,
             routes::user::me::UserInfo,
             routes::management::ManagementSchema,
@@ -0,0 +1,310 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Byte counters and throttling for the traffic relayed between browsers and devices.
+//!
+//! Traffic to the device is delayed when it exceeds the limit, which slows down reading
+//! from the websocket and with that the browser. Datagrams from the device can't be
+//! delayed without buffering them, so they are dropped and the tunneled TCP connection
+//! backs off on its own.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config::{BandwidthConfig, BandwidthLimits};
+
+/// Even slow limits must let a full sized datagram through.
+const MIN_BURST: u64 = 64 * 1024;
+
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Allows `bytes_per_sec` on average and bursts of up to one second of traffic.
+pub struct TokenBucket {
+    bytes_per_sec: f64,
+    capacity: f64,
+    state: Mutex<BucketState>,
+}
+
+impl TokenBucket {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let capacity = bytes_per_sec.max(MIN_BURST) as f64;
+        Self {
+            bytes_per_sec: bytes_per_sec as f64,
+            capacity,
+            state: Mutex::new(BucketState {
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    fn refill(&self, state: &mut BucketState, now: Instant) {
+        let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.bytes_per_sec).min(self.capacity);
+        state.last_refill = now;
+    }
+
+    /// Takes `bytes` tokens if there are enough of them.
+    pub fn try_take(&self, bytes: usize) -> bool {
+        self.try_take_at(bytes, Instant::now())
+    }
+
+    fn try_take_at(&self, bytes: usize, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state, now);
+        if state.tokens >= bytes as f64 {
+            state.tokens -= bytes as f64;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes `bytes` tokens even if that leaves the bucket in debt.
+    /// Returns how long the caller has to wait until the debt is paid off.
+    pub fn reserve(&self, bytes: usize) -> Duration {
+        self.reserve_at(bytes, Instant::now())
+    }
+
+    fn reserve_at(&self, bytes: usize, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state, now);
+        state.tokens -= bytes as f64;
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.bytes_per_sec)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq, Eq, Default)]
+pub struct TrafficSnapshot {
+    pub to_device: u64,
+    pub from_device: u64,
+    /// Bytes from the device that were dropped because of the limit.
+    pub dropped_from_device: u64,
+}
+
+/// Bytes relayed in both directions, optionally limited.
+#[derive(Default)]
+pub struct Traffic {
+    to_device: AtomicU64,
+    from_device: AtomicU64,
+    dropped_from_device: AtomicU64,
+    to_device_limit: Option<TokenBucket>,
+    from_device_limit: Option<TokenBucket>,
+}
+
+impl Traffic {
+    pub fn new(limits: &BandwidthLimits) -> Self {
+        Self {
+            to_device_limit: limits.to_device.map(TokenBucket::new),
+            from_device_limit: limits.from_device.map(TokenBucket::new),
+            ..Default::default()
+        }
+    }
+
+    pub fn to_device(&self) -> u64 {
+        self.to_device.load(Ordering::Relaxed)
+    }
+
+    pub fn from_device(&self) -> u64 {
+        self.from_device.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped_from_device(&self) -> u64 {
+        self.dropped_from_device.load(Ordering::Relaxed)
+    }
+
+    pub fn snapshot(&self) -> TrafficSnapshot {
+        TrafficSnapshot {
+            to_device: self.to_device(),
+            from_device: self.from_device(),
+            dropped_from_device: self.dropped_from_device(),
+        }
+    }
+
+    fn reserve_to_device(&self, bytes: usize) -> Duration {
+        self.to_device.fetch_add(bytes as u64, Ordering::Relaxed);
+        match &self.to_device_limit {
+            Some(limit) => limit.reserve(bytes),
+            None => Duration::ZERO,
+        }
+    }
+
+    fn try_from_device(&self, bytes: usize) -> bool {
+        match &self.from_device_limit {
+            Some(limit) => limit.try_take(bytes),
+            None => true,
+        }
+    }
+
+    fn count_from_device(&self, bytes: usize) {
+        self.from_device.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn count_dropped(&self, bytes: usize) {
+        self.dropped_from_device
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// Traffic of all open sessions to one device.
+pub struct DeviceTraffic {
+    pub traffic: Arc<Traffic>,
+    /// Open sessions. The entry is removed once the last one closed.
+    pub sessions: usize,
+}
+
+/// Traffic of one remote access session and of all sessions to the same device.
+#[derive(Default)]
+pub struct SessionTraffic {
+    pub session: Traffic,
+    pub device: Arc<Traffic>,
+}
+
+impl SessionTraffic {
+    pub fn new(config: &BandwidthConfig, device: Arc<Traffic>) -> Self {
+        Self {
+            session: Traffic::new(&config.session),
+            device,
+        }
+    }
+
+    /// Counts bytes sent to the device and returns how long to wait before sending them.
+    pub fn add_to_device(&self, bytes: usize) -> Duration {
+        let session = self.session.reserve_to_device(bytes);
+        let device = self.device.reserve_to_device(bytes);
+        session.max(device)
+    }
+
+    /// Counts a datagram from the device.
+    /// Returns false if it exceeds one of the limits and has to be dropped.
+    pub fn add_from_device(&self, bytes: usize) -> bool {
+        if self.session.try_from_device(bytes) && self.device.try_from_device(bytes) {
+            self.session.count_from_device(bytes);
+            self.device.count_from_device(bytes);
+            true
+        } else {
+            self.session.count_dropped(bytes);
+            self.device.count_dropped(bytes);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_take() {
+        let bucket = TokenBucket::new(100_000);
+        let start = Instant::now();
+        assert!(bucket.try_take_at(60_000, start));
+        assert!(bucket.try_take_at(40_000, start));
+        assert!(!bucket.try_take_at(1, start));
+
+        // Refills with the configured rate.
+        let later = start + Duration::from_millis(100);
+        assert!(bucket.try_take_at(10_000, later));
+        assert!(!bucket.try_take_at(1000, later));
+
+        // Never holds more than the capacity.
+        let much_later = start + Duration::from_secs(60);
+        assert!(bucket.try_take_at(100_000, much_later));
+        assert!(!bucket.try_take_at(1000, much_later));
+    }
+
+    #[test]
+    fn test_min_burst() {
+        let bucket = TokenBucket::new(1000);
+        assert!(bucket.try_take(65535));
+    }
+
+    #[test]
+    fn test_reserve() {
+        let bucket = TokenBucket::new(100_000);
+        let start = Instant::now();
+        assert_eq!(bucket.reserve_at(100_000, start), Duration::ZERO);
+        assert_eq!(bucket.reserve_at(50_000, start), Duration::from_millis(500));
+        assert_eq!(
+            bucket.reserve_at(50_000, start + Duration::from_millis(500)),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn test_session_traffic() {
+        let config = BandwidthConfig {
+            session: BandwidthLimits {
+                to_device: None,
+                from_device: Some(100_000),
+            },
+            device: BandwidthLimits::default(),
+        };
+        let device = Arc::new(Traffic::new(&config.device));
+        let first = SessionTraffic::new(&config, device.clone());
+        let second = SessionTraffic::new(&config, device.clone());
+
+        assert_eq!(first.add_to_device(1000), Duration::ZERO);
+        assert_eq!(second.add_to_device(500), Duration::ZERO);
+        assert_eq!(first.session.to_device(), 1000);
+        assert_eq!(device.to_device(), 1500);
+
+        assert!(first.add_from_device(100_000));
+        assert!(!first.add_from_device(1000));
+        // The limit is per session.
+        assert!(second.add_from_device(1000));
+        assert_eq!(first.session.from_device(), 100_000);
+        assert_eq!(first.session.dropped_from_device(), 1000);
+        assert_eq!(device.from_device(), 101_000);
+        assert_eq!(device.dropped_from_device(), 1000);
+    }
+
+    #[test]
+    fn test_device_limit() {
+        let config = BandwidthConfig {
+            session: BandwidthLimits::default(),
+            device: BandwidthLimits {
+                to_device: Some(100_000),
+                from_device: None,
+            },
+        };
+        let device = Arc::new(Traffic::new(&config.device));
+        let first = SessionTraffic::new(&config, device.clone());
+        let second = SessionTraffic::new(&config, device);
+
+        assert_eq!(first.add_to_device(100_000), Duration::ZERO);
+        // The device limit is shared by all sessions.
+        assert!(second.add_to_device(10_000) > Duration::ZERO);
+    }
+}
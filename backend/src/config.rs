@@ -0,0 +1,764 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Server configuration read from a TOML file.
+//! Every value can be overridden with an environment variable.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::branding::Brand;
+
+/// Used when `CONFIG_PATH` is not set. It is fine if this file does not exist.
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub bind_address: IpAddr,
+    pub port: u16,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        #[cfg(debug_assertions)]
+        let port = 8081;
+        #[cfg(not(debug_assertions))]
+        let port = 443;
+
+        Self {
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WireguardConfig {
+    /// The unspecified IPv6 address listens on IPv4 as well.
+    pub bind_address: IpAddr,
+    pub port: u16,
+}
+
+impl Default for WireguardConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            port: 51820,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// How often the certificate files are checked for changes.
+    pub reload_interval_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert_path: PathBuf::new(),
+            key_path: PathBuf::new(),
+            reload_interval_secs: 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line including the request and device ids.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown log format '{s}'")),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Directory for the hourly log files. Only the terminal is used if this is not set.
+    pub directory: Option<PathBuf>,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        #[cfg(debug_assertions)]
+        let directory = None;
+        #[cfg(not(debug_assertions))]
+        let directory = Some(PathBuf::from("/logs"));
+
+        Self {
+            directory,
+            format: LogFormat::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailConfig {
+    pub user: String,
+    pub pass: String,
+    pub relay: String,
+    pub relay_port: u16,
+    pub sender_email: String,
+    pub sender_name: String,
+}
+
+/// The monitoring mail is only sent if both values are set.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct MonitoringConfig {
+    pub server_name: Option<String>,
+    pub email: Option<String>,
+}
+
+impl MonitoringConfig {
+    /// Returns the server name and the recipient if monitoring is enabled.
+    pub fn enabled(&self) -> Option<(&str, &str)> {
+        match (&self.server_name, &self.email) {
+            (Some(server_name), Some(email)) => Some((server_name, email)),
+            _ => None,
+        }
+    }
+}
+
+/// Bytes per second, unlimited if not set.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BandwidthLimits {
+    pub to_device: Option<u64>,
+    pub from_device: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BandwidthConfig {
+    /// Limits for every remote access session on its own.
+    pub session: BandwidthLimits,
+    /// Limits for all sessions to the same device together.
+    pub device: BandwidthLimits,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RelayBus {
+    Postgres,
+}
+
+impl FromStr for RelayBus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "postgres" => Ok(Self::Postgres),
+            _ => Err(format!("Unknown relay bus '{s}'")),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub http: HttpConfig,
+    pub wireguard: WireguardConfig,
+    pub static_files_dir: PathBuf,
+    pub tls: TlsConfig,
+    pub log: LogConfig,
+    pub database_url: String,
+    /// Only needed to verify tokens without a `kid` once a signing key is configured.
+    pub jwt_secret: String,
+    /// PEM file with the Ed25519 or P-256 private key the tokens are signed with.
+    pub jwt_signing_key_path: Option<PathBuf>,
+    /// JWKS file with retired public keys that are still accepted.
+    pub jwt_verification_keys_path: Option<PathBuf>,
+    pub frontend_url: String,
+    pub brand: Brand,
+    pub email: EmailConfig,
+    pub monitoring: MonitoringConfig,
+    /// Management connections from this address can belong to any undiscovered device.
+    pub forward_host: Option<IpAddr>,
+    /// Checkout of the warp-charger repository the webinterfaces are served from in debug builds.
+    pub warp_charger_git_path: PathBuf,
+    pub metrics_bind_address: Option<SocketAddr>,
+    /// Only needed when running multiple instances.
+    pub relay_bus: Option<RelayBus>,
+    /// Device sessions are kept across restarts when this is set.
+    pub session_state_path: Option<PathBuf>,
+    /// Maximum time to wait for running charge log uploads when shutting down.
+    pub shutdown_deadline_secs: u64,
+    /// How long remote access sessions are kept in the access log.
+    pub access_log_retention_days: u32,
+    /// How long the online history of the chargers is kept.
+    pub connectivity_retention_days: u32,
+    /// Subscribed users get an email once a charger is offline for this long.
+    pub offline_notification_minutes: u32,
+    pub bandwidth: BandwidthConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            http: HttpConfig::default(),
+            wireguard: WireguardConfig::default(),
+            static_files_dir: PathBuf::new(),
+            tls: TlsConfig::default(),
+            log: LogConfig::default(),
+            database_url: String::new(),
+            jwt_secret: String::new(),
+            jwt_signing_key_path: None,
+            jwt_verification_keys_path: None,
+            frontend_url: String::new(),
+            brand: Brand::default(),
+            email: EmailConfig::default(),
+            monitoring: MonitoringConfig::default(),
+            forward_host: None,
+            warp_charger_git_path: PathBuf::from("warp-charger"),
+            metrics_bind_address: None,
+            relay_bus: None,
+            session_state_path: None,
+            shutdown_deadline_secs: 30,
+            access_log_retention_days: 90,
+            connectivity_retention_days: 90,
+            offline_notification_minutes: 15,
+            bandwidth: BandwidthConfig::default(),
+        }
+    }
+}
+
+/// Lists everything that is wrong with the configuration.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+        for error in self.0.iter() {
+            writeln!(f, "  - {error}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Applies environment variables and remembers the ones that could not be parsed.
+struct EnvOverrides<F: Fn(&str) -> Option<String>> {
+    lookup: F,
+    errors: Vec<String>,
+}
+
+impl<F: Fn(&str) -> Option<String>> EnvOverrides<F> {
+    fn parse<T: FromStr>(&mut self, key: &str) -> Option<T>
+    where
+        T::Err: std::fmt::Display,
+    {
+        let value = (self.lookup)(key).filter(|v| !v.is_empty())?;
+        match value.parse() {
+            Ok(v) => Some(v),
+            Err(err) => {
+                self.errors.push(format!("Invalid value for {key}: {err}"));
+                None
+            }
+        }
+    }
+
+    fn set<T: FromStr>(&mut self, key: &str, target: &mut T)
+    where
+        T::Err: std::fmt::Display,
+    {
+        if let Some(v) = self.parse(key) {
+            *target = v;
+        }
+    }
+
+    fn set_option<T: FromStr>(&mut self, key: &str, target: &mut Option<T>)
+    where
+        T::Err: std::fmt::Display,
+    {
+        if let Some(v) = self.parse(key) {
+            *target = Some(v);
+        }
+    }
+}
+
+impl Config {
+    /// Loads the file at `CONFIG_PATH` or `config.toml`, applies the environment
+    /// overrides and checks that everything required is set.
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_with(|key| std::env::var(key).ok())
+    }
+
+    pub fn load_with(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let config = match lookup("CONFIG_PATH").filter(|p| !p.is_empty()) {
+            Some(path) => Self::from_file(Path::new(&path))?,
+            None => {
+                let path = Path::new(DEFAULT_CONFIG_PATH);
+                if path.exists() {
+                    Self::from_file(path)?
+                } else {
+                    Self::default()
+                }
+            }
+        };
+
+        config.with_env_overrides(lookup)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|err| {
+            ConfigError(vec![format!("Failed to read config file {path:?}: {err}")])
+        })?;
+
+        Self::from_toml(&content)
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, ConfigError> {
+        toml::from_str(content).map_err(|err| ConfigError(vec![err.to_string()]))
+    }
+
+    /// Overrides the values from the file with the ones from `lookup` and validates the result.
+    pub fn with_env_overrides(
+        mut self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let mut errors = self.apply_env(lookup);
+        errors.extend(self.validate());
+        if errors.is_empty() {
+            Ok(self)
+        } else {
+            Err(ConfigError(errors))
+        }
+    }
+
+    /// Empty values are ignored. Returns the variables that could not be parsed.
+    fn apply_env(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut env = EnvOverrides {
+            lookup,
+            errors: Vec::new(),
+        };
+
+        env.set("HTTP_BIND_ADDRESS", &mut self.http.bind_address);
+        env.set("HTTP_PORT", &mut self.http.port);
+        env.set("WG_BIND_ADDRESS", &mut self.wireguard.bind_address);
+        env.set("WG_PORT", &mut self.wireguard.port);
+        env.set("STATIC_FILES_DIR", &mut self.static_files_dir);
+        env.set("TLS_CERT_PATH", &mut self.tls.cert_path);
+        env.set("TLS_KEY_PATH", &mut self.tls.key_path);
+        env.set(
+            "TLS_RELOAD_INTERVAL_SECS",
+            &mut self.tls.reload_interval_secs,
+        );
+        env.set_option("LOG_DIR", &mut self.log.directory);
+        env.set("LOG_FORMAT", &mut self.log.format);
+        env.set("DATABASE_URL", &mut self.database_url);
+        env.set("JWT_SECRET", &mut self.jwt_secret);
+        env.set_option("JWT_SIGNING_KEY_PATH", &mut self.jwt_signing_key_path);
+        env.set_option(
+            "JWT_VERIFICATION_KEYS_PATH",
+            &mut self.jwt_verification_keys_path,
+        );
+        env.set("FRONTEND_URL", &mut self.frontend_url);
+        env.set("BRAND", &mut self.brand);
+        env.set("EMAIL_USER", &mut self.email.user);
+        env.set("EMAIL_PASS", &mut self.email.pass);
+        env.set("EMAIL_RELAY", &mut self.email.relay);
+        env.set("EMAIL_RELAY_PORT", &mut self.email.relay_port);
+        env.set("SENDER_EMAIL", &mut self.email.sender_email);
+        env.set("SENDER_NAME", &mut self.email.sender_name);
+        env.set_option("SERVER_NAME", &mut self.monitoring.server_name);
+        env.set_option("MONITORING_EMAIL", &mut self.monitoring.email);
+        env.set_option("FORWARD_HOST", &mut self.forward_host);
+        env.set("WARP_CHARGER_GIT_PATH", &mut self.warp_charger_git_path);
+        env.set_option("METRICS_BIND_ADDRESS", &mut self.metrics_bind_address);
+        env.set_option("RELAY_BUS", &mut self.relay_bus);
+        env.set_option("SESSION_STATE_PATH", &mut self.session_state_path);
+        env.set("SHUTDOWN_DEADLINE_SECS", &mut self.shutdown_deadline_secs);
+        env.set(
+            "ACCESS_LOG_RETENTION_DAYS",
+            &mut self.access_log_retention_days,
+        );
+        env.set(
+            "CONNECTIVITY_RETENTION_DAYS",
+            &mut self.connectivity_retention_days,
+        );
+        env.set(
+            "OFFLINE_NOTIFICATION_MINUTES",
+            &mut self.offline_notification_minutes,
+        );
+        env.set_option(
+            "BANDWIDTH_SESSION_TO_DEVICE",
+            &mut self.bandwidth.session.to_device,
+        );
+        env.set_option(
+            "BANDWIDTH_SESSION_FROM_DEVICE",
+            &mut self.bandwidth.session.from_device,
+        );
+        env.set_option(
+            "BANDWIDTH_DEVICE_TO_DEVICE",
+            &mut self.bandwidth.device.to_device,
+        );
+        env.set_option(
+            "BANDWIDTH_DEVICE_FROM_DEVICE",
+            &mut self.bandwidth.device.from_device,
+        );
+
+        env.errors
+    }
+
+    /// Returns a message for every required value that is missing.
+    fn validate(&self) -> Vec<String> {
+        let required = [
+            (
+                "static_files_dir",
+                "STATIC_FILES_DIR",
+                self.static_files_dir.as_os_str().is_empty(),
+            ),
+            (
+                "tls.cert_path",
+                "TLS_CERT_PATH",
+                self.tls.cert_path.as_os_str().is_empty(),
+            ),
+            (
+                "tls.key_path",
+                "TLS_KEY_PATH",
+                self.tls.key_path.as_os_str().is_empty(),
+            ),
+            ("database_url", "DATABASE_URL", self.database_url.is_empty()),
+            ("frontend_url", "FRONTEND_URL", self.frontend_url.is_empty()),
+            ("email.user", "EMAIL_USER", self.email.user.is_empty()),
+            ("email.pass", "EMAIL_PASS", self.email.pass.is_empty()),
+            ("email.relay", "EMAIL_RELAY", self.email.relay.is_empty()),
+            (
+                "email.relay_port",
+                "EMAIL_RELAY_PORT",
+                self.email.relay_port == 0,
+            ),
+            (
+                "email.sender_email",
+                "SENDER_EMAIL",
+                self.email.sender_email.is_empty(),
+            ),
+            (
+                "email.sender_name",
+                "SENDER_NAME",
+                self.email.sender_name.is_empty(),
+            ),
+        ];
+
+        let mut errors: Vec<String> = required
+            .into_iter()
+            .filter(|(_, _, missing)| *missing)
+            .map(|(key, env, _)| format!("{key} ({env}) must be set"))
+            .collect();
+
+        if self.jwt_secret.is_empty() && self.jwt_signing_key_path.is_none() {
+            errors.push(
+                "jwt_secret (JWT_SECRET) or jwt_signing_key_path (JWT_SIGNING_KEY_PATH) must be set"
+                    .into(),
+            );
+        }
+
+        if self.tls.reload_interval_secs == 0 {
+            errors.push("tls.reload_interval_secs (TLS_RELOAD_INTERVAL_SECS) must not be 0".into());
+        }
+
+        let limits = [
+            (
+                "bandwidth.session.to_device",
+                "BANDWIDTH_SESSION_TO_DEVICE",
+                self.bandwidth.session.to_device,
+            ),
+            (
+                "bandwidth.session.from_device",
+                "BANDWIDTH_SESSION_FROM_DEVICE",
+                self.bandwidth.session.from_device,
+            ),
+            (
+                "bandwidth.device.to_device",
+                "BANDWIDTH_DEVICE_TO_DEVICE",
+                self.bandwidth.device.to_device,
+            ),
+            (
+                "bandwidth.device.from_device",
+                "BANDWIDTH_DEVICE_FROM_DEVICE",
+                self.bandwidth.device.from_device,
+            ),
+        ];
+        for (key, env, limit) in limits {
+            if limit == Some(0) {
+                errors.push(format!("{key} ({env}) must not be 0"));
+            }
+        }
+
+        errors
+    }
+
+    /// Checks that the files and directories the configuration points to are usable.
+    pub fn check_files(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        if !self.static_files_dir.is_dir() {
+            errors.push(format!(
+                "static_files_dir {:?} is not a directory",
+                self.static_files_dir
+            ));
+        }
+        if let Some(dir) = &self.log.directory {
+            if !dir.is_dir() {
+                errors.push(format!("log.directory {dir:?} is not a directory"));
+            }
+        }
+        if let Err(err) = crate::tls::ReloadingCertResolver::new(
+            self.tls.cert_path.clone(),
+            self.tls.key_path.clone(),
+        ) {
+            errors.push(format!("Failed to load TLS certificate: {err}"));
+        }
+        if let Err(err) = crate::jwt_keys::JwtKeys::from_config(self) {
+            errors.push(format!("Failed to load JWT keys: {err}"));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError(errors))
+        }
+    }
+
+    pub fn http_address(&self) -> SocketAddr {
+        SocketAddr::new(self.http.bind_address, self.http.port)
+    }
+
+    pub fn wireguard_address(&self) -> SocketAddr {
+        SocketAddr::new(self.wireguard.bind_address, self.wireguard.port)
+    }
+
+    pub fn shutdown_deadline(&self) -> Duration {
+        Duration::from_secs(self.shutdown_deadline_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn required_env() -> HashMap<&'static str, &'static str> {
+        [
+            ("STATIC_FILES_DIR", "/static"),
+            ("TLS_CERT_PATH", "/certs/fullchain.pem"),
+            ("TLS_KEY_PATH", "/certs/privkey.pem"),
+            (
+                "DATABASE_URL",
+                "postgres://postgres@localhost/remote_access",
+            ),
+            ("JWT_SECRET", "secret"),
+            ("FRONTEND_URL", "https://remote-access.example.com"),
+            ("EMAIL_USER", "noreply@example.com"),
+            ("EMAIL_PASS", "password"),
+            ("EMAIL_RELAY", "mail.example.com"),
+            ("EMAIL_RELAY_PORT", "465"),
+            ("SENDER_EMAIL", "noreply@example.com"),
+            ("SENDER_NAME", "Remote Access"),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml(
+            r#"
+            static_files_dir = "/static"
+            brand = "seb"
+            forward_host = "10.0.0.1"
+            relay_bus = "postgres"
+
+            [http]
+            port = 8443
+
+            [wireguard]
+            bind_address = "192.168.0.1"
+
+            [tls]
+            cert_path = "/certs/fullchain.pem"
+            key_path = "/certs/privkey.pem"
+
+            [log]
+            directory = "/var/log/backend"
+            format = "json"
+
+            [email]
+            relay_port = 587
+
+            [monitoring]
+            server_name = "test"
+
+            [bandwidth.session]
+            to_device = 1000000
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.http_address(),
+            "0.0.0.0:8443".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(
+            config.wireguard_address(),
+            "192.168.0.1:51820".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(config.static_files_dir, PathBuf::from("/static"));
+        assert_eq!(config.tls.cert_path, PathBuf::from("/certs/fullchain.pem"));
+        assert_eq!(config.tls.reload_interval_secs, 60);
+        assert_eq!(
+            config.log.directory,
+            Some(PathBuf::from("/var/log/backend"))
+        );
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert_eq!(config.brand, Brand::Seb);
+        assert_eq!(config.forward_host, Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(config.relay_bus, Some(RelayBus::Postgres));
+        assert_eq!(config.email.relay_port, 587);
+        assert_eq!(config.monitoring.enabled(), None);
+        assert_eq!(config.shutdown_deadline_secs, 30);
+        assert_eq!(config.access_log_retention_days, 90);
+        assert_eq!(config.connectivity_retention_days, 90);
+        assert_eq!(config.offline_notification_minutes, 15);
+        assert_eq!(config.jwt_signing_key_path, None);
+        assert_eq!(config.bandwidth.session.to_device, Some(1_000_000));
+        assert_eq!(config.bandwidth.session.from_device, None);
+        assert_eq!(config.bandwidth.device, BandwidthLimits::default());
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        assert!(Config::from_toml("[http]\nadress = \"0.0.0.0\"").is_err());
+        assert!(Config::from_toml("foo = 1").is_err());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let env: HashMap<&str, &str> = [
+            ("HTTP_PORT", "9443"),
+            ("WG_BIND_ADDRESS", "::1"),
+            ("STATIC_FILES_DIR", "/frontend"),
+            ("TLS_CERT_PATH", ""),
+            ("SERVER_NAME", "test"),
+            ("MONITORING_EMAIL", "monitoring@example.com"),
+            ("BANDWIDTH_DEVICE_FROM_DEVICE", "500000"),
+        ]
+        .into_iter()
+        .collect();
+        let mut config = Config::from_toml("[tls]\ncert_path = \"/certs/cert.pem\"").unwrap();
+        let errors = config.apply_env(|key| env.get(key).map(|v| v.to_string()));
+
+        assert!(errors.is_empty());
+        assert_eq!(config.http.port, 9443);
+        assert_eq!(
+            config.wireguard_address(),
+            "[::1]:51820".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(config.static_files_dir, PathBuf::from("/frontend"));
+        // Empty values dont override the file.
+        assert_eq!(config.tls.cert_path, PathBuf::from("/certs/cert.pem"));
+        assert_eq!(
+            config.monitoring.enabled(),
+            Some(("test", "monitoring@example.com"))
+        );
+        assert_eq!(config.bandwidth.device.from_device, Some(500_000));
+    }
+
+    #[test]
+    fn test_valid_env() {
+        let env = required_env();
+        let config = Config::default()
+            .with_env_overrides(|key| env.get(key).map(|v| v.to_string()))
+            .unwrap();
+        assert_eq!(config.email.relay_port, 465);
+        assert_eq!(config.brand, Brand::Warp);
+    }
+
+    #[test]
+    fn test_signing_key_replaces_secret() {
+        let mut env = required_env();
+        env.remove("JWT_SECRET");
+        env.insert("JWT_SIGNING_KEY_PATH", "/keys/jwt.pem");
+        let config = Config::default()
+            .with_env_overrides(|key| env.get(key).map(|v| v.to_string()))
+            .unwrap();
+        assert_eq!(
+            config.jwt_signing_key_path,
+            Some(PathBuf::from("/keys/jwt.pem"))
+        );
+    }
+
+    #[test]
+    fn test_all_errors_are_listed() {
+        let mut env = required_env();
+        env.remove("JWT_SECRET");
+        env.remove("EMAIL_RELAY");
+        env.insert("HTTP_PORT", "not a port");
+        env.insert("BRAND", "unknown");
+        env.insert("FORWARD_HOST", "not an ip");
+        env.insert("BANDWIDTH_SESSION_TO_DEVICE", "0");
+
+        let err = Config::default()
+            .with_env_overrides(|key| env.get(key).map(|v| v.to_string()))
+            .unwrap_err();
+        assert_eq!(err.0.len(), 6);
+        let message = err.to_string();
+        for key in [
+            "JWT_SECRET",
+            "EMAIL_RELAY",
+            "HTTP_PORT",
+            "BRAND",
+            "FORWARD_HOST",
+            "BANDWIDTH_SESSION_TO_DEVICE",
+        ] {
+            assert!(message.contains(key), "{key} missing in {message}");
+        }
+    }
+}
@@ -0,0 +1,442 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! History of the management tunnels. Every row of `device_connectivity_events` is one
+//! interval in which a charger was connected.
+
+use chrono::NaiveDateTime;
+use db_connector::{models::device_connectivity_events::ConnectivityEvent, Pool};
+use diesel::prelude::*;
+
+use crate::{
+    error::Error,
+    utils::web_block_unpacked,
+    webhooks::{self, WebhookEvent},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The charger was silent for too long.
+    Timeout,
+    /// The charger opened a new tunnel while the old one was still registered.
+    Rehandshake,
+    /// The server shut down or crashed while the charger was connected.
+    ServerRestart,
+    /// The charger deleted itself.
+    Selfdestruct,
+}
+
+impl DisconnectReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Timeout => "timeout",
+            Self::Rehandshake => "rehandshake",
+            Self::ServerRestart => "server_restart",
+            Self::Selfdestruct => "selfdestruct",
+        }
+    }
+}
+
+/// Starts a new interval for the charger. An interval that is still open was not
+/// closed properly, so it ends now.
+pub async fn record_connect(pool: &Pool, charger_id: uuid::Uuid, replaced: bool) {
+    let Ok(mut conn) = pool.get() else {
+        log::error!("Failed to get database connection to record connect of {charger_id}");
+        return;
+    };
+    let reason = if replaced {
+        DisconnectReason::Rehandshake
+    } else {
+        DisconnectReason::ServerRestart
+    };
+    let res = web_block_unpacked(move || {
+        use db_connector::schema::device_connectivity_events::dsl as events;
+
+        let now = chrono::Utc::now().naive_utc();
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            diesel::update(events::device_connectivity_events)
+                .filter(events::charger_id.eq(charger_id))
+                .filter(events::disconnected_at.is_null())
+                .set((
+                    events::disconnected_at.eq(now),
+                    events::disconnect_reason.eq(reason.as_str()),
+                ))
+                .execute(conn)?;
+            diesel::insert_into(events::device_connectivity_events)
+                .values(ConnectivityEvent {
+                    id: uuid::Uuid::new_v4(),
+                    charger_id,
+                    connected_at: now,
+                    disconnected_at: None,
+                    disconnect_reason: None,
+                })
+                .execute(conn)
+        })
+        .map_err(|_err| Error::InternalError)
+    })
+    .await;
+    if res.is_err() {
+        log::error!("Failed to record connect of charger {charger_id}");
+        return;
+    }
+
+    webhooks::emit_for_charger(
+        pool,
+        charger_id,
+        WebhookEvent::DeviceConnected,
+        serde_json::json!({ "charger_id": charger_id }),
+    )
+    .await;
+}
+
+/// Ends the open interval of the charger at `at`.
+pub async fn record_disconnect(
+    pool: &Pool,
+    charger_id: uuid::Uuid,
+    at: NaiveDateTime,
+    reason: DisconnectReason,
+) {
+    record_disconnects(pool, vec![charger_id], at, reason).await;
+}
+
+pub async fn record_disconnects(
+    pool: &Pool,
+    charger_ids: Vec<uuid::Uuid>,
+    at: NaiveDateTime,
+    reason: DisconnectReason,
+) {
+    let Ok(mut conn) = pool.get() else {
+        log::error!("Failed to get database connection to record disconnects");
+        return;
+    };
+    let res = web_block_unpacked(move || {
+        use db_connector::schema::device_connectivity_events::dsl::*;
+
+        match diesel::update(device_connectivity_events)
+            .filter(charger_id.eq_any(charger_ids))
+            .filter(disconnected_at.is_null())
+            .set((
+                disconnected_at.eq(at),
+                disconnect_reason.eq(reason.as_str()),
+            ))
+            .returning(charger_id)
+            .get_results::<uuid::Uuid>(&mut conn)
+        {
+            Ok(closed) => Ok(closed),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await;
+    let Ok(closed) = res else {
+        log::error!("Failed to record disconnects");
+        return;
+    };
+
+    emit_disconnects(pool, closed, reason).await;
+}
+
+/// Ends the intervals that were opened before `started_at` and are still open. These
+/// chargers lost their tunnel when the previous process went away, unless it is held by
+/// another instance that is still running.
+pub async fn close_stale_intervals(pool: &Pool, started_at: NaiveDateTime) {
+    let Ok(mut conn) = pool.get() else {
+        log::error!("Failed to get database connection to close stale connectivity intervals");
+        return;
+    };
+    let reason = DisconnectReason::ServerRestart;
+    let res = web_block_unpacked(move || {
+        use db_connector::schema::device_connectivity_events::dsl as events;
+        use db_connector::schema::relay_owners::dsl as relay_owners;
+
+        let live_owners = relay_owners::relay_owners
+            .filter(relay_owners::updated_at.ge(started_at - crate::relay_bus::OWNER_TTL))
+            .select(relay_owners::charger_id);
+        match diesel::update(events::device_connectivity_events)
+            .filter(events::disconnected_at.is_null())
+            .filter(events::connected_at.lt(started_at))
+            .filter(events::charger_id.ne_all(live_owners))
+            .set((
+                events::disconnected_at.eq(started_at),
+                events::disconnect_reason.eq(reason.as_str()),
+            ))
+            .returning(events::charger_id)
+            .get_results::<uuid::Uuid>(&mut conn)
+        {
+            Ok(closed) => Ok(closed),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await;
+    let Ok(closed) = res else {
+        log::error!("Failed to close stale connectivity intervals");
+        return;
+    };
+    if !closed.is_empty() {
+        log::info!(
+            "Closed {} connectivity intervals left open by a restart",
+            closed.len()
+        );
+    }
+
+    emit_disconnects(pool, closed, reason).await;
+}
+
+async fn emit_disconnects(pool: &Pool, closed: Vec<uuid::Uuid>, reason: DisconnectReason) {
+    for charger_id in closed {
+        webhooks::emit_for_charger(
+            pool,
+            charger_id,
+            WebhookEvent::DeviceDisconnected,
+            serde_json::json!({ "charger_id": charger_id, "reason": reason.as_str() }),
+        )
+        .await;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outage {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    /// Why the connection before the outage ended. Not set if the window starts offline.
+    pub reason: Option<String>,
+}
+
+/// Returns the intervals between `from` and `to` in which the charger was not connected.
+/// `events` must be sorted by `connected_at`. Intervals that are still open last until `to`.
+pub fn outages(
+    events: &[ConnectivityEvent],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Vec<Outage> {
+    let mut outages = Vec::new();
+    let mut online_until = from;
+    let mut reason = None;
+    for event in events {
+        let start = event.connected_at.clamp(from, to);
+        let end = event.disconnected_at.unwrap_or(to).clamp(from, to);
+        if start > online_until {
+            outages.push(Outage {
+                start: online_until,
+                end: start,
+                reason: reason.take(),
+            });
+        }
+        if end >= online_until {
+            online_until = end;
+            reason = event.disconnect_reason.clone();
+        }
+    }
+    if online_until < to {
+        outages.push(Outage {
+            start: online_until,
+            end: to,
+            reason,
+        });
+    }
+
+    outages
+}
+
+/// Share of the window in which the charger was connected, in percent.
+pub fn uptime_percent(outages: &[Outage], from: NaiveDateTime, to: NaiveDateTime) -> f64 {
+    let window = (to - from).num_milliseconds();
+    if window <= 0 {
+        return 0.0;
+    }
+    let offline: i64 = outages
+        .iter()
+        .map(|outage| (outage.end - outage.start).num_milliseconds())
+        .sum();
+
+    100.0 * (window - offline) as f64 / window as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::{DateTime, TimeDelta};
+
+    use super::*;
+    use crate::routes::user::tests::TestUser;
+
+    fn at(minutes: i64) -> NaiveDateTime {
+        DateTime::from_timestamp(1_700_000_000, 0)
+            .unwrap()
+            .naive_utc()
+            + TimeDelta::minutes(minutes)
+    }
+
+    fn event(connected: i64, disconnected: Option<(i64, &str)>) -> ConnectivityEvent {
+        ConnectivityEvent {
+            id: uuid::Uuid::new_v4(),
+            charger_id: uuid::Uuid::nil(),
+            connected_at: at(connected),
+            disconnected_at: disconnected.map(|(minutes, _)| at(minutes)),
+            disconnect_reason: disconnected.map(|(_, reason)| reason.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_outages() {
+        let events = [
+            event(-30, Some((10, "timeout"))),
+            event(20, Some((50, "rehandshake"))),
+            event(50, Some((70, "server_restart"))),
+            event(90, None),
+        ];
+
+        let outages = outages(&events, at(0), at(100));
+        assert_eq!(
+            outages,
+            vec![
+                Outage {
+                    start: at(10),
+                    end: at(20),
+                    reason: Some("timeout".to_string()),
+                },
+                Outage {
+                    start: at(70),
+                    end: at(90),
+                    reason: Some("server_restart".to_string()),
+                },
+            ]
+        );
+        assert_eq!(uptime_percent(&outages, at(0), at(100)), 70.0);
+    }
+
+    #[test]
+    fn test_outage_at_the_edges() {
+        let events = [event(10, Some((60, "timeout")))];
+
+        let outages = outages(&events, at(0), at(100));
+        assert_eq!(
+            outages,
+            vec![
+                Outage {
+                    start: at(0),
+                    end: at(10),
+                    reason: None,
+                },
+                Outage {
+                    start: at(60),
+                    end: at(100),
+                    reason: Some("timeout".to_string()),
+                },
+            ]
+        );
+        assert_eq!(uptime_percent(&outages, at(0), at(100)), 50.0);
+    }
+
+    fn open_interval(charger_id: uuid::Uuid, connected_at: NaiveDateTime) -> uuid::Uuid {
+        use db_connector::schema::device_connectivity_events::dsl::*;
+
+        let event = ConnectivityEvent {
+            id: uuid::Uuid::new_v4(),
+            charger_id,
+            connected_at,
+            disconnected_at: None,
+            disconnect_reason: None,
+        };
+        let event_id = event.id;
+        let pool = db_connector::test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        diesel::insert_into(device_connectivity_events)
+            .values(event)
+            .execute(&mut conn)
+            .unwrap();
+
+        event_id
+    }
+
+    fn get_event(event_id: uuid::Uuid) -> ConnectivityEvent {
+        use db_connector::schema::device_connectivity_events::dsl::*;
+
+        let pool = db_connector::test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        device_connectivity_events
+            .find(event_id)
+            .select(ConnectivityEvent::as_select())
+            .get_result(&mut conn)
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_close_stale_intervals() {
+        use db_connector::{models::relay_owners::RelayOwner, schema::relay_owners::dsl::*};
+
+        let pool = db_connector::test_connection_pool();
+        let (mut user, _) = TestUser::random().await;
+        let orphaned = user.add_random_charger().await;
+        let orphaned = uuid::Uuid::from_str(&orphaned.uuid).unwrap();
+        let owned = user.add_random_charger().await;
+        let owned = uuid::Uuid::from_str(&owned.uuid).unwrap();
+
+        // Far in the past so intervals of other tests are not touched.
+        let started_at = at(-60 * 24 * 365 * 20);
+        let orphaned_event = open_interval(orphaned, started_at - TimeDelta::hours(1));
+        let owned_event = open_interval(owned, started_at - TimeDelta::hours(1));
+        let new_event = open_interval(orphaned, started_at + TimeDelta::minutes(1));
+        {
+            let mut conn = pool.get().unwrap();
+            diesel::insert_into(relay_owners)
+                .values(RelayOwner {
+                    charger_id: owned,
+                    instance_id: uuid::Uuid::new_v4(),
+                    updated_at: chrono::Utc::now().naive_utc(),
+                })
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        close_stale_intervals(&pool, started_at).await;
+
+        let event = get_event(orphaned_event);
+        assert_eq!(event.disconnected_at, Some(started_at));
+        assert_eq!(event.disconnect_reason.as_deref(), Some("server_restart"));
+        assert_eq!(get_event(owned_event).disconnected_at, None);
+        assert_eq!(get_event(new_event).disconnected_at, None);
+    }
+
+    #[test]
+    fn test_never_connected() {
+        let outages = outages(&[], at(0), at(100));
+        assert_eq!(outages.len(), 1);
+        assert_eq!(uptime_percent(&outages, at(0), at(100)), 0.0);
+    }
+
+    #[test]
+    fn test_overlapping_intervals() {
+        // A stale interval that was closed late overlaps the next one.
+        let events = [
+            event(0, Some((80, "server_restart"))),
+            event(40, Some((60, "timeout"))),
+        ];
+
+        let outages = outages(&events, at(0), at(100));
+        assert_eq!(
+            outages,
+            vec![Outage {
+                start: at(80),
+                end: at(100),
+                reason: Some("server_restart".to_string()),
+            }]
+        );
+    }
+}
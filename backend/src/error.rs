@@ -52,6 +52,8 @@ pub enum Error {
     NoValidIp,
     #[display("Charger is currently not connected to the server")]
     ChargerDisconnected,
+    #[display("Charger did not answer")]
+    ChargerDidNotAnswer,
     #[display("Not an active session")]
     SessionDoesNotExist,
     #[display("The provided credentials are wrong")]
@@ -66,6 +68,20 @@ pub enum Error {
     AuthorizationTokenInvalid,
     #[display("Authorization token already used")]
     AuthorizationTokenAlreadyUsed,
+    #[display("The server is shutting down. Please try again in a moment")]
+    ServerShuttingDown,
+    #[display("Two-factor authentication code required")]
+    SecondFactorRequired,
+    #[display("Invalid two-factor authentication code")]
+    InvalidSecondFactor,
+    #[display("Two-factor authentication is already enabled")]
+    TotpAlreadyEnabled,
+    #[display("Two-factor authentication was not set up")]
+    TotpNotEnrolled,
+    #[display("Too many wrong two-factor authentication codes. Please try again later")]
+    SecondFactorLocked,
+    #[display("The session was already refreshed by a concurrent request")]
+    ConcurrentRefresh,
 }
 
 impl error::ResponseError for Error {
@@ -90,6 +106,7 @@ impl error::ResponseError for Error {
             Self::ChargerNotSeenYet => StatusCode::NOT_FOUND,
             Self::NoValidIp => StatusCode::BAD_REQUEST,
             Self::ChargerDisconnected => StatusCode::BAD_GATEWAY,
+            Self::ChargerDidNotAnswer => StatusCode::GATEWAY_TIMEOUT,
             Self::SessionDoesNotExist => StatusCode::UNAUTHORIZED,
             Self::ChargerCredentialsWrong => StatusCode::UNAUTHORIZED,
             Self::ChargerDoesNotExist => StatusCode::BAD_REQUEST,
@@ -97,6 +114,13 @@ impl error::ResponseError for Error {
             Self::InvalidRecoveryToken => StatusCode::BAD_REQUEST,
             Self::AuthorizationTokenInvalid => StatusCode::UNAUTHORIZED,
             Self::AuthorizationTokenAlreadyUsed => StatusCode::UNAUTHORIZED,
+            Self::ServerShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            Self::SecondFactorRequired => StatusCode::FORBIDDEN,
+            Self::InvalidSecondFactor => StatusCode::UNAUTHORIZED,
+            Self::TotpAlreadyEnabled => StatusCode::CONFLICT,
+            Self::TotpNotEnrolled => StatusCode::BAD_REQUEST,
+            Self::SecondFactorLocked => StatusCode::TOO_MANY_REQUESTS,
+            Self::ConcurrentRefresh => StatusCode::CONFLICT,
         }
     }
 }
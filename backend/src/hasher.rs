@@ -91,6 +91,11 @@ impl Default for HasherManager {
 }
 
 impl HasherManager {
+    /// Number of requests waiting in the queue for a free worker.
+    pub fn queue_depth(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
+    }
+
     pub async fn hash_password(
         &self,
         password: Vec<u8>,
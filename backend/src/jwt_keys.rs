@@ -0,0 +1,296 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Keys used to sign and verify the access and refresh tokens.
+//!
+//! Tokens are signed with an EdDSA or ES256 key that is identified by the `kid` header,
+//! so that other services can verify them with the public keys from the JWKS endpoint.
+//! Retired public keys stay valid for verification until all tokens signed with them
+//! have expired. Tokens without a `kid` are verified with the HS256 `jwt_secret`, which
+//! is also used for signing as long as no signing key is configured.
+
+use std::{collections::HashMap, path::Path};
+
+use jsonwebtoken::{
+    decode, decode_header,
+    errors::ErrorKind,
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet, PublicKeyUse, ThumbprintHash},
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{config::Config, error::Error};
+
+pub struct JwtKeys {
+    header: Header,
+    signing_key: EncodingKey,
+    verification_keys: HashMap<String, (Algorithm, DecodingKey)>,
+    secret: Option<DecodingKey>,
+    jwks: JwkSet,
+}
+
+/// Returns the signature algorithm a public key is meant for.
+fn key_algorithm(jwk: &Jwk) -> Result<Algorithm, String> {
+    match &jwk.algorithm {
+        AlgorithmParameters::OctetKeyPair(params) if params.curve == EllipticCurve::Ed25519 => {
+            Ok(Algorithm::EdDSA)
+        }
+        AlgorithmParameters::EllipticCurve(params) if params.curve == EllipticCurve::P256 => {
+            Ok(Algorithm::ES256)
+        }
+        _ => Err("Only Ed25519 and P-256 keys are supported".to_string()),
+    }
+}
+
+impl JwtKeys {
+    /// Signs and verifies with the shared secret only.
+    pub fn from_secret(secret: &str) -> Self {
+        Self {
+            header: Header::default(),
+            signing_key: EncodingKey::from_secret(secret.as_bytes()),
+            verification_keys: HashMap::new(),
+            secret: Some(DecodingKey::from_secret(secret.as_bytes())),
+            jwks: JwkSet { keys: Vec::new() },
+        }
+    }
+
+    /// `signing_key` is a PKCS#8 PEM encoded Ed25519 or P-256 private key. `retired_keys`
+    /// are public keys that are no longer used for signing but still accepted.
+    pub fn new(
+        secret: &str,
+        signing_key: Option<&[u8]>,
+        retired_keys: JwkSet,
+    ) -> Result<Self, String> {
+        let Some(pem) = signing_key else {
+            if !retired_keys.keys.is_empty() {
+                return Err("Verification keys require a signing key".to_string());
+            }
+            return Ok(Self::from_secret(secret));
+        };
+
+        let (algorithm, signing_key) = match EncodingKey::from_ed_pem(pem) {
+            Ok(key) => (Algorithm::EdDSA, key),
+            Err(_) => match EncodingKey::from_ec_pem(pem) {
+                Ok(key) => (Algorithm::ES256, key),
+                Err(err) => return Err(format!("Failed to parse signing key: {err}")),
+            },
+        };
+        let mut jwk = Jwk::from_encoding_key(&signing_key, algorithm)
+            .map_err(|err| format!("Failed to derive public key: {err}"))?;
+        let kid = jwk.thumbprint(ThumbprintHash::SHA256);
+        jwk.common.key_id = Some(kid.clone());
+        jwk.common.public_key_use = Some(PublicKeyUse::Signature);
+
+        let mut header = Header::new(algorithm);
+        header.kid = Some(kid);
+
+        let mut jwks = JwkSet { keys: vec![jwk] };
+        for jwk in retired_keys.keys {
+            let Some(kid) = &jwk.common.key_id else {
+                return Err("Verification keys need a kid".to_string());
+            };
+            if jwks.find(kid).is_some() {
+                continue;
+            }
+            key_algorithm(&jwk)?;
+            jwks.keys.push(jwk);
+        }
+
+        let mut verification_keys = HashMap::new();
+        for jwk in jwks.keys.iter() {
+            let key = DecodingKey::from_jwk(jwk)
+                .map_err(|err| format!("Failed to load verification key: {err}"))?;
+            let kid = jwk.common.key_id.clone().unwrap_or_default();
+            verification_keys.insert(kid, (key_algorithm(jwk)?, key));
+        }
+
+        Ok(Self {
+            header,
+            signing_key,
+            verification_keys,
+            secret: (!secret.is_empty()).then(|| DecodingKey::from_secret(secret.as_bytes())),
+            jwks,
+        })
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let signing_key = match &config.jwt_signing_key_path {
+            Some(path) => Some(read(path)?),
+            None => None,
+        };
+        let retired_keys = match &config.jwt_verification_keys_path {
+            Some(path) => serde_json::from_slice(&read(path)?)
+                .map_err(|err| format!("Failed to parse {path:?}: {err}"))?,
+            None => JwkSet { keys: Vec::new() },
+        };
+
+        Self::new(&config.jwt_secret, signing_key.as_deref(), retired_keys)
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, Error> {
+        match jsonwebtoken::encode(&self.header, claims, &self.signing_key) {
+            Ok(token) => Ok(token),
+            Err(_err) => Err(Error::InternalError),
+        }
+    }
+
+    /// Checks the signature and expiration of the token and returns its claims.
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> jsonwebtoken::errors::Result<T> {
+        let header = decode_header(token)?;
+        let (key, validation) = match &header.kid {
+            Some(kid) => match self.verification_keys.get(kid) {
+                Some((algorithm, key)) => (key, Validation::new(*algorithm)),
+                None => return Err(ErrorKind::InvalidToken.into()),
+            },
+            None => match &self.secret {
+                Some(secret) => (secret, Validation::default()),
+                None => return Err(ErrorKind::InvalidToken.into()),
+            },
+        };
+
+        Ok(decode::<T>(token, key, &validation)?.claims)
+    }
+
+    /// Public keys of the signing key and the retired keys.
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|err| format!("Failed to read {path:?}: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::models::token_claims::{TokenClaims, TokenType};
+
+    fn claims() -> TokenClaims {
+        let now = Utc::now();
+        TokenClaims {
+            iat: now.timestamp() as usize,
+            exp: (now + Duration::minutes(5)).timestamp() as usize,
+            sub: uuid::Uuid::new_v4().to_string(),
+            generation: 0,
+            token_type: Some(TokenType::Access),
+        }
+    }
+
+    fn generate(algorithm: &'static rcgen::SignatureAlgorithm) -> String {
+        rcgen::KeyPair::generate_for(algorithm)
+            .unwrap()
+            .serialize_pem()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        for algorithm in [&rcgen::PKCS_ED25519, &rcgen::PKCS_ECDSA_P256_SHA256] {
+            let pem = generate(algorithm);
+            let keys = JwtKeys::new("", Some(pem.as_bytes()), JwkSet { keys: Vec::new() }).unwrap();
+            assert_eq!(keys.jwks().keys.len(), 1);
+
+            let claims = claims();
+            let token = keys.encode(&claims).unwrap();
+            let header = decode_header(&token).unwrap();
+            assert_eq!(header.kid, keys.jwks().keys[0].common.key_id);
+
+            let decoded: TokenClaims = keys.decode(&token).unwrap();
+            assert_eq!(decoded.sub, claims.sub);
+
+            // Somebody who only knows the public key can verify but not sign.
+            let public = DecodingKey::from_jwk(&keys.jwks().keys[0]).unwrap();
+            let validation = Validation::new(header.alg);
+            assert!(decode::<TokenClaims>(&token, &public, &validation).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_rotation() {
+        let old = JwtKeys::new(
+            "",
+            Some(generate(&rcgen::PKCS_ED25519).as_bytes()),
+            JwkSet { keys: Vec::new() },
+        )
+        .unwrap();
+        let old_token = old.encode(&claims()).unwrap();
+
+        let retired = JwkSet {
+            keys: old.jwks().keys.clone(),
+        };
+        let new =
+            JwtKeys::new("", Some(generate(&rcgen::PKCS_ED25519).as_bytes()), retired).unwrap();
+        assert_eq!(new.jwks().keys.len(), 2);
+        assert!(new.decode::<TokenClaims>(&old_token).is_ok());
+        assert!(new
+            .decode::<TokenClaims>(&new.encode(&claims()).unwrap())
+            .is_ok());
+
+        // Without the retired key the old token is rejected.
+        let unrelated = JwtKeys::new(
+            "",
+            Some(generate(&rcgen::PKCS_ED25519).as_bytes()),
+            JwkSet { keys: Vec::new() },
+        )
+        .unwrap();
+        assert!(unrelated.decode::<TokenClaims>(&old_token).is_err());
+    }
+
+    #[test]
+    fn test_secret_tokens() {
+        let legacy = JwtKeys::from_secret("secret");
+        let token = legacy.encode(&claims()).unwrap();
+        assert!(legacy.jwks().keys.is_empty());
+
+        let keys = JwtKeys::new(
+            "secret",
+            Some(generate(&rcgen::PKCS_ED25519).as_bytes()),
+            JwkSet { keys: Vec::new() },
+        )
+        .unwrap();
+        assert!(keys.decode::<TokenClaims>(&token).is_ok());
+
+        let keys = JwtKeys::new(
+            "",
+            Some(generate(&rcgen::PKCS_ED25519).as_bytes()),
+            JwkSet { keys: Vec::new() },
+        )
+        .unwrap();
+        assert!(keys.decode::<TokenClaims>(&token).is_err());
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert!(JwtKeys::new("", Some(b"garbage"), JwkSet { keys: Vec::new() }).is_err());
+
+        let keys = JwtKeys::new(
+            "",
+            Some(generate(&rcgen::PKCS_ED25519).as_bytes()),
+            JwkSet { keys: Vec::new() },
+        )
+        .unwrap();
+        let mut retired = keys.jwks().clone();
+        retired.keys[0].common.key_id = None;
+        assert!(
+            JwtKeys::new("", Some(generate(&rcgen::PKCS_ED25519).as_bytes()), retired).is_err()
+        );
+    }
+}
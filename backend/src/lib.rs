@@ -18,9 +18,9 @@
  */
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashSet,
     net::SocketAddr,
-    sync::Arc,
+    sync::{atomic::AtomicBool, Arc},
     time::Instant,
 };
 use tokio::net::UdpSocket;
@@ -36,22 +36,33 @@ use db_connector::{
 };
 use diesel::{prelude::*, r2d2::PooledConnection, result::Error::NotFound};
 use futures_util::lock::Mutex;
-use ipnetwork::IpNetwork;
 use lettre::SmtpTransport;
 use serde::{ser::SerializeStruct, Serialize};
-use udp_server::{
-    management::RemoteConnMeta, packet::ManagementResponseV2, socket::ManagementSocket,
-};
+use udp_server::management::RemoteConnMeta;
 
+pub mod bandwidth;
 pub mod branding;
+pub mod config;
+pub mod connectivity;
 pub mod error;
 pub mod hasher;
+pub mod jwt_keys;
+pub mod logging;
+pub mod metrics;
 pub mod middleware;
 pub mod models;
+pub mod offline_notifications;
 pub mod rate_limit;
+pub mod registry;
+pub mod relay_bus;
 pub mod routes;
+pub mod shutdown;
+pub mod tls;
+pub mod token_generation;
+pub mod totp;
 pub mod udp_server;
 pub mod utils;
+pub mod webhooks;
 pub mod ws_udp_bridge;
 
 #[derive(Hash, PartialEq, Eq, Clone, Debug)]
@@ -74,23 +85,26 @@ impl Serialize for DiscoveryCharger {
 
 pub struct BridgeState<'a> {
     pub pool: Pool,
-    pub web_client_map: Mutex<HashMap<SocketAddr, Session>>,
-    pub undiscovered_clients: Mutex<HashMap<RemoteConnMeta, Session>>,
-    pub device_management_map: Arc<Mutex<HashMap<SocketAddr, Arc<Mutex<ManagementSocket<'a>>>>>>,
-    pub device_management_map_with_id:
-        Arc<Mutex<HashMap<uuid::Uuid, Arc<Mutex<ManagementSocket<'a>>>>>>,
-    pub port_discovery: Arc<Mutex<HashMap<ManagementResponseV2, Instant>>>,
-    pub device_remote_conn_map: Mutex<HashMap<RemoteConnMeta, SocketAddr>>,
-    pub undiscovered_devices: Arc<Mutex<HashMap<IpNetwork, HashSet<DiscoveryCharger>>>>,
-    pub lost_connections: Mutex<HashMap<uuid::Uuid, Vec<(i32, Session)>>>,
+    pub connections: registry::ConnectionRegistry<'a>,
     pub socket: Arc<UdpSocket>,
-    pub state_update_clients: Mutex<HashMap<uuid::Uuid, Session>>,
+    pub state_update_clients: dashmap::DashMap<uuid::Uuid, Session>,
     pub device_ratelimiter: crate::rate_limit::ChargerRateLimiter,
+    /// Only set when running with multiple instances.
+    pub relay: Option<crate::relay_bus::RelayState>,
+    pub shutting_down: AtomicBool,
+    /// Devices that were connected before the last restart, keyed by their address.
+    pub known_devices: dashmap::DashMap<SocketAddr, udp_server::session_store::KnownDevice>,
+    /// Used to find the charger of a handshake from an unknown address.
+    pub peer_index: Arc<udp_server::peer_index::PeerIndex>,
+    /// Traffic of the open remote access sessions.
+    pub session_traffic: dashmap::DashMap<RemoteConnMeta, Arc<bandwidth::SessionTraffic>>,
+    /// Traffic of all open sessions per charger.
+    pub device_traffic: dashmap::DashMap<uuid::Uuid, bandwidth::DeviceTraffic>,
 }
 
 pub struct AppState {
     pub pool: Pool,
-    pub jwt_secret: String,
+    pub jwt_keys: crate::jwt_keys::JwtKeys,
     pub mailer: Option<SmtpTransport>,
     pub frontend_url: String,
     pub sender_email: String,
@@ -98,6 +112,7 @@ pub struct AppState {
     pub brand: crate::branding::Brand,
     pub keys_in_use: Mutex<HashSet<uuid::Uuid>>,
     pub hasher: crate::hasher::HasherManager,
+    pub config: Arc<crate::config::Config>,
 }
 
 pub fn clean_recovery_tokens(
@@ -112,6 +127,91 @@ pub fn clean_recovery_tokens(
     }
 }
 
+/// Removes access log entries of sessions that started more than `retention_days` ago.
+pub fn clean_access_sessions(
+    conn: &mut PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>>,
+    retention_days: u32,
+) {
+    use db_connector::schema::access_sessions::dsl::*;
+
+    if let Some(time) = Utc::now().checked_sub_signed(TimeDelta::days(retention_days as i64)) {
+        if let Err(err) =
+            diesel::delete(access_sessions.filter(started_at.lt(time.naive_utc()))).execute(conn)
+        {
+            log::error!("Failed to clean up access log: {err}");
+        }
+    }
+}
+
+/// Ends the access log entries that were opened before `boot_time` and are still open, because
+/// the previous process went away before it could finish them.
+/// Sessions to chargers whose tunnel is held by another running instance may still be open.
+pub fn close_dangling_access_sessions(
+    conn: &mut PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>>,
+    boot_time: chrono::NaiveDateTime,
+) {
+    use db_connector::schema::access_sessions::dsl::*;
+    use db_connector::schema::relay_owners::dsl as relay_owners;
+
+    let live_owners = relay_owners::relay_owners
+        .filter(relay_owners::updated_at.ge(boot_time - relay_bus::OWNER_TTL))
+        .select(relay_owners::charger_id);
+    match diesel::update(access_sessions)
+        .filter(ended_at.is_null())
+        .filter(started_at.lt(boot_time))
+        .filter(charger_id.ne_all(live_owners))
+        .set((
+            ended_at.eq(boot_time),
+            close_reason.eq(ws_udp_bridge::SessionEndReason::ServerShutdown.as_str()),
+        ))
+        .execute(conn)
+    {
+        Ok(0) => (),
+        Ok(closed) => log::info!("Closed {closed} access log entries left open by a restart"),
+        Err(err) => log::error!("Failed to close dangling access log entries: {err}"),
+    }
+}
+
+/// Removes webhook deliveries that were sent or given up more than a month ago.
+pub fn clean_webhook_deliveries(
+    conn: &mut PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>>,
+) {
+    use db_connector::schema::webhook_deliveries::dsl::*;
+
+    if let Some(time) = Utc::now().checked_sub_signed(TimeDelta::days(30)) {
+        if let Err(err) = diesel::delete(
+            webhook_deliveries
+                .filter(created_at.lt(time.naive_utc()))
+                .filter(
+                    delivered_at
+                        .is_not_null()
+                        .or(attempts.ge(webhooks::MAX_ATTEMPTS)),
+                ),
+        )
+        .execute(conn)
+        {
+            log::error!("Failed to clean up webhook deliveries: {err}");
+        }
+    }
+}
+
+/// Removes connectivity history that ended more than `retention_days` ago.
+pub fn clean_connectivity_events(
+    conn: &mut PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>>,
+    retention_days: u32,
+) {
+    use db_connector::schema::device_connectivity_events::dsl::*;
+
+    if let Some(time) = Utc::now().checked_sub_signed(TimeDelta::days(retention_days as i64)) {
+        if let Err(err) =
+            diesel::delete(device_connectivity_events.filter(disconnected_at.lt(time.naive_utc())))
+                .execute(conn)
+        {
+            log::error!("Failed to clean up connectivity history: {err}");
+        }
+    }
+}
+
 pub fn clean_refresh_tokens(
     conn: &mut PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>>,
 ) {
@@ -122,6 +222,19 @@ pub fn clean_refresh_tokens(
         .ok();
 }
 
+/// Removes pre-auth tokens of logins that were not completed with a second factor.
+pub fn clean_pre_auth_tokens(
+    conn: &mut PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>>,
+) {
+    use db_connector::schema::pre_auth_tokens::dsl::*;
+
+    if let Err(err) =
+        diesel::delete(pre_auth_tokens.filter(expires_at.lt(Utc::now().naive_utc()))).execute(conn)
+    {
+        log::error!("Failed to clean up pre-auth tokens: {err}");
+    }
+}
+
 pub fn clean_verification_tokens(
     conn: &mut PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>>,
 ) {
@@ -257,7 +370,7 @@ pub(crate) mod tests {
         test_connection_pool,
     };
     use diesel::r2d2::ConnectionManager;
-    use ipnetwork::Ipv4Network;
+    use ipnetwork::{IpNetwork, Ipv4Network};
     use lru::LruCache;
     use rand_core::{OsRng, TryRngCore};
     use rate_limit::{ChargerRateLimiter, LoginRateLimiter};
@@ -303,7 +416,9 @@ pub(crate) mod tests {
 
         let state = AppState {
             pool: pool.clone(),
-            jwt_secret: std::env::var("JWT_SECRET").expect("JWT_SECRET must be set!"),
+            jwt_keys: crate::jwt_keys::JwtKeys::from_secret(
+                &std::env::var("JWT_SECRET").expect("JWT_SECRET must be set!"),
+            ),
             mailer: None,
             frontend_url: std::env::var("FRONTEND_URL").expect("FRONTEND_URL must be set!"),
             sender_email: String::new(),
@@ -311,6 +426,7 @@ pub(crate) mod tests {
             brand: crate::branding::Brand::default(),
             keys_in_use: Mutex::new(HashSet::new()),
             hasher: crate::hasher::HasherManager::default(),
+            config: Arc::new(crate::config::Config::default()),
         };
 
         web::Data::new(state)
@@ -339,6 +455,13 @@ pub(crate) mod tests {
 
     pub fn create_test_bridge_state(
         pool: Option<diesel::r2d2::Pool<ConnectionManager<PgConnection>>>,
+    ) -> web::Data<BridgeState<'static>> {
+        create_test_bridge_state_with_relay(pool, None)
+    }
+
+    pub fn create_test_bridge_state_with_relay(
+        pool: Option<diesel::r2d2::Pool<ConnectionManager<PgConnection>>>,
+        relay: Option<crate::relay_bus::RelayState>,
     ) -> web::Data<BridgeState<'static>> {
         let pool = pool.unwrap_or_else(db_connector::test_connection_pool);
 
@@ -346,22 +469,39 @@ pub(crate) mod tests {
         std_socket.set_nonblocking(true).unwrap();
         let bridge_state = BridgeState {
             pool: pool.clone(),
-            device_management_map: Arc::new(Mutex::new(HashMap::new())),
-            device_management_map_with_id: Arc::new(Mutex::new(HashMap::new())),
-            port_discovery: Arc::new(Mutex::new(HashMap::new())),
-            device_remote_conn_map: Mutex::new(HashMap::new()),
-            undiscovered_clients: Mutex::new(HashMap::new()),
-            web_client_map: Mutex::new(HashMap::new()),
-            undiscovered_devices: Arc::new(Mutex::new(HashMap::new())),
-            lost_connections: Mutex::new(HashMap::new()),
+            connections: registry::ConnectionRegistry::new(),
             socket: Arc::new(UdpSocket::from_std(std_socket).unwrap()),
-            state_update_clients: Mutex::new(HashMap::new()),
+            state_update_clients: dashmap::DashMap::new(),
             device_ratelimiter: crate::rate_limit::ChargerRateLimiter::new(),
+            relay,
+            shutting_down: AtomicBool::new(false),
+            known_devices: dashmap::DashMap::new(),
+            peer_index: Arc::new(udp_server::peer_index::PeerIndex::new()),
+            session_traffic: dashmap::DashMap::new(),
+            device_traffic: dashmap::DashMap::new(),
         };
 
         web::Data::new(bridge_state)
     }
 
+    /// Opens a websocket whose frames to the browser end up in the returned body.
+    pub async fn create_test_session() -> (BoxBody, Session) {
+        use actix_web::{http::header, FromRequest};
+
+        let (req, mut payload) = test::TestRequest::get()
+            .insert_header((header::UPGRADE, "websocket"))
+            .insert_header((header::CONNECTION, "upgrade"))
+            .insert_header((header::SEC_WEBSOCKET_VERSION, "13"))
+            .insert_header((header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="))
+            .to_http_parts();
+        let stream = web::Payload::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        let (resp, session, _) = actix_ws::handle(&req, stream).unwrap();
+
+        (resp.into_body(), session)
+    }
+
     pub fn configure(cfg: &mut ServiceConfig) {
         let pool = db_connector::test_connection_pool();
 
@@ -369,17 +509,16 @@ pub(crate) mod tests {
         std_socket.set_nonblocking(true).unwrap();
         let bridge_state = BridgeState {
             pool: pool.clone(),
-            device_management_map: Arc::new(Mutex::new(HashMap::new())),
-            device_management_map_with_id: Arc::new(Mutex::new(HashMap::new())),
-            port_discovery: Arc::new(Mutex::new(HashMap::new())),
-            device_remote_conn_map: Mutex::new(HashMap::new()),
-            undiscovered_clients: Mutex::new(HashMap::new()),
-            web_client_map: Mutex::new(HashMap::new()),
-            undiscovered_devices: Arc::new(Mutex::new(HashMap::new())),
-            lost_connections: Mutex::new(HashMap::new()),
+            connections: registry::ConnectionRegistry::new(),
             socket: Arc::new(UdpSocket::from_std(std_socket).unwrap()),
-            state_update_clients: Mutex::new(HashMap::new()),
+            state_update_clients: dashmap::DashMap::new(),
             device_ratelimiter: crate::rate_limit::ChargerRateLimiter::new(),
+            relay: None,
+            shutting_down: AtomicBool::new(false),
+            known_devices: dashmap::DashMap::new(),
+            peer_index: Arc::new(udp_server::peer_index::PeerIndex::new()),
+            session_traffic: dashmap::DashMap::new(),
+            device_traffic: dashmap::DashMap::new(),
         };
 
         let cache: web::Data<std::sync::Mutex<LruCache<String, Vec<u8>>>> = web::Data::new(
@@ -473,11 +612,24 @@ pub(crate) mod tests {
             id: token1_id,
             user_id: uid,
             expiration: Utc::now().timestamp() + 1,
+            created_at: Utc::now().naive_utc(),
+            last_used_at: Utc::now().naive_utc(),
+            user_agent: None,
+            ip: None,
+            family_id: token1_id,
+            rotated_at: None,
         };
+        let token2_id = uuid::Uuid::new_v4();
         let token2 = RefreshToken {
-            id: uuid::Uuid::new_v4(),
+            id: token2_id,
             user_id: uid,
             expiration: Utc::now().timestamp() - 1,
+            created_at: Utc::now().naive_utc(),
+            last_used_at: Utc::now().naive_utc(),
+            user_agent: None,
+            ip: None,
+            family_id: token2_id,
+            rotated_at: None,
         };
 
         diesel::insert_into(refresh_tokens)
@@ -518,6 +670,7 @@ pub(crate) mod tests {
             delivery_email: Some(email.clone()),
             old_email: None,
             old_delivery_email: None,
+            token_generation: 0,
         };
 
         let user2_id = uuid::Uuid::new_v4();
@@ -534,6 +687,7 @@ pub(crate) mod tests {
             delivery_email: None,
             old_email: None,
             old_delivery_email: None,
+            token_generation: 0,
         };
 
         let user3_id = uuid::Uuid::new_v4();
@@ -550,6 +704,7 @@ pub(crate) mod tests {
             delivery_email: None,
             old_email: None,
             old_delivery_email: None,
+            token_generation: 0,
         };
 
         let user4_id = uuid::Uuid::new_v4();
@@ -566,6 +721,7 @@ pub(crate) mod tests {
             delivery_email: Some(email.clone()),
             old_email: Some(email.clone()),
             old_delivery_email: Some(email.clone()),
+            token_generation: 0,
         };
 
         let verify_id = uuid::Uuid::new_v4();
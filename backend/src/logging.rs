@@ -0,0 +1,243 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Correlation ids for log lines and a logger writing them as JSON.
+//!
+//! The ids live in a task local so everything logged while handling a request
+//! or a remote access session carries them without passing them around.
+
+use std::{cell::RefCell, future::Future, io::Write, sync::Mutex};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+    Error,
+};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+use simplelog::{Config, SharedLogger};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct LogContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<uuid::Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<uuid::Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charger_id: Option<uuid::Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conn_no: Option<i32>,
+}
+
+tokio::task_local! {
+    static CONTEXT: RefCell<LogContext>;
+}
+
+/// Runs `f` with `context` attached to everything it logs.
+pub async fn scope<F: Future>(context: LogContext, f: F) -> F::Output {
+    CONTEXT.scope(RefCell::new(context), f).await
+}
+
+/// Returns the context of the running task or an empty one outside of a scope.
+pub fn current() -> LogContext {
+    CONTEXT.try_with(|c| c.borrow().clone()).unwrap_or_default()
+}
+
+/// Changes the context of the running task. Does nothing outside of a scope.
+pub fn update(f: impl FnOnce(&mut LogContext)) {
+    let _ = CONTEXT.try_with(|c| f(&mut c.borrow_mut()));
+}
+
+/// Gives every request an id that is returned in the `X-Request-Id` header.
+pub async fn request_context(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = uuid::Uuid::new_v4();
+    let context = LogContext {
+        request_id: Some(request_id),
+        ..Default::default()
+    };
+
+    // The inner services must be called inside the scope since some of them already log in `call`.
+    let mut resp = scope(context, async move { next.call(req).await }).await?;
+    resp.headers_mut().insert(
+        HeaderName::from_static(REQUEST_ID_HEADER),
+        HeaderValue::from_str(&request_id.to_string()).unwrap(),
+    );
+
+    Ok(resp)
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    time: String,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+    #[serde(flatten)]
+    context: &'a LogContext,
+}
+
+fn format_record(record: &Record, context: &LogContext) -> String {
+    let line = JsonLine {
+        time: chrono::Local::now().to_rfc3339(),
+        level: record.level().as_str(),
+        target: record.target(),
+        message: record.args().to_string(),
+        context,
+    };
+
+    serde_json::to_string(&line).unwrap_or_default()
+}
+
+/// Writes one JSON object per line.
+pub struct JsonLogger<W: Write + Send + 'static> {
+    level: LevelFilter,
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send + 'static> JsonLogger<W> {
+    pub fn new(level: LevelFilter, writer: W) -> Box<Self> {
+        Box::new(Self {
+            level,
+            writer: Mutex::new(writer),
+        })
+    }
+}
+
+impl<W: Write + Send + 'static> Log for JsonLogger<W> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format_record(record, &current());
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writeln!(writer, "{line}");
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writer.flush();
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> SharedLogger for JsonLogger<W> {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{get, middleware::from_fn, test, App, HttpResponse, Responder};
+
+    use super::*;
+
+    #[get("/context")]
+    async fn context() -> impl Responder {
+        HttpResponse::Ok().json(current().request_id)
+    }
+
+    #[actix_web::test]
+    async fn test_request_context() {
+        let app = App::new().wrap(from_fn(request_context)).service(context);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::get().uri("/context").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let header: uuid::Uuid = resp
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body: Option<uuid::Uuid> = test::read_body_json(resp).await;
+        assert_eq!(body, Some(header));
+    }
+
+    #[actix_web::test]
+    async fn test_scope() {
+        assert_eq!(current(), LogContext::default());
+        // Updates outside of a scope are ignored.
+        update(|c| c.conn_no = Some(1));
+
+        let charger_id = uuid::Uuid::new_v4();
+        let context = scope(LogContext::default(), async move {
+            update(|c| {
+                c.charger_id = Some(charger_id);
+                c.conn_no = Some(2);
+            });
+            current()
+        })
+        .await;
+        assert_eq!(context.charger_id, Some(charger_id));
+        assert_eq!(context.conn_no, Some(2));
+        assert_eq!(current(), LogContext::default());
+    }
+
+    #[test]
+    fn test_format_record() {
+        let user_id = uuid::Uuid::new_v4();
+        let context = LogContext {
+            user_id: Some(user_id),
+            conn_no: Some(3),
+            ..Default::default()
+        };
+        let line = format_record(
+            &Record::builder()
+                .args(format_args!("Closed connection"))
+                .level(log::Level::Info)
+                .target("backend::ws_udp_bridge")
+                .build(),
+            &context,
+        );
+
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "INFO");
+        assert_eq!(value["target"], "backend::ws_udp_bridge");
+        assert_eq!(value["message"], "Closed connection");
+        assert_eq!(value["user_id"], user_id.to_string());
+        assert_eq!(value["conn_no"], 3);
+        assert!(value.get("request_id").is_none());
+        assert!(value.get("charger_id").is_none());
+    }
+}
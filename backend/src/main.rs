@@ -20,41 +20,36 @@
 mod monitoring;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashSet,
     num::NonZeroUsize,
-    sync::Arc,
+    sync::{atomic::AtomicBool, Arc},
     time::Duration,
 };
-use tokio::net::UdpSocket;
 
 use actix_files::{Files, NamedFile};
 use actix_web::{
     dev::{fn_service, ServiceRequest, ServiceResponse},
-    middleware::{Compress, Logger},
+    middleware::{from_fn, Compress, Logger},
     web, App, HttpServer,
 };
 pub use backend::*;
-use backend::{rate_limit::IPRateLimiter, utils::get_connection};
+use backend::{
+    config::{Config, LogFormat, RelayBus},
+    rate_limit::IPRateLimiter,
+    utils::get_connection,
+};
 
 use db_connector::{get_connection_pool, run_migrations};
 use futures_util::lock::Mutex;
 use lettre::{transport::smtp::authentication::Credentials, SmtpTransport};
 use lru::LruCache;
 use rate_limit::{ChargerRateLimiter, LoginRateLimiter};
-use rustls::ServerConfig;
-use rustls_pki_types::pem::PemObject;
-use rustls_pki_types::{CertificateDer, PrivateKeyDer};
 use simplelog::{
-    ColorChoice, CombinedLogger, ConfigBuilder, LevelFilter, TermLogger, TerminalMode,
+    ColorChoice, CombinedLogger, ConfigBuilder, LevelFilter, SharedLogger, TermLogger,
+    TerminalMode, WriteLogger,
 };
 
-#[cfg(not(debug_assertions))]
-use simplelog::WriteLogger;
-
-use udp_server::packet::{
-    ManagementCommand, ManagementCommandId, ManagementCommandPacket, ManagementPacket,
-    ManagementPacketHeader,
-};
+use udp_server::session_store;
 
 fn cleanup_thread(state: web::Data<AppState>) {
     loop {
@@ -68,168 +63,230 @@ fn cleanup_thread(state: web::Data<AppState>) {
         };
 
         clean_refresh_tokens(&mut conn);
+        clean_pre_auth_tokens(&mut conn);
         clean_recovery_tokens(&mut conn);
         clean_verification_tokens(&mut conn);
         clean_devices(&mut conn);
+        clean_access_sessions(&mut conn, state.config.access_log_retention_days);
+        clean_connectivity_events(&mut conn, state.config.connectivity_retention_days);
+        clean_webhook_deliveries(&mut conn);
+        backend::token_generation::prune_cache();
     }
 }
 
-async fn resend_thread(bridge_state: web::Data<BridgeState<'_>>) {
+fn offline_notification_thread(state: web::Data<AppState>) {
+    let started_at = chrono::Utc::now().naive_utc();
     loop {
-        tokio::time::sleep(Duration::from_secs(1)).await;
-        let undiscovered_ports = bridge_state.port_discovery.lock().await;
-        for port in undiscovered_ports.iter() {
-            let port = port.0;
-            let command = ManagementCommand {
-                command_id: ManagementCommandId::Connect,
-                connection_no: port.connection_no,
-                connection_uuid: port.connection_uuid,
-            };
-
-            let header = ManagementPacketHeader {
-                magic: 0x1234,
-                length: std::mem::size_of::<ManagementCommand>() as u16,
-                seq_number: 0,
-                version: 1,
-                p_type: udp_server::packet::PacketType::ManagementCommand,
-            };
-
-            let packet = ManagementCommandPacket { header, command };
-            let device_id = uuid::Uuid::from_u128(port.charger_id);
-            let devices = bridge_state.device_management_map_with_id.lock().await;
-            if let Some(sock) = devices.get(&device_id) {
-                let mut sock = sock.lock().await;
-                sock.send_packet(ManagementPacket::CommandPacket(packet));
+        std::thread::sleep(Duration::from_secs(60));
+
+        let mut conn = match get_connection(&state) {
+            Ok(c) => c,
+            Err(_err) => {
+                continue;
             }
-        }
+        };
+
+        offline_notifications::send_notifications(
+            &state,
+            &mut conn,
+            chrono::Utc::now().naive_utc(),
+            started_at,
+        );
     }
 }
 
-fn load_rustls_config() -> Option<ServerConfig> {
-    let cert_path = std::env::var("TLS_CERT_PATH").ok()?;
-    let key_path = std::env::var("TLS_KEY_PATH").ok()?;
-
-    let cert_chain: Vec<CertificateDer<'static>> = CertificateDer::pem_file_iter(&cert_path)
-        .ok()?
-        .filter_map(|c| c.ok())
-        .collect();
-    let key = PrivateKeyDer::from_pem_file(&key_path).ok()?;
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    dotenvy::dotenv().ok();
 
-    let config = ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(cert_chain, key)
-        .ok()?;
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
 
-    Some(config)
-}
+    // Used by deployments to validate a configuration before the running server is replaced.
+    if std::env::args().any(|arg| arg == "--check-config") {
+        match config.check_files() {
+            Ok(()) => {
+                println!("Configuration is valid");
+                std::process::exit(0);
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+    }
+    let config = Arc::new(config);
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
     let log_config = ConfigBuilder::new()
         .set_time_format_rfc3339()
         .set_time_offset_to_local()
         .unwrap()
         .build();
 
-    #[cfg(not(debug_assertions))]
-    let write_logger = WriteLogger::new(
-        LevelFilter::Info,
-        log_config.clone(),
-        std::fs::File::create(format!(
-            "/logs/backend-{}.log",
-            chrono::Local::now().format("%Y-%m-%d-%H")
-        ))
-        .unwrap(),
-    );
-
     #[cfg(debug_assertions)]
-    CombinedLogger::init(vec![TermLogger::new(
-        LevelFilter::Debug,
-        log_config,
-        TerminalMode::Mixed,
-        ColorChoice::Auto,
-    )])
-    .unwrap();
-
+    let log_level = LevelFilter::Debug;
     #[cfg(not(debug_assertions))]
-    CombinedLogger::init(vec![
-        TermLogger::new(
-            LevelFilter::Info,
-            log_config,
-            TerminalMode::Mixed,
-            ColorChoice::Auto,
-        ),
-        write_logger,
-    ])
-    .unwrap();
+    let log_level = LevelFilter::Info;
 
-    dotenvy::dotenv().ok();
+    let log_file = config.log.directory.as_ref().map(|log_dir| {
+        std::fs::File::create(log_dir.join(format!(
+            "backend-{}.log",
+            chrono::Local::now().format("%Y-%m-%d-%H")
+        )))
+        .unwrap()
+    });
+    let mut loggers: Vec<Box<dyn SharedLogger>> = Vec::new();
+    match config.log.format {
+        LogFormat::Text => {
+            loggers.push(TermLogger::new(
+                log_level,
+                log_config.clone(),
+                TerminalMode::Mixed,
+                ColorChoice::Auto,
+            ));
+            if let Some(file) = log_file {
+                loggers.push(WriteLogger::new(log_level, log_config, file));
+            }
+        }
+        LogFormat::Json => {
+            loggers.push(logging::JsonLogger::new(log_level, std::io::stdout()));
+            if let Some(file) = log_file {
+                loggers.push(logging::JsonLogger::new(log_level, file));
+            }
+        }
+    }
+    CombinedLogger::init(loggers).unwrap();
 
-    let pool = get_connection_pool();
+    let pool = get_connection_pool(&config.database_url);
     {
         let mut conn = pool.get().expect("Failed to get connection from pool");
         run_migrations(&mut conn).expect("Failed to run migrations");
     }
 
-    let mailer = {
-        let email = std::env::var("EMAIL_USER").expect("EMAIL_USER must be set");
-        let pass = std::env::var("EMAIL_PASS").expect("EMAIL_PASS must be set");
-        let relay = std::env::var("EMAIL_RELAY").expect("EMAIL_RELAY must be set");
-        let port: u16 = std::env::var("EMAIL_RELAY_PORT")
-            .expect("EMAIL_RELAY_PORT must be set")
-            .parse()
-            .unwrap();
-        SmtpTransport::relay(&relay)
-            .unwrap()
-            .port(port)
-            .credentials(Credentials::new(email, pass))
-            .build()
-    };
+    let mailer = SmtpTransport::relay(&config.email.relay)
+        .unwrap()
+        .port(config.email.relay_port)
+        .credentials(Credentials::new(
+            config.email.user.clone(),
+            config.email.pass.clone(),
+        ))
+        .build();
 
-    let sender_email = std::env::var("SENDER_EMAIL").expect("SENDER_EMAIL must be set");
-    let sender_name = std::env::var("SENDER_NAME").expect("SENDER_NAME must be set");
-    let brand = backend::branding::Brand::from_env();
+    let jwt_keys = match backend::jwt_keys::JwtKeys::from_config(&config) {
+        Ok(keys) => keys,
+        Err(err) => {
+            log::error!("Failed to load JWT keys: {err}");
+            std::process::exit(1);
+        }
+    };
 
     let state = web::Data::new(AppState {
         pool: pool.clone(),
-        jwt_secret: std::env::var("JWT_SECRET").expect("JWT_SECRET must be set!"),
+        jwt_keys,
         mailer: Some(mailer),
-        frontend_url: std::env::var("FRONTEND_URL").expect("FRONTEND_URL must be set!"),
-        sender_email,
-        sender_name,
-        brand,
+        frontend_url: config.frontend_url.clone(),
+        sender_email: config.email.sender_email.clone(),
+        sender_name: config.email.sender_name.clone(),
+        brand: config.brand,
         keys_in_use: Mutex::new(HashSet::new()),
         hasher: backend::hasher::HasherManager::default(),
+        config: config.clone(),
     });
 
     monitoring::start_monitoring(state.clone());
 
-    let udp_socket = UdpSocket::bind("0.0.0.0:51820")
-        .await
-        .expect("Failed to bind UDP socket");
+    // Other instances revoke tokens of users whose generation this one may have cached.
+    backend::token_generation::spawn_listener(&config.database_url)
+        .expect("Failed to start token revocation listener");
+
+    let udp_socket =
+        udp_server::bind_socket(config.wireguard_address()).expect("Failed to bind UDP socket");
     let device_ratelimiter = crate::rate_limit::ChargerRateLimiter::new();
+
+    // Multiple instances exchange relayed traffic via postgres notifications.
+    let (relay, relay_rx) = match config.relay_bus {
+        Some(RelayBus::Postgres) => {
+            let instance_id = uuid::Uuid::new_v4();
+            let (bus, rx) = relay_bus::PgRelayBus::new(&config.database_url, instance_id)
+                .expect("Failed to start relay bus");
+            log::info!("Running as relay instance {instance_id}");
+            (
+                Some(relay_bus::RelayState::new(instance_id, Arc::new(bus))),
+                Some(rx),
+            )
+        }
+        None => (None, None),
+    };
     let bridge_state = web::Data::new(BridgeState {
         pool,
-        web_client_map: Mutex::new(HashMap::new()),
-        undiscovered_clients: Mutex::new(HashMap::new()),
-        device_management_map: Arc::new(Mutex::new(HashMap::new())),
-        device_management_map_with_id: Arc::new(Mutex::new(HashMap::new())),
-        port_discovery: Arc::new(Mutex::new(HashMap::new())),
-        device_remote_conn_map: Mutex::new(HashMap::new()),
-        undiscovered_devices: Arc::new(Mutex::new(HashMap::new())),
-        lost_connections: Mutex::new(HashMap::new()),
+        connections: registry::ConnectionRegistry::new(),
         socket: Arc::new(udp_socket),
-        state_update_clients: Mutex::new(HashMap::new()),
+        state_update_clients: dashmap::DashMap::new(),
         device_ratelimiter,
+        relay,
+        shutting_down: AtomicBool::new(false),
+        known_devices: dashmap::DashMap::new(),
+        peer_index: Arc::new(udp_server::peer_index::PeerIndex::new()),
+        session_traffic: dashmap::DashMap::new(),
+        device_traffic: dashmap::DashMap::new(),
     });
 
+    // Devices that were connected before the restart can be matched without searching for them.
+    if let Some(path) = &config.session_state_path {
+        match session_store::load(path) {
+            Ok(Some(snapshot)) => session_store::restore(&bridge_state, snapshot).await,
+            Ok(None) => (),
+            Err(err) => log::error!("Failed to load session state from {:?}: {err}", path),
+        }
+    }
+
+    // Intervals and sessions that were open when the previous process went away.
+    let boot_time = chrono::Utc::now().naive_utc();
+    connectivity::close_stale_intervals(&bridge_state.pool, boot_time).await;
+    match bridge_state.pool.get() {
+        Ok(mut conn) => close_dangling_access_sessions(&mut conn, boot_time),
+        Err(err) => log::error!("Failed to close dangling access log entries: {err}"),
+    }
+
+    if let Some(rx) = relay_rx {
+        actix::spawn(relay_bus::run_listener(rx, bridge_state.clone()));
+        actix::spawn(relay_bus::run_heartbeat(bridge_state.clone()));
+    }
+    actix::spawn(webhooks::run_delivery_worker(state.pool.clone()));
+
     let state_cpy = state.clone();
     std::thread::spawn(move || cleanup_thread(state_cpy));
-    let bridge_state_cpy = bridge_state.clone();
-    actix::spawn(resend_thread(bridge_state_cpy));
+    let state_cpy = state.clone();
+    std::thread::spawn(move || offline_notification_thread(state_cpy));
 
     udp_server::start_server(bridge_state.clone(), state.clone());
 
+    // The metrics are served on their own listener so they never end up behind the public one.
+    if let Some(metrics_addr) = config.metrics_bind_address {
+        let state = state.clone();
+        let bridge_state = bridge_state.clone();
+        let metrics_server = HttpServer::new(move || {
+            App::new()
+                .app_data(state.clone())
+                .app_data(bridge_state.clone())
+                .service(metrics::metrics)
+        })
+        .workers(1)
+        .bind(metrics_addr)?
+        .run();
+        log::info!("serving metrics on {:?}", metrics_addr);
+        actix::spawn(async move {
+            if let Err(err) = metrics_server.await {
+                log::error!("Metrics server stopped: {err}");
+            }
+        });
+    }
+
     // Cache for random salts of non existing users
     let cache: web::Data<std::sync::Mutex<LruCache<String, Vec<u8>>>> = web::Data::new(
         std::sync::Mutex::new(LruCache::new(NonZeroUsize::new(10000).unwrap())),
@@ -239,7 +296,8 @@ async fn main() -> std::io::Result<()> {
     let device_ratelimiter = web::Data::new(ChargerRateLimiter::new());
     let general_ratelimiter = web::Data::new(IPRateLimiter::new());
 
-    let static_files_dir = std::env::var("STATIC_FILES_DIR").unwrap();
+    let static_files_dir = config.static_files_dir.clone();
+    let shutdown_bridge_state = bridge_state.clone();
 
     let server = HttpServer::new(move || {
         let cors = actix_cors::Cors::permissive();
@@ -247,8 +305,9 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .wrap(cors)
             .wrap(Compress::default())
+            .wrap(from_fn(logging::request_context))
             .wrap(Logger::new(
-                r#"%{r}a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#,
+                r#"%{r}a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T %{x-request-id}o"#,
             ))
             .app_data(cache.clone())
             .app_data(state.clone())
@@ -256,7 +315,11 @@ async fn main() -> std::io::Result<()> {
             .app_data(device_ratelimiter.clone())
             .app_data(general_ratelimiter.clone())
             .app_data(bridge_state.clone())
-            .service(web::scope("/api").configure(routes::configure))
+            .service(
+                web::scope("/api")
+                    .wrap(from_fn(metrics::record_latency))
+                    .configure(routes::configure),
+            )
             .service(
                 Files::new("/", &static_dir)
                     .index_file("index.html")
@@ -264,7 +327,7 @@ async fn main() -> std::io::Result<()> {
                         let static_dir = static_dir.clone();
                         async move {
                             let (req, _) = req.into_parts();
-                            let index_path = format!("{}/index.html", static_dir);
+                            let index_path = static_dir.join("index.html");
                             let file = NamedFile::open(&index_path)?.set_content_disposition(
                                 actix_web::http::header::ContentDisposition {
                                     disposition: actix_web::http::header::DispositionType::Inline,
@@ -278,17 +341,35 @@ async fn main() -> std::io::Result<()> {
             )
     });
 
-    #[cfg(debug_assertions)]
-    let port = "8081";
-    #[cfg(not(debug_assertions))]
-    let port = "443";
-
-    let addr = format!("0.0.0.0:{port}");
+    let addr = config.http_address();
     log::info!("running on {:?}", addr);
 
     // crash in case of TLS loading error
-    let tls_config = load_rustls_config().unwrap();
-    server.bind_rustls_0_23(&addr, tls_config)?.run().await?;
+    let tls_config = tls::load_rustls_config(
+        config.tls.cert_path.clone(),
+        config.tls.key_path.clone(),
+        Duration::from_secs(config.tls.reload_interval_secs),
+    )
+    .unwrap();
+    let server = server
+        .bind_rustls_0_23(addr, tls_config)?
+        .disable_signals()
+        .run();
+
+    // Signals are handled here so the open sessions can be closed before the server stops.
+    let server_handle = server.handle();
+    actix::spawn(async move {
+        shutdown::wait_for_signal().await;
+        shutdown::shutdown(&shutdown_bridge_state, config.shutdown_deadline()).await;
+        if let Some(path) = &config.session_state_path {
+            if let Err(err) = session_store::save(&shutdown_bridge_state, path).await {
+                log::error!("Failed to save session state to {:?}: {err}", path);
+            }
+        }
+        server_handle.stop(true).await;
+    });
+
+    server.await?;
 
     Ok(())
 }
@@ -0,0 +1,303 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock,
+    },
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    get,
+    middleware::Next,
+    web, HttpResponse, Responder,
+};
+use dashmap::DashMap;
+
+use crate::{AppState, BridgeState};
+
+/// Upper bounds of the latency histogram buckets in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+struct Histogram {
+    // Buckets are stored cumulative so they can be rendered as is.
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        for (bucket, le) in self.buckets.iter().zip(LATENCY_BUCKETS) {
+            if secs <= le {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+static ROUTE_LATENCIES: LazyLock<DashMap<(String, String), Histogram>> =
+    LazyLock::new(DashMap::new);
+static RATE_LIMIT_REJECTIONS: LazyLock<DashMap<&'static str, AtomicU64>> =
+    LazyLock::new(DashMap::new);
+
+/// Counts a request that was rejected by the rate limiter with the given name.
+pub fn record_rate_limit_rejection(limiter: &'static str) {
+    RATE_LIMIT_REJECTIONS
+        .entry(limiter)
+        .or_insert_with(|| AtomicU64::new(0))
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+fn observe_route_latency(method: String, route: String, duration: Duration) {
+    ROUTE_LATENCIES
+        .entry((method, route))
+        .or_insert_with(Histogram::new)
+        .observe(duration);
+}
+
+/// Middleware that records the latency of every request per matched route.
+/// The route pattern is used instead of the path to keep the number of series bounded.
+pub async fn record_latency(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let res = next.call(req).await?;
+    let route = res
+        .request()
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    observe_route_latency(method, route, start.elapsed());
+
+    Ok(res)
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+fn render_rate_limit_rejections(out: &mut String) {
+    let name = "remote_access_rate_limit_rejections_total";
+    let _ = writeln!(out, "# HELP {name} Requests rejected by a rate limiter.");
+    let _ = writeln!(out, "# TYPE {name} counter");
+
+    let mut limiters: Vec<(&'static str, u64)> = RATE_LIMIT_REJECTIONS
+        .iter()
+        .map(|e| (*e.key(), e.value().load(Ordering::Relaxed)))
+        .collect();
+    limiters.sort();
+    for (limiter, value) in limiters {
+        let _ = writeln!(out, "{name}{{limiter=\"{limiter}\"}} {value}");
+    }
+}
+
+fn render_route_latencies(out: &mut String) {
+    let name = "remote_access_http_request_duration_seconds";
+    let _ = writeln!(out, "# HELP {name} Latency of HTTP requests per route.");
+    let _ = writeln!(out, "# TYPE {name} histogram");
+
+    let mut routes: Vec<(String, String)> =
+        ROUTE_LATENCIES.iter().map(|e| e.key().clone()).collect();
+    routes.sort();
+    for key in routes {
+        let Some(histogram) = ROUTE_LATENCIES.get(&key) else {
+            continue;
+        };
+        let (method, route) = key;
+        let labels = format!("method=\"{method}\",route=\"{route}\"");
+        for (bucket, le) in histogram.buckets.iter().zip(LATENCY_BUCKETS) {
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{labels},le=\"{le}\"}} {}",
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let count = histogram.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {count}");
+        let _ = writeln!(
+            out,
+            "{name}_sum{{{labels}}} {}",
+            histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        );
+        let _ = writeln!(out, "{name}_count{{{labels}}} {count}");
+    }
+}
+
+/// Renders all metrics in the prometheus text format.
+pub async fn render(state: &AppState, bridge_state: &BridgeState<'_>) -> String {
+    let mut out = String::new();
+
+    let connected_devices = bridge_state.connections.devices_by_id.len();
+    write_metric(
+        &mut out,
+        "remote_access_connected_devices",
+        "gauge",
+        "Devices with an active management connection.",
+        connected_devices as u64,
+    );
+
+    let open_relays = bridge_state.connections.web_clients.len();
+    write_metric(
+        &mut out,
+        "remote_access_open_relays",
+        "gauge",
+        "Relayed connections between a browser and a device.",
+        open_relays as u64,
+    );
+
+    let pending_discoveries = bridge_state.connections.port_discovery.len();
+    write_metric(
+        &mut out,
+        "remote_access_pending_port_discoveries",
+        "gauge",
+        "Connections waiting for the device to report its port.",
+        pending_discoveries as u64,
+    );
+
+    let lost_connections: usize = bridge_state
+        .connections
+        .lost_connections
+        .iter()
+        .map(|c| c.len())
+        .sum();
+    write_metric(
+        &mut out,
+        "remote_access_lost_connections",
+        "gauge",
+        "Connections waiting for their device to reconnect.",
+        lost_connections as u64,
+    );
+
+    write_metric(
+        &mut out,
+        "remote_access_charge_log_sends_in_flight",
+        "gauge",
+        "Charge logs that are currently being sent.",
+        crate::udp_server::current_charge_log_sends() as u64,
+    );
+
+    write_metric(
+        &mut out,
+        "remote_access_hasher_queue_depth",
+        "gauge",
+        "Password hashing requests waiting for a worker.",
+        state.hasher.queue_depth() as u64,
+    );
+
+    render_rate_limit_rejections(&mut out);
+    render_route_latencies(&mut out);
+
+    out
+}
+
+/// Serves the metrics. This is not part of the public api and must only be
+/// registered on the dedicated metrics listener.
+#[get("/metrics")]
+pub async fn metrics(
+    state: web::Data<AppState>,
+    bridge_state: web::Data<BridgeState<'static>>,
+) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(render(&state, &bridge_state).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware::from_fn, test, App};
+
+    use crate::tests::{create_test_bridge_state, create_test_state};
+
+    #[test]
+    fn test_histogram_buckets() {
+        let histogram = Histogram::new();
+        histogram.observe(Duration::from_millis(20));
+        histogram.observe(Duration::from_secs(20));
+
+        assert_eq!(histogram.buckets[0].load(Ordering::Relaxed), 0);
+        assert_eq!(histogram.buckets[2].load(Ordering::Relaxed), 1);
+        assert_eq!(histogram.buckets[10].load(Ordering::Relaxed), 1);
+        assert_eq!(histogram.count.load(Ordering::Relaxed), 2);
+        assert_eq!(histogram.sum_micros.load(Ordering::Relaxed), 20_020_000);
+    }
+
+    #[actix_web::test]
+    async fn test_metrics() {
+        #[get("/metrics_test/{id}")]
+        async fn route() -> HttpResponse {
+            HttpResponse::Ok().finish()
+        }
+
+        let state = create_test_state(None);
+        let bridge_state = create_test_bridge_state(None);
+        record_rate_limit_rejection("metrics_test");
+
+        let app = App::new()
+            .app_data(state.clone())
+            .app_data(bridge_state.clone())
+            .wrap(from_fn(record_latency))
+            .service(route)
+            .service(metrics);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::get()
+            .uri("/metrics_test/123")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(body.contains("remote_access_connected_devices 0"));
+        assert!(body.contains("remote_access_open_relays 0"));
+        assert!(body.contains("remote_access_hasher_queue_depth 0"));
+        assert!(
+            body.contains("remote_access_rate_limit_rejections_total{limiter=\"metrics_test\"} 1")
+        );
+        assert!(body.contains(
+            "remote_access_http_request_duration_seconds_count{method=\"GET\",route=\"/metrics_test/{id}\"} 1"
+        ));
+    }
+}
@@ -24,10 +24,15 @@ use actix_web::{
 };
 use chrono::Utc;
 use futures_util::future::LocalBoxFuture;
-use jsonwebtoken::{decode, DecodingKey, Validation};
-use std::future::{ready, Ready};
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
 
-use crate::{models::token_claims::TokenClaims, AppState};
+use crate::{
+    models::token_claims::{TokenClaims, TokenType},
+    token_generation, AppState,
+};
 
 use super::get_token;
 
@@ -46,26 +51,29 @@ where
     type Transform = JwtService<S>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(JwtService { service }))
+        ready(Ok(JwtService {
+            service: Rc::new(service),
+        }))
     }
 }
 
 // Trait to use JwtMiddleware as an extractor
 impl FromRequest for JwtMiddleware {
     type Error = actix_web::Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
-        if let Err(err) = validate_token(req) {
-            return ready(Err(err));
-        }
+        let req = req.clone();
+        Box::pin(async move {
+            validate_token(&req).await?;
 
-        ready(Ok(JwtMiddleware {}))
+            Ok(JwtMiddleware {})
+        })
     }
 }
 
 pub struct JwtService<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for JwtService<S>
@@ -81,28 +89,24 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        if let Err(err) = validate_token(req.request()) {
-            return Box::pin(ready(Err(err)));
-        }
+        let service = self.service.clone();
+        Box::pin(async move {
+            validate_token(req.request()).await?;
 
-        let fut = self.service.call(req);
-        Box::pin(fut)
+            service.call(req).await
+        })
     }
 }
 
-fn validate_token(req: &HttpRequest) -> Result<(), Error> {
+async fn validate_token(req: &HttpRequest) -> Result<(), Error> {
     let token = match get_token(req, "access_token") {
         Some(token) => token,
         None => return Err(ErrorUnauthorized("Jwt-Token is missing")),
     };
 
     let data = req.app_data::<web::Data<AppState>>().unwrap();
-    let claims = match decode::<TokenClaims>(
-        &token,
-        &DecodingKey::from_secret(data.jwt_secret.as_bytes()),
-        &Validation::default(),
-    ) {
-        Ok(claims) => claims.claims,
+    let claims = match data.jwt_keys.decode::<TokenClaims>(&token) {
+        Ok(claims) => claims,
         Err(_err) => {
             return Err(ErrorUnauthorized("Invalid jwt token"));
         }
@@ -113,12 +117,24 @@ fn validate_token(req: &HttpRequest) -> Result<(), Error> {
         return Err(ErrorUnauthorized("Jwt token expired"));
     }
 
+    // Refresh and pre-auth tokens are signed with the same key.
+    if claims.token_type != Some(TokenType::Access) {
+        return Err(ErrorUnauthorized("Wrong token type"));
+    }
+
     let user_id = match uuid::Uuid::parse_str(claims.sub.as_str()) {
         Ok(id) => id,
         Err(_err) => return Err(ErrorInternalServerError("")),
     };
 
+    // Tokens issued before a password change, logout from all devices or deletion of the
+    // account carry an outdated generation.
+    if token_generation::current(data, user_id).await? != Some(claims.generation) {
+        return Err(ErrorUnauthorized("Jwt token was revoked"));
+    }
+
     req.extensions_mut().insert::<uuid::Uuid>(user_id);
+    crate::logging::update(|c| c.user_id = Some(user_id));
 
     Ok(())
 }
@@ -182,6 +198,24 @@ mod tests {
         assert!(resp.status().is_success());
     }
 
+    #[actix_web::test]
+    async fn refresh_token_rejected() {
+        let (mut user, _) = TestUser::random().await;
+        user.login().await;
+        let refresh_token = user.get_refresh_token().to_owned();
+
+        let app = App::new().configure(configure).service(with_extractor);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::get()
+            .uri("/hello")
+            .cookie(Cookie::new("access_token", refresh_token))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+    }
+
     #[actix_web::test]
     async fn test_no_token_extractor() {
         let (mut user, _) = TestUser::random().await;
@@ -235,6 +269,8 @@ mod tests {
             iat,
             exp,
             sub: id.to_string(),
+            generation: 0,
+            token_type: Some(TokenType::Access),
         };
 
         let jwt_secret: String = rand::rng()
@@ -277,6 +313,8 @@ mod tests {
             iat,
             exp,
             sub: username,
+            generation: 0,
+            token_type: Some(TokenType::Access),
         };
 
         let token = jsonwebtoken::encode(
@@ -1,8 +1,25 @@
 use serde::{Deserialize, Serialize};
 
+/// What a token may be used for. All tokens are signed with the same key, so services that
+/// verify tokens with the published JWKS must only accept `access` tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    Access,
+    Refresh,
+    /// Proves the password for the second login step.
+    PreAuth,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenClaims {
     pub sub: String,
     pub iat: usize,
     pub exp: usize,
+    /// Token generation of the user at the time the token was issued.
+    #[serde(rename = "gen", default)]
+    pub generation: i32,
+    /// Missing in tokens issued before the claim was introduced.
+    #[serde(rename = "typ", default, skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<TokenType>,
 }
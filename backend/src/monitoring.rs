@@ -31,33 +31,35 @@ fn get_numbers(
     Ok((num_users, num_devices))
 }
 
-fn send_mail(state: &web::Data<AppState>, num_users: i64, num_devices: i64) -> Result<(), Error> {
+fn send_mail(
+    state: &web::Data<AppState>,
+    server_name: &str,
+    recipient: &str,
+    num_users: i64,
+    num_devices: i64,
+) -> Result<(), Error> {
     let body = MonitoringMail {
         num_users,
         num_devices,
-        server_name: &std::env::var("SERVER_NAME")?,
+        server_name,
     };
     let body = body.render()?;
 
-    utils::send_email(
-        &std::env::var("MONITORING_EMAIL")?,
-        "Monitoring mail",
-        body,
-        state,
-    );
+    utils::send_email(recipient, "Monitoring mail", body, state);
 
     Ok(())
 }
 
 pub fn start_monitoring(state: web::Data<AppState>) {
-    if std::env::var("SERVER_NAME").is_err() {
+    let Some((server_name, recipient)) = state
+        .config
+        .monitoring
+        .enabled()
+        .map(|(name, email)| (name.to_string(), email.to_string()))
+    else {
         log::info!("Monitoring Mailer disabled");
         return;
-    }
-    if std::env::var("MONITORING_EMAIL").is_err() {
-        log::info!("Monitoring Mailer disabled");
-        return;
-    }
+    };
 
     std::thread::spawn(move || loop {
         match get_connection(&state) {
@@ -70,7 +72,7 @@ pub fn start_monitoring(state: web::Data<AppState>) {
                         continue;
                     }
                 };
-                match send_mail(&state, num_users, num_devices) {
+                match send_mail(&state, &server_name, &recipient, num_users, num_devices) {
                     Ok(()) => {
                         log::info!(
                             "Monitoring email sent successfully. Users: {num_users}, Chargers: {num_devices}"
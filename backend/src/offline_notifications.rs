@@ -0,0 +1,312 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Emails to users that subscribed to a charger when it stays offline and when it is
+//! back. Whether a charger is online is taken from its connectivity history, so every
+//! server of a cluster sees the same state.
+
+use actix_web::web;
+use askama::Template;
+use chrono::{NaiveDateTime, TimeDelta};
+use db_connector::models::{
+    device_connectivity_events::ConnectivityEvent, offline_notifications::OfflineNotification,
+    users::User,
+};
+use diesel::prelude::*;
+
+use crate::{branding, utils::send_email, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notice {
+    Offline,
+    Recovered,
+}
+
+/// Decides which email a subscriber gets. `offline_since` is not set while the charger
+/// is connected. The time before `started_at` does not count as offline: after a restart
+/// of this server the chargers need a moment to reconnect, and the time the server itself
+/// was down says nothing about the charger.
+pub fn notice(
+    offline_since: Option<NaiveDateTime>,
+    notified: bool,
+    now: NaiveDateTime,
+    threshold: TimeDelta,
+    started_at: NaiveDateTime,
+) -> Option<Notice> {
+    match offline_since {
+        None if notified => Some(Notice::Recovered),
+        None => None,
+        Some(_) if notified => None,
+        Some(since) if now - since.max(started_at) >= threshold => Some(Notice::Offline),
+        Some(_) => None,
+    }
+}
+
+#[derive(Template)]
+#[template(path = "offline_notification_de.html")]
+struct OfflineNotificationDETemplate<'a> {
+    name: &'a str,
+    device: &'a str,
+    offline: bool,
+    brand: branding::Brand,
+}
+
+#[derive(Template)]
+#[template(path = "offline_notification_en.html")]
+struct OfflineNotificationENTemplate<'a> {
+    name: &'a str,
+    device: &'a str,
+    offline: bool,
+    brand: branding::Brand,
+}
+
+fn render_notification(
+    name: &str,
+    device: &str,
+    lang: &str,
+    notice: Notice,
+    brand: branding::Brand,
+) -> Option<(String, String)> {
+    let offline = notice == Notice::Offline;
+    let (rendered, subject) = match lang {
+        "de" | "de-DE" => {
+            let template = OfflineNotificationDETemplate {
+                name,
+                device,
+                offline,
+                brand,
+            };
+            let subject = if offline {
+                format!("{device} ist offline")
+            } else {
+                format!("{device} ist wieder online")
+            };
+            (template.render(), subject)
+        }
+        _ => {
+            let template = OfflineNotificationENTemplate {
+                name,
+                device,
+                offline,
+                brand,
+            };
+            let subject = if offline {
+                format!("{device} is offline")
+            } else {
+                format!("{device} is back online")
+            };
+            (template.render(), subject)
+        }
+    };
+
+    match rendered {
+        Ok(body) => Some((body, subject)),
+        Err(e) => {
+            log::error!("Failed to render offline notification for user '{name}': {e}");
+            None
+        }
+    }
+}
+
+/// Marks the notice as sent. Returns false if another server was faster.
+fn claim(
+    conn: &mut PgConnection,
+    subscription: uuid::Uuid,
+    notice: Notice,
+    now: NaiveDateTime,
+) -> bool {
+    use db_connector::schema::offline_notifications::dsl::*;
+
+    let res = match notice {
+        Notice::Offline => diesel::update(offline_notifications)
+            .filter(id.eq(subscription))
+            .filter(notified_at.is_null())
+            .set(notified_at.eq(Some(now)))
+            .execute(conn),
+        Notice::Recovered => diesel::update(offline_notifications)
+            .filter(id.eq(subscription))
+            .filter(notified_at.is_not_null())
+            .set(notified_at.eq::<Option<NaiveDateTime>>(None))
+            .execute(conn),
+    };
+
+    matches!(res, Ok(1))
+}
+
+/// Sends the emails that are due. Returns how many were sent.
+pub fn send_notifications(
+    state: &web::Data<AppState>,
+    conn: &mut PgConnection,
+    now: NaiveDateTime,
+    started_at: NaiveDateTime,
+) -> usize {
+    use db_connector::schema::allowed_users::dsl as allowed_users;
+    use db_connector::schema::chargers::dsl as chargers;
+    use db_connector::schema::device_connectivity_events::dsl as events;
+    use db_connector::schema::offline_notifications::dsl as offline_notifications;
+    use db_connector::schema::users::dsl as users;
+
+    let subscriptions: Vec<(OfflineNotification, User, i32)> =
+        match offline_notifications::offline_notifications
+            .inner_join(users::users)
+            .inner_join(chargers::chargers)
+            // Users that lost access to the charger are not told about it anymore.
+            .inner_join(
+                allowed_users::allowed_users.on(allowed_users::user_id
+                    .eq(offline_notifications::user_id)
+                    .and(allowed_users::charger_id.eq(offline_notifications::charger_id))),
+            )
+            .filter(allowed_users::valid.eq(true))
+            .select((
+                OfflineNotification::as_select(),
+                User::as_select(),
+                chargers::uid,
+            ))
+            .load(conn)
+        {
+            Ok(subscriptions) => subscriptions,
+            Err(err) => {
+                log::error!("Failed to load offline notification subscriptions: {err}");
+                return 0;
+            }
+        };
+    if subscriptions.is_empty() {
+        return 0;
+    }
+
+    let charger_ids: Vec<uuid::Uuid> = subscriptions
+        .iter()
+        .map(|(subscription, _, _)| subscription.charger_id)
+        .collect();
+    let latest: Vec<ConnectivityEvent> = match events::device_connectivity_events
+        .filter(events::charger_id.eq_any(charger_ids))
+        .distinct_on(events::charger_id)
+        .order((events::charger_id, events::connected_at.desc()))
+        .select(ConnectivityEvent::as_select())
+        .load(conn)
+    {
+        Ok(latest) => latest,
+        Err(err) => {
+            log::error!("Failed to load connectivity of subscribed chargers: {err}");
+            return 0;
+        }
+    };
+
+    let threshold = TimeDelta::minutes(state.config.offline_notification_minutes as i64);
+    let mut sent = 0;
+    for (subscription, user, uid) in subscriptions {
+        // Chargers without history never connected since the subscription was made.
+        let Some(event) = latest
+            .iter()
+            .find(|event| event.charger_id == subscription.charger_id)
+        else {
+            continue;
+        };
+        let Some(notice) = notice(
+            event.disconnected_at,
+            subscription.notified_at.is_some(),
+            now,
+            threshold,
+            started_at,
+        ) else {
+            continue;
+        };
+        if !claim(conn, subscription.id, notice, now) {
+            continue;
+        }
+
+        let device = bs58::encode(uid.to_be_bytes())
+            .with_alphabet(bs58::Alphabet::FLICKR)
+            .into_string();
+        let Some((body, subject)) =
+            render_notification(&user.name, &device, &subscription.lang, notice, state.brand)
+        else {
+            continue;
+        };
+        let email = user.delivery_email.unwrap_or(user.email);
+        log::info!("Sending {notice:?} notification for charger {device} to '{email}'");
+        send_email(&email, &subject, body, state);
+        sent += 1;
+    }
+
+    sent
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+
+    fn at(minutes: i64) -> NaiveDateTime {
+        DateTime::from_timestamp(1_700_000_000, 0)
+            .unwrap()
+            .naive_utc()
+            + TimeDelta::minutes(minutes)
+    }
+
+    #[test]
+    fn test_notice() {
+        let threshold = TimeDelta::minutes(15);
+        let started = at(-60);
+
+        assert_eq!(notice(None, false, at(0), threshold, started), None);
+        assert_eq!(
+            notice(Some(at(-10)), false, at(0), threshold, started),
+            None
+        );
+        assert_eq!(
+            notice(Some(at(-15)), false, at(0), threshold, started),
+            Some(Notice::Offline)
+        );
+        assert_eq!(notice(Some(at(-30)), true, at(0), threshold, started), None);
+        assert_eq!(
+            notice(None, true, at(0), threshold, started),
+            Some(Notice::Recovered)
+        );
+    }
+
+    #[test]
+    fn test_server_downtime_does_not_count() {
+        let threshold = TimeDelta::minutes(15);
+        // The charger was disconnected when the server shut down an hour ago.
+        let disconnected = Some(at(-60));
+        let started = at(-5);
+
+        assert_eq!(notice(disconnected, false, at(0), threshold, started), None);
+        assert_eq!(
+            notice(disconnected, false, at(10), threshold, started),
+            Some(Notice::Offline)
+        );
+    }
+
+    #[test]
+    fn test_render_notification() {
+        let (body, subject) =
+            render_notification("Max", "X8a", "de", Notice::Offline, branding::Brand::Warp)
+                .unwrap();
+        assert_eq!(subject, "X8a ist offline");
+        assert!(body.contains("X8a"));
+
+        let (_, subject) =
+            render_notification("Max", "X8a", "", Notice::Recovered, branding::Brand::Warp)
+                .unwrap();
+        assert_eq!(subject, "X8a is back online");
+    }
+}
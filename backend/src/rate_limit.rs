@@ -85,6 +85,7 @@ impl LoginRateLimiter {
         let key = LoginRateLimitKey { user: email, ip };
         if let Err(err) = self.0.check_key(&key) {
             log::warn!("RateLimiter triggered for {key:?}");
+            crate::metrics::record_rate_limit_rejection("login");
             let now = self.0.clock().now();
 
             Err(RateLimitError::new(err, now).into())
@@ -130,6 +131,7 @@ impl ChargerRateLimiter {
         let key = ChargerRateLimitKey { charger_id, ip };
         if let Err(err) = self.0.check_key(&key) {
             log::warn!("RateLimiter triggered for {key:?}");
+            crate::metrics::record_rate_limit_rejection("charger");
             let now = self.0.clock().now();
 
             Err(RateLimitError::new(err, now).into())
@@ -140,7 +142,12 @@ impl ChargerRateLimiter {
 
     pub fn check_key(&self, charger_id: String, ip: String) -> bool {
         let key = ChargerRateLimitKey { charger_id, ip };
-        self.0.check_key(&key).is_ok()
+        if self.0.check_key(&key).is_err() {
+            crate::metrics::record_rate_limit_rejection("charger");
+            return false;
+        }
+
+        true
     }
 }
 
@@ -205,6 +212,7 @@ impl IPRateLimiter {
 
         if let Err(err) = self.0.check_key(&ip) {
             log::warn!("RateLimiter triggered for {ip}");
+            crate::metrics::record_rate_limit_rejection("ip");
             let now = self.0.clock().now();
 
             Err(RateLimitError::new(err, now).into())
@@ -238,6 +246,7 @@ impl GlobalSearchRateLimiter {
 
     pub fn check(&self, socket_addr: SocketAddr) -> actix_web::Result<()> {
         if let Err(err) = self.0.check_key(&socket_addr) {
+            crate::metrics::record_rate_limit_rejection("global_search");
             let now = self.0.clock().now();
 
             Err(RateLimitError::new(err, now).into())
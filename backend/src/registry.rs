@@ -0,0 +1,343 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Connections of devices and browsers.
+//!
+//! All maps are sharded, so connections of different devices rarely contend for the same lock.
+//! Two rules keep this free of deadlocks:
+//! - A guard of one map is never held while another map is accessed.
+//! - A guard is never held across an await. Values are cloned out of the map instead.
+//!
+//! A packet from a device is looked up without waiting for any other connection:
+//! 1. `port_discovery`, only for the answer to a connect command.
+//! 2. `web_clients` by the source address. The packet is queued for the browser.
+//! 3. `devices_by_addr` by the source address. Only the management socket of this
+//!    device is locked to decrypt the packet.
+
+use std::{collections::HashSet, net::SocketAddr, sync::Arc, time::Instant};
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use futures_util::lock::Mutex;
+use ipnetwork::IpNetwork;
+
+use crate::{
+    udp_server::{
+        management::RemoteConnMeta, packet::ManagementResponseV2, socket::ManagementSocket,
+    },
+    ws_udp_bridge::WebClientHandle,
+    DiscoveryCharger,
+};
+
+pub type DeviceSocket<'a> = Arc<Mutex<ManagementSocket<'a>>>;
+
+#[derive(Default)]
+pub struct ConnectionRegistry<'a> {
+    /// Management tunnels keyed by the address of the device.
+    pub devices_by_addr: DashMap<SocketAddr, DeviceSocket<'a>>,
+    /// The same tunnels keyed by the id of the device.
+    pub devices_by_id: DashMap<uuid::Uuid, DeviceSocket<'a>>,
+    /// Connect commands waiting for the device to answer from the port of the new connection.
+    pub port_discovery: DashMap<ManagementResponseV2, Instant>,
+    /// Remote access connections whose port is known.
+    pub device_connections: DashMap<RemoteConnMeta, SocketAddr>,
+    /// Browsers keyed by the address of the device connection they are relayed to.
+    pub web_clients: DashMap<SocketAddr, WebClientHandle>,
+    /// Browsers waiting for the port of their connection.
+    pub undiscovered_clients: DashMap<RemoteConnMeta, WebClientHandle>,
+    /// Browsers of devices that checked in again, waiting for the new tunnel.
+    pub lost_connections: DashMap<uuid::Uuid, Vec<(i32, WebClientHandle)>>,
+    /// Devices that checked in without having a tunnel yet, keyed by their network.
+    pub undiscovered_devices: DashMap<IpNetwork, HashSet<DiscoveryCharger>>,
+}
+
+impl<'a> ConnectionRegistry<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn device(&self, charger_id: &uuid::Uuid) -> Option<DeviceSocket<'a>> {
+        self.devices_by_id.get(charger_id).map(|s| s.clone())
+    }
+
+    pub fn device_by_addr(&self, addr: &SocketAddr) -> Option<DeviceSocket<'a>> {
+        self.devices_by_addr.get(addr).map(|s| s.clone())
+    }
+
+    /// Adds a new management tunnel.
+    /// Returns the tunnel that is already registered for the address and false if
+    /// another packet was faster.
+    pub fn insert_device(
+        &self,
+        addr: SocketAddr,
+        charger_id: uuid::Uuid,
+        socket: ManagementSocket<'a>,
+    ) -> (DeviceSocket<'a>, bool) {
+        let socket = match self.devices_by_addr.entry(addr) {
+            Entry::Occupied(existing) => return (existing.get().clone(), false),
+            Entry::Vacant(entry) => entry.insert(Arc::new(Mutex::new(socket))).clone(),
+        };
+        self.devices_by_id.insert(charger_id, socket.clone());
+
+        (socket, true)
+    }
+
+    /// Removes the tunnel of the device from both maps.
+    pub async fn remove_device(&self, charger_id: &uuid::Uuid) -> Option<DeviceSocket<'a>> {
+        let (_, socket) = self.devices_by_id.remove(charger_id)?;
+        let addr = socket.lock().await.get_remote_address();
+        // The address might already belong to a newer tunnel.
+        self.devices_by_addr
+            .remove_if(&addr, |_, s| Arc::ptr_eq(s, &socket));
+
+        Some(socket)
+    }
+
+    /// Registers a browser. It is relayed right away if the port of its connection is known.
+    pub fn add_client(&self, meta: RemoteConnMeta, client: WebClientHandle) {
+        let addr = self.device_connections.get(&meta).map(|addr| *addr);
+        match addr {
+            Some(addr) => {
+                self.web_clients.insert(addr, client);
+            }
+            None => {
+                self.undiscovered_clients.insert(meta, client);
+            }
+        }
+    }
+
+    /// Called once the device answered a connect command from the port of the connection.
+    pub fn connection_discovered(&self, meta: &RemoteConnMeta, addr: SocketAddr) {
+        if let Some((_, client)) = self.undiscovered_clients.remove(meta) {
+            self.web_clients.insert(addr, client);
+        }
+        self.device_connections.insert(meta.clone(), addr);
+    }
+
+    /// Forgets the connection of a browser that went away.
+    pub fn remove_client(&self, meta: &RemoteConnMeta) {
+        if let Some((_, addr)) = self.device_connections.remove(meta) {
+            self.web_clients.remove(&addr);
+        }
+        self.undiscovered_clients.remove(meta);
+    }
+
+    /// A device that checks in again has restarted and lost its connections.
+    /// Its browsers wait in `lost_connections` until the new tunnel is up.
+    pub fn device_checked_in(&self, charger_id: uuid::Uuid) {
+        let mut connections = Vec::new();
+        self.device_connections.retain(|meta, addr| {
+            if meta.charger_id == charger_id {
+                connections.push((*addr, meta.conn_no));
+                false
+            } else {
+                true
+            }
+        });
+
+        let lost: Vec<(i32, WebClientHandle)> = connections
+            .into_iter()
+            .filter_map(|(addr, conn_no)| {
+                self.web_clients
+                    .remove(&addr)
+                    .map(|(_, client)| (conn_no, client))
+            })
+            .collect();
+        if !lost.is_empty() {
+            self.lost_connections.insert(charger_id, lost);
+        }
+    }
+
+    /// Moves the browsers that lost their connection back to the undiscovered clients
+    /// and returns the connections that have to be opened again.
+    pub fn reconnect_lost(&self, charger_id: uuid::Uuid) -> Vec<i32> {
+        let Some((_, lost)) = self.lost_connections.remove(&charger_id) else {
+            return Vec::new();
+        };
+
+        lost.into_iter()
+            .map(|(conn_no, client)| {
+                self.undiscovered_clients.insert(
+                    RemoteConnMeta {
+                        charger_id,
+                        conn_no,
+                    },
+                    client,
+                );
+                conn_no
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    };
+
+    use actix_web::rt;
+
+    use super::*;
+    use crate::tests::create_test_session;
+
+    const DEVICES: usize = 8;
+    const ROUNDS: usize = 50;
+    const CONNECTIONS: i32 = 5;
+
+    fn device_addr(device: usize) -> SocketAddr {
+        format!("10.0.{device}.1:51820").parse().unwrap()
+    }
+
+    fn connection_addr(device: usize, conn_no: i32) -> SocketAddr {
+        format!("10.0.{device}.1:{}", 40000 + conn_no)
+            .parse()
+            .unwrap()
+    }
+
+    /// Lets a device connect, its browsers connect, the device check in again and everything
+    /// disconnect in a loop.
+    async fn device_lifecycle(registry: Arc<ConnectionRegistry<'static>>, device: usize) {
+        let charger_id = uuid::Uuid::new_v4();
+        let addr = device_addr(device);
+        for _ in 0..ROUNDS {
+            let socket = ManagementSocket::new_for_test(charger_id, addr).await;
+            let (_, inserted) = registry.insert_device(addr, charger_id, socket);
+            assert!(inserted);
+
+            let mut bodies = Vec::new();
+            for conn_no in 0..CONNECTIONS {
+                let meta = RemoteConnMeta {
+                    charger_id,
+                    conn_no,
+                };
+                let (body, session) = create_test_session().await;
+                bodies.push(body);
+                registry.add_client(meta.clone(), WebClientHandle::new(session, Arc::default()));
+                registry.connection_discovered(&meta, connection_addr(device, conn_no));
+            }
+
+            registry.device_checked_in(charger_id);
+            let lost = registry.reconnect_lost(charger_id);
+            assert_eq!(lost.len(), CONNECTIONS as usize);
+            for conn_no in lost {
+                registry.connection_discovered(
+                    &RemoteConnMeta {
+                        charger_id,
+                        conn_no,
+                    },
+                    connection_addr(device, conn_no),
+                );
+            }
+
+            for conn_no in 0..CONNECTIONS {
+                registry.remove_client(&RemoteConnMeta {
+                    charger_id,
+                    conn_no,
+                });
+            }
+            assert!(registry.remove_device(&charger_id).await.is_some());
+            rt::task::yield_now().await;
+        }
+    }
+
+    /// Devices connect, check in and disconnect on several threads while packets are looked up.
+    #[test]
+    fn test_concurrent_connections() {
+        let registry = Arc::new(ConnectionRegistry::new());
+        let done = Arc::new(AtomicBool::new(false));
+
+        let lookups = {
+            let registry = registry.clone();
+            let done = done.clone();
+            std::thread::spawn(move || {
+                let mut lookups = 0usize;
+                while !done.load(Ordering::Relaxed) {
+                    for device in 0..DEVICES {
+                        let _ = registry.web_clients.get(&connection_addr(device, 0));
+                        let _ = registry.device_by_addr(&device_addr(device));
+                        lookups += 1;
+                    }
+                }
+                lookups
+            })
+        };
+
+        let devices: Vec<_> = (0..DEVICES)
+            .map(|device| {
+                let registry = registry.clone();
+                std::thread::spawn(move || {
+                    rt::System::new().block_on(device_lifecycle(registry, device));
+                })
+            })
+            .collect();
+
+        let start = Instant::now();
+        for device in devices {
+            device.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        assert!(lookups.join().unwrap() > 0);
+        assert!(start.elapsed() < Duration::from_secs(30));
+
+        assert!(registry.devices_by_addr.is_empty());
+        assert!(registry.devices_by_id.is_empty());
+        assert!(registry.device_connections.is_empty());
+        assert!(registry.web_clients.is_empty());
+        assert!(registry.undiscovered_clients.is_empty());
+        assert!(registry.lost_connections.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_insert_device_race() {
+        let registry = ConnectionRegistry::new();
+        let charger_id = uuid::Uuid::new_v4();
+        let addr = device_addr(0);
+
+        let first = ManagementSocket::new_for_test(charger_id, addr).await;
+        let (first, inserted) = registry.insert_device(addr, charger_id, first);
+        assert!(inserted);
+        let second = ManagementSocket::new_for_test(charger_id, addr).await;
+        let (second, inserted) = registry.insert_device(addr, charger_id, second);
+        assert!(!inserted);
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(Arc::ptr_eq(&registry.device(&charger_id).unwrap(), &first));
+    }
+
+    #[actix_web::test]
+    async fn test_remove_device_keeps_newer_tunnel() {
+        let registry = ConnectionRegistry::new();
+        let old_id = uuid::Uuid::new_v4();
+        let new_id = uuid::Uuid::new_v4();
+        let addr = device_addr(0);
+
+        let socket = ManagementSocket::new_for_test(old_id, addr).await;
+        registry.insert_device(addr, old_id, socket);
+        // Another device took over the address.
+        registry.devices_by_addr.remove(&addr);
+        let socket = ManagementSocket::new_for_test(new_id, addr).await;
+        let (new_socket, _) = registry.insert_device(addr, new_id, socket);
+
+        registry.remove_device(&old_id).await.unwrap();
+        assert!(Arc::ptr_eq(
+            &registry.device_by_addr(&addr).unwrap(),
+            &new_socket
+        ));
+    }
+}
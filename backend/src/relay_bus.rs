@@ -0,0 +1,1190 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Relaying of websocket traffic between backend instances.
+//!
+//! Every device holds its management tunnel with exactly one instance, the owner,
+//! which is recorded in the `relay_owners` table. When a browser connects to an
+//! instance that does not own the device, the connection is opened on the owner
+//! through the [`RelayBus`] and all frames are forwarded over it in both directions.
+//! Management commands for such a device are sent to the owner as well, see
+//! [`RelayState::send_command`].
+//!
+//! Owners refresh their rows periodically, see [`run_heartbeat`]. Rows of instances that
+//! crashed or were killed are ignored once they are older than [`OWNER_TTL`] and removed
+//! by the next heartbeat of any instance.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{atomic::Ordering, mpsc, Arc},
+    time::Duration,
+};
+
+use actix_web::web::{self, Bytes};
+use base64::prelude::*;
+use db_connector::{models::relay_owners::RelayOwner, Pool};
+use diesel::{prelude::*, result::Error::NotFound, sql_types::Text};
+use futures_util::lock::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+
+use crate::{
+    connectivity::{record_disconnects, DisconnectReason},
+    error::Error,
+    udp_server::{
+        commands::{CommandOutcome, DeviceCommand, COMMAND_TIMEOUT},
+        management::RemoteConnMeta,
+    },
+    utils::web_block_unpacked,
+    ws_udp_bridge::{open_connection, send_disconnect, WebClientHandle},
+    BridgeState,
+};
+
+/// The listener can't block on the connection, so it polls. Right after a notification it
+/// checks again quickly to keep the latency of relayed traffic low and backs off up to
+/// `MAX_POLL_INTERVAL` while the bus is idle.
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(1);
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often an instance confirms that it still owns its devices.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// Ownership rows that were not refreshed for this long belong to an instance that is gone.
+pub const OWNER_TTL: chrono::TimeDelta = chrono::TimeDelta::seconds(90);
+
+/// Postgres rejects notification payloads of 8000 bytes or more.
+const MAX_NOTIFY_PAYLOAD: usize = 7999;
+/// Largest websocket frame or datagram that can be relayed between instances. It has to fit
+/// into a single notification after base64 encoding, together with the envelope.
+/// Bigger frames are dropped like an oversized datagram would be. WireGuard packets stay
+/// well below this with the usual MTU.
+pub const MAX_RELAY_FRAME_SIZE: usize = 5 * 1024;
+/// The owner answers a relayed command once the device did or gave up after
+/// `COMMAND_TIMEOUT`. Waiting a bit longer covers an owner that went away meanwhile.
+const RELAYED_COMMAND_GRACE: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum RelayMessage {
+    /// Ask the owner to open a connection to the device.
+    OpenConnection,
+    /// A frame from the browser for the device. The data is base64 encoded.
+    ToDevice { data: String },
+    /// A frame from the device for the browser. The data is base64 encoded.
+    ToClient { data: String },
+    /// The connection was closed by either side.
+    CloseConnection,
+    /// Ask the owner to send a command to the device. Answered with `CommandResult`.
+    Command {
+        request_id: uuid::Uuid,
+        command: DeviceCommand,
+    },
+    /// What the device answered to `Command`.
+    CommandResult {
+        request_id: uuid::Uuid,
+        outcome: CommandOutcome,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RelayEnvelope {
+    pub from: uuid::Uuid,
+    pub to: uuid::Uuid,
+    pub charger_id: uuid::Uuid,
+    pub conn_no: i32,
+    pub message: RelayMessage,
+}
+
+impl RelayEnvelope {
+    fn meta(&self) -> RemoteConnMeta {
+        RemoteConnMeta {
+            charger_id: self.charger_id,
+            conn_no: self.conn_no,
+        }
+    }
+}
+
+/// Transport used to exchange messages between instances.
+/// Incoming envelopes are handed out by the receiver returned when the bus is created.
+pub trait RelayBus: Send + Sync {
+    /// Queues the envelope for delivery to the instance `envelope.to`.
+    fn publish(&self, envelope: RelayEnvelope);
+}
+
+/// RelayBus implementation on top of postgres LISTEN/NOTIFY.
+/// Every instance listens on its own channel so it only receives what is addressed to it.
+pub struct PgRelayBus {
+    tx: mpsc::Sender<RelayEnvelope>,
+}
+
+fn channel_name(instance_id: uuid::Uuid) -> String {
+    format!("relay_{}", instance_id.simple())
+}
+
+impl PgRelayBus {
+    pub fn new(
+        database_url: &str,
+        instance_id: uuid::Uuid,
+    ) -> anyhow::Result<(Self, UnboundedReceiver<RelayEnvelope>)> {
+        let (incoming_tx, incoming_rx) = unbounded_channel();
+        let channel = channel_name(instance_id);
+
+        // Establish the first connection here so a wrong configuration fails early.
+        let conn = Self::listen(database_url, &channel)?;
+        let url = database_url.to_string();
+        std::thread::spawn(move || Self::listen_thread(conn, url, channel, incoming_tx));
+
+        let (tx, rx) = mpsc::channel();
+        let conn = PgConnection::establish(database_url)?;
+        let url = database_url.to_string();
+        std::thread::spawn(move || Self::publish_thread(conn, url, rx));
+
+        Ok((Self { tx }, incoming_rx))
+    }
+
+    fn listen(database_url: &str, channel: &str) -> anyhow::Result<PgConnection> {
+        let mut conn = PgConnection::establish(database_url)?;
+        diesel::sql_query(format!("LISTEN {channel}")).execute(&mut conn)?;
+
+        Ok(conn)
+    }
+
+    fn listen_thread(
+        mut conn: PgConnection,
+        url: String,
+        channel: String,
+        tx: UnboundedSender<RelayEnvelope>,
+    ) {
+        let mut poll_interval = MIN_POLL_INTERVAL;
+        loop {
+            let mut received = false;
+            let mut failed = false;
+            for notification in conn.notifications_iter() {
+                let notification = match notification {
+                    Ok(n) => n,
+                    Err(err) => {
+                        log::error!("Relay bus lost its connection: {err}");
+                        failed = true;
+                        break;
+                    }
+                };
+                received = true;
+                match serde_json::from_str::<RelayEnvelope>(&notification.payload) {
+                    Ok(envelope) => {
+                        if tx.send(envelope).is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => log::error!("Received invalid relay message: {err}"),
+                }
+            }
+
+            if failed {
+                conn = loop {
+                    std::thread::sleep(RECONNECT_INTERVAL);
+                    match Self::listen(&url, &channel) {
+                        Ok(conn) => break conn,
+                        Err(err) => log::error!("Failed to reconnect relay bus: {err}"),
+                    }
+                };
+            } else if received {
+                poll_interval = MIN_POLL_INTERVAL;
+            } else {
+                std::thread::sleep(poll_interval);
+                poll_interval = (poll_interval * 2).min(MAX_POLL_INTERVAL);
+            }
+        }
+    }
+
+    fn publish_thread(mut conn: PgConnection, url: String, rx: mpsc::Receiver<RelayEnvelope>) {
+        while let Ok(envelope) = rx.recv() {
+            let payload = match serde_json::to_string(&envelope) {
+                Ok(p) => p,
+                Err(err) => {
+                    log::error!("Failed to serialize relay message: {err}");
+                    continue;
+                }
+            };
+            if payload.len() > MAX_NOTIFY_PAYLOAD {
+                log::error!(
+                    "Dropping relay message of {} bytes for charger '{}', notifications are limited to {MAX_NOTIFY_PAYLOAD} bytes",
+                    payload.len(),
+                    envelope.charger_id
+                );
+                continue;
+            }
+
+            let res = diesel::sql_query("SELECT pg_notify($1, $2)")
+                .bind::<Text, _>(channel_name(envelope.to))
+                .bind::<Text, _>(payload)
+                .execute(&mut conn);
+            if let Err(err) = res {
+                log::error!("Failed to publish relay message: {err}");
+                if let Ok(c) = PgConnection::establish(&url) {
+                    conn = c;
+                }
+            }
+        }
+    }
+}
+
+impl RelayBus for PgRelayBus {
+    fn publish(&self, envelope: RelayEnvelope) {
+        let _ = self.tx.send(envelope);
+    }
+}
+
+/// Relay related part of the BridgeState.
+pub struct RelayState {
+    pub instance_id: uuid::Uuid,
+    pub bus: Arc<dyn RelayBus>,
+    /// Browsers connected to this instance whose device is owned by another instance.
+    /// Maps to the owning instance and the browser.
+    pub remote_devices: Mutex<HashMap<RemoteConnMeta, (uuid::Uuid, WebClientHandle)>>,
+    /// Connections this instance opened on behalf of another instance.
+    pub remote_clients: Mutex<HashMap<RemoteConnMeta, uuid::Uuid>>,
+    /// Same as `remote_clients` but keyed by the address of the device once the port is known.
+    pub remote_client_addrs: Mutex<HashMap<SocketAddr, (RemoteConnMeta, uuid::Uuid)>>,
+    /// Commands sent to the owner of a device, waiting for their answer.
+    pub pending_commands: Mutex<HashMap<uuid::Uuid, oneshot::Sender<CommandOutcome>>>,
+}
+
+impl RelayState {
+    pub fn new(instance_id: uuid::Uuid, bus: Arc<dyn RelayBus>) -> Self {
+        Self {
+            instance_id,
+            bus,
+            remote_devices: Mutex::new(HashMap::new()),
+            remote_clients: Mutex::new(HashMap::new()),
+            remote_client_addrs: Mutex::new(HashMap::new()),
+            pending_commands: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Forwards a frame to the device. Returns false if it is too big to be relayed.
+    pub fn send_to_device(&self, to: uuid::Uuid, meta: &RemoteConnMeta, data: &[u8]) -> bool {
+        if data.len() > MAX_RELAY_FRAME_SIZE {
+            log::warn!(
+                "Dropping frame of {} bytes for charger '{}', too big to be relayed",
+                data.len(),
+                meta.charger_id
+            );
+            return false;
+        }
+        self.send(
+            to,
+            meta,
+            RelayMessage::ToDevice {
+                data: BASE64_STANDARD.encode(data),
+            },
+        );
+
+        true
+    }
+
+    pub fn send(&self, to: uuid::Uuid, meta: &RemoteConnMeta, message: RelayMessage) {
+        self.bus.publish(RelayEnvelope {
+            from: self.instance_id,
+            to,
+            charger_id: meta.charger_id,
+            conn_no: meta.conn_no,
+            message,
+        });
+    }
+
+    /// Records this instance as owner of the device's management tunnel.
+    pub async fn claim_device(&self, pool: &Pool, device_id: uuid::Uuid) {
+        let Ok(mut conn) = pool.get() else {
+            log::error!("Failed to get database connection to claim device {device_id}");
+            return;
+        };
+        let owner = RelayOwner {
+            charger_id: device_id,
+            instance_id: self.instance_id,
+            updated_at: chrono::Utc::now().naive_utc(),
+        };
+        let res = web_block_unpacked(move || {
+            use db_connector::schema::relay_owners::dsl::*;
+
+            match diesel::insert_into(relay_owners)
+                .values(&owner)
+                .on_conflict(charger_id)
+                .do_update()
+                .set((
+                    instance_id.eq(owner.instance_id),
+                    updated_at.eq(owner.updated_at),
+                ))
+                .execute(&mut conn)
+            {
+                Ok(_) => Ok(()),
+                Err(_err) => Err(Error::InternalError),
+            }
+        })
+        .await;
+        if res.is_err() {
+            log::error!("Failed to claim device {device_id}");
+        }
+    }
+
+    /// Removes the ownership of the device unless another instance took it over in the meantime.
+    pub async fn release_device(&self, pool: &Pool, device_id: uuid::Uuid) {
+        let Ok(mut conn) = pool.get() else {
+            log::error!("Failed to get database connection to release device {device_id}");
+            return;
+        };
+        let self_id = self.instance_id;
+        let _ = web_block_unpacked(move || {
+            use db_connector::schema::relay_owners::dsl::*;
+
+            match diesel::delete(
+                relay_owners
+                    .filter(charger_id.eq(device_id))
+                    .filter(instance_id.eq(self_id)),
+            )
+            .execute(&mut conn)
+            {
+                Ok(_) => Ok(()),
+                Err(_err) => Err(Error::InternalError),
+            }
+        })
+        .await;
+    }
+
+    /// Removes all ownerships of this instance. Called when shutting down.
+    pub async fn release_all(&self, pool: &Pool) {
+        let Ok(mut conn) = pool.get() else {
+            log::error!("Failed to get database connection to release devices");
+            return;
+        };
+        let self_id = self.instance_id;
+        let res = web_block_unpacked(move || {
+            use db_connector::schema::relay_owners::dsl::*;
+
+            match diesel::delete(relay_owners.filter(instance_id.eq(self_id))).execute(&mut conn) {
+                Ok(_) => Ok(()),
+                Err(_err) => Err(Error::InternalError),
+            }
+        })
+        .await;
+        if res.is_err() {
+            log::error!("Failed to release devices of instance {self_id}");
+        }
+    }
+
+    /// Confirms the ownership of the devices connected to this instance and
+    /// removes the rows of instances that stopped refreshing theirs. The devices of
+    /// those instances are recorded as disconnected.
+    pub async fn heartbeat(&self, pool: &Pool, devices: Vec<uuid::Uuid>) {
+        let Ok(mut conn) = pool.get() else {
+            log::error!("Failed to get database connection for relay heartbeat");
+            return;
+        };
+        let self_id = self.instance_id;
+        let now = chrono::Utc::now().naive_utc();
+        let res = web_block_unpacked(move || {
+            use db_connector::schema::relay_owners::dsl::*;
+
+            let owners: Vec<RelayOwner> = devices
+                .into_iter()
+                .map(|device_id| RelayOwner {
+                    charger_id: device_id,
+                    instance_id: self_id,
+                    updated_at: now,
+                })
+                .collect();
+            let res = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                let expired = diesel::delete(relay_owners.filter(updated_at.lt(now - OWNER_TTL)))
+                    .returning(charger_id)
+                    .get_results::<uuid::Uuid>(conn)?;
+                diesel::update(relay_owners.filter(instance_id.eq(self_id)))
+                    .set(updated_at.eq(now))
+                    .execute(conn)?;
+                // Rows that were lost, e.g. while the database was unreachable for too long.
+                diesel::insert_into(relay_owners)
+                    .values(&owners)
+                    .on_conflict(charger_id)
+                    .do_nothing()
+                    .execute(conn)?;
+                Ok(expired)
+            });
+            match res {
+                Ok(expired) => Ok(expired),
+                Err(_err) => Err(Error::InternalError),
+            }
+        })
+        .await;
+        match res {
+            Ok(expired) if !expired.is_empty() => {
+                record_disconnects(pool, expired, now, DisconnectReason::ServerRestart).await
+            }
+            Ok(_) => (),
+            Err(_) => log::error!("Failed to refresh device ownerships of instance {self_id}"),
+        }
+    }
+
+    /// Returns the instance holding the management tunnel of the device.
+    pub async fn get_owner(
+        &self,
+        pool: &Pool,
+        device_id: uuid::Uuid,
+    ) -> actix_web::Result<Option<uuid::Uuid>> {
+        let Ok(mut conn) = pool.get() else {
+            return Err(Error::InternalError.into());
+        };
+        web_block_unpacked(move || {
+            use db_connector::schema::relay_owners::dsl::*;
+
+            match relay_owners
+                .filter(charger_id.eq(device_id))
+                .filter(updated_at.ge(chrono::Utc::now().naive_utc() - OWNER_TTL))
+                .select(instance_id)
+                .get_result(&mut conn)
+            {
+                Ok(owner) => Ok(Some(owner)),
+                Err(NotFound) => Ok(None),
+                Err(_err) => Err(Error::InternalError),
+            }
+        })
+        .await
+    }
+
+    /// Returns the devices of the list that are connected to another instance.
+    pub async fn remotely_connected(
+        &self,
+        pool: &Pool,
+        device_ids: Vec<uuid::Uuid>,
+    ) -> actix_web::Result<HashSet<uuid::Uuid>> {
+        let Ok(mut conn) = pool.get() else {
+            return Err(Error::InternalError.into());
+        };
+        let self_id = self.instance_id;
+        web_block_unpacked(move || {
+            use db_connector::schema::relay_owners::dsl::*;
+
+            match relay_owners
+                .filter(charger_id.eq_any(device_ids))
+                .filter(instance_id.ne(self_id))
+                .filter(updated_at.ge(chrono::Utc::now().naive_utc() - OWNER_TTL))
+                .select(charger_id)
+                .load::<uuid::Uuid>(&mut conn)
+            {
+                Ok(ids) => Ok(ids.into_iter().collect()),
+                Err(_err) => Err(Error::InternalError),
+            }
+        })
+        .await
+    }
+
+    /// Asks the owner of the device to open a connection on behalf of this instance.
+    pub async fn open_remote_connection(
+        &self,
+        pool: &Pool,
+        charger_id: uuid::Uuid,
+        conn_no: i32,
+    ) -> actix_web::Result<uuid::Uuid> {
+        let owner = match self.get_owner(pool, charger_id).await? {
+            Some(owner) if owner != self.instance_id => owner,
+            _ => return Err(Error::ChargerDisconnected.into()),
+        };
+
+        let meta = RemoteConnMeta {
+            charger_id,
+            conn_no,
+        };
+        self.send(owner, &meta, RelayMessage::OpenConnection);
+
+        Ok(owner)
+    }
+
+    /// Sends a command to a device owned by another instance and waits for the answer.
+    pub async fn send_command(
+        &self,
+        pool: &Pool,
+        charger_id: uuid::Uuid,
+        command: DeviceCommand,
+    ) -> actix_web::Result<CommandOutcome> {
+        let owner = match self.get_owner(pool, charger_id).await? {
+            Some(owner) if owner != self.instance_id => owner,
+            _ => return Err(Error::ChargerDisconnected.into()),
+        };
+
+        let request_id = uuid::Uuid::new_v4();
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut map = self.pending_commands.lock().await;
+            map.insert(request_id, tx);
+        }
+        // Commands don't belong to a connection.
+        let meta = RemoteConnMeta {
+            charger_id,
+            conn_no: 0,
+        };
+        self.send(
+            owner,
+            &meta,
+            RelayMessage::Command {
+                request_id,
+                command,
+            },
+        );
+
+        let outcome =
+            match actix_web::rt::time::timeout(COMMAND_TIMEOUT + RELAYED_COMMAND_GRACE, rx).await {
+                Ok(Ok(outcome)) => outcome,
+                Ok(Err(_)) => CommandOutcome::Cancelled,
+                Err(_elapsed) => CommandOutcome::TimedOut,
+            };
+        {
+            let mut map = self.pending_commands.lock().await;
+            map.remove(&request_id);
+        }
+
+        Ok(outcome)
+    }
+
+    /// Forwards a datagram from a device to the instance the browser is connected to.
+    /// Returns false if the address does not belong to a relayed connection.
+    pub async fn forward_to_client(&self, addr: SocketAddr, data: &[u8]) -> bool {
+        let map = self.remote_client_addrs.lock().await;
+        let Some((meta, origin)) = map.get(&addr) else {
+            return false;
+        };
+        if data.len() > MAX_RELAY_FRAME_SIZE {
+            log::warn!(
+                "Dropping datagram of {} bytes from charger '{}', too big to be relayed",
+                data.len(),
+                meta.charger_id
+            );
+            return true;
+        }
+        self.send(
+            *origin,
+            meta,
+            RelayMessage::ToClient {
+                data: BASE64_STANDARD.encode(data),
+            },
+        );
+
+        true
+    }
+
+    /// Called once the port of a connection is known.
+    pub async fn connection_discovered(&self, meta: &RemoteConnMeta, addr: SocketAddr) {
+        let origin = {
+            let map = self.remote_clients.lock().await;
+            match map.get(meta) {
+                Some(origin) => *origin,
+                None => return,
+            }
+        };
+        let mut map = self.remote_client_addrs.lock().await;
+        map.insert(addr, (meta.clone(), origin));
+    }
+}
+
+/// Forgets a connection another instance asked for and tells that instance it was closed.
+async fn reject_remote_client(
+    relay: &RelayState,
+    bridge_state: &BridgeState<'static>,
+    meta: &RemoteConnMeta,
+    origin: uuid::Uuid,
+) {
+    {
+        let mut map = relay.remote_clients.lock().await;
+        map.remove(meta);
+    }
+    if let Some((_, addr)) = bridge_state.connections.device_connections.remove(meta) {
+        let mut map = relay.remote_client_addrs.lock().await;
+        map.remove(&addr);
+    }
+    relay.send(origin, meta, RelayMessage::CloseConnection);
+}
+
+async fn handle_envelope(envelope: RelayEnvelope, bridge_state: &web::Data<BridgeState<'static>>) {
+    let Some(relay) = &bridge_state.relay else {
+        return;
+    };
+    let meta = envelope.meta();
+
+    match envelope.message {
+        RelayMessage::OpenConnection => {
+            let Some(management_sock) = bridge_state.connections.device(&meta.charger_id) else {
+                relay.send(envelope.from, &meta, RelayMessage::CloseConnection);
+                return;
+            };
+
+            // Inserted before the command is sent since the device might answer right away.
+            {
+                let mut map = relay.remote_clients.lock().await;
+                map.insert(meta.clone(), envelope.from);
+            }
+            let opened = match open_connection(
+                meta.conn_no,
+                meta.charger_id,
+                management_sock,
+                &bridge_state.connections.port_discovery,
+            )
+            .await
+            {
+                Ok(opened) => opened,
+                Err(_) => {
+                    reject_remote_client(relay, bridge_state, &meta, envelope.from).await;
+                    return;
+                }
+            };
+            let bridge_state = bridge_state.clone();
+            actix::spawn(async move {
+                let outcome = opened.await;
+                if outcome.is_failure() {
+                    log::warn!("Charger did not accept the relayed connection: {outcome:?}");
+                    if let Some(relay) = &bridge_state.relay {
+                        reject_remote_client(relay, &bridge_state, &meta, envelope.from).await;
+                    }
+                }
+            });
+        }
+        RelayMessage::ToDevice { data } => {
+            let Ok(data) = BASE64_STANDARD.decode(data) else {
+                return;
+            };
+            let addr = match bridge_state.connections.device_connections.get(&meta) {
+                Some(addr) => *addr,
+                None => return,
+            };
+            if let Err(err) = bridge_state.socket.send_to(&data, addr).await {
+                log::error!(
+                    "Failed to send relayed message to charger '{}': {}",
+                    meta.charger_id,
+                    err
+                );
+            }
+        }
+        RelayMessage::ToClient { data } => {
+            let Ok(data) = BASE64_STANDARD.decode(data) else {
+                return;
+            };
+            let map = relay.remote_devices.lock().await;
+            if let Some((_, client)) = map.get(&meta) {
+                if client.traffic.add_from_device(data.len()) {
+                    client.send(Bytes::from(data));
+                }
+            }
+        }
+        RelayMessage::CloseConnection => {
+            let client = {
+                let mut map = relay.remote_devices.lock().await;
+                map.remove(&meta)
+            };
+            if let Some((_, client)) = client {
+                client.session.close(None).await.ok();
+                return;
+            }
+
+            let origin = {
+                let mut map = relay.remote_clients.lock().await;
+                map.remove(&meta)
+            };
+            if origin.is_none() {
+                return;
+            }
+
+            if let Some((_, addr)) = bridge_state.connections.device_connections.remove(&meta) {
+                let mut map = relay.remote_client_addrs.lock().await;
+                map.remove(&addr);
+            }
+            send_disconnect(bridge_state, meta.charger_id, meta.conn_no).await;
+        }
+        RelayMessage::Command {
+            request_id,
+            command,
+        } => {
+            let Some(socket) = bridge_state.connections.device(&meta.charger_id) else {
+                relay.send(
+                    envelope.from,
+                    &meta,
+                    RelayMessage::CommandResult {
+                        request_id,
+                        outcome: CommandOutcome::Cancelled,
+                    },
+                );
+                return;
+            };
+            let answer = socket.lock().await.send_command(command.into());
+            log::info!(
+                "Sent {command:?} to charger {} for instance {}",
+                meta.charger_id,
+                envelope.from
+            );
+
+            let bridge_state = bridge_state.clone();
+            actix::spawn(async move {
+                let outcome = answer.await;
+                if let Some(relay) = &bridge_state.relay {
+                    relay.send(
+                        envelope.from,
+                        &meta,
+                        RelayMessage::CommandResult {
+                            request_id,
+                            outcome,
+                        },
+                    );
+                }
+            });
+        }
+        RelayMessage::CommandResult {
+            request_id,
+            outcome,
+        } => {
+            let pending = {
+                let mut map = relay.pending_commands.lock().await;
+                map.remove(&request_id)
+            };
+            if let Some(pending) = pending {
+                let _ = pending.send(outcome);
+            }
+        }
+    }
+}
+
+/// Handles the messages other instances sent to this one.
+pub async fn run_listener(
+    mut rx: UnboundedReceiver<RelayEnvelope>,
+    bridge_state: web::Data<BridgeState<'static>>,
+) {
+    while let Some(envelope) = rx.recv().await {
+        handle_envelope(envelope, &bridge_state).await;
+    }
+}
+
+/// Keeps the ownership rows of this instance from expiring.
+pub async fn run_heartbeat(bridge_state: web::Data<BridgeState<'static>>) {
+    let Some(relay) = &bridge_state.relay else {
+        return;
+    };
+    loop {
+        actix_web::rt::time::sleep(HEARTBEAT_INTERVAL).await;
+        if bridge_state.shutting_down.load(Ordering::SeqCst) {
+            return;
+        }
+        let devices = bridge_state
+            .connections
+            .devices_by_id
+            .iter()
+            .map(|entry| *entry.key())
+            .collect();
+        relay.heartbeat(&bridge_state.pool, devices).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{
+        routes::user::tests::TestUser,
+        tests::{create_test_bridge_state_with_relay, create_test_state},
+        udp_server::{
+            self,
+            packet::{
+                DeviceStatus, ManagementPacketHeader, ManagementResponsePacket,
+                ManagementResponseV2, NackReason, PacketType, WireFormat,
+            },
+            socket::ManagementSocket,
+        },
+    };
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let envelope = RelayEnvelope {
+            from: uuid::Uuid::new_v4(),
+            to: uuid::Uuid::new_v4(),
+            charger_id: uuid::Uuid::new_v4(),
+            conn_no: 3,
+            message: RelayMessage::ToDevice {
+                data: BASE64_STANDARD.encode(b"data"),
+            },
+        };
+        let json = serde_json::to_string(&envelope).unwrap();
+        let parsed: RelayEnvelope = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, envelope);
+
+        let envelope = RelayEnvelope {
+            message: RelayMessage::CloseConnection,
+            ..envelope
+        };
+        let json = serde_json::to_string(&envelope).unwrap();
+        let parsed: RelayEnvelope = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, envelope);
+    }
+
+    /// Collects what would have been sent to other instances.
+    #[derive(Default)]
+    struct TestBus(std::sync::Mutex<Vec<RelayEnvelope>>);
+
+    impl RelayBus for TestBus {
+        fn publish(&self, envelope: RelayEnvelope) {
+            self.0.lock().unwrap().push(envelope);
+        }
+    }
+
+    #[test]
+    fn test_max_frame_fits_notification() {
+        let envelope = RelayEnvelope {
+            from: uuid::Uuid::new_v4(),
+            to: uuid::Uuid::new_v4(),
+            charger_id: uuid::Uuid::new_v4(),
+            conn_no: i32::MIN,
+            message: RelayMessage::ToClient {
+                data: BASE64_STANDARD.encode([0xffu8; MAX_RELAY_FRAME_SIZE]),
+            },
+        };
+        let json = serde_json::to_string(&envelope).unwrap();
+        assert!(json.len() <= MAX_NOTIFY_PAYLOAD);
+    }
+
+    #[test]
+    fn test_oversized_frame_dropped() {
+        let bus = Arc::new(TestBus::default());
+        let relay = RelayState::new(uuid::Uuid::new_v4(), bus.clone());
+        let meta = RemoteConnMeta {
+            charger_id: uuid::Uuid::new_v4(),
+            conn_no: 0,
+        };
+        let to = uuid::Uuid::new_v4();
+
+        assert!(relay.send_to_device(to, &meta, &[0u8; MAX_RELAY_FRAME_SIZE]));
+        assert!(!relay.send_to_device(to, &meta, &[0u8; MAX_RELAY_FRAME_SIZE + 1]));
+        assert_eq!(bus.0.lock().unwrap().len(), 1);
+    }
+
+    fn set_owner_age(device_id: uuid::Uuid, age: chrono::TimeDelta) {
+        use db_connector::schema::relay_owners::dsl::*;
+
+        let pool = db_connector::test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        diesel::update(relay_owners.find(device_id))
+            .set(updated_at.eq(chrono::Utc::now().naive_utc() - age))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_stale_owner_expires() {
+        let pool = db_connector::test_connection_pool();
+        let (mut user, _) = TestUser::random().await;
+        let charger = user.add_random_charger().await;
+        let charger_id = uuid::Uuid::from_str(&charger.uuid).unwrap();
+
+        // An instance that crashed without releasing its devices.
+        let crashed = RelayState::new(uuid::Uuid::new_v4(), Arc::new(TestBus::default()));
+        crashed.claim_device(&pool, charger_id).await;
+
+        let other = RelayState::new(uuid::Uuid::new_v4(), Arc::new(TestBus::default()));
+        assert_eq!(
+            other.get_owner(&pool, charger_id).await.unwrap(),
+            Some(crashed.instance_id)
+        );
+
+        set_owner_age(charger_id, OWNER_TTL + chrono::TimeDelta::seconds(1));
+        assert_eq!(other.get_owner(&pool, charger_id).await.unwrap(), None);
+        assert!(other
+            .remotely_connected(&pool, vec![charger_id])
+            .await
+            .unwrap()
+            .is_empty());
+
+        // The device reconnected to the other instance, whose heartbeat replaces the stale row.
+        other.heartbeat(&pool, vec![charger_id]).await;
+        assert_eq!(
+            crashed.get_owner(&pool, charger_id).await.unwrap(),
+            Some(other.instance_id)
+        );
+
+        other.release_all(&pool).await;
+        assert_eq!(other.get_owner(&pool, charger_id).await.unwrap(), None);
+    }
+
+    #[actix_web::test]
+    async fn test_heartbeat_keeps_owner() {
+        let pool = db_connector::test_connection_pool();
+        let (mut user, _) = TestUser::random().await;
+        let charger = user.add_random_charger().await;
+        let charger_id = uuid::Uuid::from_str(&charger.uuid).unwrap();
+
+        let relay = RelayState::new(uuid::Uuid::new_v4(), Arc::new(TestBus::default()));
+        relay.claim_device(&pool, charger_id).await;
+        set_owner_age(charger_id, OWNER_TTL - chrono::TimeDelta::seconds(5));
+
+        relay.heartbeat(&pool, Vec::new()).await;
+        let owner: RelayOwner = {
+            use db_connector::schema::relay_owners::dsl::*;
+
+            let mut conn = pool.get().unwrap();
+            relay_owners.find(charger_id).get_result(&mut conn).unwrap()
+        };
+        assert_eq!(owner.instance_id, relay.instance_id);
+        assert!(chrono::Utc::now().naive_utc() - owner.updated_at < chrono::TimeDelta::seconds(5));
+    }
+
+    async fn wait_for<F, Fut>(mut f: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = bool>,
+    {
+        for _ in 0..500 {
+            if f().await {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Condition was not met in time");
+    }
+
+    /// Instance a sends a command to a device whose management connection is held by b.
+    #[actix_web::test]
+    async fn test_relay_command() {
+        let pool = db_connector::test_connection_pool();
+        let (mut user, _) = TestUser::random().await;
+        let charger = user.add_random_charger().await;
+        let charger_id = uuid::Uuid::from_str(&charger.uuid).unwrap();
+
+        let a_bus = Arc::new(TestBus::default());
+        let a_state = create_test_bridge_state_with_relay(
+            Some(pool.clone()),
+            Some(RelayState::new(uuid::Uuid::new_v4(), a_bus.clone())),
+        );
+        let b_bus = Arc::new(TestBus::default());
+        let b_state = create_test_bridge_state_with_relay(
+            Some(pool.clone()),
+            Some(RelayState::new(uuid::Uuid::new_v4(), b_bus.clone())),
+        );
+        let addr = "123.123.123.123:12345".parse().unwrap();
+        let socket = ManagementSocket::new_for_test(charger_id, addr).await;
+        let (socket, _) = b_state.connections.insert_device(addr, charger_id, socket);
+        let b = b_state.relay.as_ref().unwrap();
+        b.claim_device(&pool, charger_id).await;
+
+        let status = DeviceStatus {
+            uptime_secs: 120,
+            free_heap: 50_000,
+            wifi_rssi: None,
+            open_connections: 0,
+        };
+        let (a_bus_ref, b_bus_ref) = (&a_bus, &b_bus);
+        let deliver = async {
+            wait_for(move || async move { !a_bus_ref.0.lock().unwrap().is_empty() }).await;
+            let envelope = a_bus.0.lock().unwrap().remove(0);
+            handle_envelope(envelope, &b_state).await;
+
+            // The first packet sent by a new socket has the sequence number 1.
+            assert!(socket
+                .lock()
+                .await
+                .command_answered(1, CommandOutcome::Status(status)));
+
+            wait_for(move || async move { !b_bus_ref.0.lock().unwrap().is_empty() }).await;
+            let envelope = b_bus.0.lock().unwrap().remove(0);
+            handle_envelope(envelope, &a_state).await;
+        };
+        let a = a_state.relay.as_ref().unwrap();
+        let (outcome, ()) = futures_util::join!(
+            a.send_command(&pool, charger_id, DeviceCommand::GetStatus),
+            deliver
+        );
+        assert_eq!(outcome.unwrap(), CommandOutcome::Status(status));
+        assert!(a.pending_commands.lock().await.is_empty());
+
+        b.release_all(&pool).await;
+    }
+
+    /// The device refuses a connection that another instance opened on its behalf.
+    #[actix_web::test]
+    async fn test_rejected_remote_connection() {
+        let charger_id = uuid::Uuid::new_v4();
+        let bus = Arc::new(TestBus::default());
+        let state = create_test_bridge_state_with_relay(
+            None,
+            Some(RelayState::new(uuid::Uuid::new_v4(), bus.clone())),
+        );
+        let relay = state.relay.as_ref().unwrap();
+        let origin = uuid::Uuid::new_v4();
+        let open = RelayEnvelope {
+            from: origin,
+            to: relay.instance_id,
+            charger_id,
+            conn_no: 1,
+            message: RelayMessage::OpenConnection,
+        };
+
+        // Unknown device
+        handle_envelope(open.clone(), &state).await;
+        assert!(relay.remote_clients.lock().await.is_empty());
+        let close = bus.0.lock().unwrap().remove(0);
+        assert_eq!(close.to, origin);
+        assert_eq!(close.message, RelayMessage::CloseConnection);
+
+        let addr = "123.123.123.123:12345".parse().unwrap();
+        let socket = ManagementSocket::new_for_test(charger_id, addr).await;
+        let (socket, _) = state.connections.insert_device(addr, charger_id, socket);
+        handle_envelope(open, &state).await;
+        assert_eq!(relay.remote_clients.lock().await.len(), 1);
+
+        assert!(socket
+            .lock()
+            .await
+            .command_answered(1, CommandOutcome::Nacked(NackReason::Busy)));
+        let bus_ref = &bus;
+        wait_for(move || async move { !bus_ref.0.lock().unwrap().is_empty() }).await;
+        assert!(relay.remote_clients.lock().await.is_empty());
+        let close = bus.0.lock().unwrap().remove(0);
+        assert_eq!(close.to, origin);
+        assert_eq!(close.message, RelayMessage::CloseConnection);
+    }
+
+    /// Runs two instances against the test database. Instance a accepts the browser
+    /// while instance b holds the management connection of the device.
+    #[actix_web::test]
+    async fn test_relay_between_instances() {
+        dotenvy::dotenv().ok();
+        let url = std::env::var("DATABASE_URL").unwrap();
+
+        let (mut user, _) = TestUser::random().await;
+        let charger = user.add_random_charger().await;
+        let charger_id = uuid::Uuid::from_str(&charger.uuid).unwrap();
+        let meta = RemoteConnMeta {
+            charger_id,
+            conn_no: 1,
+        };
+
+        let a_id = uuid::Uuid::new_v4();
+        let (a_bus, mut a_rx) = PgRelayBus::new(&url, a_id).unwrap();
+        let a = RelayState::new(a_id, Arc::new(a_bus));
+
+        let b_id = uuid::Uuid::new_v4();
+        let (b_bus, b_rx) = PgRelayBus::new(&url, b_id).unwrap();
+        let b_state =
+            create_test_bridge_state_with_relay(None, Some(RelayState::new(b_id, Arc::new(b_bus))));
+        actix::spawn(run_listener(b_rx, b_state.clone()));
+        udp_server::start_server(b_state.clone(), create_test_state(None));
+        let b_port = b_state.socket.local_addr().unwrap().port();
+        let b_state_ref = &b_state;
+        let b = b_state.relay.as_ref().unwrap();
+
+        let device = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let device_addr = device.local_addr().unwrap();
+        {
+            let sock = ManagementSocket::new_for_test(charger_id, device_addr).await;
+            b_state
+                .connections
+                .devices_by_id
+                .insert(charger_id, Arc::new(Mutex::new(sock)));
+        }
+        b.claim_device(&b_state.pool, charger_id).await;
+
+        // Browser connects to a
+        let owner = a
+            .open_remote_connection(&b_state.pool, charger_id, meta.conn_no)
+            .await
+            .unwrap();
+        assert_eq!(owner, b_id);
+
+        // b opens the connection to the device
+        wait_for(move || async move { !b_state_ref.connections.port_discovery.is_empty() }).await;
+        let response: ManagementResponseV2 = *b_state
+            .connections
+            .port_discovery
+            .iter()
+            .next()
+            .unwrap()
+            .key();
+        let packet = ManagementResponsePacket {
+            header: ManagementPacketHeader::new(
+                ManagementResponseV2::SIZE as u16,
+                0,
+                1,
+                PacketType::ManagementCommand,
+            ),
+            data: response,
+        };
+        device
+            .send_to(&packet.to_bytes(), ("127.0.0.1", b_port))
+            .await
+            .unwrap();
+        wait_for(move || async move {
+            b.remote_client_addrs
+                .lock()
+                .await
+                .contains_key(&device_addr)
+        })
+        .await;
+
+        // Browser to device
+        a.send(
+            b_id,
+            &meta,
+            RelayMessage::ToDevice {
+                data: BASE64_STANDARD.encode(b"to device"),
+            },
+        );
+        let mut buf = [0u8; 2048];
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let (len, _) = device.recv_from(&mut buf).await.unwrap();
+                if &buf[..len] == b"to device" {
+                    break;
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        // Device to browser
+        device
+            .send_to(b"to client", ("127.0.0.1", b_port))
+            .await
+            .unwrap();
+        let envelope = tokio::time::timeout(Duration::from_secs(5), a_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            envelope,
+            RelayEnvelope {
+                from: b_id,
+                to: a_id,
+                charger_id,
+                conn_no: meta.conn_no,
+                message: RelayMessage::ToClient {
+                    data: BASE64_STANDARD.encode(b"to client"),
+                },
+            }
+        );
+
+        // Browser disconnects
+        a.send(b_id, &meta, RelayMessage::CloseConnection);
+        wait_for(move || async move { b.remote_clients.lock().await.is_empty() }).await;
+        assert!(!b_state.connections.device_connections.contains_key(&meta));
+        assert!(b.remote_client_addrs.lock().await.is_empty());
+    }
+}
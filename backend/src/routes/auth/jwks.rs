@@ -0,0 +1,37 @@
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::AppState;
+
+/// Get the public keys the access and refresh tokens can be verified with. The `kid`
+/// header of a token names the key that signed it.
+#[utoipa::path(
+    context_path = "/auth",
+    responses(
+        (status = 200, description = "JSON Web Key Set as defined in RFC 7517", content_type = "application/json")
+    )
+)]
+#[get("/jwks")]
+pub async fn jwks(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(state.jwt_keys.jwks())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+    use jsonwebtoken::jwk::JwkSet;
+
+    use super::*;
+    use crate::tests::configure;
+
+    #[actix_web::test]
+    async fn test_jwks() {
+        let app = App::new().configure(configure).service(jwks);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::get().uri("/jwks").to_request();
+        let resp: JwkSet = test::call_and_read_body_json(&app, req).await;
+
+        // The tests sign with the shared secret, which must never be published.
+        assert!(resp.keys.is_empty());
+    }
+}
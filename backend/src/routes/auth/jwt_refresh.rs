@@ -1,34 +1,43 @@
 use std::str::FromStr;
 
 use actix_web::{
-    cookie::Cookie, error::ErrorUnauthorized, get, web, HttpRequest, HttpResponse, Responder,
+    cookie::Cookie, error::ErrorUnauthorized, get, http::StatusCode, web, HttpRequest,
+    HttpResponse, Responder,
 };
+use askama::Template;
 use chrono::{TimeDelta, Utc};
 use db_connector::models::{refresh_tokens::RefreshToken, users::User};
 use diesel::{prelude::*, result::Error::NotFound};
-use jsonwebtoken::{decode, DecodingKey, Validation};
 
 use crate::{
+    branding,
     error::Error,
+    jwt_keys::JwtKeys,
     middleware::get_token,
-    models::token_claims::TokenClaims,
+    models::token_claims::{TokenClaims, TokenType},
     routes::auth::login::create_refresh_token,
-    utils::{get_connection, web_block_unpacked},
+    utils::{get_connection, send_email, web_block_unpacked},
     AppState,
 };
 
-pub fn extract_token(token: String, jwt_secret: &str) -> actix_web::Result<(uuid::Uuid, usize)> {
-    let claims = match decode::<TokenClaims>(
-        &token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &Validation::default(),
-    ) {
-        Ok(claims) => claims.claims,
+/// Returns the id in the subject and the expiration of a refresh or pre-auth token.
+pub fn extract_token(
+    token: String,
+    keys: &JwtKeys,
+    expected: TokenType,
+) -> actix_web::Result<(uuid::Uuid, usize)> {
+    let claims = match keys.decode::<TokenClaims>(&token) {
+        Ok(claims) => claims,
         Err(_err) => {
             return Err(ErrorUnauthorized("Invalid jwt token"));
         }
     };
 
+    // Only refresh tokens were issued without a type.
+    if claims.token_type.unwrap_or(TokenType::Refresh) != expected {
+        return Err(ErrorUnauthorized("Wrong token type"));
+    }
+
     let session_id = match uuid::Uuid::from_str(&claims.sub) {
         Ok(id) => id,
         Err(_err) => return Err(ErrorUnauthorized("Invalid session id")),
@@ -37,7 +46,87 @@ pub fn extract_token(token: String, jwt_secret: &str) -> actix_web::Result<(uuid
     Ok((session_id, claims.exp))
 }
 
-async fn validate_token(req: &HttpRequest) -> actix_web::Result<User> {
+/// A rotated token that is presented again within this many seconds is most likely a
+/// concurrent refresh of the same client and is only rejected with a conflict instead of
+/// revoking the family.
+const REUSE_GRACE_SECONDS: i64 = 10;
+
+#[derive(Template)]
+#[template(path = "refresh_token_reuse_en.html")]
+struct RefreshTokenReuseEn {
+    name: String,
+    sender_email: String,
+    brand: branding::Brand,
+}
+
+#[derive(Template)]
+#[template(path = "refresh_token_reuse_de.html")]
+struct RefreshTokenReuseDe {
+    name: String,
+    sender_email: String,
+    brand: branding::Brand,
+}
+
+fn send_reuse_notification(user: User, lang: String, state: web::Data<AppState>) {
+    std::thread::spawn(move || {
+        let (body, subject) = match lang.as_str() {
+            "de" | "de-DE" => {
+                let template = RefreshTokenReuseDe {
+                    name: user.name.clone(),
+                    sender_email: state.sender_email.clone(),
+                    brand: state.brand,
+                };
+                match template.render() {
+                    Ok(body) => (body, "Sitzung aus Sicherheitsgründen abgemeldet"),
+                    Err(e) => {
+                        log::error!("Failed to render German refresh token reuse template for user '{}': {e}", user.id);
+                        return;
+                    }
+                }
+            }
+            _ => {
+                let template = RefreshTokenReuseEn {
+                    name: user.name.clone(),
+                    sender_email: state.sender_email.clone(),
+                    brand: state.brand,
+                };
+                match template.render() {
+                    Ok(body) => (body, "Session signed out for your security"),
+                    Err(e) => {
+                        log::error!("Failed to render English refresh token reuse template for user '{}': {e}", user.id);
+                        return;
+                    }
+                }
+            }
+        };
+
+        send_email(&user.email, subject, body, &state);
+    });
+}
+
+async fn get_token_user(
+    user_id: uuid::Uuid,
+    state: &web::Data<AppState>,
+) -> actix_web::Result<User> {
+    let mut conn = get_connection(state)?;
+    let user = web_block_unpacked(move || {
+        use db_connector::schema::users::dsl::*;
+
+        match users.find(user_id).get_result(&mut conn) {
+            Ok(user) => Ok(user),
+            Err(NotFound) => Err(Error::UserDoesNotExist),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    Ok(user)
+}
+
+async fn validate_token(
+    req: &HttpRequest,
+    lang: String,
+) -> actix_web::Result<(User, RefreshToken)> {
     let token = match get_token(req, "refresh_token") {
         Some(token) => token,
         None => return Err(ErrorUnauthorized("Refresh-Token is missing")),
@@ -45,7 +134,7 @@ async fn validate_token(req: &HttpRequest) -> actix_web::Result<User> {
 
     let state = req.app_data::<web::Data<AppState>>().unwrap();
 
-    let (token_id, exp) = extract_token(token, &state.jwt_secret)?;
+    let (token_id, exp) = extract_token(token, &state.jwt_keys, TokenType::Refresh)?;
 
     // Check expiration BEFORE doing any database operations
     if exp < Utc::now().timestamp() as usize {
@@ -64,28 +153,49 @@ async fn validate_token(req: &HttpRequest) -> actix_web::Result<User> {
     })
     .await?;
 
-    // Only delete the token after we've confirmed it exists and is valid
-    delete_refresh_token(token_id, state).await?;
+    let now = Utc::now().naive_utc();
+    if let Some(rotated) = refresh_token.rotated_at {
+        if now - rotated < TimeDelta::seconds(REUSE_GRACE_SECONDS) {
+            return Err(Error::ConcurrentRefresh.into());
+        }
+
+        // The token was already exchanged, so either it or its successor is in the wrong hands.
+        log::warn!(
+            "Refresh token of user '{}' was reused, revoking its session",
+            refresh_token.user_id
+        );
+        revoke_token_family(token_id, state).await?;
+        let user = get_token_user(refresh_token.user_id, state).await?;
+        send_reuse_notification(user, lang, state.clone());
+
+        return Err(ErrorUnauthorized("Refresh token was reused"));
+    }
 
+    // Only rotate the token after we've confirmed it exists and is valid. The condition makes
+    // sure that only one of several concurrent requests gets to exchange it.
     let mut conn = get_connection(state)?;
-    let user: User = web_block_unpacked(move || {
-        use db_connector::schema::users::dsl::*;
+    web_block_unpacked(move || {
+        use db_connector::schema::refresh_tokens::dsl::*;
 
-        match users.find(refresh_token.user_id).get_result(&mut conn) {
-            Ok(user) => Ok(user),
-            Err(NotFound) => Err(Error::UserDoesNotExist),
-            Err(_err) => {
-                println!("here3");
-                Err(Error::InternalError)
-            }
+        match diesel::update(refresh_tokens.find(token_id))
+            .filter(rotated_at.is_null())
+            .set(rotated_at.eq(now))
+            .execute(&mut conn)
+        {
+            Ok(0) => Err(Error::ConcurrentRefresh),
+            Ok(_) => Ok(()),
+            Err(_err) => Err(Error::InternalError),
         }
     })
     .await?;
 
-    Ok(user)
+    let user = get_token_user(refresh_token.user_id, state).await?;
+
+    Ok((user, refresh_token))
 }
 
-pub async fn delete_refresh_token(
+/// Deletes the refresh token and every other token of its family, which ends the session.
+pub async fn revoke_token_family(
     token_id: uuid::Uuid,
     state: &web::Data<AppState>,
 ) -> actix_web::Result<()> {
@@ -94,7 +204,17 @@ pub async fn delete_refresh_token(
     web_block_unpacked(move || {
         use db_connector::schema::refresh_tokens::dsl::*;
 
-        match diesel::delete(refresh_tokens.find(token_id)).execute(&mut conn) {
+        let family: uuid::Uuid = match refresh_tokens
+            .find(token_id)
+            .select(family_id)
+            .get_result(&mut conn)
+        {
+            Ok(family) => family,
+            Err(NotFound) => return Ok(()),
+            Err(_err) => return Err(Error::InternalError),
+        };
+
+        match diesel::delete(refresh_tokens.filter(family_id.eq(family))).execute(&mut conn) {
             Ok(_) => Ok(()),
             Err(_err) => Err(Error::InternalError),
         }
@@ -108,7 +228,8 @@ pub async fn delete_refresh_token(
     context_path = "/auth",
     responses(
         (status = 200),
-        (status = 401, description = "The refresh token was invalid", body = String)
+        (status = 401, description = "The refresh token was invalid", body = String),
+        (status = 409, description = "The refresh token was just exchanged by a concurrent request. The cookies of that request stay valid.", body = String)
     ),
     security(
         ("refresh" = [])
@@ -118,9 +239,15 @@ pub async fn delete_refresh_token(
 pub async fn jwt_refresh(
     req: HttpRequest,
     state: web::Data<AppState>,
+    lang: crate::models::lang::Lang,
 ) -> actix_web::Result<impl Responder> {
-    let user = match validate_token(&req).await {
-        Ok(u) => u,
+    let (user, session) = match validate_token(&req, lang.into()).await {
+        Ok(v) => v,
+        Err(err) if err.as_response_error().status_code() == StatusCode::CONFLICT => {
+            // The winning request already set new cookies that must not be removed again.
+            log::debug!("JWT-Refresh raced with a concurrent refresh: {err}");
+            return Ok(err.error_response());
+        }
         Err(err) => {
             let access_token = Cookie::build("access_token", "")
                 .path("/")
@@ -155,16 +282,11 @@ pub async fn jwt_refresh(
         iat,
         exp,
         sub: user.id.to_string(),
+        generation: user.token_generation,
+        token_type: Some(TokenType::Access),
     };
 
-    let token = match jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &jsonwebtoken::EncodingKey::from_secret(state.jwt_secret.as_ref()),
-    ) {
-        Ok(token) => token,
-        Err(_err) => return Err(Error::InternalError.into()),
-    };
+    let token = state.jwt_keys.encode(&claims)?;
 
     let cookie = Cookie::build("access_token", token)
         .path("/")
@@ -178,7 +300,8 @@ pub async fn jwt_refresh(
 
     let cookie_string = format!("{cookie}; Partitioned;");
 
-    let refresh_cookie = create_refresh_token(&state, user.id).await?;
+    // The new token continues the session of the old one.
+    let refresh_cookie = create_refresh_token(&state, user.id, &req, Some(&session)).await?;
 
     Ok(HttpResponse::Ok()
         .append_header(("Set-Cookie", cookie_string))
@@ -198,7 +321,9 @@ mod tests {
     use rand::{distr::Alphanumeric, RngExt};
 
     use crate::{
-        models::token_claims::TokenClaims, routes::user::tests::TestUser, tests::configure,
+        models::token_claims::{TokenClaims, TokenType},
+        routes::user::tests::TestUser,
+        tests::configure,
     };
 
     use super::jwt_refresh;
@@ -234,6 +359,146 @@ mod tests {
         assert_eq!(bitmap, 3);
     }
 
+    #[actix_web::test]
+    async fn refresh_keeps_session() {
+        use db_connector::models::refresh_tokens::RefreshToken;
+        use db_connector::schema::refresh_tokens::dsl::*;
+        use diesel::prelude::*;
+
+        let app = App::new().configure(configure).service(jwt_refresh);
+        let app = test::init_service(app).await;
+
+        let (mut user, mail) = TestUser::random().await;
+        user.login().await;
+        let token = user.get_refresh_token().to_owned();
+        let uid = crate::routes::user::me::tests::get_test_user(&mail).id;
+        let load = || {
+            let pool = db_connector::test_connection_pool();
+            let mut conn = pool.get().unwrap();
+            refresh_tokens
+                .filter(user_id.eq(uid))
+                .select(RefreshToken::as_select())
+                .load::<RefreshToken>(&mut conn)
+                .unwrap()
+        };
+        let before = load();
+        assert_eq!(before.len(), 1);
+
+        let req = TestRequest::get()
+            .uri("/jwt_refresh")
+            .insert_header(("User-Agent", "Test Browser"))
+            .cookie(Cookie::new("refresh_token", token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let after = load();
+        assert_eq!(after.len(), 2);
+        let old = after.iter().find(|t| t.id == before[0].id).unwrap();
+        assert!(old.rotated_at.is_some());
+        let new = after.iter().find(|t| t.id != before[0].id).unwrap();
+        assert!(new.rotated_at.is_none());
+        assert_eq!(new.family_id, before[0].family_id);
+        assert_eq!(new.created_at, before[0].created_at);
+        assert!(new.last_used_at >= before[0].last_used_at);
+        assert_eq!(new.user_agent.as_deref(), Some("Test Browser"));
+    }
+
+    fn family_tokens(uid: uuid::Uuid) -> Vec<db_connector::models::refresh_tokens::RefreshToken> {
+        use db_connector::models::refresh_tokens::RefreshToken;
+        use db_connector::schema::refresh_tokens::dsl::*;
+        use diesel::prelude::*;
+
+        let pool = db_connector::test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        refresh_tokens
+            .filter(user_id.eq(uid))
+            .select(RefreshToken::as_select())
+            .load(&mut conn)
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn reuse_revokes_family() {
+        use db_connector::schema::refresh_tokens::dsl::*;
+        use diesel::prelude::*;
+
+        let app = App::new().configure(configure).service(jwt_refresh);
+        let app = test::init_service(app).await;
+
+        let (mut user, mail) = TestUser::random().await;
+        user.login().await;
+        let token = user.get_refresh_token().to_owned();
+        let uid = crate::routes::user::me::tests::get_test_user(&mail).id;
+
+        let req = TestRequest::get()
+            .uri("/jwt_refresh")
+            .cookie(Cookie::new("refresh_token", token.clone()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(family_tokens(uid).len(), 2);
+
+        // Move the rotation out of the grace period.
+        {
+            let pool = db_connector::test_connection_pool();
+            let mut conn = pool.get().unwrap();
+            diesel::update(refresh_tokens.filter(user_id.eq(uid)))
+                .filter(rotated_at.is_not_null())
+                .set(rotated_at.eq(Utc::now().naive_utc() - Duration::minutes(1)))
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        let req = TestRequest::get()
+            .uri("/jwt_refresh")
+            .cookie(Cookie::new("refresh_token", token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+        assert!(family_tokens(uid).is_empty());
+    }
+
+    #[actix_web::test]
+    async fn concurrent_refresh_keeps_family() {
+        let app = App::new().configure(configure).service(jwt_refresh);
+        let app = test::init_service(app).await;
+
+        let (mut user, mail) = TestUser::random().await;
+        user.login().await;
+        let token = user.get_refresh_token().to_owned();
+        let uid = crate::routes::user::me::tests::get_test_user(&mail).id;
+
+        let request = || {
+            TestRequest::get()
+                .uri("/jwt_refresh")
+                .cookie(Cookie::new("refresh_token", token.clone()))
+                .to_request()
+        };
+        let (first, second) = futures_util::future::join(
+            test::call_service(&app, request()),
+            test::call_service(&app, request()),
+        )
+        .await;
+
+        let mut statuses = [first.status().as_u16(), second.status().as_u16()];
+        statuses.sort();
+        assert_eq!(statuses, [200, 409]);
+
+        // The losing request must not remove the cookies the winner just set.
+        let loser = if first.status() == 409 { first } else { second };
+        assert_eq!(loser.response().cookies().count(), 0);
+
+        // A retry within the grace period is still only a conflict.
+        let resp = test::call_service(&app, request()).await;
+        assert_eq!(resp.status(), 409);
+        assert_eq!(resp.response().cookies().count(), 0);
+
+        let tokens = family_tokens(uid);
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens.iter().filter(|t| t.rotated_at.is_none()).count(), 1);
+    }
+
     #[actix_web::test]
     async fn no_refresh_token() {
         let app = App::new().configure(configure).service(jwt_refresh);
@@ -272,6 +537,23 @@ mod tests {
         assert_eq!(resp.status(), 401);
     }
 
+    #[actix_web::test]
+    async fn access_token_rejected() {
+        let app = App::new().configure(configure).service(jwt_refresh);
+        let app = test::init_service(app).await;
+
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+
+        let req = TestRequest::get()
+            .uri("/jwt_refresh")
+            .cookie(Cookie::new("refresh_token", access_token))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+    }
+
     #[actix_web::test]
     async fn fake_token() {
         let (mut user, _) = TestUser::random().await;
@@ -288,6 +570,8 @@ mod tests {
             iat,
             exp,
             sub: id.to_string(),
+            generation: 0,
+            token_type: Some(TokenType::Refresh),
         };
 
         let jwt_secret: String = rand::rng()
@@ -333,6 +617,8 @@ mod tests {
             iat,
             exp,
             sub: claims.claims.sub,
+            generation: 0,
+            token_type: Some(TokenType::Refresh),
         };
 
         let token = encode(
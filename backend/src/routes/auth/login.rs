@@ -17,7 +17,9 @@
  * Boston, MA 02111-1307, USA.
  */
 
-use actix_web::{cookie::Cookie, post, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{
+    cookie::Cookie, http::header::USER_AGENT, post, web, HttpRequest, HttpResponse, Responder,
+};
 use actix_web_validator::Json;
 use argon2::password_hash::PasswordHashString;
 use chrono::{Days, TimeDelta, Utc};
@@ -33,7 +35,7 @@ use validator::Validate;
 
 use crate::{
     error::Error,
-    models::token_claims::TokenClaims,
+    models::token_claims::{TokenClaims, TokenType},
     rate_limit::LoginRateLimiter,
     utils::{get_connection, web_block_unpacked},
     AppState,
@@ -49,6 +51,13 @@ pub struct LoginSchema {
     pub login_key: Vec<u8>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct LoginResponseSchema {
+    /// The login has to be completed at /auth/login_totp with the pre_auth_token cookie
+    /// that was set instead of the session cookies.
+    pub second_factor_required: bool,
+}
+
 pub enum FindBy {
     Uuid(uuid::Uuid),
     Email(String),
@@ -105,7 +114,7 @@ pub async fn validate_password(
     context_path = "/auth",
     request_body = LoginSchema,
     responses(
-        (status = 200, description = "Login was successful"),
+        (status = 200, description = "Login was successful or needs a second factor", body = LoginResponseSchema),
         (status = 401, description = "Credentials were incorrect"),
         (status = 403, description = "Not verified"),
     )
@@ -127,6 +136,34 @@ pub async fn login(
 
     let uuid =
         validate_password(&data.login_key, FindBy::Email(email), conn, &state.hasher).await?;
+    if crate::totp::is_enabled(&state, uuid).await? {
+        let pre_auth_cookie = create_pre_auth_token(&state, uuid).await?;
+        return Ok(HttpResponse::Ok()
+            .append_header(("Set-Cookie", pre_auth_cookie))
+            .json(LoginResponseSchema {
+                second_factor_required: true,
+            }));
+    }
+
+    let (cookie_string, refresh_cookie) = create_session_cookies(&state, uuid, &req).await?;
+
+    Ok(HttpResponse::Ok()
+        .append_header(("Set-Cookie", cookie_string))
+        .append_header(("Set-Cookie", refresh_cookie))
+        .json(LoginResponseSchema {
+            second_factor_required: false,
+        }))
+}
+
+/// Creates the access and refresh token cookies of a new session.
+pub async fn create_session_cookies(
+    state: &web::Data<AppState>,
+    uuid: uuid::Uuid,
+    req: &HttpRequest,
+) -> actix_web::Result<(String, String)> {
+    let Some(generation) = crate::token_generation::current(state, uuid).await? else {
+        return Err(Error::WrongCredentials.into());
+    };
 
     let now = Utc::now();
     let iat = now.timestamp() as usize;
@@ -141,16 +178,11 @@ pub async fn login(
         iat,
         exp,
         sub: uuid.to_string(),
+        generation,
+        token_type: Some(TokenType::Access),
     };
 
-    let token = match jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &jsonwebtoken::EncodingKey::from_secret(state.jwt_secret.as_ref()),
-    ) {
-        Ok(token) => token,
-        Err(_err) => return Err(Error::InternalError.into()),
-    };
+    let token = state.jwt_keys.encode(&claims)?;
 
     let cookie = Cookie::build("access_token", token)
         .path("/")
@@ -163,19 +195,68 @@ pub async fn login(
         .finish();
 
     let cookie_string = format!("{cookie}; Partitioned;");
-    let refresh_cookie = create_refresh_token(&state, uuid).await?;
+    let refresh_cookie = create_refresh_token(state, uuid, req, None).await?;
 
-    Ok(HttpResponse::Ok()
-        .append_header(("Set-Cookie", cookie_string))
-        .append_header(("Set-Cookie", refresh_cookie))
-        .body(""))
+    Ok((cookie_string, refresh_cookie))
+}
+
+/// Creates the cookie that lets the user complete the login with a second factor.
+async fn create_pre_auth_token(
+    state: &web::Data<AppState>,
+    user_id: uuid::Uuid,
+) -> actix_web::Result<String> {
+    let token_id = crate::totp::create_pre_auth_token(state, user_id).await?;
+
+    let now = Utc::now();
+    let claims = TokenClaims {
+        iat: now.timestamp() as usize,
+        exp: (now + TimeDelta::minutes(crate::totp::PRE_AUTH_TOKEN_MINUTES)).timestamp() as usize,
+        sub: token_id.to_string(),
+        // Pre-auth tokens are revoked by deleting them.
+        generation: 0,
+        token_type: Some(TokenType::PreAuth),
+    };
+    let token = state.jwt_keys.encode(&claims)?;
+
+    let cookie = Cookie::build("pre_auth_token", token)
+        .path("/")
+        .max_age(actix_web::cookie::time::Duration::minutes(
+            crate::totp::PRE_AUTH_TOKEN_MINUTES,
+        ))
+        .http_only(true)
+        .same_site(actix_web::cookie::SameSite::Strict)
+        .secure(true)
+        .finish();
+
+    Ok(format!("{cookie}; Partitioned;"))
 }
 
+/// User agents are cut off after this many characters.
+const MAX_USER_AGENT_LEN: usize = 256;
+
+/// Creates a refresh token and records from where it was requested. When `previous` is
+/// given the new token replaces it and stays in its token family, otherwise a new
+/// session is started.
 pub async fn create_refresh_token(
     state: &web::Data<AppState>,
     user_id: uuid::Uuid,
+    req: &HttpRequest,
+    previous: Option<&RefreshToken>,
 ) -> actix_web::Result<String> {
     let token_id = uuid::Uuid::new_v4();
+    let (family_id, created_at) = match previous {
+        Some(previous) => (previous.family_id, previous.created_at),
+        None => (token_id, Utc::now().naive_utc()),
+    };
+    let user_agent = req
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|agent| agent.to_str().ok())
+        .map(|agent| agent.chars().take(MAX_USER_AGENT_LEN).collect::<String>());
+    let ip = req
+        .connection_info()
+        .realip_remote_addr()
+        .map(|ip| ip.to_string());
     let mut conn = get_connection(state)?;
 
     let now = Utc::now();
@@ -190,6 +271,9 @@ pub async fn create_refresh_token(
         iat,
         exp,
         sub: token_id.to_string(),
+        // Refresh tokens are revoked by deleting them.
+        generation: 0,
+        token_type: Some(TokenType::Refresh),
     };
     web_block_unpacked(move || {
         use db_connector::schema::refresh_tokens::dsl as refresh_tokens;
@@ -198,6 +282,12 @@ pub async fn create_refresh_token(
             id: token_id,
             user_id,
             expiration: exp as i64,
+            created_at,
+            last_used_at: now.naive_utc(),
+            user_agent,
+            ip,
+            family_id,
+            rotated_at: None,
         };
         match diesel::insert_into(refresh_tokens::refresh_tokens)
             .values(&token)
@@ -209,14 +299,7 @@ pub async fn create_refresh_token(
     })
     .await?;
 
-    let token = match jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &jsonwebtoken::EncodingKey::from_secret(state.jwt_secret.as_ref()),
-    ) {
-        Ok(token) => token,
-        Err(_err) => return Err(Error::InternalError.into()),
-    };
+    let token = state.jwt_keys.encode(&claims)?;
 
     let cookie = Cookie::build("refresh_token", token)
         .path("/")
@@ -327,6 +410,66 @@ pub(crate) mod tests {
         assert_eq!(bitmap, 3);
     }
 
+    /// The refresh token and the pre-auth token are accepted where they are used next.
+    #[actix_web::test]
+    async fn test_issued_tokens_accepted() {
+        use actix_web::cookie::Cookie;
+
+        use crate::{
+            routes::{
+                auth::{jwt_refresh::jwt_refresh, login_totp::login_totp},
+                user::tests::TestUser,
+            },
+            totp::tests::{current_code, enable_test_totp},
+        };
+
+        let (user, mail) = TestUser::random().await;
+        let (_, refresh_token) = login_user(&mail, user.get_login_key().await).await;
+
+        let app = App::new()
+            .configure(configure)
+            .service(login)
+            .service(jwt_refresh)
+            .service(login_totp);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::get()
+            .uri("/jwt_refresh")
+            .cookie(Cookie::new("refresh_token", refresh_token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let (secret, _) = enable_test_totp(&mail);
+        let req = test::TestRequest::post()
+            .uri("/login")
+            .insert_header(ContentType::json())
+            .insert_header(("X-Forwarded-For", "123.123.123.2"))
+            .set_json(LoginSchema {
+                email: mail.clone(),
+                login_key: user.get_login_key().await,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let pre_auth_token = resp
+            .response()
+            .cookies()
+            .find(|c| c.name() == "pre_auth_token")
+            .map(|c| c.value().to_string())
+            .unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/login_totp")
+            .cookie(Cookie::new("pre_auth_token", pre_auth_token))
+            .set_json(crate::routes::auth::login_totp::LoginTotpSchema {
+                code: current_code(&secret),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+
     #[actix_web::test]
     async fn test_unverified() {
         let mail = "unverified_login@test.invalid";
@@ -0,0 +1,211 @@
+use actix_web::{
+    cookie::{time::Duration, Cookie},
+    error::ErrorUnauthorized,
+    post, web, HttpRequest, HttpResponse, Responder,
+};
+use actix_web_validator::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{
+    middleware::get_token,
+    models::token_claims::TokenType,
+    routes::auth::{jwt_refresh::extract_token, login::create_session_cookies},
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Validate, ToSchema)]
+pub struct LoginTotpSchema {
+    /// Code of the authenticator app or one of the backup codes.
+    #[validate(length(max = 32))]
+    pub code: String,
+}
+
+/// Complete the login of a user with two-factor authentication. Needs the
+/// pre_auth_token cookie set by /auth/login.
+#[utoipa::path(
+    context_path = "/auth",
+    request_body = LoginTotpSchema,
+    responses(
+        (status = 200, description = "Login was successful"),
+        (status = 401, description = "The code was wrong or the login has to be started again"),
+        (status = 429, description = "Too many wrong two-factor authentication codes"),
+    )
+)]
+#[post("/login_totp")]
+pub async fn login_totp(
+    state: web::Data<AppState>,
+    data: Json<LoginTotpSchema>,
+    req: HttpRequest,
+) -> actix_web::Result<impl Responder> {
+    let Some(token) = get_token(&req, "pre_auth_token") else {
+        return Err(ErrorUnauthorized("Pre-auth token is missing"));
+    };
+    let (token_id, _) = extract_token(token, &state.jwt_keys, TokenType::PreAuth)?;
+
+    let uid = crate::totp::redeem_pre_auth_token(&state, token_id, data.code.clone()).await?;
+
+    let (cookie_string, refresh_cookie) = create_session_cookies(&state, uid, &req).await?;
+    let pre_auth_token = Cookie::build("pre_auth_token", "")
+        .path("/")
+        .max_age(Duration::new(-1, 0))
+        .http_only(true)
+        .finish();
+
+    Ok(HttpResponse::Ok()
+        .append_header(("Set-Cookie", cookie_string))
+        .append_header(("Set-Cookie", refresh_cookie))
+        .cookie(pre_auth_token)
+        .body(""))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{cookie::Cookie, http::header::ContentType, test, App};
+
+    use super::*;
+    use crate::{
+        routes::{
+            auth::login::{login, LoginResponseSchema, LoginSchema},
+            user::tests::TestUser,
+        },
+        tests::configure,
+        totp::tests::{current_code, enable_test_totp},
+    };
+
+    async fn start_login(user: &TestUser) -> (LoginResponseSchema, Option<String>) {
+        let app = App::new().configure(configure).service(login);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::post()
+            .uri("/login")
+            .insert_header(ContentType::json())
+            .insert_header(("X-Forwarded-For", "123.123.123.3"))
+            .set_json(LoginSchema {
+                email: user.get_mail().to_string(),
+                login_key: user.get_login_key().await,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let has_session = resp
+            .response()
+            .cookies()
+            .any(|c| c.name() == "access_token");
+        let pre_auth_token = resp
+            .response()
+            .cookies()
+            .find(|c| c.name() == "pre_auth_token")
+            .map(|c| c.value().to_string());
+        let body: LoginResponseSchema = test::read_body_json(resp).await;
+        assert_eq!(has_session, !body.second_factor_required);
+
+        (body, pre_auth_token)
+    }
+
+    async fn finish_login(pre_auth_token: &str, code: &str) -> actix_web::dev::ServiceResponse {
+        let app = App::new().configure(configure).service(login_totp);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::post()
+            .uri("/login_totp")
+            .cookie(Cookie::new("pre_auth_token", pre_auth_token))
+            .set_json(LoginTotpSchema {
+                code: code.to_string(),
+            })
+            .to_request();
+        test::call_service(&app, req).await
+    }
+
+    #[actix_web::test]
+    async fn test_login_totp() {
+        let (user, mail) = TestUser::random().await;
+        let (secret, _) = enable_test_totp(&mail);
+
+        let (body, pre_auth_token) = start_login(&user).await;
+        assert!(body.second_factor_required);
+        let pre_auth_token = pre_auth_token.unwrap();
+
+        let resp = finish_login(&pre_auth_token, "000000").await;
+        assert_eq!(resp.status(), 401);
+
+        let resp = finish_login(&pre_auth_token, &current_code(&secret)).await;
+        assert!(resp.status().is_success());
+        let cookies: Vec<String> = resp
+            .response()
+            .cookies()
+            .map(|c| c.name().to_string())
+            .collect();
+        assert!(cookies.contains(&"access_token".to_string()));
+        assert!(cookies.contains(&"refresh_token".to_string()));
+
+        // The pre-auth token is used up.
+        let resp = finish_login(&pre_auth_token, &current_code(&secret)).await;
+        assert_eq!(resp.status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn test_login_backup_code() {
+        let (user, mail) = TestUser::random().await;
+        let (_, backup_code) = enable_test_totp(&mail);
+
+        let (_, pre_auth_token) = start_login(&user).await;
+        let resp = finish_login(&pre_auth_token.unwrap(), &backup_code).await;
+        assert!(resp.status().is_success());
+
+        // Backup codes can only be used once.
+        let (_, pre_auth_token) = start_login(&user).await;
+        let resp = finish_login(&pre_auth_token.unwrap(), &backup_code).await;
+        assert_eq!(resp.status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn test_login_attempts_limited() {
+        let (user, mail) = TestUser::random().await;
+        let (secret, _) = enable_test_totp(&mail);
+
+        let (_, pre_auth_token) = start_login(&user).await;
+        let pre_auth_token = pre_auth_token.unwrap();
+        for _ in 0..5 {
+            let resp = finish_login(&pre_auth_token, "abcde-fghij").await;
+            assert_eq!(resp.status(), 401);
+        }
+
+        let resp = finish_login(&pre_auth_token, &current_code(&secret)).await;
+        assert_eq!(resp.status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn test_login_with_other_token() {
+        let (mut user, mail) = TestUser::random().await;
+        user.login().await;
+        let (secret, _) = enable_test_totp(&mail);
+
+        // Only pre-auth tokens may finish a login.
+        for token in [
+            user.get_access_token().to_owned(),
+            user.get_refresh_token().to_owned(),
+        ] {
+            let resp = finish_login(&token, &current_code(&secret)).await;
+            assert_eq!(resp.status(), 401);
+            let body = test::read_body(resp).await;
+            assert_eq!(std::str::from_utf8(&body).unwrap(), "Wrong token type");
+        }
+
+        // The same code is accepted together with a real pre-auth token.
+        let (_, pre_auth_token) = start_login(&user).await;
+        let resp = finish_login(&pre_auth_token.unwrap(), &current_code(&secret)).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_login_without_totp() {
+        let (user, _) = TestUser::random().await;
+
+        let (body, pre_auth_token) = start_login(&user).await;
+        assert!(!body.second_factor_required);
+        assert!(pre_auth_token.is_none());
+    }
+}
@@ -21,8 +21,10 @@ use actix_web::web::{self, ServiceConfig};
 
 pub mod generate_salt;
 pub mod get_login_salt;
+pub mod jwks;
 pub mod jwt_refresh;
 pub mod login;
+pub mod login_totp;
 pub mod recovery;
 pub mod register;
 pub mod resend_verification;
@@ -39,8 +41,10 @@ pub fn configure(cfg: &mut ServiceConfig) {
         .service(get_login_salt::get_login_salt)
         .service(generate_salt::generate_salt)
         .service(jwt_refresh::jwt_refresh)
+        .service(jwks::jwks)
         .service(start_recovery::start_recovery)
         .service(recovery::recovery)
-        .service(login::login);
+        .service(login::login)
+        .service(login_totp::login_totp);
     cfg.service(scope);
 }
@@ -10,6 +10,7 @@ use uuid::Uuid;
 use crate::{
     error::Error,
     routes::auth::register::hash_key,
+    token_generation::revoke_access_tokens,
     utils::{get_connection, web_block_unpacked},
     AppState,
 };
@@ -148,6 +149,8 @@ pub async fn recovery(
     })
     .await?;
 
+    revoke_access_tokens(&state, user_id).await?;
+
     Ok(HttpResponse::Ok())
 }
 
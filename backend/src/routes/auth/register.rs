@@ -194,6 +194,7 @@ pub async fn register(
         delivery_email: Some(data.email.clone()),
         old_delivery_email: None,
         old_email: None,
+        token_generation: 0,
     };
 
     let mut conn = get_connection(&state)?;
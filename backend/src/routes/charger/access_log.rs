@@ -0,0 +1,375 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+use actix_web::{get, web, HttpResponse, Responder};
+use db_connector::models::access_sessions::AccessSession;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::Error,
+    routes::charger::user_is_allowed,
+    utils::{get_connection, parse_uuid, web_block_unpacked},
+    AppState,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Serialize, Deserialize, IntoParams)]
+pub struct AccessLogQuery {
+    charger: String,
+    /// Starts at 0.
+    page: Option<i64>,
+    page_size: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, PartialEq)]
+pub struct AccessLogEntry {
+    pub id: String,
+    /// The user fields are not set if the user deleted their account since.
+    pub user_id: Option<String>,
+    pub user_name: Option<String>,
+    pub user_email: Option<String>,
+    pub key_id: String,
+    pub connection_no: i32,
+    pub started_at: i64,
+    /// Not set while the session is still open.
+    pub ended_at: Option<i64>,
+    pub bytes_to_device: i64,
+    pub bytes_from_device: i64,
+    pub close_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AccessLogResponseSchema {
+    pub entries: Vec<AccessLogEntry>,
+    /// Number of entries on all pages.
+    pub total: i64,
+}
+
+/// Get the remote access sessions of a charger, newest first.
+#[utoipa::path(
+    context_path = "/charger",
+    responses(
+        (status = 200, body = AccessLogResponseSchema),
+        (status = 400, description = "Invalid charger id"),
+        (status = 401, description = "The user has no access to this charger"),
+    ),
+    security(
+        ("jwt" = [])
+    ),
+    params(
+        AccessLogQuery
+    )
+)]
+#[get("/access_log")]
+pub async fn access_log(
+    state: web::Data<AppState>,
+    uid: crate::models::uuid::Uuid,
+    query: web::Query<AccessLogQuery>,
+) -> actix_web::Result<impl Responder> {
+    use db_connector::schema::access_sessions::dsl as access_sessions;
+    use db_connector::schema::users::dsl as users;
+
+    let cid = parse_uuid(&query.charger)?;
+    user_is_allowed(&state, uid.into(), cid).await?;
+
+    let page = query.page.unwrap_or(0).max(0);
+    let page_size = query
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let mut conn = get_connection(&state)?;
+    let (sessions, total): (Vec<(AccessSession, Option<String>, Option<String>)>, i64) =
+        web_block_unpacked(move || {
+            let total = match access_sessions::access_sessions
+                .filter(access_sessions::charger_id.eq(cid))
+                .count()
+                .get_result(&mut conn)
+            {
+                Ok(total) => total,
+                Err(_err) => return Err(Error::InternalError),
+            };
+
+            match access_sessions::access_sessions
+                .left_join(users::users)
+                .filter(access_sessions::charger_id.eq(cid))
+                .order(access_sessions::started_at.desc())
+                .limit(page_size)
+                .offset(page * page_size)
+                .select((
+                    AccessSession::as_select(),
+                    users::name.nullable(),
+                    users::email.nullable(),
+                ))
+                .load(&mut conn)
+            {
+                Ok(sessions) => Ok((sessions, total)),
+                Err(_err) => Err(Error::InternalError),
+            }
+        })
+        .await?;
+
+    let entries = sessions
+        .into_iter()
+        .map(|(session, user_name, user_email)| AccessLogEntry {
+            id: session.id.to_string(),
+            user_id: session.user_id.map(|id| id.to_string()),
+            user_name,
+            user_email,
+            key_id: session.key_id.to_string(),
+            connection_no: session.connection_no,
+            started_at: session.started_at.and_utc().timestamp(),
+            ended_at: session.ended_at.map(|t| t.and_utc().timestamp()),
+            bytes_to_device: session.bytes_to_device,
+            bytes_from_device: session.bytes_from_device,
+            close_reason: session.close_reason,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(AccessLogResponseSchema { entries, total }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use actix_web::{cookie::Cookie, test, App};
+    use chrono::{TimeDelta, Utc};
+    use db_connector::test_connection_pool;
+
+    use super::*;
+    use crate::{
+        clean_access_sessions,
+        middleware::jwt::JwtMiddleware,
+        routes::user::{me::tests::get_test_user, tests::TestUser},
+        tests::configure,
+    };
+
+    fn insert_session(
+        user_id: uuid::Uuid,
+        charger_id: uuid::Uuid,
+        started_at: chrono::NaiveDateTime,
+    ) -> uuid::Uuid {
+        use db_connector::schema::access_sessions::dsl::*;
+
+        let session = AccessSession {
+            id: uuid::Uuid::new_v4(),
+            user_id: Some(user_id),
+            charger_id,
+            key_id: uuid::Uuid::new_v4(),
+            connection_no: 1,
+            started_at,
+            ended_at: Some(started_at + TimeDelta::minutes(5)),
+            bytes_to_device: 1000,
+            bytes_from_device: 20000,
+            close_reason: Some("client_closed".to_string()),
+        };
+        let session_id = session.id;
+
+        let pool = test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        diesel::insert_into(access_sessions)
+            .values(session)
+            .execute(&mut conn)
+            .unwrap();
+
+        session_id
+    }
+
+    #[actix_web::test]
+    async fn test_access_log() {
+        let (mut user, mail) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+        let charger = user.add_random_charger().await;
+        let other_charger = user.add_random_charger().await;
+        let charger_id = uuid::Uuid::from_str(&charger.uuid).unwrap();
+        let user_id = get_test_user(&mail).id;
+
+        let now = Utc::now().naive_utc();
+        let older = insert_session(user_id, charger_id, now - TimeDelta::hours(2));
+        let newer = insert_session(user_id, charger_id, now - TimeDelta::hours(1));
+        insert_session(
+            user_id,
+            uuid::Uuid::from_str(&other_charger.uuid).unwrap(),
+            now,
+        );
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(access_log);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/access_log?charger={}", charger.uuid))
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body: AccessLogResponseSchema = test::read_body_json(resp).await;
+        assert_eq!(body.total, 2);
+        assert_eq!(body.entries.len(), 2);
+        assert_eq!(body.entries[0].id, newer.to_string());
+        assert_eq!(body.entries[1].id, older.to_string());
+        assert_eq!(body.entries[0].user_email, Some(mail));
+        assert_eq!(body.entries[0].bytes_from_device, 20000);
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/access_log?charger={}&page=1&page_size=1",
+                charger.uuid
+            ))
+            .cookie(Cookie::new("access_token", access_token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body: AccessLogResponseSchema = test::read_body_json(resp).await;
+        assert_eq!(body.total, 2);
+        assert_eq!(body.entries.len(), 1);
+        assert_eq!(body.entries[0].id, older.to_string());
+    }
+
+    #[actix_web::test]
+    async fn test_access_log_of_deleted_user() {
+        let (mut owner, _) = TestUser::random().await;
+        let access_token = owner.login().await.to_owned();
+        let charger = owner.add_random_charger().await;
+        let charger_id = uuid::Uuid::from_str(&charger.uuid).unwrap();
+
+        let (_, other_mail) = TestUser::random().await;
+        let other_id = get_test_user(&other_mail).id;
+        let session = insert_session(other_id, charger_id, Utc::now().naive_utc());
+        crate::routes::auth::register::tests::delete_user(&other_mail);
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(access_log);
+        let app = test::init_service(app).await;
+
+        // The entry stays in the log of the charger, but no longer points to the user.
+        let req = test::TestRequest::get()
+            .uri(&format!("/access_log?charger={}", charger.uuid))
+            .cookie(Cookie::new("access_token", access_token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body: AccessLogResponseSchema = test::read_body_json(resp).await;
+        assert_eq!(body.entries.len(), 1);
+        assert_eq!(body.entries[0].id, session.to_string());
+        assert_eq!(body.entries[0].user_id, None);
+        assert_eq!(body.entries[0].user_email, None);
+    }
+
+    #[actix_web::test]
+    async fn test_close_dangling_access_sessions() {
+        use db_connector::schema::access_sessions::dsl::*;
+
+        let (mut user, mail) = TestUser::random().await;
+        user.login().await;
+        let charger = user.add_random_charger().await;
+        let cid = uuid::Uuid::from_str(&charger.uuid).unwrap();
+        let uid = get_test_user(&mail).id;
+
+        // Far in the past so sessions of other tests are not touched.
+        let boot_time = chrono::DateTime::from_timestamp(1_000_000_000, 0)
+            .unwrap()
+            .naive_utc();
+        let dangling = insert_session(uid, cid, boot_time - TimeDelta::hours(1));
+        let pool = test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        diesel::update(access_sessions.find(dangling))
+            .set((
+                ended_at.eq(None::<chrono::NaiveDateTime>),
+                close_reason.eq(None::<String>),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+        let finished = insert_session(uid, cid, boot_time - TimeDelta::hours(2));
+
+        crate::close_dangling_access_sessions(&mut conn, boot_time);
+
+        let session: AccessSession = access_sessions
+            .find(dangling)
+            .select(AccessSession::as_select())
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(session.ended_at, Some(boot_time));
+        assert_eq!(session.close_reason.as_deref(), Some("server_shutdown"));
+        let session: AccessSession = access_sessions
+            .find(finished)
+            .select(AccessSession::as_select())
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(session.close_reason.as_deref(), Some("client_closed"));
+    }
+
+    #[actix_web::test]
+    async fn test_access_log_not_allowed() {
+        let (mut owner, _) = TestUser::random().await;
+        owner.login().await;
+        let charger = owner.add_random_charger().await;
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(access_log);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/access_log?charger={}", charger.uuid))
+            .cookie(Cookie::new("access_token", access_token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn test_clean_access_sessions() {
+        use db_connector::schema::access_sessions::dsl::*;
+
+        let (mut user, mail) = TestUser::random().await;
+        user.login().await;
+        let charger = user.add_random_charger().await;
+        let cid = uuid::Uuid::from_str(&charger.uuid).unwrap();
+        let uid = get_test_user(&mail).id;
+
+        let now = Utc::now().naive_utc();
+        let old = insert_session(uid, cid, now - TimeDelta::days(31));
+        let recent = insert_session(uid, cid, now - TimeDelta::days(29));
+
+        let pool = test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        clean_access_sessions(&mut conn, 30);
+
+        let remaining: Vec<uuid::Uuid> = access_sessions
+            .filter(charger_id.eq(cid))
+            .select(id)
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(remaining, vec![recent]);
+        assert!(!remaining.contains(&old));
+    }
+}
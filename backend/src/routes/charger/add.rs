@@ -87,6 +87,18 @@ fn validate_add_charger_schema(schema: &AddChargerSchema) -> Result<(), Validati
 
     validate_wg_key(&schema.charger.charger_pub)?;
     validate_charger_id(&schema.charger.uid)?;
+    validate_wg_ips(&schema.charger)?;
+
+    Ok(())
+}
+
+/// The tunnel can either use IPv4 or IPv6 but not both.
+pub fn validate_wg_ips(charger: &ChargerSchema) -> Result<(), ValidationError> {
+    if charger.wg_charger_ip.is_ipv4() != charger.wg_server_ip.is_ipv4() {
+        return Err(ValidationError::new(
+            "Tunnel addresses must be of the same address family",
+        ));
+    }
 
     Ok(())
 }
@@ -808,4 +820,19 @@ pub(crate) mod tests {
 
         assert!(validate_add_charger_schema(&schema).is_ok());
     }
+
+    #[actix_web::test]
+    async fn test_validate_wg_ips() {
+        let mut charger = ChargerSchema {
+            uid: String::new(),
+            charger_pub: String::new(),
+            wg_charger_ip: "fd00::2/64".parse().unwrap(),
+            wg_server_ip: "fd00::1/64".parse().unwrap(),
+            psk: String::new(),
+        };
+        assert!(validate_wg_ips(&charger).is_ok());
+
+        charger.wg_server_ip = "10.0.0.1/24".parse().unwrap();
+        assert!(validate_wg_ips(&charger).is_err());
+    }
 }
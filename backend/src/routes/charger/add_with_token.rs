@@ -5,7 +5,7 @@ use utoipa::ToSchema;
 use validator::{Validate, ValidationError};
 
 use crate::{
-    routes::charger::add::{register_charger, AddChargerSchema, ChargerSchema},
+    routes::charger::add::{register_charger, validate_wg_ips, AddChargerSchema, ChargerSchema},
     utils::{parse_uuid, validate_auth_token},
     AppState,
 };
@@ -32,6 +32,7 @@ fn validate_add_charger_with_token_schema(
 
     validate_wg_key(&schema.charger.charger_pub)?;
     validate_charger_id(&schema.charger.uid)?;
+    validate_wg_ips(&schema.charger)?;
 
     Ok(())
 }
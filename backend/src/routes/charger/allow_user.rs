@@ -34,6 +34,7 @@ use crate::{
     utils::{
         get_charger_from_db, get_connection, parse_uuid, validate_auth_token, web_block_unpacked,
     },
+    webhooks::{self, WebhookEvent},
     AppState,
 };
 
@@ -212,6 +213,14 @@ pub async fn allow_user(
         Err(_err) => {}
     }
 
+    webhooks::emit_for_charger(
+        &state.pool,
+        cid,
+        WebhookEvent::AccessGranted,
+        serde_json::json!({ "charger_id": cid, "user_id": allowed_uuid }),
+    )
+    .await;
+
     Ok(HttpResponse::Ok().json(AllowUserResponse {
         user_id: allowed_uuid.to_string(),
     }))
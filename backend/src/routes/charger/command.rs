@@ -0,0 +1,274 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+use actix_web::{post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::Error,
+    routes::charger::user_is_allowed,
+    udp_server::{
+        commands::{CommandOutcome, DeviceCommand},
+        packet::{DeviceStatus, NackReason},
+    },
+    utils::parse_uuid,
+    AppState, BridgeState,
+};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SendCommandSchema {
+    pub command: DeviceCommand,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, PartialEq)]
+pub struct CommandResultSchema {
+    pub acked: bool,
+    /// Only set if the charger refused the command.
+    pub nack_reason: Option<NackReason>,
+    /// Only set for `get_status`.
+    pub status: Option<DeviceStatus>,
+}
+
+/// Send a command to a charger and wait for its answer.
+#[utoipa::path(
+    context_path = "/charger",
+    request_body = SendCommandSchema,
+    responses(
+        (status = 200, description = "The charger answered the command", body = CommandResultSchema),
+        (status = 400, description = "Invalid charger id"),
+        (status = 401, description = "The user has no access to this charger"),
+        (status = 502, description = "The charger is not connected to any server"),
+        (status = 504, description = "The charger did not answer in time"),
+    ),
+    params(
+        ("charger_id" = String, Path, description = "Id of the charger")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+#[post("/{charger_id}/command")]
+pub async fn send_command(
+    state: web::Data<AppState>,
+    bridge_state: web::Data<BridgeState<'_>>,
+    uid: crate::models::uuid::Uuid,
+    charger_id: web::Path<String>,
+    payload: web::Json<SendCommandSchema>,
+) -> actix_web::Result<impl Responder> {
+    let cid = parse_uuid(&charger_id)?;
+    user_is_allowed(&state, uid.into(), cid).await?;
+
+    let outcome = match bridge_state.connections.device(&cid) {
+        Some(socket) => {
+            let answer = socket.lock().await.send_command(payload.command.into());
+            log::info!("Sent {:?} to charger {cid}", payload.command);
+            answer.await
+        }
+        // The charger might be connected to another instance.
+        None => match &bridge_state.relay {
+            Some(relay) => {
+                relay
+                    .send_command(&bridge_state.pool, cid, payload.command)
+                    .await?
+            }
+            None => return Err(Error::ChargerDisconnected.into()),
+        },
+    };
+
+    let result = match outcome {
+        CommandOutcome::Acked => CommandResultSchema {
+            acked: true,
+            nack_reason: None,
+            status: None,
+        },
+        CommandOutcome::Status(status) => CommandResultSchema {
+            acked: true,
+            nack_reason: None,
+            status: Some(status),
+        },
+        CommandOutcome::Nacked(reason) => CommandResultSchema {
+            acked: false,
+            nack_reason: Some(reason),
+            status: None,
+        },
+        CommandOutcome::TimedOut => return Err(Error::ChargerDidNotAnswer.into()),
+        CommandOutcome::Cancelled => return Err(Error::ChargerDisconnected.into()),
+    };
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, time::Duration};
+
+    use actix_web::{cookie::Cookie, test, App};
+
+    use super::*;
+    use crate::{
+        middleware::jwt::JwtMiddleware,
+        routes::user::tests::TestUser,
+        tests::{configure, create_test_bridge_state, create_test_state},
+        udp_server::socket::ManagementSocket,
+    };
+
+    /// Sends `command` to a connected charger that answers it with `outcome`.
+    async fn answer_command(
+        command: DeviceCommand,
+        outcome: CommandOutcome,
+    ) -> actix_web::dev::ServiceResponse {
+        let (mut user, _) = TestUser::random().await;
+        let state = create_test_state(None);
+        let bridge_state = create_test_bridge_state(None);
+        let access_token = user.login().await.to_owned();
+        let charger = user.add_random_charger().await;
+        let charger_id = uuid::Uuid::from_str(&charger.uuid).unwrap();
+        let addr = "123.123.123.123:12345".parse().unwrap();
+        let socket = ManagementSocket::new_for_test(charger_id, addr).await;
+        let (socket, _) = bridge_state
+            .connections
+            .insert_device(addr, charger_id, socket);
+
+        let app = App::new()
+            .app_data(state)
+            .app_data(bridge_state)
+            .wrap(JwtMiddleware)
+            .service(send_command);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/{}/command", charger.uuid))
+            .cookie(Cookie::new("access_token", access_token))
+            .set_json(SendCommandSchema { command })
+            .to_request();
+        // The first packet sent by a new socket has the sequence number 1.
+        let device = async {
+            while !socket.lock().await.command_answered(1, outcome) {
+                actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        let (resp, ()) = futures_util::join!(test::call_service(&app, req), device);
+
+        resp
+    }
+
+    #[actix_web::test]
+    async fn test_send_command_acked() {
+        let resp = answer_command(DeviceCommand::Reboot, CommandOutcome::Acked).await;
+        assert_eq!(resp.status(), 200);
+        let body: CommandResultSchema = test::read_body_json(resp).await;
+        assert_eq!(
+            body,
+            CommandResultSchema {
+                acked: true,
+                nack_reason: None,
+                status: None,
+            }
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_send_command_nacked() {
+        let resp = answer_command(
+            DeviceCommand::RotateKeys,
+            CommandOutcome::Nacked(NackReason::Busy),
+        )
+        .await;
+        assert_eq!(resp.status(), 200);
+        let body: CommandResultSchema = test::read_body_json(resp).await;
+        assert_eq!(
+            body,
+            CommandResultSchema {
+                acked: false,
+                nack_reason: Some(NackReason::Busy),
+                status: None,
+            }
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_send_command_status() {
+        let status = DeviceStatus {
+            uptime_secs: 3600,
+            free_heap: 40_000,
+            wifi_rssi: Some(-60),
+            open_connections: 1,
+        };
+        let resp = answer_command(DeviceCommand::GetStatus, CommandOutcome::Status(status)).await;
+        assert_eq!(resp.status(), 200);
+        let body: CommandResultSchema = test::read_body_json(resp).await;
+        assert_eq!(
+            body,
+            CommandResultSchema {
+                acked: true,
+                nack_reason: None,
+                status: Some(status),
+            }
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_send_command_disconnected() {
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+        let charger = user.add_random_charger().await;
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(send_command);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/{}/command", charger.uuid))
+            .cookie(Cookie::new("access_token", access_token))
+            .set_json(SendCommandSchema {
+                command: DeviceCommand::CheckIn,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 502);
+    }
+
+    #[actix_web::test]
+    async fn test_send_command_not_allowed() {
+        let (mut owner, _) = TestUser::random().await;
+        owner.login().await;
+        let charger = owner.add_random_charger().await;
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(send_command);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/{}/command", charger.uuid))
+            .cookie(Cookie::new("access_token", access_token))
+            .set_json(SendCommandSchema {
+                command: DeviceCommand::Reboot,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+    }
+}
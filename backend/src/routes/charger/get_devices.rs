@@ -26,12 +26,16 @@ use diesel::{prelude::*, result::Error::NotFound};
 use futures_util::future::Either;
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::str::FromStr;
 use std::time::{Duration, Instant};
 use utoipa::ToSchema;
 
 use crate::{
+    bandwidth::TrafficSnapshot,
     error::Error,
     routes::user::get_user,
+    udp_server::health::ConnectionHealth,
     utils::{get_connection, web_block_unpacked},
     AppState, BridgeState,
 };
@@ -58,11 +62,69 @@ pub struct GetChargerSchema {
     pub(crate) firmware_version: String,
 }
 
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct ChargerTraffic {
+    pub id: String,
+    /// Number of open remote access sessions.
+    pub sessions: usize,
+    /// Traffic of all open sessions together.
+    pub traffic: TrafficSnapshot,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct ChargerHealth {
+    pub id: String,
+    pub health: ConnectionHealth,
+}
+
 #[derive(Serialize, Clone)]
 #[serde(tag = "type")]
 pub enum StateUpdateMessage {
     #[serde(rename = "state_change")]
     StateChange { chargers: Vec<GetChargerSchema> },
+    /// Sent with every heartbeat while a charger has open remote access sessions.
+    #[serde(rename = "traffic")]
+    Traffic { chargers: Vec<ChargerTraffic> },
+    /// Sent with every heartbeat for the chargers connected to this server.
+    #[serde(rename = "health")]
+    Health { chargers: Vec<ChargerHealth> },
+}
+
+/// Returns the traffic of the chargers in `charger_ids` that have open remote access sessions.
+pub fn collect_traffic(
+    bridge_state: &BridgeState<'_>,
+    charger_ids: &HashSet<uuid::Uuid>,
+) -> Vec<ChargerTraffic> {
+    bridge_state
+        .device_traffic
+        .iter()
+        .filter(|entry| charger_ids.contains(entry.key()))
+        .map(|entry| ChargerTraffic {
+            id: entry.key().to_string(),
+            sessions: entry.value().sessions,
+            traffic: entry.value().traffic.snapshot(),
+        })
+        .collect()
+}
+
+/// Returns the connection health of the chargers in `charger_ids` that are connected to this server.
+pub async fn collect_health(
+    bridge_state: &BridgeState<'_>,
+    charger_ids: &HashSet<uuid::Uuid>,
+) -> Vec<ChargerHealth> {
+    let mut chargers = Vec::new();
+    for id in charger_ids {
+        let Some(socket) = bridge_state.connections.device(id) else {
+            continue;
+        };
+        let health = socket.lock().await.health();
+        chargers.push(ChargerHealth {
+            id: id.to_string(),
+            health,
+        });
+    }
+
+    chargers
 }
 
 pub async fn fetch_chargers(
@@ -117,11 +179,20 @@ pub async fn fetch_chargers(
     })
     .await?;
 
-    let device_map = bridge_state.device_management_map_with_id.lock().await;
+    let remotely_connected = match &bridge_state.relay {
+        Some(relay) => {
+            relay
+                .remotely_connected(&state.pool, devices.iter().map(|(c, _)| c.id).collect())
+                .await?
+        }
+        None => HashSet::new(),
+    };
+
+    let device_map = &bridge_state.connections.devices_by_id;
     let devices = devices
         .into_iter()
         .map(|(c, allowed_user)| {
-            let status = if device_map.contains_key(&c.id) {
+            let status = if device_map.contains_key(&c.id) || remotely_connected.contains(&c.id) {
                 ChargerStatus::Connected
             } else {
                 ChargerStatus::Disconnected
@@ -191,8 +262,9 @@ async fn handle_websocket(
     let user_id: uuid::Uuid = uid.into();
 
     if resp.status() == 101 {
-        let mut state_update_clients = bridge_state.state_update_clients.lock().await;
-        state_update_clients.insert(user_id, session.clone());
+        bridge_state
+            .state_update_clients
+            .insert(user_id, session.clone());
     }
 
     rt::spawn(async move {
@@ -200,9 +272,16 @@ async fn handle_websocket(
         let mut interval_timer = interval(HEARTBEAT_INTERVAL);
 
         // Send initial charger list
-        if let Ok(chargers) = fetch_chargers(&state, user_id, &bridge_state).await {
+        // Traffic is only reported for these chargers, new ones show up after reconnecting.
+        let charger_ids: HashSet<uuid::Uuid> = if let Ok(chargers) =
+            fetch_chargers(&state, user_id, &bridge_state).await
+        {
             if let Ok(json) = serde_json::to_string(&chargers) {
                 let _ = session.text(json).await;
+                chargers
+                    .iter()
+                    .filter_map(|c| uuid::Uuid::from_str(&c.id).ok())
+                    .collect()
             } else {
                 log::error!(
                     "Failed to serialize chargers for user {} during get_devices WebSocket connection.",
@@ -218,7 +297,8 @@ async fn handle_websocket(
             );
             let _ = session.close(None).await;
             return;
-        }
+        };
+        let mut sent_traffic = false;
 
         loop {
             let tick = interval_timer.tick();
@@ -247,12 +327,32 @@ async fn handle_websocket(
                         break;
                     }
                     let _ = session.ping(b"").await;
+
+                    let chargers = collect_health(&bridge_state, &charger_ids).await;
+                    if !chargers.is_empty() {
+                        if let Ok(json) =
+                            serde_json::to_string(&StateUpdateMessage::Health { chargers })
+                        {
+                            let _ = session.text(json).await;
+                        }
+                    }
+
+                    // Sent once more after the last session closed so the counters can be cleared.
+                    let chargers = collect_traffic(&bridge_state, &charger_ids);
+                    if chargers.is_empty() && !sent_traffic {
+                        continue;
+                    }
+                    sent_traffic = !chargers.is_empty();
+                    if let Ok(json) =
+                        serde_json::to_string(&StateUpdateMessage::Traffic { chargers })
+                    {
+                        let _ = session.text(json).await;
+                    }
                 }
             }
         }
 
-        let mut state_update_clients = bridge_state.state_update_clients.lock().await;
-        state_update_clients.remove(&user_id);
+        bridge_state.state_update_clients.remove(&user_id);
         let _ = session.close(None).await;
     });
 
@@ -389,4 +489,86 @@ mod tests {
         assert_eq!(resp.len(), 1);
         assert_eq!(resp[0].id, device2.uuid);
     }
+
+    #[actix_web::test]
+    async fn test_collect_traffic() {
+        use std::sync::Arc;
+
+        use crate::{
+            bandwidth::{DeviceTraffic, SessionTraffic, Traffic},
+            config::BandwidthConfig,
+            udp_server::management::RemoteConnMeta,
+        };
+
+        let (_, bridge_state) = get_test_state();
+        let charger_id = uuid::Uuid::new_v4();
+        let other_charger_id = uuid::Uuid::new_v4();
+        let config = BandwidthConfig::default();
+        for (id, conn_no) in [(charger_id, 1), (charger_id, 2), (other_charger_id, 1)] {
+            let device = {
+                let mut entry =
+                    bridge_state
+                        .device_traffic
+                        .entry(id)
+                        .or_insert_with(|| DeviceTraffic {
+                            traffic: Arc::new(Traffic::default()),
+                            sessions: 0,
+                        });
+                entry.sessions += 1;
+                entry.traffic.clone()
+            };
+            let traffic = SessionTraffic::new(&config, device);
+            traffic.add_to_device(100);
+            traffic.add_from_device(1000);
+            bridge_state.session_traffic.insert(
+                RemoteConnMeta {
+                    charger_id: id,
+                    conn_no,
+                },
+                Arc::new(traffic),
+            );
+        }
+
+        let charger_ids = HashSet::from([charger_id, uuid::Uuid::new_v4()]);
+        let traffic = collect_traffic(&bridge_state, &charger_ids);
+        assert_eq!(
+            traffic,
+            vec![ChargerTraffic {
+                id: charger_id.to_string(),
+                sessions: 2,
+                traffic: TrafficSnapshot {
+                    to_device: 200,
+                    from_device: 2000,
+                    dropped_from_device: 0,
+                },
+            }]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_collect_health() {
+        use crate::udp_server::socket::ManagementSocket;
+
+        let (_, bridge_state) = get_test_state();
+        let charger_id = uuid::Uuid::new_v4();
+        let other_charger_id = uuid::Uuid::new_v4();
+        for (id, addr) in [
+            (charger_id, "123.123.123.123:12345"),
+            (other_charger_id, "123.123.123.124:12345"),
+        ] {
+            let addr = addr.parse().unwrap();
+            let socket = ManagementSocket::new_for_test(id, addr).await;
+            bridge_state.connections.insert_device(addr, id, socket);
+        }
+
+        let charger_ids = HashSet::from([charger_id, uuid::Uuid::new_v4()]);
+        let health = collect_health(&bridge_state, &charger_ids).await;
+        assert_eq!(
+            health,
+            vec![ChargerHealth {
+                id: charger_id.to_string(),
+                health: ConnectionHealth::default(),
+            }]
+        );
+    }
 }
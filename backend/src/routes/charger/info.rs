@@ -6,6 +6,7 @@ use utoipa::ToSchema;
 
 use crate::{
     error::Error,
+    udp_server::health::ConnectionHealth,
     utils::{get_connection, parse_uuid, web_block_unpacked},
     AppState, BridgeState,
 };
@@ -18,6 +19,8 @@ pub struct ChargerInfo {
     pub connected: bool,
     pub firmware_version: String,
     pub mtu: i32,
+    /// Only set while the charger is connected to this server.
+    pub health: Option<ConnectionHealth>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -74,8 +77,17 @@ pub async fn charger_info(
     })
     .await?;
 
-    let map = bridge_state.device_management_map_with_id.lock().await;
-    let connected = map.get(&device_id).is_some();
+    let health = match bridge_state.connections.device(&device_id) {
+        Some(socket) => Some(socket.lock().await.health()),
+        None => None,
+    };
+    let mut connected = health.is_some();
+    if let (false, Some(relay)) = (connected, &bridge_state.relay) {
+        connected = !relay
+            .remotely_connected(&state.pool, vec![device_id])
+            .await?
+            .is_empty();
+    }
 
     let info = ChargerInfo {
         id: device_id.to_string(),
@@ -84,6 +96,7 @@ pub async fn charger_info(
         connected,
         firmware_version,
         mtu: mtu.unwrap_or(1240),
+        health,
     };
 
     Ok(HttpResponse::Ok().json(info))
@@ -172,6 +185,8 @@ mod tests {
         assert_eq!(body.name, name);
         assert_eq!(body.configured_port, port);
         assert_eq!(body.firmware_version, version);
+        assert!(!body.connected);
+        assert_eq!(body.health, None);
     }
 
     #[actix::test]
@@ -17,14 +17,18 @@
  * Boston, MA 02111-1307, USA.
  */
 
+pub mod access_log;
 pub mod add;
 pub mod add_with_token;
 pub mod allow_user;
+pub mod command;
 pub mod get_devices;
 pub mod get_key;
 pub mod info;
+pub mod offline_notification;
 pub mod remove;
 pub mod update_note;
+pub mod uptime;
 
 use crate::{
     error::Error,
@@ -44,6 +48,11 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         .service(get_devices::get_devices)
         .service(update_note::update_note)
         .service(info::charger_info)
+        .service(access_log::access_log)
+        .service(command::send_command)
+        .service(uptime::uptime)
+        .service(offline_notification::get_offline_notification)
+        .service(offline_notification::set_offline_notification)
         // TODO: Remove this when we stop supporting the old API
         .service(allow_user::allow_user)
         .service(get_key::get_key);
@@ -0,0 +1,281 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+use actix_web::{get, put, web, HttpResponse, Responder};
+use db_connector::models::offline_notifications::OfflineNotification;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::Error,
+    models::lang::Lang,
+    routes::charger::user_is_allowed,
+    utils::{get_connection, parse_uuid, web_block_unpacked},
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct OfflineNotificationSchema {
+    pub charger: String,
+    pub enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, IntoParams)]
+pub struct OfflineNotificationQuery {
+    charger: String,
+}
+
+/// Get whether the user is notified when the charger goes offline.
+#[utoipa::path(
+    context_path = "/charger",
+    responses(
+        (status = 200, body = OfflineNotificationSchema),
+        (status = 400, description = "Invalid charger id"),
+        (status = 401, description = "The user has no access to this charger"),
+    ),
+    security(
+        ("jwt" = [])
+    ),
+    params(
+        OfflineNotificationQuery
+    )
+)]
+#[get("/offline_notification")]
+pub async fn get_offline_notification(
+    state: web::Data<AppState>,
+    uid: crate::models::uuid::Uuid,
+    query: web::Query<OfflineNotificationQuery>,
+) -> actix_web::Result<impl Responder> {
+    let cid = parse_uuid(&query.charger)?;
+    let uid: uuid::Uuid = uid.into();
+    user_is_allowed(&state, uid, cid).await?;
+
+    let mut conn = get_connection(&state)?;
+    let enabled = web_block_unpacked(move || {
+        use db_connector::schema::offline_notifications::dsl::*;
+
+        match diesel::select(diesel::dsl::exists(
+            offline_notifications
+                .filter(user_id.eq(uid))
+                .filter(charger_id.eq(cid)),
+        ))
+        .get_result::<bool>(&mut conn)
+        {
+            Ok(enabled) => Ok(enabled),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(OfflineNotificationSchema {
+        charger: cid.to_string(),
+        enabled,
+    }))
+}
+
+/// Enable or disable emails when the charger stays offline and when it is back.
+/// The emails are sent in the language of the request.
+#[utoipa::path(
+    context_path = "/charger",
+    request_body = OfflineNotificationSchema,
+    responses(
+        (status = 200, description = "Notifications were updated"),
+        (status = 400, description = "Invalid charger id"),
+        (status = 401, description = "The user has no access to this charger"),
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+#[put("/offline_notification")]
+pub async fn set_offline_notification(
+    state: web::Data<AppState>,
+    uid: crate::models::uuid::Uuid,
+    lang: Lang,
+    schema: web::Json<OfflineNotificationSchema>,
+) -> actix_web::Result<impl Responder> {
+    let cid = parse_uuid(&schema.charger)?;
+    let uid: uuid::Uuid = uid.into();
+    user_is_allowed(&state, uid, cid).await?;
+
+    let enabled = schema.enabled;
+    let lang: String = lang.into();
+    let mut conn = get_connection(&state)?;
+    web_block_unpacked(move || {
+        use db_connector::schema::offline_notifications::dsl as offline_notifications;
+
+        let res = if enabled {
+            diesel::insert_into(offline_notifications::offline_notifications)
+                .values(OfflineNotification {
+                    id: uuid::Uuid::new_v4(),
+                    user_id: uid,
+                    charger_id: cid,
+                    lang: lang.clone(),
+                    notified_at: None,
+                })
+                .on_conflict((
+                    offline_notifications::user_id,
+                    offline_notifications::charger_id,
+                ))
+                .do_update()
+                .set(offline_notifications::lang.eq(&lang))
+                .execute(&mut conn)
+        } else {
+            diesel::delete(offline_notifications::offline_notifications)
+                .filter(offline_notifications::user_id.eq(uid))
+                .filter(offline_notifications::charger_id.eq(cid))
+                .execute(&mut conn)
+        };
+        match res {
+            Ok(_) => Ok(()),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use actix_web::{cookie::Cookie, test, App};
+    use chrono::{TimeDelta, Utc};
+    use db_connector::{
+        models::device_connectivity_events::ConnectivityEvent, test_connection_pool,
+    };
+
+    use super::*;
+    use crate::{
+        middleware::jwt::JwtMiddleware,
+        offline_notifications::send_notifications,
+        routes::user::tests::TestUser,
+        tests::{configure, create_test_state},
+    };
+
+    async fn set_enabled(access_token: &str, charger: &str, enabled: bool) -> u16 {
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(set_offline_notification);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::put()
+            .uri("/offline_notification")
+            .cookie(Cookie::new("access_token", access_token))
+            .insert_header(("X-Lang", "de"))
+            .set_json(OfflineNotificationSchema {
+                charger: charger.to_string(),
+                enabled,
+            })
+            .to_request();
+        test::call_service(&app, req).await.status().as_u16()
+    }
+
+    async fn is_enabled(access_token: &str, charger: &str) -> bool {
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(get_offline_notification);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/offline_notification?charger={charger}"))
+            .cookie(Cookie::new("access_token", access_token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body: OfflineNotificationSchema = test::read_body_json(resp).await;
+        body.enabled
+    }
+
+    #[actix_web::test]
+    async fn test_offline_notification() {
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+        let charger = user.add_random_charger().await;
+
+        assert!(!is_enabled(&access_token, &charger.uuid).await);
+        assert_eq!(set_enabled(&access_token, &charger.uuid, true).await, 200);
+        // Enabling twice only updates the language.
+        assert_eq!(set_enabled(&access_token, &charger.uuid, true).await, 200);
+        assert!(is_enabled(&access_token, &charger.uuid).await);
+        assert_eq!(set_enabled(&access_token, &charger.uuid, false).await, 200);
+        assert!(!is_enabled(&access_token, &charger.uuid).await);
+    }
+
+    #[actix_web::test]
+    async fn test_offline_notification_not_allowed() {
+        let (mut owner, _) = TestUser::random().await;
+        owner.login().await;
+        let charger = owner.add_random_charger().await;
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+
+        assert_eq!(set_enabled(&access_token, &charger.uuid, true).await, 401);
+    }
+
+    #[actix_web::test]
+    async fn test_send_notifications() {
+        use db_connector::schema::device_connectivity_events::dsl::device_connectivity_events;
+        use db_connector::schema::offline_notifications::dsl::*;
+
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+        let charger = user.add_random_charger().await;
+        let cid = uuid::Uuid::from_str(&charger.uuid).unwrap();
+        set_enabled(&access_token, &charger.uuid, true).await;
+
+        let now = Utc::now().naive_utc();
+        let event = ConnectivityEvent {
+            id: uuid::Uuid::new_v4(),
+            charger_id: cid,
+            connected_at: now - TimeDelta::hours(2),
+            disconnected_at: Some(now - TimeDelta::hours(1)),
+            disconnect_reason: Some("timeout".to_string()),
+        };
+        let pool = test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        diesel::insert_into(device_connectivity_events)
+            .values(event)
+            .execute(&mut conn)
+            .unwrap();
+
+        let state = create_test_state(None);
+        let started_at = now - TimeDelta::days(1);
+        assert!(send_notifications(&state, &mut conn, now, started_at) >= 1);
+        let first: Option<chrono::NaiveDateTime> = offline_notifications
+            .filter(charger_id.eq(cid))
+            .select(notified_at)
+            .get_result(&mut conn)
+            .unwrap();
+        assert!(first.is_some());
+
+        // The user is told only once.
+        send_notifications(&state, &mut conn, now + TimeDelta::minutes(1), started_at);
+        let second: Option<chrono::NaiveDateTime> = offline_notifications
+            .filter(charger_id.eq(cid))
+            .select(notified_at)
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(first, second);
+    }
+}
@@ -26,6 +26,7 @@ use crate::{
     error::Error,
     routes::charger::user_is_allowed,
     utils::{get_connection, parse_uuid, web_block_unpacked},
+    webhooks::{self, WebhookEvent},
     AppState, BridgeState,
 };
 
@@ -88,17 +89,7 @@ pub async fn delete_charger(
 }
 
 pub async fn remove_charger_from_state(charger: uuid::Uuid, state: &web::Data<BridgeState<'_>>) {
-    let socket = {
-        let mut map = state.device_management_map_with_id.lock().await;
-        map.remove(&charger)
-    };
-
-    if let Some(socket) = socket {
-        let socket = socket.lock().await;
-        let remote_address = socket.get_remote_address();
-        let mut map = state.device_management_map.lock().await;
-        let _ = map.remove(&remote_address);
-    }
+    state.connections.remove_device(&charger).await;
 }
 
 async fn is_last_user(cid: uuid::Uuid, state: &web::Data<AppState>) -> actix_web::Result<bool> {
@@ -186,6 +177,9 @@ pub async fn remove(
 ) -> Result<impl Responder, actix_web::Error> {
     let device_id = parse_uuid(&data.charger)?;
     user_is_allowed(&state, user_id.clone().into(), device_id).await?;
+    // Includes the user that is removed.
+    let users = webhooks::charger_users(&state.pool, device_id).await;
+    let removed: uuid::Uuid = user_id.clone().into();
 
     if is_last_user(device_id, &state).await? {
         delete_all_keys(device_id, &state).await?;
@@ -209,6 +203,14 @@ pub async fn remove(
         delete_keys_for_user(device_id, user_id.into(), &state).await?;
     }
 
+    webhooks::emit(
+        &state.pool,
+        users,
+        WebhookEvent::AccessRevoked,
+        serde_json::json!({ "charger_id": device_id, "user_id": removed }),
+    )
+    .await;
+
     Ok(HttpResponse::Ok())
 }
 
@@ -0,0 +1,295 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+use actix_web::{get, web, HttpResponse, Responder};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use db_connector::models::device_connectivity_events::ConnectivityEvent;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    connectivity::{outages, uptime_percent},
+    error::Error,
+    routes::charger::user_is_allowed,
+    utils::{get_connection, parse_uuid, web_block_unpacked},
+    AppState,
+};
+
+const DEFAULT_WINDOW_DAYS: i64 = 7;
+
+#[derive(Serialize, Deserialize, IntoParams)]
+pub struct UptimeQuery {
+    charger: String,
+    /// Start of the window as unix timestamp. Defaults to seven days before `to`.
+    from: Option<i64>,
+    /// End of the window as unix timestamp. Defaults to now.
+    to: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, PartialEq)]
+pub struct OutageSchema {
+    pub start: i64,
+    pub end: i64,
+    /// Why the charger disconnected. Not set if it was already offline when the window started.
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct UptimeSchema {
+    pub from: i64,
+    pub to: i64,
+    pub uptime_percent: f64,
+    pub outages: Vec<OutageSchema>,
+}
+
+fn timestamp(secs: i64) -> actix_web::Result<NaiveDateTime> {
+    match DateTime::from_timestamp(secs, 0) {
+        Some(time) => Ok(time.naive_utc()),
+        None => Err(Error::InvalidPayload.into()),
+    }
+}
+
+/// Get the uptime and the outages of a charger in a time window.
+#[utoipa::path(
+    context_path = "/charger",
+    responses(
+        (status = 200, body = UptimeSchema),
+        (status = 400, description = "Invalid charger id or time window"),
+        (status = 401, description = "The user has no access to this charger"),
+    ),
+    security(
+        ("jwt" = [])
+    ),
+    params(
+        UptimeQuery
+    )
+)]
+#[get("/uptime")]
+pub async fn uptime(
+    state: web::Data<AppState>,
+    uid: crate::models::uuid::Uuid,
+    query: web::Query<UptimeQuery>,
+) -> actix_web::Result<impl Responder> {
+    let cid = parse_uuid(&query.charger)?;
+    user_is_allowed(&state, uid.into(), cid).await?;
+
+    let to = match query.to {
+        Some(to) => timestamp(to)?,
+        None => Utc::now().naive_utc(),
+    };
+    let from = match query.from {
+        Some(from) => timestamp(from)?,
+        None => to - TimeDelta::days(DEFAULT_WINDOW_DAYS),
+    };
+    if from >= to {
+        return Err(Error::InvalidPayload.into());
+    }
+
+    let mut conn = get_connection(&state)?;
+    let events: Vec<ConnectivityEvent> = web_block_unpacked(move || {
+        use db_connector::schema::device_connectivity_events::dsl::*;
+
+        match device_connectivity_events
+            .filter(charger_id.eq(cid))
+            .filter(connected_at.lt(to))
+            .filter(disconnected_at.is_null().or(disconnected_at.gt(from)))
+            .order(connected_at.asc())
+            .select(ConnectivityEvent::as_select())
+            .load(&mut conn)
+        {
+            Ok(events) => Ok(events),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    let outages = outages(&events, from, to);
+    let response = UptimeSchema {
+        from: from.and_utc().timestamp(),
+        to: to.and_utc().timestamp(),
+        uptime_percent: uptime_percent(&outages, from, to),
+        outages: outages
+            .into_iter()
+            .map(|outage| OutageSchema {
+                start: outage.start.and_utc().timestamp(),
+                end: outage.end.and_utc().timestamp(),
+                reason: outage.reason,
+            })
+            .collect(),
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use actix_web::{cookie::Cookie, test, App};
+    use db_connector::test_connection_pool;
+
+    use super::*;
+    use crate::{
+        clean_connectivity_events, middleware::jwt::JwtMiddleware, routes::user::tests::TestUser,
+        tests::configure,
+    };
+
+    fn insert_event(
+        charger_id: uuid::Uuid,
+        connected_at: NaiveDateTime,
+        disconnected: Option<(NaiveDateTime, &str)>,
+    ) -> uuid::Uuid {
+        use db_connector::schema::device_connectivity_events::dsl::device_connectivity_events;
+
+        let event = ConnectivityEvent {
+            id: uuid::Uuid::new_v4(),
+            charger_id,
+            connected_at,
+            disconnected_at: disconnected.map(|(at, _)| at),
+            disconnect_reason: disconnected.map(|(_, reason)| reason.to_string()),
+        };
+        let event_id = event.id;
+
+        let pool = test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        diesel::insert_into(device_connectivity_events)
+            .values(event)
+            .execute(&mut conn)
+            .unwrap();
+
+        event_id
+    }
+
+    #[actix_web::test]
+    async fn test_uptime() {
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+        let charger = user.add_random_charger().await;
+        let charger_id = uuid::Uuid::from_str(&charger.uuid).unwrap();
+
+        let from = timestamp(1_700_000_000).unwrap();
+        let to = from + TimeDelta::hours(10);
+        insert_event(
+            charger_id,
+            from - TimeDelta::hours(1),
+            Some((from + TimeDelta::hours(4), "timeout")),
+        );
+        insert_event(charger_id, from + TimeDelta::hours(5), None);
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(uptime);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/uptime?charger={}&from={}&to={}",
+                charger.uuid,
+                from.and_utc().timestamp(),
+                to.and_utc().timestamp()
+            ))
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body: UptimeSchema = test::read_body_json(resp).await;
+        assert_eq!(body.uptime_percent, 90.0);
+        assert_eq!(
+            body.outages,
+            vec![OutageSchema {
+                start: (from + TimeDelta::hours(4)).and_utc().timestamp(),
+                end: (from + TimeDelta::hours(5)).and_utc().timestamp(),
+                reason: Some("timeout".to_string()),
+            }]
+        );
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/uptime?charger={}&from={}&to={}",
+                charger.uuid,
+                to.and_utc().timestamp(),
+                from.and_utc().timestamp()
+            ))
+            .cookie(Cookie::new("access_token", access_token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_uptime_not_allowed() {
+        let (mut owner, _) = TestUser::random().await;
+        owner.login().await;
+        let charger = owner.add_random_charger().await;
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(uptime);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/uptime?charger={}", charger.uuid))
+            .cookie(Cookie::new("access_token", access_token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn test_clean_connectivity_events() {
+        use db_connector::schema::device_connectivity_events::dsl::*;
+
+        let (mut user, _) = TestUser::random().await;
+        user.login().await;
+        let charger = user.add_random_charger().await;
+        let cid = uuid::Uuid::from_str(&charger.uuid).unwrap();
+
+        let now = Utc::now().naive_utc();
+        insert_event(
+            cid,
+            now - TimeDelta::days(40),
+            Some((now - TimeDelta::days(31), "timeout")),
+        );
+        let recent = insert_event(
+            cid,
+            now - TimeDelta::days(31),
+            Some((now - TimeDelta::days(29), "rehandshake")),
+        );
+        let open = insert_event(cid, now - TimeDelta::days(29), None);
+
+        let pool = test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        clean_connectivity_events(&mut conn, 30);
+
+        let mut remaining: Vec<uuid::Uuid> = device_connectivity_events
+            .filter(charger_id.eq(cid))
+            .select(id)
+            .load(&mut conn)
+            .unwrap();
+        remaining.sort();
+        let mut expected = vec![recent, open];
+        expected.sort();
+        assert_eq!(remaining, expected);
+    }
+}
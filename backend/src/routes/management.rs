@@ -18,7 +18,7 @@
  */
 
 use std::{
-    collections::HashSet,
+    net::IpAddr,
     time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
@@ -321,8 +321,9 @@ pub async fn management(
         }
     };
 
-    let ip: IpNetwork = match ip.parse() {
-        Ok(ip) => ip,
+    // Mapped IPv4 addresses are stored as plain IPv4 so they match what the UDP server sees.
+    let ip: IpNetwork = match ip.parse::<IpAddr>() {
+        Ok(ip) => IpNetwork::from(ip.to_canonical()),
         Err(_err) => {
             log::error!("Error while parsing ip: {_err}");
             return Err(Error::InternalError.into());
@@ -331,44 +332,17 @@ pub async fn management(
 
     let configured_users = update_configured_users(&state, charger_id, &data.data).await?;
 
-    {
-        let mut map = bridge_state.undiscovered_devices.lock().await;
-        let set = map.entry(ip).or_insert(HashSet::new());
-        set.insert(crate::DiscoveryCharger {
+    bridge_state
+        .connections
+        .undiscovered_devices
+        .entry(ip)
+        .or_default()
+        .insert(crate::DiscoveryCharger {
             id: device.id,
             last_request: Instant::now(),
         });
-    }
 
-    let addresses = {
-        let mut map = bridge_state.device_remote_conn_map.lock().await;
-        let mut addresses = Vec::new();
-        map.retain(|key, addr| {
-            if key.charger_id == charger_id {
-                addresses.push((*addr, key.conn_no));
-                false
-            } else {
-                true
-            }
-        });
-        addresses
-    };
-
-    let losing_conns = {
-        let mut clients = bridge_state.web_client_map.lock().await;
-        let mut losing_conns = Vec::new();
-        for (addr, conn_no) in addresses.into_iter() {
-            if let Some(recipient) = clients.remove(&addr) {
-                losing_conns.push((conn_no, recipient));
-            }
-        }
-        losing_conns
-    };
-
-    if !losing_conns.is_empty() {
-        let mut lost_conns = bridge_state.lost_connections.lock().await;
-        lost_conns.insert(charger_id, losing_conns);
-    }
+    bridge_state.connections.device_checked_in(charger_id);
 
     let (fw_version, port, mtu) = match &data.data {
         ManagementDataVersion::V1(v) => (v.firmware_version.clone(), v.port, None),
@@ -1109,12 +1083,12 @@ mod tests {
     ///
     /// Sequence under test:
     ///   1. A charger establishes its WireGuard tunnel. The UDP server inserts
-    ///      an entry into `device_management_map_with_id` and notifies clients
+    ///      an entry into `connections.devices_by_id` and notifies clients
     ///      (`status = Connected`).
     ///   2. The charger immediately calls `PUT /management` to identify itself
     ///      and register its configured users.
     ///   3. The management handler used to remove the entry from
-    ///      `device_management_map_with_id` before calling
+    ///      `connections.devices_by_id` before calling
     ///      `update_charger_state_change`. The notification read the now-empty
     ///      map and reported `status = Disconnected`, briefly flipping the
     ///      `/devices` page to show the just-connected charger as offline.
@@ -1127,8 +1101,6 @@ mod tests {
             routes::charger::get_devices::{fetch_chargers, ChargerStatus},
             udp_server::socket::ManagementSocket,
         };
-        use futures_util::lock::Mutex;
-        use std::sync::Arc;
 
         let (mut user, mail) = TestUser::random().await;
         user.login().await;
@@ -1148,15 +1120,9 @@ mod tests {
         // ManagementSocket into the same map the UDP server uses.
         let remote_addr: std::net::SocketAddr = "123.123.123.123:12345".parse().unwrap();
         let mgmt_socket = ManagementSocket::new_for_test(device_uuid, remote_addr).await;
-        let mgmt_socket = Arc::new(Mutex::new(mgmt_socket));
-        {
-            let mut id_map = bridge_state.device_management_map_with_id.lock().await;
-            id_map.insert(device_uuid, mgmt_socket.clone());
-        }
-        {
-            let mut addr_map = bridge_state.device_management_map.lock().await;
-            addr_map.insert(remote_addr, mgmt_socket);
-        }
+        bridge_state
+            .connections
+            .insert_device(remote_addr, device_uuid, mgmt_socket);
 
         // Sanity check: before the management request the charger is Connected.
         let before = fetch_chargers(&state, user_id, &bridge_state)
@@ -1217,9 +1183,11 @@ mod tests {
 
         // The map entry should also still be present so that
         // `start_ws` (browser→charger tunnel setup) can find it.
-        let id_map = bridge_state.device_management_map_with_id.lock().await;
         assert!(
-            id_map.contains_key(&device_uuid),
+            bridge_state
+                .connections
+                .devices_by_id
+                .contains_key(&device_uuid),
             "management request must not remove the active tunnel entry",
         );
     }
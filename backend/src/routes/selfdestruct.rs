@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use crate::{
+    connectivity::{record_disconnect, DisconnectReason},
     error::Error,
     rate_limit::ChargerRateLimiter,
     routes::charger::add::password_matches,
@@ -61,6 +62,15 @@ pub async fn selfdestruct(
     // funtion does also the rate limiting
     let device = get_charger(payload.0, &state, &rate_limiter, &req).await?;
 
+    // While the owners can still be found for the webhooks.
+    record_disconnect(
+        &state.pool,
+        device.id,
+        chrono::Utc::now().naive_utc(),
+        DisconnectReason::Selfdestruct,
+    )
+    .await;
+
     let mut conn = get_connection(&state)?;
     web_block_unpacked(move || {
         use db_connector::schema::allowed_users::dsl as allowed_users;
@@ -16,6 +16,7 @@ use crate::{
     },
     udp_server::packet::ChargeLogSendMetadata,
     utils::{get_charger_from_db, parse_uuid, send_email_with_attachment},
+    webhooks::{self, WebhookEvent},
     AppState,
 };
 
@@ -212,6 +213,17 @@ pub async fn send_chargelog(
         &metadata.filename,
         &state,
     );
+    webhooks::emit(
+        &state.pool,
+        vec![user.id],
+        WebhookEvent::ChargeLogGenerated,
+        serde_json::json!({
+            "charger_id": device_id,
+            "filename": metadata.filename,
+            "monthly": metadata.monthly_send,
+        }),
+    )
+    .await;
 
     Ok(HttpResponse::Ok())
 }
@@ -305,6 +317,17 @@ pub async fn send_charge_log_to_user<R: IntoBody>(
         &metadata.filename,
         state,
     );
+    webhooks::emit(
+        &state.pool,
+        vec![user_uuid],
+        WebhookEvent::ChargeLogGenerated,
+        serde_json::json!({
+            "charger_id": device_uuid,
+            "filename": metadata.filename,
+            "monthly": metadata.is_monthly_email,
+        }),
+    )
+    .await;
 
     log::error!(
         "Successfully sent charge log from charger '{}' to user '{}' ({})",
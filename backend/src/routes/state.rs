@@ -8,6 +8,7 @@ use ipnetwork::IpNetwork;
 use serde::Serialize;
 
 use crate::{
+    bandwidth::TrafficSnapshot,
     udp_server::{management::RemoteConnMeta, packet::ManagementResponseV2},
     BridgeState, DiscoveryCharger,
 };
@@ -22,65 +23,77 @@ pub struct ServerState {
     pub device_remote_conn_map: Vec<RemoteConnMeta>,
     pub undiscovered_devices: HashMap<IpNetwork, HashSet<DiscoveryCharger>>,
     pub lost_connections: Vec<(String, Vec<i32>)>,
+    pub session_traffic: Vec<(RemoteConnMeta, TrafficSnapshot)>,
+    pub device_traffic: Vec<(String, TrafficSnapshot)>,
 }
 
 #[get("/state")]
 pub async fn state(brige_state: web::Data<BridgeState<'_>>) -> actix_web::Result<impl Responder> {
-    let clients: Vec<SocketAddr> = {
-        let web_client_map = brige_state.web_client_map.lock().await;
-        web_client_map
-            .keys()
-            .map(|client| client.to_owned())
-            .collect()
-    };
+    let connections = &brige_state.connections;
+    let clients: Vec<SocketAddr> = connections
+        .web_clients
+        .iter()
+        .map(|entry| *entry.key())
+        .collect();
 
-    let undiscovered_clients: Vec<RemoteConnMeta> = {
-        let undiscoverd_clients = brige_state.undiscovered_clients.lock().await;
-        undiscoverd_clients.keys().cloned().collect()
-    };
+    let undiscovered_clients: Vec<RemoteConnMeta> = connections
+        .undiscovered_clients
+        .iter()
+        .map(|entry| entry.key().clone())
+        .collect();
 
-    let device_management_map: Vec<SocketAddr> = {
-        let device_management_map = brige_state.device_management_map.lock().await;
-        device_management_map
-            .keys()
-            .map(|sock| sock.to_owned())
-            .collect()
-    };
+    let device_management_map: Vec<SocketAddr> = connections
+        .devices_by_addr
+        .iter()
+        .map(|entry| *entry.key())
+        .collect();
 
-    let device_management_map_with_id: Vec<String> = {
-        let device_management_map_with_id = brige_state.device_management_map_with_id.lock().await;
-        device_management_map_with_id
-            .keys()
-            .map(|id| id.to_string())
-            .collect()
-    };
+    let device_management_map_with_id: Vec<String> = connections
+        .devices_by_id
+        .iter()
+        .map(|entry| entry.key().to_string())
+        .collect();
 
-    let port_discovery: Vec<ManagementResponseV2> = {
-        let port_discovery = brige_state.port_discovery.lock().await;
-        port_discovery.keys().copied().collect()
-    };
+    let port_discovery: Vec<ManagementResponseV2> = connections
+        .port_discovery
+        .iter()
+        .map(|entry| *entry.key())
+        .collect();
 
-    let device_remote_conn_map: Vec<RemoteConnMeta> = {
-        let device_remote_conn_map = brige_state.device_remote_conn_map.lock().await;
-        device_remote_conn_map.keys().cloned().collect()
-    };
+    let device_remote_conn_map: Vec<RemoteConnMeta> = connections
+        .device_connections
+        .iter()
+        .map(|entry| entry.key().clone())
+        .collect();
 
-    let undiscovered_devices = {
-        let map = brige_state.undiscovered_devices.lock().await;
-        map.clone()
-    };
+    let undiscovered_devices: HashMap<IpNetwork, HashSet<DiscoveryCharger>> = connections
+        .undiscovered_devices
+        .iter()
+        .map(|entry| (*entry.key(), entry.value().clone()))
+        .collect();
 
-    let lost_connections: Vec<(String, Vec<i32>)> = {
-        let map = brige_state.lost_connections.lock().await;
-        map.iter()
-            .map(|(id, conns)| {
-                (
-                    id.to_string(),
-                    conns.iter().map(|(conn_no, _)| *conn_no).collect(),
-                )
-            })
-            .collect()
-    };
+    let lost_connections: Vec<(String, Vec<i32>)> = connections
+        .lost_connections
+        .iter()
+        .map(|entry| {
+            (
+                entry.key().to_string(),
+                entry.value().iter().map(|(conn_no, _)| *conn_no).collect(),
+            )
+        })
+        .collect();
+
+    let session_traffic: Vec<(RemoteConnMeta, TrafficSnapshot)> = brige_state
+        .session_traffic
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().session.snapshot()))
+        .collect();
+
+    let device_traffic: Vec<(String, TrafficSnapshot)> = brige_state
+        .device_traffic
+        .iter()
+        .map(|entry| (entry.key().to_string(), entry.value().traffic.snapshot()))
+        .collect();
 
     let state = ServerState {
         clients,
@@ -91,6 +104,8 @@ pub async fn state(brige_state: web::Data<BridgeState<'_>>) -> actix_web::Result
         device_remote_conn_map,
         undiscovered_devices,
         lost_connections,
+        session_traffic,
+        device_traffic,
     };
 
     Ok(HttpResponse::Ok().json(state))
@@ -17,6 +17,10 @@ use crate::{
 pub struct CreateAuthorizationTokenSchema {
     use_once: bool,
     name: String,
+    /// Code of the authenticator app or a backup code. Required when two-factor
+    /// authentication is enabled.
+    #[serde(default)]
+    totp_code: Option<String>,
 }
 
 #[utoipa::path(
@@ -24,6 +28,9 @@ pub struct CreateAuthorizationTokenSchema {
     request_body = CreateAuthorizationTokenSchema,
     responses(
         (status = 201, body = ResponseAuthorizationToken),
+        (status = 401, description = "Wrong two-factor authentication code"),
+        (status = 403, description = "Two-factor authentication code required"),
+        (status = 429, description = "Too many wrong two-factor authentication codes"),
     ),
     security(
         ("jwt" = [])
@@ -35,6 +42,9 @@ pub async fn create_authorization_token(
     user_id: crate::models::uuid::Uuid,
     schema: web::Json<CreateAuthorizationTokenSchema>,
 ) -> actix_web::Result<impl Responder> {
+    crate::totp::require_second_factor(&state, user_id.clone().into(), schema.totp_code.as_deref())
+        .await?;
+
     let id = uuid::Uuid::new_v4();
     let mut token = vec![0u8; 32];
     rand::rng().fill_bytes(&mut token);
@@ -84,8 +94,11 @@ pub mod tests {
     };
 
     use crate::{
-        middleware::jwt::JwtMiddleware, models::response_auth_token::ResponseAuthorizationToken,
-        routes::user::tests::TestUser, tests::configure,
+        middleware::jwt::JwtMiddleware,
+        models::response_auth_token::ResponseAuthorizationToken,
+        routes::user::tests::TestUser,
+        tests::configure,
+        totp::tests::{current_code, enable_test_totp},
     };
 
     use super::{create_authorization_token, CreateAuthorizationTokenSchema};
@@ -109,6 +122,7 @@ pub mod tests {
             .set_json(CreateAuthorizationTokenSchema {
                 use_once,
                 name: "Test Token".to_string(),
+                totp_code: None,
             })
             .to_request();
 
@@ -133,10 +147,43 @@ pub mod tests {
             .set_json(CreateAuthorizationTokenSchema {
                 use_once: true,
                 name: "Test Token".to_string(),
+                totp_code: None,
             })
             .to_request();
 
         let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), 201);
     }
+
+    #[actix_web::test]
+    async fn test_authorization_token_requires_totp() {
+        let (mut user, mail) = TestUser::random().await;
+        let token = user.login().await.to_owned();
+        let (secret, _) = enable_test_totp(&mail);
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(create_authorization_token);
+        let app = test::init_service(app).await;
+
+        for (totp_code, status) in [
+            (None, 403),
+            (Some("000000".to_string()), 401),
+            (Some(current_code(&secret)), 201),
+        ] {
+            let req = TestRequest::post()
+                .uri("/create_authorization_token")
+                .cookie(Cookie::new("access_token", token.clone()))
+                .set_json(CreateAuthorizationTokenSchema {
+                    use_once: true,
+                    name: "Test Token".to_string(),
+                    totp_code,
+                })
+                .to_request();
+
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), status);
+        }
+    }
 }
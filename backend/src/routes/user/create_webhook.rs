@@ -0,0 +1,221 @@
+use actix_web::{post, web, HttpResponse, Responder};
+use db_connector::models::webhooks::Webhook;
+use diesel::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::Error,
+    utils::{get_connection, web_block_unpacked},
+    webhooks::{self, WebhookEvent},
+    AppState,
+};
+
+const MAX_WEBHOOKS: i64 = 10;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreateWebhookSchema {
+    /// Must be a https URL of a public host.
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct WebhookSchema {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct CreateWebhookResponseSchema {
+    pub webhook: WebhookSchema,
+    /// Key for the HMAC-SHA256 signature of the deliveries. It is only shown once.
+    pub secret: String,
+}
+
+impl From<Webhook> for WebhookSchema {
+    fn from(webhook: Webhook) -> Self {
+        let events = webhook
+            .events
+            .iter()
+            .flatten()
+            .filter_map(|event| {
+                serde_json::from_value(serde_json::Value::String(event.clone())).ok()
+            })
+            .collect();
+
+        Self {
+            id: webhook.id.to_string(),
+            url: webhook.url,
+            events,
+            created_at: webhook.created_at.and_utc().timestamp(),
+        }
+    }
+}
+
+fn is_https_url(url: &str) -> bool {
+    match url.parse::<awc::http::Uri>() {
+        Ok(uri) => uri.scheme_str() == Some("https") && uri.host().is_some(),
+        Err(_) => false,
+    }
+}
+
+/// Register a URL that receives the selected events of the user.
+#[utoipa::path(
+    context_path = "/user",
+    request_body = CreateWebhookSchema,
+    responses(
+        (status = 201, body = CreateWebhookResponseSchema),
+        (status = 400, description = "The URL is no https URL of a public host, no events were selected or the user has too many webhooks"),
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+#[post("/create_webhook")]
+pub async fn create_webhook(
+    state: web::Data<AppState>,
+    user_id: crate::models::uuid::Uuid,
+    schema: web::Json<CreateWebhookSchema>,
+) -> actix_web::Result<impl Responder> {
+    if !is_https_url(&schema.url) || schema.events.is_empty() {
+        return Err(Error::InvalidPayload.into());
+    }
+    if let Err(err) = webhooks::check_destination(&schema.url).await {
+        log::info!("Refusing webhook URL {}: {err}", schema.url);
+        return Err(Error::InvalidPayload.into());
+    }
+
+    let mut secret = vec![0u8; 32];
+    rand::rng().fill_bytes(&mut secret);
+    let secret = hex::encode(secret);
+    let mut events: Vec<Option<String>> = Vec::new();
+    for event in schema.events.iter() {
+        let event = Some(event.as_str().to_string());
+        if !events.contains(&event) {
+            events.push(event);
+        }
+    }
+    let webhook = Webhook {
+        id: uuid::Uuid::new_v4(),
+        user_id: user_id.into(),
+        url: schema.url.clone(),
+        secret: secret.clone(),
+        events,
+        created_at: chrono::Utc::now().naive_utc(),
+    };
+
+    let mut conn = get_connection(&state)?;
+    let created = webhook.clone();
+    web_block_unpacked(move || {
+        use db_connector::schema::webhooks::dsl as webhooks;
+
+        let count: i64 = match webhooks::webhooks
+            .filter(webhooks::user_id.eq(webhook.user_id))
+            .count()
+            .get_result(&mut conn)
+        {
+            Ok(count) => count,
+            Err(_err) => return Err(Error::InternalError),
+        };
+        if count >= MAX_WEBHOOKS {
+            return Err(Error::InvalidPayload);
+        }
+
+        match diesel::insert_into(webhooks::webhooks)
+            .values(&webhook)
+            .execute(&mut conn)
+        {
+            Ok(_) => Ok(()),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    Ok(HttpResponse::Created().json(CreateWebhookResponseSchema {
+        webhook: created.into(),
+        secret,
+    }))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use actix_web::{
+        cookie::Cookie,
+        test::{self, TestRequest},
+        App,
+    };
+
+    use super::*;
+    use crate::{middleware::jwt::JwtMiddleware, routes::user::tests::TestUser, tests::configure};
+
+    pub async fn create_test_webhook(
+        access_token: &str,
+        url: &str,
+        events: Vec<WebhookEvent>,
+    ) -> actix_web::dev::ServiceResponse {
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(create_webhook);
+        let app = test::init_service(app).await;
+
+        let req = TestRequest::post()
+            .uri("/create_webhook")
+            .cookie(Cookie::new("access_token", access_token))
+            .set_json(CreateWebhookSchema {
+                url: url.to_string(),
+                events,
+            })
+            .to_request();
+        test::call_service(&app, req).await
+    }
+
+    #[actix_web::test]
+    async fn test_create_webhook() {
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_string();
+
+        let resp = create_test_webhook(
+            &access_token,
+            "https://1.1.1.1/hook",
+            vec![WebhookEvent::DeviceConnected, WebhookEvent::AccessGranted],
+        )
+        .await;
+        assert_eq!(resp.status(), 201);
+        let body: CreateWebhookResponseSchema = test::read_body_json(resp).await;
+        assert_eq!(body.secret.len(), 64);
+        assert_eq!(body.webhook.url, "https://1.1.1.1/hook");
+        assert_eq!(
+            body.webhook.events,
+            vec![WebhookEvent::DeviceConnected, WebhookEvent::AccessGranted]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_create_webhook_invalid() {
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_string();
+
+        for url in [
+            "http://1.1.1.1/hook",
+            "example.com",
+            "https://",
+            "https://127.0.0.1:8443/hook",
+            "https://localhost/hook",
+            "https://169.254.169.254/latest/meta-data",
+            "https://10.0.0.1/hook",
+            "https://[::1]/hook",
+            "https://[fd12:3456::1]/hook",
+        ] {
+            let resp =
+                create_test_webhook(&access_token, url, vec![WebhookEvent::DeviceConnected]).await;
+            assert_eq!(resp.status(), 400, "{url}");
+        }
+        let resp = create_test_webhook(&access_token, "https://1.1.1.1/hook", vec![]).await;
+        assert_eq!(resp.status(), 400);
+    }
+}
@@ -11,6 +11,7 @@ use crate::{
         charger::remove::{delete_charger, remove_charger_from_state},
         user::logout::delete_all_refresh_tokens,
     },
+    token_generation::revoke_access_tokens,
     utils::{get_connection, web_block_unpacked},
     AppState, BridgeState,
 };
@@ -19,6 +20,10 @@ use crate::{
 pub struct DeleteUserSchema {
     #[schema(value_type = Vec<u32>)]
     pub login_key: Vec<u8>,
+    /// Code of the authenticator app or a backup code. Required when two-factor
+    /// authentication is enabled.
+    #[serde(default)]
+    pub totp_code: Option<String>,
 }
 
 async fn get_all_chargers_for_user(
@@ -65,6 +70,9 @@ async fn get_all_chargers_for_user(
     responses(
         (status = 200),
         (status = 400, description = "Wrong password"),
+        (status = 401, description = "Wrong two-factor authentication code"),
+        (status = 403, description = "Two-factor authentication code required"),
+        (status = 429, description = "Too many wrong two-factor authentication codes"),
         (status = 500)
     )
 )]
@@ -79,6 +87,7 @@ pub async fn delete_user(
 
     let conn = get_connection(&state)?;
     let _ = validate_password(&payload.login_key, FindBy::Uuid(uid), conn, &state.hasher).await?;
+    crate::totp::require_second_factor(&state, uid, payload.totp_code.as_deref()).await?;
 
     let devices = get_all_chargers_for_user(uid, &state).await?;
     let device_ids: Vec<uuid::Uuid> = devices.iter().map(|c| c.id).collect();
@@ -139,6 +148,7 @@ pub async fn delete_user(
     }
 
     delete_all_refresh_tokens(uid, &state).await?;
+    revoke_access_tokens(&state, uid).await?;
     let mut conn = get_connection(&state)?;
     web_block_unpacked(move || {
         use db_connector::schema::users::dsl::*;
@@ -204,16 +214,28 @@ mod tests {
 
         let login_salt = get_test_login_salt(&user1_mail).await;
         let login_key = hash_test_key(&user1.password, &login_salt, None);
-        let schema = DeleteUserSchema { login_key };
+        let schema = DeleteUserSchema {
+            login_key,
+            totp_code: None,
+        };
         let req = test::TestRequest::delete()
             .uri("/delete")
-            .cookie(Cookie::new("access_token", token))
-            .set_json(schema)
+            .cookie(Cookie::new("access_token", token.clone()))
+            .set_json(&schema)
             .to_request();
 
         let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), 200);
 
+        // The access token of the deleted account is rejected right away.
+        let req = test::TestRequest::delete()
+            .uri("/delete")
+            .cookie(Cookie::new("access_token", token))
+            .set_json(&schema)
+            .to_request();
+        let resp = crate::tests::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
         let pool = test_connection_pool();
         let mut conn = pool.get().unwrap();
 
@@ -311,6 +333,7 @@ mod tests {
 
         let schema = DeleteUserSchema {
             login_key: generate_random_bytes(),
+            totp_code: None,
         };
         let req = test::TestRequest::delete()
             .uri("/delete")
@@ -387,4 +410,33 @@ mod tests {
             assert!(res.is_ok());
         }
     }
+
+    #[actix_web::test]
+    async fn test_delete_requires_totp() {
+        let (mut user, mail) = TestUser::random().await;
+        let token = user.login().await.to_owned();
+        let (_, backup_code) = crate::totp::tests::enable_test_totp(&mail);
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(delete_user);
+        let app = test::init_service(app).await;
+
+        let login_key = user.get_login_key().await;
+        for (totp_code, status) in [(None, 403), (Some(backup_code), 200)] {
+            let req = test::TestRequest::delete()
+                .uri("/delete")
+                .cookie(Cookie::new("access_token", token.clone()))
+                .set_json(DeleteUserSchema {
+                    login_key: login_key.clone(),
+                    totp_code,
+                })
+                .to_request();
+
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), status);
+        }
+        assert!(get_test_uuid(&mail).is_err());
+    }
 }
@@ -0,0 +1,123 @@
+use actix_web::{delete, web, HttpResponse, Responder};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::Error,
+    utils::{get_connection, parse_uuid, web_block_unpacked},
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct DeleteWebhookSchema {
+    pub id: String,
+}
+
+/// Delete a webhook together with its pending deliveries.
+#[utoipa::path(
+    context_path = "/user",
+    request_body = DeleteWebhookSchema,
+    responses(
+        (status = 200),
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+#[delete("/delete_webhook")]
+pub async fn delete_webhook(
+    state: web::Data<AppState>,
+    payload: web::Json<DeleteWebhookSchema>,
+    user_id: crate::models::uuid::Uuid,
+) -> actix_web::Result<impl Responder> {
+    let webhook_id = parse_uuid(&payload.id)?;
+    let user_id: uuid::Uuid = user_id.into();
+
+    let mut conn = get_connection(&state)?;
+    web_block_unpacked(move || {
+        use db_connector::schema::webhooks::dsl as webhooks;
+        match diesel::delete(
+            webhooks::webhooks
+                .filter(webhooks::id.eq(webhook_id))
+                .filter(webhooks::user_id.eq(user_id)),
+        )
+        .execute(&mut conn)
+        {
+            Ok(_) => Ok(()),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        cookie::Cookie,
+        test::{self, TestRequest},
+        App,
+    };
+    use db_connector::test_connection_pool;
+
+    use super::*;
+    use crate::{
+        middleware::jwt::JwtMiddleware,
+        routes::user::{
+            create_webhook::{tests::create_test_webhook, CreateWebhookResponseSchema},
+            tests::TestUser,
+        },
+        tests::configure,
+        webhooks::WebhookEvent,
+    };
+
+    #[actix_web::test]
+    async fn test_delete_webhook() {
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_string();
+        let resp = create_test_webhook(
+            &access_token,
+            "https://1.1.1.1/hook",
+            vec![WebhookEvent::AccessRevoked],
+        )
+        .await;
+        let created: CreateWebhookResponseSchema = test::read_body_json(resp).await;
+
+        // Other users can't delete it.
+        let (mut other, _) = TestUser::random().await;
+        let other_token = other.login().await.to_string();
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(delete_webhook);
+        let app = test::init_service(app).await;
+
+        let id = parse_uuid(&created.webhook.id).unwrap();
+        let remaining = || {
+            use db_connector::schema::webhooks::dsl as webhooks;
+
+            let pool = test_connection_pool();
+            let mut conn = pool.get().unwrap();
+            webhooks::webhooks
+                .filter(webhooks::id.eq(id))
+                .count()
+                .get_result::<i64>(&mut conn)
+                .unwrap()
+        };
+        for (token, expected) in [(other_token, 1), (access_token, 0)] {
+            let req = TestRequest::delete()
+                .uri("/delete_webhook")
+                .cookie(Cookie::new("access_token", token))
+                .set_json(DeleteWebhookSchema {
+                    id: created.webhook.id.clone(),
+                })
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+            assert_eq!(remaining(), expected);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use db_connector::models::webhooks::Webhook;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::Error,
+    routes::user::create_webhook::WebhookSchema,
+    utils::{get_connection, web_block_unpacked},
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct GetWebhooksResponseSchema {
+    pub webhooks: Vec<WebhookSchema>,
+}
+
+#[utoipa::path(
+    context_path = "/user",
+    responses(
+        (status = 200, body = GetWebhooksResponseSchema),
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+#[get("/get_webhooks")]
+pub async fn get_webhooks(
+    state: web::Data<AppState>,
+    user_id: crate::models::uuid::Uuid,
+) -> actix_web::Result<impl Responder> {
+    let mut conn = get_connection(&state)?;
+    let user_id: uuid::Uuid = user_id.into();
+    let webhooks: Vec<Webhook> = web_block_unpacked(move || {
+        use db_connector::schema::webhooks::dsl as webhooks;
+
+        match webhooks::webhooks
+            .filter(webhooks::user_id.eq(user_id))
+            .order(webhooks::created_at.asc())
+            .select(Webhook::as_select())
+            .load(&mut conn)
+        {
+            Ok(w) => Ok(w),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(GetWebhooksResponseSchema {
+        webhooks: webhooks.into_iter().map(WebhookSchema::from).collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{cookie::Cookie, test, App};
+
+    use super::*;
+    use crate::{
+        middleware::jwt::JwtMiddleware,
+        routes::user::{create_webhook::tests::create_test_webhook, tests::TestUser},
+        tests::configure,
+        webhooks::WebhookEvent,
+    };
+
+    #[actix_web::test]
+    async fn test_get_webhooks() {
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_string();
+        create_test_webhook(
+            &access_token,
+            "https://1.1.1.1/hook",
+            vec![WebhookEvent::ChargeLogGenerated],
+        )
+        .await;
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(get_webhooks);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::get()
+            .uri("/get_webhooks")
+            .cookie(Cookie::new("access_token", access_token))
+            .to_request();
+        let resp: GetWebhooksResponseSchema = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.webhooks.len(), 1);
+        assert_eq!(
+            resp.webhooks[0].events,
+            vec![WebhookEvent::ChargeLogGenerated]
+        );
+    }
+}
@@ -29,10 +29,12 @@ use utoipa::IntoParams;
 use crate::{
     error::Error,
     middleware::get_token,
+    models::token_claims::TokenType,
     routes::{
-        auth::jwt_refresh::{delete_refresh_token, extract_token},
+        auth::jwt_refresh::{extract_token, revoke_token_family},
         user::get_user,
     },
+    token_generation::revoke_access_tokens,
     utils::{get_connection, web_block_unpacked},
     AppState,
 };
@@ -63,10 +65,11 @@ pub async fn logout(
     user_id: crate::models::uuid::Uuid,
 ) -> actix_web::Result<impl Responder> {
     if query.logout_all {
-        delete_all_refresh_tokens(user_id.into(), &state).await?;
+        delete_all_refresh_tokens(user_id.clone().into(), &state).await?;
+        revoke_access_tokens(&state, user_id.into()).await?;
     } else if let Some(token) = get_token(&req, "refresh_token") {
-        let (token, _) = extract_token(token, &state.jwt_secret)?;
-        delete_refresh_token(token, &state).await?;
+        let (token, _) = extract_token(token, &state.jwt_keys, TokenType::Refresh)?;
+        revoke_token_family(token, &state).await?;
     }
 
     let access_token = Cookie::build("access_token", "")
@@ -105,6 +108,45 @@ pub async fn delete_all_refresh_tokens(
     Ok(())
 }
 
+/// Deletes the refresh tokens of all sessions of the user except the one the refresh
+/// token `current` belongs to.
+pub async fn delete_other_refresh_tokens(
+    uid: uuid::Uuid,
+    current: Option<uuid::Uuid>,
+    state: &web::Data<AppState>,
+) -> actix_web::Result<()> {
+    let mut conn = get_connection(state)?;
+    web_block_unpacked(move || {
+        use db_connector::schema::refresh_tokens::dsl as refresh_tokens;
+
+        let current_family: Option<uuid::Uuid> = match current {
+            Some(current) => match refresh_tokens::refresh_tokens
+                .find(current)
+                .filter(refresh_tokens::user_id.eq(uid))
+                .select(refresh_tokens::family_id)
+                .get_result(&mut conn)
+                .optional()
+            {
+                Ok(family) => family,
+                Err(_err) => return Err(Error::InternalError),
+            },
+            None => None,
+        };
+
+        let query = refresh_tokens::refresh_tokens.filter(refresh_tokens::user_id.eq(uid));
+        let res = match current_family {
+            Some(family) => diesel::delete(query.filter(refresh_tokens::family_id.ne(family)))
+                .execute(&mut conn),
+            None => diesel::delete(query).execute(&mut conn),
+        };
+        match res {
+            Ok(_) => Ok(()),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await
+}
+
 #[cfg(test)]
 mod tests {
     use actix_web::{
@@ -173,13 +215,49 @@ mod tests {
 
         let req = TestRequest::get()
             .uri("/logout?logout_all=true")
-            .cookie(Cookie::new("access_token", token))
+            .cookie(Cookie::new("access_token", token.clone()))
             .cookie(Cookie::new("refresh_token", refresh_token))
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), 200);
 
         assert_eq!(get_tokens(&mail).len(), 0);
+
+        // The access token is revoked as well.
+        let req = TestRequest::get()
+            .uri("/logout?logout_all=false")
+            .cookie(Cookie::new("access_token", token))
+            .to_request();
+        let resp = crate::tests::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn simple_logout_keeps_access_tokens() {
+        let (mut user, _) = TestUser::random().await;
+        let token = user.login().await.to_owned();
+        let refresh_token = user.get_refresh_token().to_owned();
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(logout);
+        let app = test::init_service(app).await;
+
+        let req = TestRequest::get()
+            .uri("/logout?logout_all=false")
+            .cookie(Cookie::new("access_token", token.clone()))
+            .cookie(Cookie::new("refresh_token", refresh_token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = TestRequest::get()
+            .uri("/logout?logout_all=false")
+            .cookie(Cookie::new("access_token", token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
     }
 
     #[actix_web::test]
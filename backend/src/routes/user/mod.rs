@@ -18,12 +18,17 @@
  */
 
 pub mod create_authorization_token;
+pub mod create_webhook;
 pub mod delete;
 pub mod delete_authorization_token;
+pub mod delete_webhook;
 pub mod get_authorization_tokens;
 pub mod get_secret;
+pub mod get_webhooks;
 pub mod logout;
 pub mod me;
+pub mod sessions;
+pub mod totp;
 pub mod update_password;
 pub mod update_user;
 
@@ -50,6 +55,16 @@ pub fn configure(cfg: &mut ServiceConfig) {
         .service(create_authorization_token::create_authorization_token)
         .service(get_authorization_tokens::get_authorization_tokens)
         .service(delete_authorization_token::delete_authorization_token)
+        .service(create_webhook::create_webhook)
+        .service(get_webhooks::get_webhooks)
+        .service(delete_webhook::delete_webhook)
+        .service(sessions::get_sessions)
+        .service(sessions::revoke_session)
+        .service(totp::get_totp)
+        .service(totp::enroll_totp)
+        .service(totp::confirm_totp)
+        .service(totp::regenerate_backup_codes)
+        .service(totp::disable_totp)
         .service(me::me);
     cfg.service(scope);
 }
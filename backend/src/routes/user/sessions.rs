@@ -0,0 +1,213 @@
+use actix_web::{delete, get, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use db_connector::models::refresh_tokens::RefreshToken;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::Error,
+    middleware::get_token,
+    models::token_claims::TokenType,
+    routes::auth::jwt_refresh::extract_token,
+    utils::{get_connection, parse_uuid, web_block_unpacked},
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct SessionSchema {
+    /// Id of the token family. It stays the same when the refresh token is rotated.
+    pub id: String,
+    pub created_at: i64,
+    pub last_used_at: i64,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// Whether this is the session of the request.
+    pub current: bool,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct GetSessionsResponseSchema {
+    pub sessions: Vec<SessionSchema>,
+}
+
+/// List the sessions of the user, most recently used first.
+#[utoipa::path(
+    context_path = "/user",
+    responses(
+        (status = 200, body = GetSessionsResponseSchema),
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+#[get("/sessions")]
+pub async fn get_sessions(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    user_id: crate::models::uuid::Uuid,
+) -> actix_web::Result<impl Responder> {
+    let current = get_token(&req, "refresh_token")
+        .and_then(|token| extract_token(token, &state.jwt_keys, TokenType::Refresh).ok())
+        .map(|(id, _)| id);
+
+    let mut conn = get_connection(&state)?;
+    let user_id: uuid::Uuid = user_id.into();
+    let tokens: Vec<RefreshToken> = web_block_unpacked(move || {
+        use db_connector::schema::refresh_tokens::dsl as refresh_tokens;
+
+        match refresh_tokens::refresh_tokens
+            .filter(refresh_tokens::user_id.eq(user_id))
+            .filter(refresh_tokens::expiration.gt(Utc::now().timestamp()))
+            .filter(refresh_tokens::rotated_at.is_null())
+            .order(refresh_tokens::last_used_at.desc())
+            .select(RefreshToken::as_select())
+            .load(&mut conn)
+        {
+            Ok(tokens) => Ok(tokens),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    let sessions = tokens
+        .into_iter()
+        .map(|token| SessionSchema {
+            id: token.family_id.to_string(),
+            created_at: token.created_at.and_utc().timestamp(),
+            last_used_at: token.last_used_at.and_utc().timestamp(),
+            user_agent: token.user_agent,
+            ip: token.ip,
+            current: current == Some(token.id),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(GetSessionsResponseSchema { sessions }))
+}
+
+/// Revoke a single session. Its access token stays valid until it expires.
+#[utoipa::path(
+    context_path = "/user",
+    responses(
+        (status = 200, description = "The session was revoked"),
+        (status = 400, description = "Invalid session id"),
+        (status = 401, description = "The session does not exist"),
+    ),
+    params(
+        ("session_id" = String, Path, description = "Id of the session")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+#[delete("/sessions/{session_id}")]
+pub async fn revoke_session(
+    state: web::Data<AppState>,
+    user_id: crate::models::uuid::Uuid,
+    session_id: web::Path<String>,
+) -> actix_web::Result<impl Responder> {
+    let session_id = parse_uuid(&session_id)?;
+    let user_id: uuid::Uuid = user_id.into();
+
+    let mut conn = get_connection(&state)?;
+    web_block_unpacked(move || {
+        use db_connector::schema::refresh_tokens::dsl as refresh_tokens;
+
+        match diesel::delete(
+            refresh_tokens::refresh_tokens
+                .filter(refresh_tokens::family_id.eq(session_id))
+                .filter(refresh_tokens::user_id.eq(user_id)),
+        )
+        .execute(&mut conn)
+        {
+            Ok(0) => Err(Error::SessionDoesNotExist),
+            Ok(_) => Ok(()),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        cookie::Cookie,
+        test::{self, TestRequest},
+        App,
+    };
+
+    use super::*;
+    use crate::{middleware::jwt::JwtMiddleware, routes::user::tests::TestUser, tests::configure};
+
+    #[actix_web::test]
+    async fn test_sessions() {
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+        let refresh_token = user.get_refresh_token().to_owned();
+        user.additional_login().await;
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(get_sessions)
+            .service(revoke_session);
+        let app = test::init_service(app).await;
+
+        let req = TestRequest::get()
+            .uri("/sessions")
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .cookie(Cookie::new("refresh_token", refresh_token.clone()))
+            .to_request();
+        let resp: GetSessionsResponseSchema = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.sessions.len(), 2);
+        assert_eq!(resp.sessions.iter().filter(|s| s.current).count(), 1);
+        let other = resp.sessions.iter().find(|s| !s.current).unwrap();
+
+        let req = TestRequest::delete()
+            .uri(&format!("/sessions/{}", other.id))
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = TestRequest::get()
+            .uri("/sessions")
+            .cookie(Cookie::new("access_token", access_token))
+            .cookie(Cookie::new("refresh_token", refresh_token))
+            .to_request();
+        let resp: GetSessionsResponseSchema = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.sessions.len(), 1);
+        assert!(resp.sessions[0].current);
+    }
+
+    #[actix_web::test]
+    async fn test_revoke_foreign_session() {
+        let (mut owner, _) = TestUser::random().await;
+        let owner_token = owner.login().await.to_owned();
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(get_sessions)
+            .service(revoke_session);
+        let app = test::init_service(app).await;
+
+        let req = TestRequest::get()
+            .uri("/sessions")
+            .cookie(Cookie::new("access_token", owner_token))
+            .to_request();
+        let resp: GetSessionsResponseSchema = test::call_and_read_body_json(&app, req).await;
+        let session = &resp.sessions[0];
+
+        let req = TestRequest::delete()
+            .uri(&format!("/sessions/{}", session.id))
+            .cookie(Cookie::new("access_token", access_token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+    }
+}
@@ -0,0 +1,433 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web_validator::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{
+    error::Error,
+    routes::{
+        auth::login::{validate_password, FindBy},
+        user::get_user,
+    },
+    totp,
+    utils::get_connection,
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct TotpStatusSchema {
+    pub enabled: bool,
+    pub backup_codes_left: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct TotpEnrollResponseSchema {
+    /// Base32 encoded secret for entering it manually.
+    pub secret: String,
+    /// otpauth:// URI to show as QR code.
+    pub uri: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Validate, ToSchema)]
+pub struct TotpEnrollSchema {
+    #[schema(value_type = Vec<u32>)]
+    pub login_key: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Validate, ToSchema)]
+pub struct TotpCodeSchema {
+    /// Code of the authenticator app or one of the backup codes.
+    #[validate(length(max = 32))]
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Validate, ToSchema)]
+pub struct TotpChangeSchema {
+    #[schema(value_type = Vec<u32>)]
+    pub login_key: Vec<u8>,
+    /// Code of the authenticator app or one of the backup codes.
+    #[validate(length(max = 32))]
+    pub code: String,
+}
+
+/// Changing the second factor needs the password, so that a stolen session can't lock
+/// the owner out of the account.
+async fn check_password(
+    state: &web::Data<AppState>,
+    uid: uuid::Uuid,
+    login_key: &[u8],
+) -> actix_web::Result<()> {
+    let conn = get_connection(state)?;
+    validate_password(login_key, FindBy::Uuid(uid), conn, &state.hasher).await?;
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct BackupCodesSchema {
+    /// Single-use codes for when the authenticator app is not available. They are only
+    /// shown once.
+    pub backup_codes: Vec<String>,
+}
+
+/// Get whether two-factor authentication is enabled.
+#[utoipa::path(
+    context_path = "/user",
+    responses(
+        (status = 200, body = TotpStatusSchema),
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+#[get("/totp")]
+pub async fn get_totp(
+    state: web::Data<AppState>,
+    uid: crate::models::uuid::Uuid,
+) -> actix_web::Result<impl Responder> {
+    let uid: uuid::Uuid = uid.into();
+    let enabled = totp::is_enabled(&state, uid).await?;
+    let backup_codes_left = if enabled {
+        totp::backup_codes_left(&state, uid).await?
+    } else {
+        0
+    };
+
+    Ok(HttpResponse::Ok().json(TotpStatusSchema {
+        enabled,
+        backup_codes_left,
+    }))
+}
+
+/// Start enabling two-factor authentication. It is enabled once a code was sent to
+/// /user/totp/confirm.
+#[utoipa::path(
+    context_path = "/user",
+    request_body = TotpEnrollSchema,
+    responses(
+        (status = 200, body = TotpEnrollResponseSchema),
+        (status = 401, description = "The password is wrong"),
+        (status = 409, description = "Two-factor authentication is already enabled"),
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+#[post("/totp/enroll")]
+pub async fn enroll_totp(
+    state: web::Data<AppState>,
+    uid: crate::models::uuid::Uuid,
+    data: Json<TotpEnrollSchema>,
+) -> actix_web::Result<impl Responder> {
+    let uid: uuid::Uuid = uid.into();
+    check_password(&state, uid, &data.login_key).await?;
+
+    let user = get_user(&state, uid).await?;
+    let secret = totp::enroll(&state, user.id).await?;
+
+    Ok(HttpResponse::Ok().json(TotpEnrollResponseSchema {
+        secret: totp::base32(&secret),
+        uri: totp::provisioning_uri(&secret, &state.sender_name, &user.email),
+    }))
+}
+
+/// Enable two-factor authentication with a code of the enrolled secret.
+#[utoipa::path(
+    context_path = "/user",
+    request_body = TotpCodeSchema,
+    responses(
+        (status = 200, body = BackupCodesSchema),
+        (status = 400, description = "No secret was enrolled"),
+        (status = 401, description = "The code is wrong"),
+        (status = 429, description = "Too many wrong two-factor authentication codes"),
+        (status = 409, description = "Two-factor authentication is already enabled"),
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+#[post("/totp/confirm")]
+pub async fn confirm_totp(
+    state: web::Data<AppState>,
+    uid: crate::models::uuid::Uuid,
+    data: Json<TotpCodeSchema>,
+) -> actix_web::Result<impl Responder> {
+    let backup_codes = totp::confirm(&state, uid.into(), data.code.clone()).await?;
+
+    Ok(HttpResponse::Ok().json(BackupCodesSchema { backup_codes }))
+}
+
+/// Replace the backup codes with new ones.
+#[utoipa::path(
+    context_path = "/user",
+    request_body = TotpChangeSchema,
+    responses(
+        (status = 200, body = BackupCodesSchema),
+        (status = 400, description = "Two-factor authentication is not enabled"),
+        (status = 401, description = "The password or the code is wrong"),
+        (status = 429, description = "Too many wrong two-factor authentication codes"),
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+#[post("/totp/backup_codes")]
+pub async fn regenerate_backup_codes(
+    state: web::Data<AppState>,
+    uid: crate::models::uuid::Uuid,
+    data: Json<TotpChangeSchema>,
+) -> actix_web::Result<impl Responder> {
+    let uid: uuid::Uuid = uid.into();
+    check_password(&state, uid, &data.login_key).await?;
+    if !totp::is_enabled(&state, uid).await? {
+        return Err(Error::TotpNotEnrolled.into());
+    }
+    totp::require_second_factor(&state, uid, Some(&data.code)).await?;
+
+    let backup_codes = totp::regenerate_backup_codes(&state, uid).await?;
+
+    Ok(HttpResponse::Ok().json(BackupCodesSchema { backup_codes }))
+}
+
+/// Disable two-factor authentication.
+#[utoipa::path(
+    context_path = "/user",
+    request_body = TotpChangeSchema,
+    responses(
+        (status = 200),
+        (status = 400, description = "Two-factor authentication is not enabled"),
+        (status = 401, description = "The password or the code is wrong"),
+        (status = 429, description = "Too many wrong two-factor authentication codes"),
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+#[post("/totp/disable")]
+pub async fn disable_totp(
+    state: web::Data<AppState>,
+    uid: crate::models::uuid::Uuid,
+    data: Json<TotpChangeSchema>,
+) -> actix_web::Result<impl Responder> {
+    let uid: uuid::Uuid = uid.into();
+    check_password(&state, uid, &data.login_key).await?;
+    if !totp::is_enabled(&state, uid).await? {
+        return Err(Error::TotpNotEnrolled.into());
+    }
+    totp::require_second_factor(&state, uid, Some(&data.code)).await?;
+
+    totp::disable(&state, uid).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        cookie::Cookie,
+        test::{self, TestRequest},
+        App,
+    };
+
+    use super::*;
+    use crate::{
+        middleware::jwt::JwtMiddleware,
+        routes::user::tests::{get_test_uuid, TestUser},
+        tests::configure,
+        totp::tests::{current_code, enable_test_totp},
+        utils::generate_random_bytes,
+    };
+
+    #[actix_web::test]
+    async fn test_enroll_and_disable() {
+        let (mut user, mail) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+        let login_key = user.get_login_key().await;
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(get_totp)
+            .service(enroll_totp)
+            .service(confirm_totp)
+            .service(regenerate_backup_codes)
+            .service(disable_totp);
+        let app = test::init_service(app).await;
+
+        let req = TestRequest::post()
+            .uri("/totp/confirm")
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .set_json(TotpCodeSchema {
+                code: "123456".to_string(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+
+        let req = TestRequest::post()
+            .uri("/totp/enroll")
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .set_json(TotpEnrollSchema {
+                login_key: generate_random_bytes(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        let req = TestRequest::post()
+            .uri("/totp/enroll")
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .set_json(TotpEnrollSchema {
+                login_key: login_key.clone(),
+            })
+            .to_request();
+        let resp: TotpEnrollResponseSchema = test::call_and_read_body_json(&app, req).await;
+        assert!(resp.uri.starts_with("otpauth://totp/"));
+        assert!(resp.uri.contains(&format!("secret={}", resp.secret)));
+
+        // Enrolling again replaces the unconfirmed secret.
+        let req = TestRequest::post()
+            .uri("/totp/enroll")
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .set_json(TotpEnrollSchema {
+                login_key: login_key.clone(),
+            })
+            .to_request();
+        let _: TotpEnrollResponseSchema = test::call_and_read_body_json(&app, req).await;
+
+        let secret = {
+            use db_connector::schema::totp_secrets::dsl as totp_secrets;
+            use diesel::prelude::*;
+
+            let pool = db_connector::test_connection_pool();
+            let mut conn = pool.get().unwrap();
+            totp_secrets::totp_secrets
+                .find(get_test_uuid(&mail).unwrap())
+                .select(totp_secrets::secret)
+                .get_result::<Vec<u8>>(&mut conn)
+                .unwrap()
+        };
+
+        let req = TestRequest::post()
+            .uri("/totp/confirm")
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .set_json(TotpCodeSchema {
+                code: current_code(&secret),
+            })
+            .to_request();
+        let resp: BackupCodesSchema = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.backup_codes.len(), totp::BACKUP_CODE_COUNT);
+        let backup_codes = resp.backup_codes;
+
+        let req = TestRequest::post()
+            .uri("/totp/enroll")
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .set_json(TotpEnrollSchema {
+                login_key: login_key.clone(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 409);
+
+        let req = TestRequest::post()
+            .uri("/totp/backup_codes")
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .set_json(TotpChangeSchema {
+                login_key: login_key.clone(),
+                code: backup_codes[0].clone(),
+            })
+            .to_request();
+        let resp: BackupCodesSchema = test::call_and_read_body_json(&app, req).await;
+        let new_codes = resp.backup_codes;
+
+        let req = TestRequest::get()
+            .uri("/totp")
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .to_request();
+        let resp: TotpStatusSchema = test::call_and_read_body_json(&app, req).await;
+        assert!(resp.enabled);
+        assert_eq!(resp.backup_codes_left, totp::BACKUP_CODE_COUNT as i64);
+
+        // The password is needed as well.
+        let req = TestRequest::post()
+            .uri("/totp/disable")
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .set_json(TotpChangeSchema {
+                login_key: generate_random_bytes(),
+                code: new_codes[1].clone(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        // The old codes were replaced.
+        let req = TestRequest::post()
+            .uri("/totp/disable")
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .set_json(TotpChangeSchema {
+                login_key: login_key.clone(),
+                code: backup_codes[1].clone(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        let req = TestRequest::post()
+            .uri("/totp/disable")
+            .cookie(Cookie::new("access_token", access_token.clone()))
+            .set_json(TotpChangeSchema {
+                login_key: login_key.clone(),
+                code: new_codes[0].clone(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = TestRequest::get()
+            .uri("/totp")
+            .cookie(Cookie::new("access_token", access_token))
+            .to_request();
+        let resp: TotpStatusSchema = test::call_and_read_body_json(&app, req).await;
+        assert!(!resp.enabled);
+    }
+
+    #[actix_web::test]
+    async fn test_wrong_codes_lock_out() {
+        let (mut user, mail) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+        let login_key = user.get_login_key().await;
+        let (_, backup_code) = enable_test_totp(&mail);
+
+        let app = App::new()
+            .configure(configure)
+            .wrap(JwtMiddleware)
+            .service(disable_totp);
+        let app = test::init_service(app).await;
+
+        for _ in 0..totp::MAX_FAILED_ATTEMPTS {
+            let req = TestRequest::post()
+                .uri("/totp/disable")
+                .cookie(Cookie::new("access_token", access_token.clone()))
+                .set_json(TotpChangeSchema {
+                    login_key: login_key.clone(),
+                    code: "abcde-fghij".to_string(),
+                })
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 401);
+        }
+
+        // Even a valid code is rejected until the lockout is over.
+        let req = TestRequest::post()
+            .uri("/totp/disable")
+            .cookie(Cookie::new("access_token", access_token))
+            .set_json(TotpChangeSchema {
+                login_key,
+                code: backup_code,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 429);
+    }
+}
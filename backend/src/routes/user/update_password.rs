@@ -17,7 +17,7 @@
  * Boston, MA 02111-1307, USA.
  */
 
-use actix_web::{put, web, HttpResponse, Responder};
+use actix_web::{put, web, HttpRequest, HttpResponse, Responder};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
@@ -25,10 +25,17 @@ use validator::Validate;
 
 use crate::{
     error::Error,
-    routes::auth::{
-        login::{validate_password, FindBy},
-        register::hash_key,
+    middleware::get_token,
+    models::token_claims::TokenType,
+    routes::{
+        auth::{
+            jwt_refresh::extract_token,
+            login::{validate_password, FindBy},
+            register::hash_key,
+        },
+        user::logout::delete_other_refresh_tokens,
     },
+    token_generation::revoke_access_tokens,
     utils::get_connection,
     AppState,
 };
@@ -47,6 +54,10 @@ pub struct PasswordUpdateSchema {
     new_secret_salt: Vec<u8>,
     #[schema(value_type = Vec<u32>)]
     new_encrypted_secret: Vec<u8>,
+    /// Code of the authenticator app or a backup code. Required when two-factor
+    /// authentication is enabled.
+    #[serde(default)]
+    totp_code: Option<String>,
 }
 
 /// Update the user password
@@ -54,8 +65,11 @@ pub struct PasswordUpdateSchema {
     context_path = "/user",
     request_body = PasswordUpdateSchema,
     responses(
-        (status = 200, description = "Password update was successful."),
-        (status = 400, description = "The old password was wrong.")
+        (status = 200, description = "Password update was successful. All other sessions are signed out."),
+        (status = 400, description = "The old password was wrong."),
+        (status = 401, description = "The two-factor authentication code was wrong."),
+        (status = 403, description = "A two-factor authentication code is required."),
+        (status = 429, description = "Too many wrong two-factor authentication codes.")
     ),
     security(
         ("jwt" = [])
@@ -63,6 +77,7 @@ pub struct PasswordUpdateSchema {
 )]
 #[put("/update_password")]
 pub async fn update_password(
+    req: HttpRequest,
     state: web::Data<AppState>,
     uid: crate::models::uuid::Uuid,
     data: actix_web_validator::Json<PasswordUpdateSchema>,
@@ -77,15 +92,18 @@ pub async fn update_password(
         &state.hasher,
     )
     .await?;
+    crate::totp::require_second_factor(&state, uid.clone().into(), data.totp_code.as_deref())
+        .await?;
 
     let new_hash = match hash_key(data.new_login_key.clone(), &state.hasher).await {
         Ok(hash) => hash,
         Err(_err) => return Err(Error::InternalError.into()),
     };
 
+    let uid: uuid::Uuid = uid.into();
     let mut conn = get_connection(&state)?;
     match web::block(move || {
-        match diesel::update(users.find::<uuid::Uuid>(uid.into()))
+        match diesel::update(users.find(uid))
             .set((
                 login_key.eq(new_hash),
                 secret_nonce.eq(&data.new_secret_nonce),
@@ -104,11 +122,21 @@ pub async fn update_password(
     .await
     {
         Ok(res) => match res {
-            Ok(()) => Ok(HttpResponse::Ok()),
-            Err(err) => Err(err.into()),
+            Ok(()) => (),
+            Err(err) => return Err(err.into()),
         },
-        Err(_err) => Err(Error::InternalError.into()),
+        Err(_err) => return Err(Error::InternalError.into()),
     }
+
+    // Stolen refresh tokens would otherwise keep getting new access tokens. The session
+    // that changed the password stays signed in.
+    let current = get_token(&req, "refresh_token")
+        .and_then(|token| extract_token(token, &state.jwt_keys, TokenType::Refresh).ok())
+        .map(|(id, _)| id);
+    delete_other_refresh_tokens(uid, current, &state).await?;
+    revoke_access_tokens(&state, uid).await?;
+
+    Ok(HttpResponse::Ok())
 }
 
 #[cfg(test)]
@@ -122,7 +150,10 @@ mod tests {
 
     use crate::{
         routes::{
-            auth::get_login_salt::tests::get_test_login_salt,
+            auth::{
+                get_login_salt::tests::get_test_login_salt, jwt_refresh::jwt_refresh,
+                login::tests::login_user,
+            },
             user::{
                 get_secret::tests::get_test_secret,
                 tests::{generate_random_bytes_len, hash_test_key, TestUser},
@@ -136,6 +167,8 @@ mod tests {
     async fn test_valid_password_update() {
         let (mut user, mail) = TestUser::random().await;
         let token = user.login().await.to_owned();
+        let refresh_token = user.get_refresh_token().to_owned();
+        let (_, other_refresh_token) = login_user(&mail, user.get_login_key().await).await;
 
         let login_salt = get_test_login_salt(&mail).await;
         let login_key = hash_test_key(&user.password, &login_salt, None);
@@ -197,15 +230,43 @@ mod tests {
             new_secret_nonce,
             new_secret_salt,
             new_encrypted_secret,
+            totp_code: None,
         };
 
         let req = test::TestRequest::put()
             .uri("/update_password")
-            .cookie(Cookie::new("access_token", token))
-            .set_json(data)
+            .cookie(Cookie::new("access_token", token.clone()))
+            .cookie(Cookie::new("refresh_token", refresh_token.clone()))
+            .set_json(&data)
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
+
+        // Other sessions can't get a new access token, the one that changed the password can.
+        let refresh_app = App::new().configure(configure).service(jwt_refresh);
+        let refresh_app = test::init_service(refresh_app).await;
+        let req = test::TestRequest::get()
+            .uri("/jwt_refresh")
+            .cookie(Cookie::new("refresh_token", other_refresh_token))
+            .to_request();
+        let resp = test::call_service(&refresh_app, req).await;
+        assert_eq!(resp.status(), 401);
+        let req = test::TestRequest::get()
+            .uri("/jwt_refresh")
+            .cookie(Cookie::new("refresh_token", refresh_token))
+            .to_request();
+        let resp = test::call_service(&refresh_app, req).await;
+        assert!(resp.status().is_success());
+
+        // The access token issued before the change is revoked.
+        let req = test::TestRequest::put()
+            .uri("/update_password")
+            .cookie(Cookie::new("access_token", token))
+            .set_json(&data)
+            .to_request();
+        let resp = crate::tests::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
         user.password = new_password;
         let _ = user.additional_login().await;
     }
@@ -229,6 +290,7 @@ mod tests {
             new_secret_nonce: generate_random_bytes(),
             new_secret_salt: generate_random_bytes(),
             new_encrypted_secret: generate_random_bytes(),
+            totp_code: None,
         };
         let req = test::TestRequest::put()
             .uri("/update_password")
@@ -22,24 +22,25 @@ use actix_web::{get, web, Result};
 use std::path::PathBuf;
 use utoipa::IntoParams;
 
-use crate::utils::{get_charger_from_db, parse_uuid};
+use crate::{
+    utils::{get_charger_from_db, parse_uuid},
+    AppState,
+};
 
 #[cfg(not(debug_assertions))]
 const STATIC_SERVE_FROM: &str = "/static/";
 
-fn get_file(filename: String) -> Result<NamedFile> {
+#[cfg_attr(not(debug_assertions), allow(unused_variables))]
+fn get_file(filename: String, state: &AppState) -> Result<NamedFile> {
     let path: PathBuf = filename.parse().unwrap();
 
     #[cfg(debug_assertions)]
-    let static_serve_from = {
-        let env =
-            std::env::var("WARP_CHARGER_GIT_PATH").unwrap_or_else(|_| "warp-charger".to_string());
-        format!("{env}/firmwares/static_html/")
-    };
+    let base_path = state
+        .config
+        .warp_charger_git_path
+        .join("firmwares/static_html/");
     #[cfg(not(debug_assertions))]
-    let static_serve_from = "/static/";
-
-    let base_path = PathBuf::from(static_serve_from);
+    let base_path = PathBuf::from(STATIC_SERVE_FROM);
     let full_path = base_path.join(&path);
 
     let file = NamedFile::open(full_path)?;
@@ -82,5 +83,5 @@ pub async fn get_webinterface(
     let firmware = device.firmware_version.replace('+', "_");
     let firmware = firmware.replace('.', "_");
     let firmware = format!("{firmware}_index.html");
-    get_file(firmware)
+    get_file(firmware, &state)
 }
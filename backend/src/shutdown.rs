@@ -0,0 +1,303 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+use std::{
+    collections::HashSet,
+    hash::Hash,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use actix_web::web;
+use actix_ws::{CloseCode, CloseReason, Session};
+use dashmap::DashMap;
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::{
+    connectivity::{record_disconnects, DisconnectReason},
+    relay_bus::RelayMessage,
+    udp_server::{current_charge_log_sends, management::RemoteConnMeta},
+    ws_udp_bridge::send_disconnect,
+    BridgeState,
+};
+
+/// Resolves once SIGTERM or SIGINT was received.
+pub async fn wait_for_signal() {
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(s) => s,
+        Err(err) => {
+            log::error!("Failed to install SIGTERM handler: {err}");
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = sigterm.recv() => (),
+        _ = tokio::signal::ctrl_c() => (),
+    }
+}
+
+fn close_reason() -> CloseReason {
+    CloseReason {
+        code: CloseCode::Restart,
+        description: Some("Server is restarting".to_string()),
+    }
+}
+
+/// Stops accepting new relay sessions, closes all open ones and waits until
+/// running charge log uploads are done or the deadline passed.
+pub async fn shutdown(bridge_state: &web::Data<BridgeState<'_>>, deadline: Duration) {
+    log::info!("Shutting down.");
+    bridge_state.shutting_down.store(true, Ordering::SeqCst);
+
+    // Tell the devices to close all connections.
+    let connections: HashSet<RemoteConnMeta> = bridge_state
+        .connections
+        .device_connections
+        .iter()
+        .map(|entry| entry.key().clone())
+        .chain(
+            bridge_state
+                .connections
+                .undiscovered_clients
+                .iter()
+                .map(|entry| entry.key().clone()),
+        )
+        .collect();
+    for meta in connections.iter() {
+        send_disconnect(bridge_state, meta.charger_id, meta.conn_no).await;
+    }
+
+    let devices: Vec<uuid::Uuid> = bridge_state
+        .connections
+        .devices_by_id
+        .iter()
+        .map(|entry| *entry.key())
+        .collect();
+    record_disconnects(
+        &bridge_state.pool,
+        devices,
+        chrono::Utc::now().naive_utc(),
+        DisconnectReason::ServerRestart,
+    )
+    .await;
+
+    // Close the websockets of all browsers.
+    let mut sessions: Vec<Session> = Vec::new();
+    sessions.extend(
+        drain(&bridge_state.connections.web_clients)
+            .into_iter()
+            .map(|client| client.session),
+    );
+    sessions.extend(
+        drain(&bridge_state.connections.undiscovered_clients)
+            .into_iter()
+            .map(|client| client.session),
+    );
+    sessions.extend(
+        drain(&bridge_state.connections.lost_connections)
+            .into_iter()
+            .flat_map(|conns| conns.into_iter().map(|(_, client)| client.session)),
+    );
+    sessions.extend(drain(&bridge_state.state_update_clients));
+    if let Some(relay) = &bridge_state.relay {
+        {
+            let mut map = relay.remote_devices.lock().await;
+            for (meta, (owner, client)) in map.drain() {
+                relay.send(owner, &meta, RelayMessage::CloseConnection);
+                sessions.push(client.session);
+            }
+        }
+        {
+            let mut map = relay.remote_clients.lock().await;
+            for (meta, origin) in map.drain() {
+                relay.send(origin, &meta, RelayMessage::CloseConnection);
+            }
+        }
+        // The devices will reconnect to another instance.
+        relay.release_all(&bridge_state.pool).await;
+    }
+    log::info!("Closing {} websockets.", sessions.len());
+    for session in sessions.into_iter() {
+        session.close(Some(close_reason())).await.ok();
+    }
+
+    let start = Instant::now();
+    while current_charge_log_sends() > 0 {
+        if start.elapsed() > deadline {
+            log::warn!(
+                "Aborting {} running charge log uploads.",
+                current_charge_log_sends()
+            );
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    log::info!("Shutdown finished.");
+}
+
+/// Removes all entries without holding a guard of the map while the values are used.
+fn drain<K: Eq + Hash + Clone, V>(map: &DashMap<K, V>) -> Vec<V> {
+    let keys: Vec<K> = map.iter().map(|entry| entry.key().clone()).collect();
+    keys.iter()
+        .filter_map(|key| map.remove(key))
+        .map(|(_, value)| value)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{pin::Pin, str::FromStr, sync::Arc};
+
+    use actix_web::{
+        body::{BoxBody, MessageBody},
+        cookie::Cookie,
+        rt, test, App,
+    };
+    use futures_util::future::poll_fn;
+
+    use super::*;
+    use crate::{
+        middleware::jwt::JwtMiddleware,
+        routes::user::tests::TestUser,
+        tests::{configure, create_test_bridge_state, create_test_session},
+        udp_server::{
+            commands::CommandOutcome, socket::ManagementSocket, CurrentChargeLogSendsRAII,
+        },
+        ws_udp_bridge::{start_ws, WebClientHandle},
+    };
+
+    /// Reads the frames sent to a browser until the close frame and returns its code and reason.
+    async fn close_frame(mut body: BoxBody) -> (u16, String) {
+        while let Some(Ok(chunk)) = poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await {
+            // Frames of the server are not masked and the reason is short.
+            if chunk[0] == 0x88 {
+                let code = u16::from_be_bytes([chunk[2], chunk[3]]);
+                return (code, String::from_utf8_lossy(&chunk[4..]).to_string());
+            }
+        }
+        panic!("Websocket ended without close frame");
+    }
+
+    #[actix_web::test]
+    async fn test_shutdown() {
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+        let charger = user.add_random_charger().await;
+        let charger_id = uuid::Uuid::from_str(&charger.uuid).unwrap();
+
+        let bridge_state = create_test_bridge_state(None);
+        let device_addr = "123.123.123.123:12345".parse().unwrap();
+        let socket = ManagementSocket::new_for_test(charger_id, device_addr).await;
+        let (device, _) = bridge_state
+            .connections
+            .insert_device(device_addr, charger_id, socket);
+
+        // A browser with an open connection to the device.
+        let meta = RemoteConnMeta {
+            charger_id,
+            conn_no: 1,
+        };
+        let (client_body, session) = create_test_session().await;
+        bridge_state
+            .connections
+            .add_client(meta.clone(), WebClientHandle::new(session, Arc::default()));
+        bridge_state
+            .connections
+            .connection_discovered(&meta, "123.123.123.123:40001".parse().unwrap());
+        let (state_body, session) = create_test_session().await;
+        bridge_state
+            .state_update_clients
+            .insert(uuid::Uuid::new_v4(), session);
+
+        // Shutdown waits for the charge log that is being sent.
+        let charge_log = CurrentChargeLogSendsRAII::new().unwrap();
+        let shutdown_state = bridge_state.clone();
+        let done = rt::spawn(async move {
+            shutdown(&shutdown_state, Duration::from_secs(10)).await;
+        });
+        rt::time::sleep(Duration::from_millis(300)).await;
+        assert!(!done.is_finished());
+        drop(charge_log);
+        rt::time::timeout(Duration::from_secs(5), done)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(bridge_state.shutting_down.load(Ordering::SeqCst));
+        assert!(bridge_state.connections.web_clients.is_empty());
+        assert!(bridge_state.connections.undiscovered_clients.is_empty());
+        assert!(bridge_state.state_update_clients.is_empty());
+
+        // The Disconnect is the first packet sent to the device and waits for its answer.
+        assert!(device
+            .lock()
+            .await
+            .command_answered(1, CommandOutcome::Acked));
+
+        let expected = (
+            CloseCode::Restart.into(),
+            "Server is restarting".to_string(),
+        );
+        for body in [client_body, state_body] {
+            let frame = rt::time::timeout(Duration::from_secs(5), close_frame(body))
+                .await
+                .unwrap();
+            assert_eq!(frame, expected);
+        }
+
+        // New browsers are turned away.
+        let app = App::new()
+            .configure(configure)
+            .app_data(bridge_state)
+            .wrap(JwtMiddleware)
+            .service(start_ws);
+        let app = test::init_service(app).await;
+        let req = test::TestRequest::get()
+            .uri(&format!("/ws?key_id={}", uuid::Uuid::new_v4()))
+            .cookie(Cookie::new("access_token", access_token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 503);
+    }
+
+    #[actix_web::test]
+    async fn test_no_new_sessions_during_shutdown() {
+        let (mut user, _) = TestUser::random().await;
+        let access_token = user.login().await.to_owned();
+
+        let bridge_state = create_test_bridge_state(None);
+        bridge_state.shutting_down.store(true, Ordering::SeqCst);
+
+        let app = App::new()
+            .configure(configure)
+            .app_data(bridge_state)
+            .wrap(JwtMiddleware)
+            .service(start_ws);
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/ws?key_id={}", uuid::Uuid::new_v4()))
+            .cookie(Cookie::new("access_token", access_token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 503);
+    }
+}
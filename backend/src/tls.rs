@@ -0,0 +1,187 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig,
+};
+use rustls_pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> anyhow::Result<CertifiedKey> {
+    let cert_chain: Vec<CertificateDer<'static>> =
+        CertificateDer::pem_file_iter(cert_path)?.collect::<Result<_, _>>()?;
+    if cert_chain.is_empty() {
+        return Err(anyhow::anyhow!("No certificate found in {cert_path:?}"));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)?;
+    let key = rustls::crypto::aws_lc_rs::sign::any_supported_type(&key)?;
+
+    Ok(CertifiedKey::new(cert_chain, key))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+struct LoadedCert {
+    key: Arc<CertifiedKey>,
+    cert_modified: Option<SystemTime>,
+    key_modified: Option<SystemTime>,
+}
+
+/// Serves the certificate from disk and picks up renewed certificates without a restart.
+#[derive(Debug)]
+pub struct ReloadingCertResolver {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<LoadedCert>,
+}
+
+impl std::fmt::Debug for LoadedCert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoadedCert")
+            .field("cert_modified", &self.cert_modified)
+            .field("key_modified", &self.key_modified)
+            .finish()
+    }
+}
+
+impl ReloadingCertResolver {
+    pub fn new(cert_path: PathBuf, key_path: PathBuf) -> anyhow::Result<Self> {
+        let current = LoadedCert {
+            cert_modified: modified(&cert_path),
+            key_modified: modified(&key_path),
+            key: Arc::new(load_certified_key(&cert_path, &key_path)?),
+        };
+
+        Ok(Self {
+            cert_path,
+            key_path,
+            current: RwLock::new(current),
+        })
+    }
+
+    pub fn current(&self) -> Arc<CertifiedKey> {
+        self.current.read().unwrap().key.clone()
+    }
+
+    /// Loads the certificate again if one of the files changed.
+    /// Returns true if a new certificate is in use.
+    /// A broken certificate is not taken over so a half finished renewal does not break the server.
+    pub fn reload_if_changed(&self) -> bool {
+        let cert_modified = modified(&self.cert_path);
+        let key_modified = modified(&self.key_path);
+        {
+            let current = self.current.read().unwrap();
+            if current.cert_modified == cert_modified && current.key_modified == key_modified {
+                return false;
+            }
+        }
+
+        match load_certified_key(&self.cert_path, &self.key_path) {
+            Ok(key) => {
+                let mut current = self.current.write().unwrap();
+                *current = LoadedCert {
+                    key: Arc::new(key),
+                    cert_modified,
+                    key_modified,
+                };
+                log::info!("Loaded new TLS certificate from {:?}", self.cert_path);
+                true
+            }
+            Err(err) => {
+                log::error!("Failed to reload TLS certificate: {err}");
+                false
+            }
+        }
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+/// Creates the rustls config and starts a thread that checks the certificate for changes.
+pub fn load_rustls_config(
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    reload_interval: Duration,
+) -> anyhow::Result<ServerConfig> {
+    let resolver = Arc::new(ReloadingCertResolver::new(cert_path, key_path)?);
+
+    let reload_resolver = resolver.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(reload_interval);
+        reload_resolver.reload_if_changed();
+    });
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_cert(dir: &Path, name: &str) -> Vec<u8> {
+        let cert = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+        std::fs::write(dir.join("cert.pem"), cert.cert.pem()).unwrap();
+        std::fs::write(dir.join("key.pem"), cert.key_pair.serialize_pem()).unwrap();
+
+        cert.cert.der().to_vec()
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = std::env::temp_dir().join(format!("tls_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let first = write_cert(&dir, "first.example.com");
+
+        let resolver =
+            ReloadingCertResolver::new(dir.join("cert.pem"), dir.join("key.pem")).unwrap();
+        assert_eq!(resolver.current().cert[0].as_ref(), first.as_slice());
+        assert!(!resolver.reload_if_changed());
+
+        // Make sure the modification time differs on filesystems with a coarse resolution.
+        std::thread::sleep(Duration::from_millis(1100));
+        let second = write_cert(&dir, "second.example.com");
+        assert!(resolver.reload_if_changed());
+        assert_eq!(resolver.current().cert[0].as_ref(), second.as_slice());
+
+        // A broken certificate keeps the old one in place.
+        std::thread::sleep(Duration::from_millis(1100));
+        std::fs::write(dir.join("cert.pem"), "broken").unwrap();
+        assert!(!resolver.reload_if_changed());
+        assert_eq!(resolver.current().cert[0].as_ref(), second.as_slice());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Per-user generation counter that every access token carries in its claims.
+//! Incrementing the counter revokes all access tokens issued before.
+//!
+//! The counter is cached so that validating a token usually needs no database query.
+//! Every revocation is announced on the [`REVOCATION_CHANNEL`] postgres channel, and
+//! every instance drops its cached entry when it receives it, see [`spawn_listener`].
+
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use actix_web::web;
+use dashmap::DashMap;
+use diesel::{pg::PgConnection, prelude::*, result::Error::NotFound, sql_types::Text};
+
+use crate::{
+    error::Error,
+    utils::{get_connection, web_block_unpacked},
+    AppState,
+};
+
+const CACHE_SECONDS: u64 = 30;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Postgres notification channel carrying the ids of users whose tokens were revoked.
+pub const REVOCATION_CHANNEL: &str = "token_revocations";
+
+/// `None` marks a user that does not exist (anymore).
+static GENERATIONS: LazyLock<DashMap<uuid::Uuid, (Option<i32>, Instant)>> =
+    LazyLock::new(DashMap::new);
+
+fn cached(user_id: uuid::Uuid) -> Option<Option<i32>> {
+    let entry = GENERATIONS.get(&user_id)?;
+    let (generation, fetched_at) = *entry;
+    if fetched_at.elapsed() < Duration::from_secs(CACHE_SECONDS) {
+        Some(generation)
+    } else {
+        None
+    }
+}
+
+/// Caches the generation unless a newer one was stored in the meantime, e.g. by a
+/// revocation that finished while this generation was still being fetched.
+fn store(user_id: uuid::Uuid, generation: Option<i32>) {
+    GENERATIONS
+        .entry(user_id)
+        .and_modify(|cached| {
+            let outdated = matches!((generation, cached.0), (Some(new), Some(old)) if new < old);
+            if !outdated {
+                *cached = (generation, Instant::now());
+            }
+        })
+        .or_insert((generation, Instant::now()));
+}
+
+/// Returns the current token generation of the user or `None` if the user does not exist.
+pub async fn current(
+    state: &web::Data<AppState>,
+    user_id: uuid::Uuid,
+) -> actix_web::Result<Option<i32>> {
+    if let Some(generation) = cached(user_id) {
+        return Ok(generation);
+    }
+
+    let mut conn = get_connection(state)?;
+    let generation = web_block_unpacked(move || {
+        use db_connector::schema::users::dsl::*;
+
+        match users
+            .find(user_id)
+            .select(token_generation)
+            .get_result::<i32>(&mut conn)
+        {
+            Ok(generation) => Ok(Some(generation)),
+            Err(NotFound) => Ok(None),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    store(user_id, generation);
+
+    Ok(generation)
+}
+
+/// Invalidates all access tokens of the user that were issued until now.
+pub async fn revoke_access_tokens(
+    state: &web::Data<AppState>,
+    user_id: uuid::Uuid,
+) -> actix_web::Result<()> {
+    let mut conn = get_connection(state)?;
+    let generation = web_block_unpacked(move || {
+        use db_connector::schema::users::dsl::*;
+
+        match diesel::update(users.find(user_id))
+            .set(token_generation.eq(token_generation + 1))
+            .returning(token_generation)
+            .get_result::<i32>(&mut conn)
+        {
+            Ok(generation) => {
+                // Other instances may still have the old generation cached.
+                if let Err(err) = diesel::sql_query("SELECT pg_notify($1, $2)")
+                    .bind::<Text, _>(REVOCATION_CHANNEL)
+                    .bind::<Text, _>(user_id.to_string())
+                    .execute(&mut conn)
+                {
+                    log::error!("Failed to announce token revocation of user {user_id}: {err}");
+                }
+                Ok(Some(generation))
+            }
+            Err(NotFound) => Ok(None),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    store(user_id, generation);
+
+    Ok(())
+}
+
+/// Called when another instance announced a revocation for the user.
+pub fn evict(user_id: uuid::Uuid) {
+    GENERATIONS.remove(&user_id);
+}
+
+/// Drops the whole cache, e.g. when announcements may have been missed.
+pub fn clear_cache() {
+    GENERATIONS.clear();
+}
+
+fn listen(database_url: &str) -> anyhow::Result<PgConnection> {
+    let mut conn = PgConnection::establish(database_url)?;
+    diesel::sql_query(format!("LISTEN {REVOCATION_CHANNEL}")).execute(&mut conn)?;
+
+    Ok(conn)
+}
+
+/// Starts a thread evicting cache entries of users whose tokens were revoked by another instance.
+/// This is needed for every deployment with more than one instance, regardless of the relay bus.
+pub fn spawn_listener(database_url: &str) -> anyhow::Result<()> {
+    // Establish the first connection here so a wrong configuration fails early.
+    let mut conn = listen(database_url)?;
+    let url = database_url.to_string();
+    std::thread::spawn(move || loop {
+        let mut failed = false;
+        for notification in conn.notifications_iter() {
+            match notification {
+                Ok(notification) => match uuid::Uuid::parse_str(&notification.payload) {
+                    Ok(user_id) => evict(user_id),
+                    Err(err) => log::error!("Received invalid token revocation: {err}"),
+                },
+                Err(err) => {
+                    log::error!("Token revocation listener lost its connection: {err}");
+                    failed = true;
+                    break;
+                }
+            }
+        }
+
+        if failed {
+            conn = loop {
+                std::thread::sleep(RECONNECT_INTERVAL);
+                match listen(&url) {
+                    Ok(conn) => break conn,
+                    Err(err) => log::error!("Failed to reconnect token revocation listener: {err}"),
+                }
+            };
+            // Revocations announced while disconnected were missed.
+            clear_cache();
+        } else {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+
+    Ok(())
+}
+
+/// Drops cache entries that would be fetched again anyway.
+pub fn prune_cache() {
+    GENERATIONS
+        .retain(|_, (_, fetched_at)| fetched_at.elapsed() < Duration::from_secs(CACHE_SECONDS));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        routes::user::{me::tests::get_test_user, tests::TestUser},
+        tests::create_test_state,
+    };
+
+    #[actix_web::test]
+    async fn test_revoke_updates_cache() {
+        let (mut user, mail) = TestUser::random().await;
+        user.login().await;
+        let uid = get_test_user(&mail).id;
+        let state = create_test_state(None);
+
+        let before = current(&state, uid).await.unwrap().unwrap();
+        revoke_access_tokens(&state, uid).await.unwrap();
+        assert_eq!(current(&state, uid).await.unwrap(), Some(before + 1));
+        assert_eq!(get_test_user(&mail).token_generation, before + 1);
+    }
+
+    /// Another instance revokes the tokens while this one has the generation cached.
+    #[actix_web::test]
+    async fn test_revocation_evicts_cache() {
+        dotenvy::dotenv().ok();
+        let url = std::env::var("DATABASE_URL").unwrap();
+        spawn_listener(&url).unwrap();
+
+        let (mut user, mail) = TestUser::random().await;
+        user.login().await;
+        let uid = get_test_user(&mail).id;
+        let state = create_test_state(None);
+        let before = current(&state, uid).await.unwrap().unwrap();
+
+        {
+            use db_connector::schema::users::dsl::*;
+
+            let pool = db_connector::test_connection_pool();
+            let mut conn = pool.get().unwrap();
+            diesel::update(users.find(uid))
+                .set(token_generation.eq(token_generation + 1))
+                .execute(&mut conn)
+                .unwrap();
+            diesel::sql_query("SELECT pg_notify($1, $2)")
+                .bind::<Text, _>(REVOCATION_CHANNEL)
+                .bind::<Text, _>(uid.to_string())
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        for _ in 0..500 {
+            if cached(uid).is_none() {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(current(&state, uid).await.unwrap(), Some(before + 1));
+    }
+
+    /// A lookup that started before a revocation must not cache the old generation
+    /// after the revocation stored the new one.
+    #[actix_web::test]
+    async fn test_outdated_generation_not_cached() {
+        let uid = uuid::Uuid::new_v4();
+        store(uid, Some(2));
+        store(uid, Some(1));
+        assert_eq!(cached(uid), Some(Some(2)));
+        store(uid, Some(3));
+        assert_eq!(cached(uid), Some(Some(3)));
+        store(uid, None);
+        assert_eq!(cached(uid), Some(None));
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_user() {
+        let state = create_test_state(None);
+        assert_eq!(current(&state, uuid::Uuid::new_v4()).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,669 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Time-based one-time passwords (RFC 6238) as optional second login factor.
+//!
+//! Users enroll a secret in their authenticator app and confirm it with a first code,
+//! which also creates single-use backup codes. Accounts with a confirmed secret need a
+//! code for the second login step and for sensitive changes of the account.
+
+use actix_web::web;
+use chrono::{DateTime, TimeDelta, Utc};
+use db_connector::models::{
+    pre_auth_tokens::PreAuthToken, totp_backup_codes::TotpBackupCode, totp_secrets::TotpSecret,
+};
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use rand::{Rng, RngExt};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::Error,
+    utils::{get_connection, web_block_unpacked},
+    AppState,
+};
+
+pub const PERIOD_SECONDS: i64 = 30;
+pub const DIGITS: u32 = 6;
+/// 160 bits as recommended by RFC 4226.
+const SECRET_LEN: usize = 20;
+pub const BACKUP_CODE_COUNT: usize = 10;
+/// Characters that can't be confused with each other when typed from paper.
+const BACKUP_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const BACKUP_CODE_LEN: usize = 10;
+/// How long the second login step may take.
+pub const PRE_AUTH_TOKEN_MINUTES: i64 = 5;
+/// Wrong codes that can be entered before the login has to be started again.
+const MAX_PRE_AUTH_ATTEMPTS: i32 = 5;
+/// Wrong codes after which the second factor of a user is locked for `LOCKOUT_MINUTES`.
+pub const MAX_FAILED_ATTEMPTS: i32 = 10;
+const LOCKOUT_MINUTES: i64 = 15;
+
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; SECRET_LEN];
+    rand::rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// Base32 without padding, the format authenticator apps expect the secret in.
+pub fn base32(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    encoded
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// The `otpauth://` URI that authenticator apps read from a QR code.
+pub fn provisioning_uri(secret: &[u8], issuer: &str, account: &str) -> String {
+    let issuer = percent_encode(issuer);
+    format!(
+        "otpauth://totp/{issuer}:{}?secret={}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={PERIOD_SECONDS}",
+        percent_encode(account),
+        base32(secret)
+    )
+}
+
+/// HOTP value (RFC 4226) of the counter.
+pub fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) & 0x7fff_ffff;
+
+    value % 10u32.pow(DIGITS)
+}
+
+/// Returns the time step the code belongs to. One step of clock drift is tolerated.
+fn matching_step(secret: &[u8], code: &str, now: i64) -> Option<i64> {
+    let code: u32 = code.parse().ok()?;
+    let step = now.div_euclid(PERIOD_SECONDS);
+    (step - 1..=step + 1).find(|step| *step >= 0 && hotp(secret, *step as u64) == code)
+}
+
+/// Codes are compared without case, spaces and dashes.
+fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn is_totp_code(code: &str) -> bool {
+    code.len() == DIGITS as usize && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+pub fn generate_backup_codes() -> Vec<String> {
+    let mut rng = rand::rng();
+    (0..BACKUP_CODE_COUNT)
+        .map(|_| {
+            let code: String = (0..BACKUP_CODE_LEN)
+                .map(|_| {
+                    BACKUP_CODE_ALPHABET[rng.random_range(0..BACKUP_CODE_ALPHABET.len())] as char
+                })
+                .collect();
+            let (first, second) = code.split_at(BACKUP_CODE_LEN / 2);
+            format!("{first}-{second}")
+        })
+        .collect()
+}
+
+pub fn hash_backup_code(code: &str) -> String {
+    hex::encode(Sha256::digest(normalize(code).as_bytes()))
+}
+
+fn load_secret(conn: &mut PgConnection, user_id: uuid::Uuid) -> Result<Option<TotpSecret>, Error> {
+    use db_connector::schema::totp_secrets::dsl as totp_secrets;
+
+    match totp_secrets::totp_secrets
+        .find(user_id)
+        .select(TotpSecret::as_select())
+        .get_result(conn)
+        .optional()
+    {
+        Ok(secret) => Ok(secret),
+        Err(_err) => Err(Error::InternalError),
+    }
+}
+
+/// Whether the code is valid and not used yet. Marks a valid code as used, so that an
+/// observed code can't be replayed.
+fn check_code(
+    conn: &mut PgConnection,
+    secret: &TotpSecret,
+    code: &str,
+    now: DateTime<Utc>,
+) -> QueryResult<bool> {
+    let code = normalize(code);
+    if is_totp_code(&code) {
+        let Some(step) = matching_step(&secret.secret, &code, now.timestamp()) else {
+            return Ok(false);
+        };
+        if step <= secret.last_used_step {
+            return Ok(false);
+        }
+
+        use db_connector::schema::totp_secrets::dsl as totp_secrets;
+        diesel::update(totp_secrets::totp_secrets.find(secret.user_id))
+            .set(totp_secrets::last_used_step.eq(step))
+            .execute(conn)?;
+        return Ok(true);
+    }
+
+    if !secret.confirmed {
+        return Ok(false);
+    }
+
+    use db_connector::schema::totp_backup_codes::dsl as totp_backup_codes;
+    let updated = diesel::update(
+        totp_backup_codes::totp_backup_codes
+            .filter(totp_backup_codes::user_id.eq(secret.user_id))
+            .filter(totp_backup_codes::code_hash.eq(hash_backup_code(&code)))
+            .filter(totp_backup_codes::used_at.is_null()),
+    )
+    .set(totp_backup_codes::used_at.eq(now.naive_utc()))
+    .execute(conn)?;
+
+    Ok(updated == 1)
+}
+
+enum CodeCheck {
+    Valid,
+    Invalid,
+    Locked,
+}
+
+/// Accepts a current code of the authenticator app or an unused backup code. Wrong codes
+/// are counted per user for all routes that take a code, so that guessing is limited no
+/// matter where the codes are sent to.
+fn use_code(
+    conn: &mut PgConnection,
+    user_id: uuid::Uuid,
+    code: &str,
+    now: DateTime<Utc>,
+) -> Result<bool, Error> {
+    use db_connector::schema::totp_secrets::dsl as totp_secrets;
+
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        // Locking the row serializes concurrent checks, so they can't exceed the limit.
+        let Some(secret) = totp_secrets::totp_secrets
+            .find(user_id)
+            .select(TotpSecret::as_select())
+            .for_update()
+            .get_result(conn)
+            .optional()?
+        else {
+            return Ok(CodeCheck::Invalid);
+        };
+
+        if secret
+            .locked_until
+            .is_some_and(|until| until > now.naive_utc())
+        {
+            return Ok(CodeCheck::Locked);
+        }
+
+        if check_code(conn, &secret, code, now)? {
+            diesel::update(totp_secrets::totp_secrets.find(user_id))
+                .set(totp_secrets::failed_attempts.eq(0))
+                .execute(conn)?;
+            return Ok(CodeCheck::Valid);
+        }
+
+        let failed_attempts = secret.failed_attempts + 1;
+        if failed_attempts >= MAX_FAILED_ATTEMPTS {
+            let locked_until = now + TimeDelta::minutes(LOCKOUT_MINUTES);
+            diesel::update(totp_secrets::totp_secrets.find(user_id))
+                .set((
+                    totp_secrets::failed_attempts.eq(0),
+                    totp_secrets::locked_until.eq(Some(locked_until.naive_utc())),
+                ))
+                .execute(conn)?;
+        } else {
+            diesel::update(totp_secrets::totp_secrets.find(user_id))
+                .set(totp_secrets::failed_attempts.eq(failed_attempts))
+                .execute(conn)?;
+        }
+
+        Ok(CodeCheck::Invalid)
+    });
+
+    match result {
+        Ok(CodeCheck::Valid) => Ok(true),
+        Ok(CodeCheck::Invalid) => Ok(false),
+        Ok(CodeCheck::Locked) => Err(Error::SecondFactorLocked),
+        Err(_err) => Err(Error::InternalError),
+    }
+}
+
+/// Replaces all backup codes of the user and returns the new ones.
+fn replace_backup_codes(
+    conn: &mut PgConnection,
+    user_id: uuid::Uuid,
+) -> Result<Vec<String>, Error> {
+    use db_connector::schema::totp_backup_codes::dsl as totp_backup_codes;
+
+    let codes = generate_backup_codes();
+    let rows: Vec<TotpBackupCode> = codes
+        .iter()
+        .map(|code| TotpBackupCode {
+            id: uuid::Uuid::new_v4(),
+            user_id,
+            code_hash: hash_backup_code(code),
+            used_at: None,
+        })
+        .collect();
+
+    if diesel::delete(
+        totp_backup_codes::totp_backup_codes.filter(totp_backup_codes::user_id.eq(user_id)),
+    )
+    .execute(conn)
+    .is_err()
+    {
+        return Err(Error::InternalError);
+    }
+    match diesel::insert_into(totp_backup_codes::totp_backup_codes)
+        .values(&rows)
+        .execute(conn)
+    {
+        Ok(_) => Ok(codes),
+        Err(_err) => Err(Error::InternalError),
+    }
+}
+
+/// Whether the user has confirmed a secret and therefore needs a second factor.
+pub async fn is_enabled(
+    state: &web::Data<AppState>,
+    user_id: uuid::Uuid,
+) -> actix_web::Result<bool> {
+    let mut conn = get_connection(state)?;
+    web_block_unpacked(move || Ok(load_secret(&mut conn, user_id)?.is_some_and(|s| s.confirmed)))
+        .await
+}
+
+/// Checks the code for sensitive routes. Users without two-factor authentication don't
+/// need to send one.
+pub async fn require_second_factor(
+    state: &web::Data<AppState>,
+    user_id: uuid::Uuid,
+    code: Option<&str>,
+) -> actix_web::Result<()> {
+    if !is_enabled(state, user_id).await? {
+        return Ok(());
+    }
+    let Some(code) = code else {
+        return Err(Error::SecondFactorRequired.into());
+    };
+
+    let code = code.to_string();
+    let mut conn = get_connection(state)?;
+    let valid = web_block_unpacked(move || use_code(&mut conn, user_id, &code, Utc::now())).await?;
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidSecondFactor.into())
+    }
+}
+
+/// Starts enrollment with a new secret. A secret that was not confirmed yet is replaced.
+pub async fn enroll(
+    state: &web::Data<AppState>,
+    user_id: uuid::Uuid,
+) -> actix_web::Result<Vec<u8>> {
+    let secret = generate_secret();
+    let totp = TotpSecret {
+        user_id,
+        secret: secret.clone(),
+        confirmed: false,
+        last_used_step: 0,
+        created_at: Utc::now().naive_utc(),
+        failed_attempts: 0,
+        locked_until: None,
+    };
+
+    let mut conn = get_connection(state)?;
+    web_block_unpacked(move || {
+        use db_connector::schema::totp_secrets::dsl as totp_secrets;
+
+        if diesel::delete(
+            totp_secrets::totp_secrets
+                .filter(totp_secrets::user_id.eq(user_id))
+                .filter(totp_secrets::confirmed.eq(false)),
+        )
+        .execute(&mut conn)
+        .is_err()
+        {
+            return Err(Error::InternalError);
+        }
+
+        // A confirmed secret is left in place and makes the insert fail.
+        match diesel::insert_into(totp_secrets::totp_secrets)
+            .values(&totp)
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+        {
+            Ok(0) => Err(Error::TotpAlreadyEnabled),
+            Ok(_) => Ok(()),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    Ok(secret)
+}
+
+/// Enables two-factor authentication once the user proved that the authenticator app
+/// works. Returns the backup codes.
+pub async fn confirm(
+    state: &web::Data<AppState>,
+    user_id: uuid::Uuid,
+    code: String,
+) -> actix_web::Result<Vec<String>> {
+    let mut conn = get_connection(state)?;
+    web_block_unpacked(move || {
+        use db_connector::schema::totp_secrets::dsl as totp_secrets;
+
+        match load_secret(&mut conn, user_id)? {
+            None => return Err(Error::TotpNotEnrolled),
+            Some(secret) if secret.confirmed => return Err(Error::TotpAlreadyEnabled),
+            Some(_) => (),
+        }
+        if !use_code(&mut conn, user_id, &code, Utc::now())? {
+            return Err(Error::InvalidSecondFactor);
+        }
+
+        match diesel::update(totp_secrets::totp_secrets.find(user_id))
+            .filter(totp_secrets::confirmed.eq(false))
+            .set(totp_secrets::confirmed.eq(true))
+            .execute(&mut conn)
+        {
+            Ok(0) => return Err(Error::TotpAlreadyEnabled),
+            Ok(_) => (),
+            Err(_err) => return Err(Error::InternalError),
+        }
+
+        replace_backup_codes(&mut conn, user_id)
+    })
+    .await
+}
+
+pub async fn regenerate_backup_codes(
+    state: &web::Data<AppState>,
+    user_id: uuid::Uuid,
+) -> actix_web::Result<Vec<String>> {
+    let mut conn = get_connection(state)?;
+    web_block_unpacked(move || replace_backup_codes(&mut conn, user_id)).await
+}
+
+/// Removes the secret and the backup codes.
+pub async fn disable(state: &web::Data<AppState>, user_id: uuid::Uuid) -> actix_web::Result<()> {
+    let mut conn = get_connection(state)?;
+    web_block_unpacked(move || {
+        use db_connector::schema::totp_backup_codes::dsl as totp_backup_codes;
+        use db_connector::schema::totp_secrets::dsl as totp_secrets;
+
+        if diesel::delete(totp_secrets::totp_secrets.find(user_id))
+            .execute(&mut conn)
+            .is_err()
+        {
+            return Err(Error::InternalError);
+        }
+        match diesel::delete(
+            totp_backup_codes::totp_backup_codes.filter(totp_backup_codes::user_id.eq(user_id)),
+        )
+        .execute(&mut conn)
+        {
+            Ok(_) => Ok(()),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await
+}
+
+/// Number of backup codes that were not used yet.
+pub async fn backup_codes_left(
+    state: &web::Data<AppState>,
+    user_id: uuid::Uuid,
+) -> actix_web::Result<i64> {
+    let mut conn = get_connection(state)?;
+    web_block_unpacked(move || {
+        use db_connector::schema::totp_backup_codes::dsl as totp_backup_codes;
+
+        match totp_backup_codes::totp_backup_codes
+            .filter(totp_backup_codes::user_id.eq(user_id))
+            .filter(totp_backup_codes::used_at.is_null())
+            .count()
+            .get_result(&mut conn)
+        {
+            Ok(count) => Ok(count),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await
+}
+
+/// Issued by the first login step of users with two-factor authentication.
+pub async fn create_pre_auth_token(
+    state: &web::Data<AppState>,
+    user_id: uuid::Uuid,
+) -> actix_web::Result<uuid::Uuid> {
+    let token = PreAuthToken {
+        id: uuid::Uuid::new_v4(),
+        user_id,
+        expires_at: (Utc::now() + TimeDelta::minutes(PRE_AUTH_TOKEN_MINUTES)).naive_utc(),
+        attempts: 0,
+    };
+    let id = token.id;
+
+    let mut conn = get_connection(state)?;
+    web_block_unpacked(move || {
+        use db_connector::schema::pre_auth_tokens::dsl as pre_auth_tokens;
+
+        match diesel::insert_into(pre_auth_tokens::pre_auth_tokens)
+            .values(&token)
+            .execute(&mut conn)
+        {
+            Ok(_) => Ok(()),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await?;
+
+    Ok(id)
+}
+
+/// Completes the login with the second factor and returns the user it belongs to.
+pub async fn redeem_pre_auth_token(
+    state: &web::Data<AppState>,
+    token_id: uuid::Uuid,
+    code: String,
+) -> actix_web::Result<uuid::Uuid> {
+    let mut conn = get_connection(state)?;
+    web_block_unpacked(move || {
+        use db_connector::schema::pre_auth_tokens::dsl as pre_auth_tokens;
+
+        let now = Utc::now();
+        // Counting the attempt before checking the code keeps concurrent guesses within the limit.
+        let user_id: uuid::Uuid =
+            match diesel::update(pre_auth_tokens::pre_auth_tokens.find(token_id))
+                .filter(pre_auth_tokens::attempts.lt(MAX_PRE_AUTH_ATTEMPTS))
+                .filter(pre_auth_tokens::expires_at.gt(now.naive_utc()))
+                .set(pre_auth_tokens::attempts.eq(pre_auth_tokens::attempts + 1))
+                .returning(pre_auth_tokens::user_id)
+                .get_result(&mut conn)
+                .optional()
+            {
+                Ok(Some(user_id)) => user_id,
+                Ok(None) => return Err(Error::SessionDoesNotExist),
+                Err(_err) => return Err(Error::InternalError),
+            };
+
+        if !use_code(&mut conn, user_id, &code, now)? {
+            return Err(Error::InvalidSecondFactor);
+        }
+
+        match diesel::delete(pre_auth_tokens::pre_auth_tokens.find(token_id)).execute(&mut conn) {
+            Ok(_) => Ok(user_id),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Secret of the test vectors in RFC 4226 and RFC 6238.
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    pub fn current_code(secret: &[u8]) -> String {
+        let step = Utc::now().timestamp().div_euclid(PERIOD_SECONDS);
+        format!("{:06}", hotp(secret, step as u64))
+    }
+
+    /// Enables two-factor authentication for the test user and returns the secret and
+    /// a backup code.
+    pub fn enable_test_totp(mail: &str) -> (Vec<u8>, String) {
+        use db_connector::schema::totp_backup_codes::dsl as totp_backup_codes;
+        use db_connector::schema::totp_secrets::dsl as totp_secrets;
+
+        let user_id = crate::routes::user::tests::get_test_uuid(mail).unwrap();
+        let secret = generate_secret();
+        let backup_code = generate_backup_codes().remove(0);
+
+        let pool = db_connector::test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        diesel::insert_into(totp_secrets::totp_secrets)
+            .values(&TotpSecret {
+                user_id,
+                secret: secret.clone(),
+                confirmed: true,
+                last_used_step: 0,
+                created_at: Utc::now().naive_utc(),
+                failed_attempts: 0,
+                locked_until: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+        diesel::insert_into(totp_backup_codes::totp_backup_codes)
+            .values(&TotpBackupCode {
+                id: uuid::Uuid::new_v4(),
+                user_id,
+                code_hash: hash_backup_code(&backup_code),
+                used_at: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+
+        (secret, backup_code)
+    }
+
+    #[test]
+    fn test_hotp() {
+        assert_eq!(hotp(RFC_SECRET, 0), 755224);
+        assert_eq!(hotp(RFC_SECRET, 1), 287082);
+        assert_eq!(hotp(RFC_SECRET, 9), 520489);
+    }
+
+    #[test]
+    fn test_totp() {
+        // Last six digits of the SHA1 vectors in RFC 6238.
+        for (time, code) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+        ] {
+            let step = time / PERIOD_SECONDS;
+            assert_eq!(matching_step(RFC_SECRET, code, time), Some(step));
+            assert_eq!(
+                matching_step(RFC_SECRET, code, time + PERIOD_SECONDS),
+                Some(step)
+            );
+            assert_eq!(
+                matching_step(RFC_SECRET, code, time + 3 * PERIOD_SECONDS),
+                None
+            );
+        }
+        assert_eq!(matching_step(RFC_SECRET, "abcdef", 59), None);
+    }
+
+    #[test]
+    fn test_base32() {
+        assert_eq!(base32(RFC_SECRET), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(base32(b"f"), "MY");
+        assert_eq!(base32(b"foobar"), "MZXW6YTBOI");
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        assert_eq!(
+            provisioning_uri(RFC_SECRET, "Remote Access", "max@example.com"),
+            "otpauth://totp/Remote%20Access:max%40example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=Remote%20Access&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[test]
+    fn test_backup_codes() {
+        let codes = generate_backup_codes();
+        assert_eq!(codes.len(), BACKUP_CODE_COUNT);
+        for code in codes.iter() {
+            assert_eq!(code.len(), BACKUP_CODE_LEN + 1);
+            assert!(!is_totp_code(&normalize(code)));
+        }
+        assert_eq!(
+            hash_backup_code(&codes[0]),
+            hash_backup_code(&format!(" {} ", codes[0].to_uppercase().replace('-', "")))
+        );
+    }
+}
@@ -0,0 +1,443 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Acknowledgement tracking for management commands.
+//!
+//! Devices answer a command with an Ack or Nack that carries the sequence number of the
+//! command. Until then the command is sent again with exponential backoff. Firmwares that
+//! don't ack a Connect answer it by opening the connection, which resolves it as well.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use utoipa::ToSchema;
+
+use super::packet::{DeviceStatus, ManagementCommand, ManagementCommandId, NackReason};
+
+/// Delay before the first retransmit. It doubles with every attempt.
+pub const RETRANSMIT_INITIAL: Duration = Duration::from_millis(500);
+pub const RETRANSMIT_MAX: Duration = Duration::from_secs(8);
+/// Commands that are not answered within this time are given up. Matches how long a
+/// connection waits for port discovery.
+pub const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+/// How many sequence numbers of received packets, and our answers to them, are remembered
+/// to recognize retransmits.
+const SEEN_SEQUENCES: usize = 64;
+
+/// Commands that users can send to their devices.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceCommand {
+    Reboot,
+    /// Check in at the server right away, e.g. to pick up changed users.
+    CheckIn,
+    RotateKeys,
+    /// Fetch a short diagnostic status, returned in `status`.
+    GetStatus,
+}
+
+impl From<DeviceCommand> for ManagementCommandId {
+    fn from(command: DeviceCommand) -> Self {
+        match command {
+            DeviceCommand::Reboot => Self::Reboot,
+            DeviceCommand::CheckIn => Self::CheckIn,
+            DeviceCommand::RotateKeys => Self::RotateKeys,
+            DeviceCommand::GetStatus => Self::GetStatus,
+        }
+    }
+}
+
+impl From<DeviceCommand> for ManagementCommand {
+    fn from(command: DeviceCommand) -> Self {
+        Self {
+            command_id: command.into(),
+            connection_no: 0,
+            connection_uuid: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommandOutcome {
+    Acked,
+    /// Answer to a GetStatus command.
+    Status(DeviceStatus),
+    Nacked(NackReason),
+    TimedOut,
+    /// The command was superseded by a newer one for the same connection or the device
+    /// disconnected.
+    Cancelled,
+}
+
+impl CommandOutcome {
+    pub fn is_failure(&self) -> bool {
+        matches!(self, Self::Nacked(_) | Self::TimedOut)
+    }
+}
+
+/// Resolves once the device answered the command or it was given up.
+#[derive(Debug)]
+pub struct CommandHandle {
+    receiver: oneshot::Receiver<CommandOutcome>,
+}
+
+impl Future for CommandHandle {
+    type Output = CommandOutcome;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.get_mut().receiver)
+            .poll(cx)
+            .map(|res| res.unwrap_or(CommandOutcome::Cancelled))
+    }
+}
+
+struct PendingCommand {
+    command: ManagementCommand,
+    /// The packet as it was sent, so retransmits keep the sequence number.
+    bytes: Vec<u8>,
+    attempts: u32,
+    next_retry: Instant,
+    deadline: Instant,
+    responder: oneshot::Sender<CommandOutcome>,
+}
+
+impl PendingCommand {
+    fn resolve(self, outcome: CommandOutcome) {
+        let _ = self.responder.send(outcome);
+    }
+}
+
+fn supersedes(new: &ManagementCommand, old: &ManagementCommand) -> bool {
+    new.command_id.targets_connection()
+        && old.command_id.targets_connection()
+        && new.connection_no == old.connection_no
+}
+
+#[derive(Default)]
+pub struct CommandTracker {
+    pending: HashMap<u16, PendingCommand>,
+    /// Received sequence numbers with the last answer sent for them.
+    seen: VecDeque<(u16, Option<Vec<u8>>)>,
+}
+
+impl CommandTracker {
+    /// Starts tracking a command that was just sent as `bytes` with `seq_number`.
+    /// Connect and Disconnect commands that are still pending for the same connection
+    /// are cancelled.
+    pub fn track(
+        &mut self,
+        seq_number: u16,
+        command: ManagementCommand,
+        bytes: Vec<u8>,
+        now: Instant,
+    ) -> CommandHandle {
+        let superseded: Vec<u16> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| supersedes(&command, &pending.command))
+            .map(|(seq, _)| *seq)
+            .collect();
+        for seq in superseded {
+            self.resolve(seq, CommandOutcome::Cancelled);
+        }
+
+        let (responder, receiver) = oneshot::channel();
+        let pending = PendingCommand {
+            command,
+            bytes,
+            attempts: 0,
+            next_retry: now + RETRANSMIT_INITIAL,
+            deadline: now + COMMAND_TIMEOUT,
+            responder,
+        };
+        if let Some(old) = self.pending.insert(seq_number, pending) {
+            // Only happens when the sequence number wrapped around.
+            old.resolve(CommandOutcome::Cancelled);
+        }
+
+        CommandHandle { receiver }
+    }
+
+    /// Returns false if no command with `seq_number` is pending.
+    pub fn resolve(&mut self, seq_number: u16, outcome: CommandOutcome) -> bool {
+        match self.pending.remove(&seq_number) {
+            Some(pending) => {
+                pending.resolve(outcome);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Resolves the Connect for `connection_uuid` as acked.
+    pub fn connection_opened(&mut self, connection_uuid: u128) -> bool {
+        let seq = self
+            .pending
+            .iter()
+            .find(|(_, pending)| pending.command.connection_uuid == connection_uuid)
+            .map(|(seq, _)| *seq);
+        match seq {
+            Some(seq) => self.resolve(seq, CommandOutcome::Acked),
+            None => false,
+        }
+    }
+
+    /// Returns the packets that have to be sent again and gives up on commands past
+    /// their deadline.
+    pub fn due(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let expired: Vec<u16> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.deadline <= now)
+            .map(|(seq, _)| *seq)
+            .collect();
+        for seq in expired {
+            self.resolve(seq, CommandOutcome::TimedOut);
+        }
+
+        let mut due = Vec::new();
+        for pending in self.pending.values_mut() {
+            if pending.next_retry > now {
+                continue;
+            }
+            pending.attempts += 1;
+            let backoff = RETRANSMIT_INITIAL
+                .saturating_mul(1 << pending.attempts.min(16))
+                .min(RETRANSMIT_MAX);
+            pending.next_retry = now + backoff;
+            due.push(pending.bytes.clone());
+        }
+
+        due
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns false if a packet with `seq_number` was received recently, which means
+    /// the device sent it again because our answer got lost.
+    pub fn first_receipt(&mut self, seq_number: u16) -> bool {
+        if self.seen.iter().any(|(seq, _)| *seq == seq_number) {
+            return false;
+        }
+        if self.seen.len() == SEEN_SEQUENCES {
+            self.seen.pop_front();
+        }
+        self.seen.push_back((seq_number, None));
+
+        true
+    }
+
+    /// Remembers `bytes` as the answer to the received packet `seq_number`.
+    /// A later answer to the same packet replaces an earlier one.
+    pub fn reply_sent(&mut self, seq_number: u16, bytes: Vec<u8>) {
+        if let Some((_, reply)) = self.seen.iter_mut().find(|(seq, _)| *seq == seq_number) {
+            *reply = Some(bytes);
+        }
+    }
+
+    /// Returns the last answer to the received packet `seq_number`, if it was answered yet.
+    pub fn reply_to(&self, seq_number: u16) -> Option<&[u8]> {
+        self.seen
+            .iter()
+            .find(|(seq, _)| *seq == seq_number)
+            .and_then(|(_, reply)| reply.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use super::*;
+    use crate::udp_server::packet::ManagementCommandId;
+
+    fn connect(connection_no: i32) -> ManagementCommand {
+        ManagementCommand {
+            command_id: ManagementCommandId::Connect,
+            connection_no,
+            connection_uuid: connection_no as u128 + 100,
+        }
+    }
+
+    #[test]
+    fn test_ack_resolves_command() {
+        let mut tracker = CommandTracker::default();
+        let now = Instant::now();
+        let handle = tracker.track(1, connect(0), vec![1], now);
+
+        assert!(tracker.resolve(1, CommandOutcome::Acked));
+        assert!(!tracker.resolve(1, CommandOutcome::Acked));
+        assert_eq!(handle.now_or_never(), Some(CommandOutcome::Acked));
+        assert_eq!(tracker.pending(), 0);
+    }
+
+    #[test]
+    fn test_nack_resolves_command() {
+        let mut tracker = CommandTracker::default();
+        let handle = tracker.track(1, connect(0), vec![1], Instant::now());
+
+        tracker.resolve(1, CommandOutcome::Nacked(NackReason::Busy));
+        assert_eq!(
+            handle.now_or_never(),
+            Some(CommandOutcome::Nacked(NackReason::Busy))
+        );
+    }
+
+    #[test]
+    fn test_connection_opened_acks_connect() {
+        let mut tracker = CommandTracker::default();
+        let handle = tracker.track(1, connect(3), vec![1], Instant::now());
+
+        assert!(!tracker.connection_opened(42));
+        assert!(tracker.connection_opened(103));
+        assert_eq!(handle.now_or_never(), Some(CommandOutcome::Acked));
+    }
+
+    #[test]
+    fn test_retransmit_backoff() {
+        let mut tracker = CommandTracker::default();
+        let start = Instant::now();
+        let mut handle = tracker.track(7, connect(0), vec![7], start);
+
+        assert!(tracker.due(start).is_empty());
+        assert!(tracker
+            .due(start + RETRANSMIT_INITIAL - Duration::from_millis(1))
+            .is_empty());
+
+        let mut now = start;
+        let mut delay = RETRANSMIT_INITIAL;
+        let mut sent_at = Vec::new();
+        while now < start + Duration::from_secs(20) {
+            now += Duration::from_millis(100);
+            if !tracker.due(now).is_empty() {
+                sent_at.push(now - start);
+            }
+        }
+        let gaps: Vec<Duration> = sent_at.windows(2).map(|w| w[1] - w[0]).collect();
+        for gap in gaps {
+            delay = (delay * 2).min(RETRANSMIT_MAX);
+            assert_eq!(gap, delay);
+        }
+        assert_eq!(sent_at[0], RETRANSMIT_INITIAL);
+        assert!((&mut handle).now_or_never().is_none());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut tracker = CommandTracker::default();
+        let start = Instant::now();
+        let handle = tracker.track(1, connect(0), vec![1], start);
+
+        tracker.due(start + COMMAND_TIMEOUT);
+        assert_eq!(tracker.pending(), 0);
+        assert_eq!(handle.now_or_never(), Some(CommandOutcome::TimedOut));
+    }
+
+    #[test]
+    fn test_newer_command_supersedes() {
+        let mut tracker = CommandTracker::default();
+        let now = Instant::now();
+        let first = tracker.track(1, connect(0), vec![1], now);
+        let other = tracker.track(2, connect(1), vec![2], now);
+        let second = tracker.track(3, connect(0), vec![3], now);
+
+        assert_eq!(first.now_or_never(), Some(CommandOutcome::Cancelled));
+        assert_eq!(tracker.pending(), 2);
+        tracker.resolve(2, CommandOutcome::Acked);
+        tracker.resolve(3, CommandOutcome::Acked);
+        assert_eq!(other.now_or_never(), Some(CommandOutcome::Acked));
+        assert_eq!(second.now_or_never(), Some(CommandOutcome::Acked));
+    }
+
+    #[test]
+    fn test_device_commands_do_not_supersede() {
+        let mut tracker = CommandTracker::default();
+        let now = Instant::now();
+        let reboot = ManagementCommand {
+            command_id: ManagementCommandId::Reboot,
+            connection_no: 0,
+            connection_uuid: 0,
+        };
+        let connect = tracker.track(1, connect(0), vec![1], now);
+        let first = tracker.track(2, reboot, vec![2], now);
+        let second = tracker.track(3, reboot, vec![3], now);
+
+        assert_eq!(tracker.pending(), 3);
+        tracker.resolve(1, CommandOutcome::Acked);
+        tracker.resolve(2, CommandOutcome::Acked);
+        tracker.resolve(3, CommandOutcome::Acked);
+        assert_eq!(connect.now_or_never(), Some(CommandOutcome::Acked));
+        assert_eq!(first.now_or_never(), Some(CommandOutcome::Acked));
+        assert_eq!(second.now_or_never(), Some(CommandOutcome::Acked));
+    }
+
+    #[test]
+    fn test_dropped_tracker_cancels() {
+        let mut tracker = CommandTracker::default();
+        let handle = tracker.track(1, connect(0), vec![1], Instant::now());
+        drop(tracker);
+
+        assert_eq!(handle.now_or_never(), Some(CommandOutcome::Cancelled));
+    }
+
+    #[test]
+    fn test_duplicates_are_recognized() {
+        let mut tracker = CommandTracker::default();
+
+        assert!(tracker.first_receipt(5));
+        assert!(!tracker.first_receipt(5));
+        assert!(tracker.first_receipt(6));
+
+        for seq in 100..100 + SEEN_SEQUENCES as u16 {
+            assert!(tracker.first_receipt(seq));
+        }
+        // Old sequence numbers are forgotten so the counter can wrap around.
+        assert!(tracker.first_receipt(5));
+    }
+
+    #[test]
+    fn test_replies_are_remembered() {
+        let mut tracker = CommandTracker::default();
+
+        // Packets that were not received are not answered from the cache.
+        tracker.reply_sent(5, vec![1]);
+        assert_eq!(tracker.reply_to(5), None);
+
+        assert!(tracker.first_receipt(5));
+        assert_eq!(tracker.reply_to(5), None);
+        tracker.reply_sent(5, vec![1]);
+        assert_eq!(tracker.reply_to(5), Some(&[1u8][..]));
+        tracker.reply_sent(5, vec![2]);
+        assert_eq!(tracker.reply_to(5), Some(&[2u8][..]));
+
+        for seq in 100..100 + SEEN_SEQUENCES as u16 {
+            assert!(tracker.first_receipt(seq));
+        }
+        assert_eq!(tracker.reply_to(5), None);
+    }
+}
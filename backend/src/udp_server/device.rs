@@ -19,7 +19,7 @@
 
 use boringtun::noise::{Tunn, TunnResult};
 use smoltcp::phy::{self, DeviceCapabilities, Medium};
-use std::{collections::VecDeque, net::SocketAddr, sync::Arc};
+use std::{collections::VecDeque, net::SocketAddr, sync::Arc, time::Duration};
 use tokio::net::UdpSocket;
 
 use super::multiplex::send_data;
@@ -58,6 +58,10 @@ impl ManagementDevice {
         self.rx_buf.push_back(data)
     }
 
+    pub fn time_since_last_handshake(&self) -> Option<Duration> {
+        self.tunn.time_since_last_handshake()
+    }
+
     pub fn decapsulate<'a>(&mut self, src: &'a [u8], dst: &'a mut [u8]) -> TunnResult<'a> {
         self.tunn.decapsulate(None, src, dst)
     }
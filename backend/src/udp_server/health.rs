@@ -0,0 +1,166 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Connection quality of the management tunnels, measured with ping packets.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+pub const PING_INTERVAL: Duration = Duration::from_secs(10);
+/// Pings that are not answered within this time count as lost.
+const PONG_TIMEOUT: Duration = Duration::from_secs(5);
+/// Number of pings the packet loss is computed over.
+const LOSS_WINDOW: usize = 30;
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConnectionHealth {
+    /// Smoothed round trip time through the tunnel.
+    pub rtt_ms: Option<u32>,
+    /// Share of the recent pings that were not answered, between 0 and 1.
+    /// Not set until the charger answered a ping, older firmwares don't.
+    pub packet_loss: Option<f32>,
+    pub last_handshake_secs: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct HealthProbe {
+    outstanding: VecDeque<(u16, Instant)>,
+    /// Whether the recent pings were answered, oldest first.
+    results: VecDeque<bool>,
+    rtt: Option<Duration>,
+}
+
+impl HealthProbe {
+    pub fn ping_sent(&mut self, seq_number: u16, now: Instant) {
+        self.expire(now);
+        self.outstanding.push_back((seq_number, now));
+    }
+
+    /// Returns false for pongs to unknown or expired pings.
+    pub fn pong_received(&mut self, seq_number: u16, now: Instant) -> bool {
+        self.expire(now);
+        let Some(pos) = self
+            .outstanding
+            .iter()
+            .position(|(seq, _)| *seq == seq_number)
+        else {
+            return false;
+        };
+        let Some((_, sent)) = self.outstanding.remove(pos) else {
+            return false;
+        };
+
+        let sample = now - sent;
+        // Same smoothing as the TCP round trip estimate.
+        self.rtt = Some(match self.rtt {
+            Some(rtt) => (rtt * 7 + sample) / 8,
+            None => sample,
+        });
+        self.record(true);
+
+        true
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((_, sent)) = self.outstanding.front() {
+            if now - *sent < PONG_TIMEOUT {
+                break;
+            }
+            self.outstanding.pop_front();
+            self.record(false);
+        }
+    }
+
+    fn record(&mut self, answered: bool) {
+        if self.results.len() == LOSS_WINDOW {
+            self.results.pop_front();
+        }
+        self.results.push_back(answered);
+    }
+
+    pub fn snapshot(&mut self, now: Instant) -> ConnectionHealth {
+        self.expire(now);
+        let packet_loss = self.rtt.map(|_| {
+            let lost = self.results.iter().filter(|answered| !**answered).count();
+            lost as f32 / self.results.len().max(1) as f32
+        });
+
+        ConnectionHealth {
+            rtt_ms: self.rtt.map(|rtt| rtt.as_millis() as u32),
+            packet_loss,
+            last_handshake_secs: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rtt() {
+        let mut probe = HealthProbe::default();
+        let start = Instant::now();
+
+        probe.ping_sent(1, start);
+        assert!(probe.pong_received(1, start + Duration::from_millis(80)));
+        assert!(!probe.pong_received(1, start + Duration::from_millis(90)));
+        let health = probe.snapshot(start + Duration::from_millis(100));
+        assert_eq!(health.rtt_ms, Some(80));
+        assert_eq!(health.packet_loss, Some(0.0));
+
+        let next = start + PING_INTERVAL;
+        probe.ping_sent(2, next);
+        probe.pong_received(2, next + Duration::from_millis(160));
+        assert_eq!(probe.snapshot(next + PING_INTERVAL).rtt_ms, Some(90));
+    }
+
+    #[test]
+    fn test_packet_loss() {
+        let mut probe = HealthProbe::default();
+        let mut now = Instant::now();
+
+        for seq in 0..4 {
+            probe.ping_sent(seq, now);
+            if seq % 2 == 0 {
+                probe.pong_received(seq, now + Duration::from_millis(10));
+            }
+            now += PING_INTERVAL;
+        }
+
+        assert_eq!(probe.snapshot(now).packet_loss, Some(0.5));
+    }
+
+    #[test]
+    fn test_no_answers() {
+        let mut probe = HealthProbe::default();
+        let start = Instant::now();
+
+        probe.ping_sent(1, start);
+        let health = probe.snapshot(start + PING_INTERVAL);
+        assert_eq!(health.rtt_ms, None);
+        assert_eq!(health.packet_loss, None);
+        assert!(!probe.pong_received(1, start + PING_INTERVAL));
+    }
+}
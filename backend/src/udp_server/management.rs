@@ -25,7 +25,10 @@ use serde::{ser::SerializeStruct, Serialize};
 
 use crate::BridgeState;
 
-use super::packet::{ManagementResponsePacket, ManagementResponseV2, OldManagementResponse};
+use super::packet::{
+    ManagementResponsePacket, ManagementResponseV2, OldManagementResponse, WireFormat,
+    LENGTH_CHECKED_VERSION,
+};
 
 #[derive(PartialEq, Hash, Eq, Debug, Clone)]
 pub struct RemoteConnMeta {
@@ -49,30 +52,29 @@ async fn process_old_packet(
     state: &web::Data<BridgeState<'_>>,
     data: &[u8],
 ) -> anyhow::Result<ManagementResponseV2> {
-    let packet: OldManagementResponse = unsafe { std::ptr::read(data.as_ptr() as *const _) };
-
-    let map = state.port_discovery.lock().await;
-    for meta in map.iter() {
-        let meta = meta.0;
-        if meta.connection_no == packet.connection_no
-            && meta.connection_uuid == packet.connection_uuid
-        {
-            return Ok(*meta);
-        }
-    }
-
-    Err(Error::msg("Unknown connection"))
+    let packet = OldManagementResponse::from_bytes(data)?;
+
+    state
+        .connections
+        .port_discovery
+        .iter()
+        .map(|entry| *entry.key())
+        .find(|meta| {
+            meta.connection_no == packet.connection_no
+                && meta.connection_uuid == packet.connection_uuid
+        })
+        .ok_or_else(|| Error::msg("Unknown connection"))
 }
 
 async fn unpack_packet(
     state: &web::Data<BridgeState<'_>>,
     data: &[u8],
 ) -> anyhow::Result<ManagementResponseV2> {
-    if data.len() == ::core::mem::size_of::<OldManagementResponse>() {
+    if data.len() == OldManagementResponse::SIZE {
         process_old_packet(state, data).await
-    } else if data.len() == ::core::mem::size_of::<ManagementResponsePacket>() {
-        let packet: ManagementResponsePacket = unsafe { std::ptr::read(data.as_ptr() as *const _) };
-        if packet.header.magic != 0x1234 || packet.header.version != 1 {
+    } else if data.len() == ManagementResponsePacket::SIZE {
+        let packet = ManagementResponsePacket::from_bytes(data)?;
+        if !(1..=LENGTH_CHECKED_VERSION).contains(&packet.header.version) {
             return Err(Error::msg("Not a valid ManagementResponse packet"));
         }
 
@@ -89,11 +91,8 @@ pub async fn try_port_discovery(
 ) -> anyhow::Result<()> {
     let response = unpack_packet(state, data).await?;
 
-    {
-        let mut set = state.port_discovery.lock().await;
-        if set.remove(&response).is_none() {
-            return Err(Error::msg("Connection does not exist"));
-        }
+    if state.connections.port_discovery.remove(&response).is_none() {
+        return Err(Error::msg("Connection does not exist"));
     }
 
     let meta = RemoteConnMeta {
@@ -101,16 +100,18 @@ pub async fn try_port_discovery(
         conn_no: response.connection_no,
     };
 
-    {
-        let mut map = state.undiscovered_clients.lock().await;
-        if let Some(r) = map.remove(&meta) {
-            let mut map = state.web_client_map.lock().await;
-            map.insert(addr, r);
-        }
+    // Firmwares that don't ack the Connect answer it with this packet.
+    if let Some(sock) = state.connections.device(&meta.charger_id) {
+        sock.lock()
+            .await
+            .connection_opened(response.connection_uuid);
     }
 
-    let mut map = state.device_remote_conn_map.lock().await;
-    map.insert(meta, addr);
+    state.connections.connection_discovered(&meta, addr);
+
+    if let Some(relay) = &state.relay {
+        relay.connection_discovered(&meta, addr).await;
+    }
 
     Ok(())
 }
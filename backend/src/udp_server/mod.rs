@@ -17,131 +17,224 @@
  * Boston, MA 02111-1307, USA.
  */
 
+pub mod commands;
 pub mod device;
+pub mod health;
 pub mod management;
 mod multiplex;
 pub mod packet;
 pub mod pcap_logger;
+pub mod peer_index;
+pub mod session_store;
 pub mod socket;
 
-use futures_util::lock::Mutex;
+pub use multiplex::current_charge_log_sends;
+pub(crate) use multiplex::CurrentChargeLogSendsRAII;
+
+use socket2::{Domain, Protocol, Socket, Type};
 use std::{
-    collections::{HashMap, HashSet},
-    net::SocketAddr,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
     sync::Arc,
-    time::{Duration, Instant},
+    time::Duration,
 };
+use tokio::net::UdpSocket;
 
-use self::socket::ManagementSocket;
 use crate::{
-    udp_server::multiplex::run_server, utils::update_charger_state_change, AppState, BridgeState,
-    DiscoveryCharger,
+    connectivity::{record_disconnect, DisconnectReason},
+    registry::DeviceSocket,
+    udp_server::multiplex::run_server,
+    utils::update_charger_state_change,
+    AppState, BridgeState,
 };
 use actix_web::web;
-use ipnetwork::IpNetwork;
-use packet::ManagementResponseV2;
+
+/// Management tunnels that were silent for this long are removed.
+const DEVICE_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often unanswered commands are checked. Finer than the smallest retransmit delay.
+const RETRANSMIT_INTERVAL: Duration = Duration::from_millis(100);
 
 /// Since boringtun doesnt reset the internal ratelimiter for us we need to do it manually.
 /// We can do this with a very low frequency since the management connection
 /// is always one to one and the esps keepalive is two minutes.
 async fn start_rate_limiters_reset_thread(
-    device_map: Arc<Mutex<HashMap<SocketAddr, Arc<Mutex<ManagementSocket<'static>>>>>>,
-    device_map_id: Arc<Mutex<HashMap<uuid::Uuid, Arc<Mutex<ManagementSocket<'static>>>>>>,
-    discovery_map: Arc<Mutex<HashMap<ManagementResponseV2, Instant>>>,
-    undiscovered_devices: Arc<Mutex<HashMap<IpNetwork, HashSet<DiscoveryCharger>>>>,
     state: web::Data<AppState>,
     bridge_state: web::Data<BridgeState<'static>>,
 ) {
+    let connections = &bridge_state.connections;
     loop {
-        {
-            let mut device_map = device_map.lock().await;
-            let mut to_remove = Vec::with_capacity(device_map.len());
-            for (addr, socket) in device_map.iter() {
+        // The sockets are cloned out of the map so no shard is locked while waiting for them.
+        let devices: Vec<(SocketAddr, DeviceSocket<'static>)> = connections
+            .devices_by_addr
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        for (addr, socket) in devices.into_iter() {
+            let id = {
                 let socket = socket.lock().await;
-                if socket.last_seen() > Duration::from_secs(30) {
-                    to_remove.push(addr.to_owned());
+                if socket.last_seen() <= DEVICE_TIMEOUT {
+                    socket.reset_rate_limiter();
                     continue;
                 }
-                socket.reset_rate_limiter();
-            }
-            for addr in to_remove.into_iter() {
-                let socket = device_map.remove(&addr).unwrap();
-                let socket = socket.lock().await;
-                let mut map = device_map_id.lock().await;
-                let (remove, id) = if let Some(c) = map.get(&socket.id()) {
-                    drop(socket);
-                    let c = c.lock().await;
-                    if c.last_seen() > Duration::from_secs(30) {
-                        (true, c.id())
-                    } else {
-                        (false, uuid::Uuid::nil())
-                    }
-                } else {
-                    (false, uuid::Uuid::nil())
-                };
-                if remove {
-                    log::info!("Charger {id} has timeouted and will be removed.");
-                    map.remove(&id);
-                    drop(map);
-                    update_charger_state_change(id, state.clone(), bridge_state.clone()).await;
-                }
-            }
-        }
-        {
-            let mut map = discovery_map.lock().await;
-            let mut to_remove = Vec::with_capacity(map.len());
-            for (cmd, created) in map.iter() {
-                if created.elapsed() > Duration::from_secs(30) {
-                    to_remove.push(cmd.to_owned());
-                }
+                socket.id()
+            };
+            connections
+                .devices_by_addr
+                .remove_if(&addr, |_, s| Arc::ptr_eq(s, &socket));
+
+            // The device might have connected again from another address.
+            let Some(current) = connections.device(&id) else {
+                continue;
+            };
+            let last_seen = current.lock().await.last_seen();
+            if last_seen <= DEVICE_TIMEOUT {
+                continue;
             }
-            for cmd in to_remove.iter() {
-                map.remove(cmd);
+            if connections
+                .devices_by_id
+                .remove_if(&id, |_, s| Arc::ptr_eq(s, &current))
+                .is_none()
+            {
+                continue;
             }
-        }
-        {
-            let mut map = undiscovered_devices.lock().await;
-            let to_remove: Vec<Option<IpNetwork>> = map
-                .iter_mut()
-                .map(|(ip, devices)| {
-                    let to_remove: Vec<Option<DiscoveryCharger>> = devices
-                        .iter()
-                        .map(|device| {
-                            if device.last_request.elapsed() > Duration::from_secs(60) {
-                                Some(device.to_owned())
-                            } else {
-                                None
-                            }
-                        })
-                        .collect();
-                    for c in to_remove.iter().flatten() {
-                        devices.remove(c);
-                    }
-                    if devices.is_empty() {
-                        Some(ip.to_owned())
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-            for ip in to_remove.into_iter().flatten() {
-                map.remove(&ip);
+            log::info!("Charger {id} has timeouted and will be removed.");
+            let silent_since = chrono::Utc::now().naive_utc()
+                - chrono::TimeDelta::from_std(last_seen).unwrap_or_default();
+            record_disconnect(
+                &bridge_state.pool,
+                id,
+                silent_since,
+                DisconnectReason::Timeout,
+            )
+            .await;
+            if let Some(relay) = &bridge_state.relay {
+                relay.release_device(&bridge_state.pool, id).await;
             }
+            update_charger_state_change(id, state.clone(), bridge_state.clone()).await;
         }
+        connections
+            .port_discovery
+            .retain(|_, created| created.elapsed() <= Duration::from_secs(30));
+        connections.undiscovered_devices.retain(|_, devices| {
+            devices.retain(|device| device.last_request.elapsed() <= Duration::from_secs(60));
+            !devices.is_empty()
+        });
+        bridge_state
+            .known_devices
+            .retain(|_, device| device.since.elapsed() < session_store::KNOWN_DEVICE_TIMEOUT);
         tokio::time::sleep(Duration::from_secs(10)).await;
     }
 }
 
+async fn start_retransmit_thread(bridge_state: web::Data<BridgeState<'static>>) {
+    loop {
+        tokio::time::sleep(RETRANSMIT_INTERVAL).await;
+        let devices: Vec<DeviceSocket<'static>> = bridge_state
+            .connections
+            .devices_by_id
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        for socket in devices {
+            socket.lock().await.retransmit();
+        }
+    }
+}
+
+async fn start_ping_thread(bridge_state: web::Data<BridgeState<'static>>) {
+    loop {
+        tokio::time::sleep(health::PING_INTERVAL).await;
+        send_pings(&bridge_state).await;
+    }
+}
+
+/// Pings every connected device whose firmware announced that it answers pings.
+async fn send_pings(bridge_state: &BridgeState<'_>) {
+    let devices: Vec<DeviceSocket<'_>> = bridge_state
+        .connections
+        .devices_by_id
+        .iter()
+        .map(|entry| entry.value().clone())
+        .collect();
+    for socket in devices {
+        let mut socket = socket.lock().await;
+        if socket.answers_pings() {
+            socket.send_ping();
+        }
+    }
+}
+
+/// Binds the management socket. The unspecified IPv6 address listens on IPv4 as well
+/// and falls back to IPv4 only if the host has no IPv6 support.
+pub fn bind_socket(addr: SocketAddr) -> std::io::Result<UdpSocket> {
+    let socket = match Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP)) {
+        Ok(socket) => {
+            if addr.is_ipv6() {
+                socket.set_only_v6(false)?;
+            }
+            socket.bind(&addr.into())?;
+            socket
+        }
+        Err(err) if addr.ip() == Ipv6Addr::UNSPECIFIED => {
+            log::warn!("Failed to create IPv6 socket, only listening on IPv4: {err}");
+            let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+            let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, addr.port()));
+            socket.bind(&addr.into())?;
+            socket
+        }
+        Err(err) => return Err(err),
+    };
+    socket.set_nonblocking(true)?;
+
+    UdpSocket::from_std(socket.into())
+}
+
 pub fn start_server(bridge_state: web::Data<BridgeState<'static>>, app_state: web::Data<AppState>) {
     log::info!("Starting Wireguard server.");
     actix::spawn(start_rate_limiters_reset_thread(
-        bridge_state.device_management_map.clone(),
-        bridge_state.device_management_map_with_id.clone(),
-        bridge_state.port_discovery.clone(),
-        bridge_state.undiscovered_devices.clone(),
         app_state.clone(),
         bridge_state.clone(),
     ));
 
+    // Building the index derives the public key of every charger, so it is done in the background.
+    bridge_state
+        .peer_index
+        .refresh_in_background(bridge_state.pool.clone());
+
+    actix::spawn(start_retransmit_thread(bridge_state.clone()));
+    actix::spawn(start_ping_thread(bridge_state.clone()));
     actix::spawn(run_server(bridge_state, app_state));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_bridge_state;
+    use crate::udp_server::socket::ManagementSocket;
+
+    #[actix_web::test]
+    async fn test_only_ping_versions_that_answer() {
+        let bridge_state = create_test_bridge_state(None);
+        let old_id = uuid::Uuid::new_v4();
+        let new_id = uuid::Uuid::new_v4();
+        for (id, addr, version) in [
+            (old_id, "123.123.123.123:12345", 1),
+            (
+                new_id,
+                "123.123.123.124:12345",
+                packet::LENGTH_CHECKED_VERSION,
+            ),
+        ] {
+            let addr = addr.parse().unwrap();
+            let mut socket = ManagementSocket::new_for_test(id, addr).await;
+            socket.version_announced(version);
+            bridge_state.connections.insert_device(addr, id, socket);
+        }
+
+        send_pings(&bridge_state).await;
+
+        let old = bridge_state.connections.device(&old_id).unwrap();
+        assert!(!old.lock().await.pong_received(1));
+        let new = bridge_state.connections.device(&new_id).unwrap();
+        assert!(new.lock().await.pong_received(1));
+    }
+}
@@ -18,12 +18,11 @@
  */
 
 use std::{
-    collections::hash_map::Entry,
     io::{BufWriter, Write},
     net::{IpAddr, SocketAddr},
     sync::{atomic::AtomicUsize, Arc},
 };
-use tokio::net::UdpSocket;
+use tokio::{net::UdpSocket, sync::Semaphore};
 
 use actix_web::web::{self, Bytes};
 use base64::prelude::*;
@@ -31,18 +30,19 @@ use boringtun::noise::{rate_limiter::RateLimiter, TunnResult};
 use db_connector::models::chargers::Charger;
 use diesel::prelude::*;
 use futures_util::lock::Mutex;
-use ipnetwork::{IpNetwork, Ipv4Network};
+use ipnetwork::IpNetwork;
 use rand_core::{OsRng, TryRngCore};
 
 use crate::{
+    connectivity::record_connect,
+    logging::{self, LogContext},
     rate_limit::GlobalSearchRateLimiter,
     routes::{charger::user_is_allowed, send_chargelog_to_user::send_charge_log_to_user},
     udp_server::{
-        management::RemoteConnMeta,
+        commands::CommandOutcome,
         packet::{
-            extract_management_packet_header, AckPacket, ChargeLogSendMetadata,
-            ChargeLogSendMetadataPacket, ManagementPacket, NackPacket, NackReason, PacketType,
-            RequestChargeLogSendPacket,
+            AckPacket, ChargeLogSendMetadata, DevicePacket, ManagementPacket,
+            ManagementPacketHeader, NackPacket, NackReason, PacketError, LENGTH_CHECKED_VERSION,
         },
     },
     utils::{
@@ -55,15 +55,25 @@ use crate::{
 
 use super::{
     management::try_port_discovery,
+    peer_index::is_handshake_initiation,
     socket::{ManagementSocket, ManagementSocketTCPReceiver, TCPRecvResult},
 };
 
+/// Maximum number of received packets that are processed at the same time.
+const MAX_PACKET_TASKS: usize = 4096;
+
 static CURRENT_CHARGE_LOG_SENDS: AtomicUsize = AtomicUsize::new(0);
 
-struct CurrentChargeLogSendsRAII;
+/// Number of charge logs that are currently being sent.
+pub fn current_charge_log_sends() -> usize {
+    CURRENT_CHARGE_LOG_SENDS.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Counts a charge log send while it is alive.
+pub(crate) struct CurrentChargeLogSendsRAII;
 
 impl CurrentChargeLogSendsRAII {
-    fn new() -> anyhow::Result<Self> {
+    pub(crate) fn new() -> anyhow::Result<Self> {
         match CURRENT_CHARGE_LOG_SENDS.fetch_update(
             std::sync::atomic::Ordering::SeqCst,
             std::sync::atomic::Ordering::SeqCst,
@@ -110,75 +120,127 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+/// Prefix length that devices behind the same IPv4 NAT usually share.
+const SITE_PREFIX_V4: u8 = 24;
+/// Prefix length that is usually delegated to a single IPv6 site.
+const SITE_PREFIX_V6: u8 = 64;
+
+/// Returns the network a device at this address most likely shares with the other devices of its site.
+fn site_network(ip: IpAddr) -> anyhow::Result<IpNetwork> {
+    let prefix = match ip {
+        IpAddr::V4(_) => SITE_PREFIX_V4,
+        IpAddr::V6(_) => SITE_PREFIX_V6,
+    };
+
+    Ok(IpNetwork::new(ip, prefix)?)
+}
+
 async fn create_tunn<'a>(
     state: &web::Data<BridgeState<'_>>,
     addr: SocketAddr,
     data: &[u8],
     rate_limiter: Arc<GlobalSearchRateLimiter>,
+    forward_host: Option<IpAddr>,
 ) -> anyhow::Result<(uuid::Uuid, ManagementSocket<'a>)> {
     use db_connector::schema::chargers::dsl as chargers;
 
     let mut conn = state.pool.get()?;
 
-    let ip = IpNetwork::new(addr.ip(), 32)?;
+    // Devices that were connected before a restart are tried first so they dont need to be searched for.
+    let known_device = state
+        .known_devices
+        .get(&addr)
+        .map(|d| (d.charger_id, d.out_sequence));
+    if let Some((device_id, out_sequence)) = known_device {
+        let devices: Vec<Charger> = chargers::chargers
+            .filter(chargers::id.eq(device_id))
+            .select(Charger::as_select())
+            .load(&mut conn)?;
+        if let Some((id, mut socket)) = try_devices(state, addr, data, devices)? {
+            socket.set_out_sequence(out_sequence);
+            state.known_devices.remove(&addr);
+            return Ok((id, socket));
+        }
+    }
 
+    // IPv4 peers show up as mapped addresses on the dual stack socket.
+    let remote_ip = addr.ip().to_canonical();
+    let ip = IpNetwork::from(remote_ip);
+
+    let undiscovered_devices = &state.connections.undiscovered_devices;
+    let exact_match: Option<Vec<uuid::Uuid>> = undiscovered_devices
+        .get(&ip)
+        .map(|set| set.iter().map(|c| c.id).collect());
     let devices: Vec<Charger> = {
-        let map = state.undiscovered_devices.lock().await;
-        if let Some(set) = map.get(&ip) {
-            let device_ids: Vec<uuid::Uuid> = set.iter().map(|c| c.id).collect();
+        if let Some(device_ids) = exact_match {
             chargers::chargers
                 .filter(chargers::id.eq_any(device_ids))
                 .select(Charger::as_select())
                 .load(&mut conn)?
         } else {
-            let IpNetwork::V4(ip) = ip else {
-                return Err(anyhow::Error::msg(Error::UnknownPeer));
-            };
-            let subnet = Ipv4Network::new(ip.ip(), 24)?;
-            let matching_entries = map
+            let subnet = site_network(remote_ip)?;
+            let device_ids: Vec<uuid::Uuid> = undiscovered_devices
                 .iter()
-                .filter(|(network, _)| {
-                    let ipv4_network = match *network {
-                        IpNetwork::V4(ipv4) => ipv4,
-                        _ => return false,
-                    };
-                    subnet.contains(ipv4_network.ip())
-                })
-                .collect::<Vec<_>>();
-            if !matching_entries.is_empty() {
-                let device_ids: Vec<uuid::Uuid> = matching_entries
-                    .iter()
-                    .flat_map(|(_, devices)| devices.iter().map(|d| d.id))
-                    .collect();
+                .filter(|entry| subnet.contains(entry.key().ip()))
+                .flat_map(|entry| entry.value().iter().map(|d| d.id).collect::<Vec<_>>())
+                .collect();
+            if !device_ids.is_empty() {
                 log::info!("Found possible matches for ip '{subnet}: {device_ids:?}'");
                 chargers::chargers
                     .filter(chargers::id.eq_any(device_ids))
                     .select(Charger::as_select())
                     .load(&mut conn)?
-            } else if Ipv4Network::new(std::env::var("FORWARD_HOST")?.parse()?, 32)? == ip {
+            } else if forward_host == Some(remote_ip) {
                 log::info!("Found forwarded management connection");
-                let mut device_ids: Vec<uuid::Uuid> = Vec::new();
-                for devices in map.iter() {
-                    for device in devices.1.iter() {
-                        device_ids.push(device.id);
-                    }
-                }
+                let device_ids: Vec<uuid::Uuid> = undiscovered_devices
+                    .iter()
+                    .flat_map(|entry| entry.value().iter().map(|d| d.id).collect::<Vec<_>>())
+                    .collect();
                 chargers::chargers
                     .filter(chargers::id.eq_any(device_ids))
                     .select(Charger::as_select())
                     .load(&mut conn)?
             } else {
-                if rate_limiter.check(addr).is_err() {
-                    log::warn!("Rate limit exceeded for unknown peer with ip '{ip}'");
+                // Only a handshake initiation can start a new connection and its MAC1 tells us
+                // which charger it is meant for.
+                if !is_handshake_initiation(data) {
+                    return Err(anyhow::Error::msg(Error::UnknownPeer));
+                }
+                let device_ids = state.peer_index.lookup(data, remote_ip);
+                if device_ids.is_empty() {
+                    // The charger might have been added since the index was last refreshed.
+                    // It sends its handshake again in a few seconds, when the index is up to date.
+                    if rate_limiter.check(addr).is_err() {
+                        log::warn!("Rate limit exceeded for unknown peer with ip '{ip}'");
+                    } else {
+                        state.peer_index.refresh_in_background(state.pool.clone());
+                    }
                     return Err(anyhow::Error::msg(Error::UnknownPeer));
                 }
                 chargers::chargers
+                    .filter(chargers::id.eq_any(device_ids))
                     .select(Charger::as_select())
                     .load(&mut conn)?
             }
         }
     };
 
+    match try_devices(state, addr, data, devices)? {
+        Some(device) => {
+            state.peer_index.connected_from(device.0, remote_ip);
+            Ok(device)
+        }
+        None => Err(anyhow::Error::new(Error::UnknownPeer)),
+    }
+}
+
+/// Tries to start a handshake with every device in the list and returns the first that matches.
+fn try_devices<'a>(
+    state: &web::Data<BridgeState<'_>>,
+    addr: SocketAddr,
+    data: &[u8],
+    devices: Vec<Charger>,
+) -> anyhow::Result<Option<(uuid::Uuid, ManagementSocket<'a>)>> {
     let mut dst = vec![0u8; data.len()];
     for device in devices.into_iter() {
         let static_private: [u8; 32] = match BASE64_STANDARD
@@ -232,21 +294,13 @@ async fn create_tunn<'a>(
             _ => continue,
         }
 
-        let self_ip = if let IpAddr::V4(ip) = device.wg_server_ip.ip() {
-            ip
-        } else {
+        let self_ip = device.wg_server_ip.ip();
+        let peer_ip = device.wg_charger_ip.ip();
+        if self_ip.is_ipv4() != peer_ip.is_ipv4() {
             return Err(anyhow::Error::msg(
-                "Somehow a IPv6 address got into the database",
+                "Somehow the tunnel addresses of a charger have different address families",
             ));
-        };
-
-        let peer_ip = if let IpAddr::V4(ip) = device.wg_charger_ip.ip() {
-            ip
-        } else {
-            return Err(anyhow::Error::msg(
-                "Somehow a IPv6 address got into the database",
-            ));
-        };
+        }
 
         let udp_socket = Arc::clone(&state.socket);
         let socket = ManagementSocket::new(
@@ -277,10 +331,10 @@ async fn create_tunn<'a>(
             }
         }
 
-        return Ok((device.id, socket));
+        return Ok(Some((device.id, socket)));
     }
 
-    Err(anyhow::Error::new(Error::UnknownPeer))
+    Ok(None)
 }
 
 pub fn send_data(socket: &UdpSocket, addr: SocketAddr, data: &[u8]) {
@@ -297,6 +351,7 @@ pub fn send_data(socket: &UdpSocket, addr: SocketAddr, data: &[u8]) {
 }
 
 async fn handle_charge_log<'a>(
+    request_seq: u16,
     meta_data: ChargeLogSendMetadata,
     tunn_sock: Arc<Mutex<ManagementSocket<'a>>>,
     app_state: web::Data<AppState>,
@@ -305,7 +360,7 @@ async fn handle_charge_log<'a>(
     let ack_packet = ManagementPacket::AckPacket(AckPacket::new());
     {
         let mut tun_sock = tunn_sock.lock().await;
-        tun_sock.send_packet(ack_packet);
+        tun_sock.send_reply(request_seq, ack_packet);
     }
 
     let mut buf = BufWriter::new(Vec::with_capacity(10 * 1024 * 1024));
@@ -358,15 +413,22 @@ pub async fn run_server(
 ) {
     let mut buf = vec![0u8; 65535];
     let rate_limiter = Arc::new(GlobalSearchRateLimiter::new());
+    let packet_tasks = Arc::new(Semaphore::new(MAX_PACKET_TASKS));
 
     loop {
         let rate_limiter = Arc::clone(&rate_limiter);
+        // Once the limit is reached packets queue up in the socket buffer instead of in memory.
+        let Ok(permit) = packet_tasks.clone().acquire_owned().await else {
+            return;
+        };
         if let Ok((s, addr)) = bridge_state.socket.recv_from(&mut buf).await {
             let bridge_state = bridge_state.clone();
             let app_state = app_state.clone();
-            let buf = buf.clone();
+            let buf = buf[..s].to_vec();
+
+            actix::spawn(logging::scope(LogContext::default(), async move {
+                let _permit = permit;
 
-            actix::spawn(async move {
                 // Check if the packet is for port discovery
                 if try_port_discovery(&bridge_state, &buf[..s], addr)
                     .await
@@ -376,62 +438,58 @@ pub async fn run_server(
                 }
 
                 // Check if we need to relay the packet
-                {
-                    let mut client_map = bridge_state.web_client_map.lock().await;
-                    if let Some(client) = client_map.get_mut(&addr) {
-                        let payload = Bytes::copy_from_slice(&buf[0..s]);
-                        client.binary(payload).await.ok();
+                if let Some(client) = bridge_state.connections.web_clients.get(&addr) {
+                    // Dropped datagrams are resent by the tunneled TCP connection, which slows it down.
+                    if client.traffic.add_from_device(s) {
+                        client.send(Bytes::copy_from_slice(&buf[0..s]));
+                    }
+                    return;
+                }
+
+                // The browser might be connected to another instance
+                if let Some(relay) = &bridge_state.relay {
+                    if relay.forward_to_client(addr, &buf[..s]).await {
                         return;
                     }
                 }
 
                 // Get the management socket or create a new one when it does not exist
-                let tunn_sock = {
-                    // Maybe we could release the lock when adding a new management connection and get it back later
-                    // in case it turns out that holding it causes major connection issues.
-                    let mut charger_map = bridge_state.device_management_map.lock().await;
-
-                    match charger_map.entry(addr) {
-                        Entry::Occupied(tunn) => tunn.into_mut().clone(),
-                        Entry::Vacant(v) => {
-                            let (id, tunn_data) =
-                                match create_tunn(&bridge_state, addr, &buf[..s], rate_limiter)
-                                    .await
-                                {
-                                    Ok(tunn) => tunn,
-                                    Err(_err) => {
-                                        return;
-                                    }
-                                };
-
-                            let tunn_data = Arc::new(Mutex::new(tunn_data));
-                            {
-                                let mut map =
-                                    bridge_state.device_management_map_with_id.lock().await;
-                                map.insert(id, tunn_data.clone());
-                                v.insert(tunn_data.clone());
-                                let tunn = tunn_data.clone();
-                                let mut lost_map = bridge_state.lost_connections.lock().await;
-                                let mut undiscovered_clients =
-                                    bridge_state.undiscovered_clients.lock().await;
-                                if let Some(conns) = lost_map.remove(&id) {
-                                    for (conn_no, recipient) in conns.into_iter() {
-                                        let meta = RemoteConnMeta {
-                                            charger_id: id,
-                                            conn_no,
-                                        };
-                                        undiscovered_clients.insert(meta, recipient);
-
-                                        open_connection(
-                                            conn_no,
-                                            id,
-                                            tunn.clone(),
-                                            bridge_state.port_discovery.clone(),
-                                        )
-                                        .await
-                                        .ok();
-                                    }
-                                }
+                let tunn_sock = match bridge_state.connections.device_by_addr(&addr) {
+                    Some(tunn) => tunn,
+                    None => {
+                        // No map is locked while the handshake is checked against the candidates,
+                        // so a second packet from the same address may race us here.
+                        let (id, tunn_data) = match create_tunn(
+                            &bridge_state,
+                            addr,
+                            &buf[..s],
+                            rate_limiter,
+                            app_state.config.forward_host,
+                        )
+                        .await
+                        {
+                            Ok(tunn) => tunn,
+                            Err(_err) => {
+                                return;
+                            }
+                        };
+
+                        let replaced = bridge_state.connections.device(&id).is_some();
+                        let (tunn_data, inserted) =
+                            bridge_state.connections.insert_device(addr, id, tunn_data);
+                        if inserted {
+                            record_connect(&bridge_state.pool, id, replaced).await;
+                            for conn_no in bridge_state.connections.reconnect_lost(id) {
+                                let _ = open_connection(
+                                    conn_no,
+                                    id,
+                                    tunn_data.clone(),
+                                    &bridge_state.connections.port_discovery,
+                                )
+                                .await;
+                            }
+                            if let Some(relay) = &bridge_state.relay {
+                                relay.claim_device(&bridge_state.pool, id).await;
                             }
                             update_charger_state_change(
                                 id,
@@ -439,8 +497,8 @@ pub async fn run_server(
                                 bridge_state.clone(),
                             )
                             .await;
-                            tunn_data.clone()
                         }
+                        tunn_data
                     }
                 };
 
@@ -457,65 +515,130 @@ pub async fn run_server(
                     }
                 };
 
-                let Ok(header) = extract_management_packet_header(&data, id) else {
-                    return;
+                logging::update(|c| c.charger_id = Some(id));
+
+                let packet = match DevicePacket::decode(&data) {
+                    Ok(packet) => packet,
+                    Err(PacketError::UnknownPacketType(p_type)) => {
+                        log::debug!("Received unknown management packet type {p_type:02x} from charger with id '{id}'");
+                        let Some(seq_number) = ManagementPacketHeader::peek_seq_number(&data)
+                        else {
+                            return;
+                        };
+                        let mut tun_sock = tunn_sock.lock().await;
+                        tun_sock.send_reply(
+                            seq_number,
+                            ManagementPacket::NackPacket(NackPacket::new(
+                                NackReason::UnknownPacketType,
+                            )),
+                        );
+                        return;
+                    }
+                    Err(err) => {
+                        log::error!(
+                            "Failed to parse management packet from charger with id '{id}': {err}"
+                        );
+                        return;
+                    }
                 };
 
-                match header.p_type {
-                    // Charge log send metadata packet
-                    PacketType::MetadataForChargeLog => {
-                        if let Ok(meta_data) =
-                            ChargeLogSendMetadataPacket::try_from(data.as_slice())
-                        {
-                            let user_uuid = uuid::Uuid::from_u128(meta_data.data.user_uuid);
-                            let sender = {
-                                let mut tun_sock = tunn_sock.lock().await;
-                                tun_sock.take_sender()
-                            };
+                // Version 2 firmwares send requests again until they are answered. Requests that
+                // were already received are not started twice, they get the same answer again or
+                // none while they are still being handled.
+                let header = *packet.header();
+                let is_answer = matches!(
+                    packet,
+                    DevicePacket::Ack(_)
+                        | DevicePacket::Nack(_)
+                        | DevicePacket::Pong(_)
+                        | DevicePacket::Status(_)
+                );
+                {
+                    let mut tun_sock = tunn_sock.lock().await;
+                    tun_sock.version_announced(header.version);
+                    if !is_answer
+                        && header.version >= LENGTH_CHECKED_VERSION
+                        && !tun_sock.first_receipt(header.seq_number)
+                    {
+                        if tun_sock.resend_reply(header.seq_number) {
+                            log::debug!(
+                                "Answered retransmitted packet {} again",
+                                header.seq_number
+                            );
+                        } else {
+                            log::debug!("Ignoring retransmitted packet {}", header.seq_number);
+                        }
+                        return;
+                    }
+                }
 
-                            // Check if the user is allowed to access this charger
-                            if let Err(e) = user_is_allowed(&app_state, user_uuid, id).await {
-                                log::error!(
-                                    "Failed to check if user '{}' is allowed to access charger '{}': {:?}",
-                                    user_uuid,
-                                    id,
-                                    e
-                                );
-                                let mut tun_sock = tunn_sock.lock().await;
-                                let nack_packet = ManagementPacket::NackPacket(NackPacket::new(
-                                    NackReason::Unauthorized,
-                                ));
-                                tun_sock.send_packet(nack_packet);
-                                return;
-                            }
+                match packet {
+                    // Charge log send metadata packet
+                    DevicePacket::MetadataForChargeLog(meta_data) => {
+                        let user_uuid = uuid::Uuid::from_u128(meta_data.data.user_uuid);
+                        logging::update(|c| c.user_id = Some(user_uuid));
+                        let sender = {
+                            let mut tun_sock = tunn_sock.lock().await;
+                            tun_sock.take_sender()
+                        };
 
+                        // Check if the user is allowed to access this charger
+                        if let Err(e) = user_is_allowed(&app_state, user_uuid, id).await {
+                            log::error!(
+                                "Failed to check if user '{}' is allowed to access charger '{}': {:?}",
+                                user_uuid,
+                                id,
+                                e
+                            );
                             let mut tun_sock = tunn_sock.lock().await;
-                            if let Some(sender) = sender {
-                                if sender.send(meta_data.data).is_err() {
-                                    log::error!(
-                                        "Failed to send charge log send trigger for charger '{}' to TCP socket",
-                                        id
-                                    );
-                                    let nack_packet = ManagementPacket::NackPacket(
-                                        NackPacket::new(NackReason::InternalError),
-                                    );
-                                    tun_sock.send_packet(nack_packet);
-                                }
-                            } else {
+                            let nack_packet = ManagementPacket::NackPacket(NackPacket::new(
+                                NackReason::Unauthorized,
+                            ));
+                            tun_sock.send_reply(header.seq_number, nack_packet);
+                            return;
+                        }
+
+                        let mut tun_sock = tunn_sock.lock().await;
+                        if let Some(sender) = sender {
+                            if sender.send(meta_data.data).is_err() {
                                 log::error!(
-                                    "Failed to get sender for charge log send request for charger '{}'",
+                                    "Failed to send charge log send trigger for charger '{}' to TCP socket",
                                     id
                                 );
                                 let nack_packet = ManagementPacket::NackPacket(NackPacket::new(
                                     NackReason::InternalError,
                                 ));
-                                tun_sock.send_packet(nack_packet);
+                                tun_sock.send_reply(header.seq_number, nack_packet);
                             }
-                            tun_sock.send_packet(ManagementPacket::AckPacket(AckPacket::new()));
+                        } else {
+                            log::error!(
+                                "Failed to get sender for charge log send request for charger '{}'",
+                                id
+                            );
+                            let nack_packet = ManagementPacket::NackPacket(NackPacket::new(
+                                NackReason::InternalError,
+                            ));
+                            tun_sock.send_reply(header.seq_number, nack_packet);
                         }
+                        tun_sock.send_reply(
+                            header.seq_number,
+                            ManagementPacket::AckPacket(AckPacket::new()),
+                        );
                     }
                     // Charge log send request
-                    PacketType::RequestChargeLogSend => {
+                    DevicePacket::RequestChargeLogSend(packet) => {
+                        // Uploads that are already running may finish during shutdown but no new ones are started.
+                        if bridge_state
+                            .shutting_down
+                            .load(std::sync::atomic::Ordering::SeqCst)
+                        {
+                            let mut tun_sock = tunn_sock.lock().await;
+                            let nack_packet =
+                                ManagementPacket::NackPacket(NackPacket::new(NackReason::Busy));
+                            tun_sock.send_reply(header.seq_number, nack_packet);
+                            return;
+                        }
+
                         // Check rate limit first
                         let device_id_str = id.to_string();
                         let ip_str = addr.ip().to_string();
@@ -528,16 +651,10 @@ pub async fn run_server(
                             let nack_packet = ManagementPacket::NackPacket(NackPacket::new(
                                 NackReason::ToManyRequests,
                             ));
-                            tun_sock.send_packet(nack_packet);
+                            tun_sock.send_reply(header.seq_number, nack_packet);
                             return;
                         }
 
-                        let Ok(packet) = RequestChargeLogSendPacket::try_from(data.as_slice())
-                        else {
-                            log::error!("Failed to parse charge log send request packet from charger with id '{}'", id);
-                            return;
-                        };
-
                         let Ok(last_charge_log_upload_hashes) =
                             get_last_charge_log_upload_hash(id, &app_state).await
                         else {
@@ -546,7 +663,7 @@ pub async fn run_server(
                             let nack_packet = ManagementPacket::NackPacket(NackPacket::new(
                                 NackReason::InternalError,
                             ));
-                            tun_sock.send_packet(nack_packet);
+                            tun_sock.send_reply(header.seq_number, nack_packet);
                             return;
                         };
 
@@ -564,7 +681,7 @@ pub async fn run_server(
                             let nack_packet = ManagementPacket::NackPacket(NackPacket::new(
                                 NackReason::AlreadySent,
                             ));
-                            tun_sock.send_packet(nack_packet);
+                            tun_sock.send_reply(header.seq_number, nack_packet);
                             return;
                         }
 
@@ -575,7 +692,7 @@ pub async fn run_server(
                                 let nack_packet = ManagementPacket::NackPacket(NackPacket::new(
                                     NackReason::OngoingRequest,
                                 ));
-                                tunn_sock.send_packet(nack_packet);
+                                tunn_sock.send_reply(header.seq_number, nack_packet);
                                 return;
                             }
                         }
@@ -585,7 +702,7 @@ pub async fn run_server(
                             let mut tun_sock = tunn_sock.lock().await;
                             let nack_packet =
                                 ManagementPacket::NackPacket(NackPacket::new(NackReason::Busy));
-                            tun_sock.send_packet(nack_packet);
+                            tun_sock.send_reply(header.seq_number, nack_packet);
                             return;
                         };
                         let (sender, receiver) = tokio::sync::oneshot::channel();
@@ -597,7 +714,7 @@ pub async fn run_server(
                         {
                             let mut tun_sock = tunn_sock.lock().await;
                             let ack_packet = ManagementPacket::AckPacket(AckPacket::new());
-                            tun_sock.send_packet(ack_packet);
+                            tun_sock.send_reply(header.seq_number, ack_packet);
                         }
 
                         let meta_data = tokio::select! {
@@ -612,7 +729,7 @@ pub async fn run_server(
                             },
                             _ = tokio::time::sleep(std::time::Duration::from_secs(30)) => {
                                 let mut tun_sock = tunn_sock.lock().await;
-                                tun_sock.send_packet(ManagementPacket::NackPacket(NackPacket::new(NackReason::Timeout)));
+                                tun_sock.send_reply(header.seq_number, ManagementPacket::NackPacket(NackPacket::new(NackReason::Timeout)));
 
                                 log::error!("Did not receive trigger for charge log send from charger with id '{}' within timeout", id);
                                 return;
@@ -620,8 +737,13 @@ pub async fn run_server(
                         };
 
                         let is_monthly_email = meta_data.is_monthly_email;
-                        match handle_charge_log(meta_data, tunn_sock.clone(), app_state.clone())
-                            .await
+                        match handle_charge_log(
+                            header.seq_number,
+                            meta_data,
+                            tunn_sock.clone(),
+                            app_state.clone(),
+                        )
+                        .await
                         {
                             Ok(_) => {
                                 if is_monthly_email {
@@ -637,7 +759,7 @@ pub async fn run_server(
                                 }
                                 let mut tunn_sock = tunn_sock.lock().await;
                                 let ack_packet = ManagementPacket::AckPacket(AckPacket::new());
-                                tunn_sock.send_packet(ack_packet);
+                                tunn_sock.send_reply(header.seq_number, ack_packet);
                             }
                             Err(e) => {
                                 log::error!("Failed to handle charge log: {:?}", e);
@@ -645,15 +767,54 @@ pub async fn run_server(
                                 let nack_packet = ManagementPacket::NackPacket(NackPacket::new(
                                     NackReason::Timeout,
                                 ));
-                                tunn_sock.send_packet(nack_packet);
+                                tunn_sock.send_reply(header.seq_number, nack_packet);
                             }
                         }
                     }
-                    _ => {
-                        log::error!("Received unknown management packet type {:02x} from charger with id '{}'", header.p_type as u8, id);
+                    // Devices do not expect an answer to these.
+                    DevicePacket::Ack(ack) => {
+                        let mut tun_sock = tunn_sock.lock().await;
+                        tun_sock.command_answered(ack.header.seq_number, CommandOutcome::Acked);
+                    }
+                    DevicePacket::Status(status) => {
+                        let mut tun_sock = tunn_sock.lock().await;
+                        tun_sock.command_answered(
+                            status.header.seq_number,
+                            CommandOutcome::Status(status.status),
+                        );
+                    }
+                    DevicePacket::Pong(pong) => {
+                        let mut tun_sock = tunn_sock.lock().await;
+                        tun_sock.pong_received(pong.header.seq_number);
+                    }
+                    DevicePacket::Nack(nack) => {
+                        log::debug!("Charger refused command: {:?}", nack.reason);
+                        let mut tun_sock = tunn_sock.lock().await;
+                        tun_sock.command_answered(
+                            nack.header.seq_number,
+                            CommandOutcome::Nacked(nack.reason),
+                        );
                     }
                 }
-            });
+            }));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_site_network() {
+        let v4 = site_network("192.168.1.20".parse().unwrap()).unwrap();
+        assert!(v4.contains("192.168.1.200".parse().unwrap()));
+        assert!(!v4.contains("192.168.2.20".parse().unwrap()));
+        assert!(!v4.contains("::ffff:192.168.1.20".parse().unwrap()));
+
+        let v6 = site_network("2001:db8:1:2::10".parse().unwrap()).unwrap();
+        assert!(v6.contains("2001:db8:1:2:abcd::1".parse().unwrap()));
+        assert!(!v6.contains("2001:db8:1:3::10".parse().unwrap()));
+        assert!(!v6.contains("192.168.1.20".parse().unwrap()));
+    }
+}
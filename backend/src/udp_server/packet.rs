@@ -1,6 +1,120 @@
-use serde::Serialize;
+//! Packets of the management protocol.
+//!
+//! All fields are little endian, except for the user uuid of the charge log metadata.
+//! Every packet starts with a [`ManagementPacketHeader`]. Devices that speak version 2 of the
+//! protocol set its `length` to the size of the payload and packets where it does not match are
+//! rejected. Version 1 firmwares did not fill it consistently, so it is ignored for them.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+pub const MANAGEMENT_MAGIC: u16 = 0x1234;
+/// Version of the packets sent by the server.
+pub const PROTOCOL_VERSION: u8 = 1;
+/// First version where `length` is checked against the payload.
+pub const LENGTH_CHECKED_VERSION: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketError {
+    TooShort,
+    InvalidMagic(u16),
+    /// The packet type is unknown or not one the server handles.
+    UnknownPacketType(u8),
+    LengthMismatch {
+        length: u16,
+        payload: usize,
+    },
+    InvalidValue(&'static str),
+}
+
+impl std::fmt::Display for PacketError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort => write!(f, "Packet too short"),
+            Self::InvalidMagic(magic) => write!(
+                f,
+                "Invalid magic number: expected 0x{MANAGEMENT_MAGIC:04x}, got 0x{magic:04x}"
+            ),
+            Self::UnknownPacketType(p_type) => write!(f, "Invalid packet type: {p_type}"),
+            Self::LengthMismatch { length, payload } => write!(
+                f,
+                "Packet length {length} does not match the payload of {payload} bytes"
+            ),
+            Self::InvalidValue(field) => write!(f, "Invalid value for {field}"),
+        }
+    }
+}
+
+impl std::error::Error for PacketError {}
+
+/// Reads the fields of a packet one after another without ever reading past its end.
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], PacketError> {
+        if self.data.len() < len {
+            return Err(PacketError::TooShort);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
 
-use crate::utils::as_u8_slice;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], PacketError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, PacketError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, PacketError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, PacketError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, PacketError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u128(&mut self) -> Result<u128, PacketError> {
+        Ok(u128::from_le_bytes(self.array()?))
+    }
+}
+
+/// Encoding of a packet or a part of it on the wire.
+pub trait WireFormat: Sized {
+    fn write(&self, out: &mut Vec<u8>);
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError>;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out);
+        out
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self, PacketError> {
+        Self::read(&mut Reader::new(data))
+    }
+}
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
@@ -10,6 +124,9 @@ pub enum PacketType {
     Nack = 0x02,
     MetadataForChargeLog = 0x03,
     RequestChargeLogSend = 0x04,
+    Ping = 0x05,
+    Pong = 0x06,
+    Status = 0x07,
 }
 
 impl TryFrom<u8> for PacketType {
@@ -22,30 +139,82 @@ impl TryFrom<u8> for PacketType {
             0x02 => Ok(PacketType::Nack),
             0x03 => Ok(PacketType::MetadataForChargeLog),
             0x04 => Ok(PacketType::RequestChargeLogSend),
+            0x05 => Ok(PacketType::Ping),
+            0x06 => Ok(PacketType::Pong),
+            0x07 => Ok(PacketType::Status),
             _ => Err(anyhow::anyhow!("Invalid packet type: {}", value)),
         }
     }
 }
 
-#[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ManagementCommandId {
-    Connect,
-    Disconnect,
+    Connect = 0,
+    Disconnect = 1,
+    Reboot = 2,
+    /// Check in at `/api/management` right away instead of waiting for the next interval.
+    CheckIn = 3,
+    /// Generate a new set of WireGuard keys and register them.
+    RotateKeys = 4,
+    /// Answered with a [`StatusPacket`] instead of an Ack.
+    GetStatus = 5,
 }
 
-#[repr(C, packed)]
-#[derive(Debug, Clone, Copy)]
+impl ManagementCommandId {
+    /// Connect and Disconnect refer to a connection of the device, the other commands
+    /// to the device itself.
+    pub fn targets_connection(&self) -> bool {
+        matches!(self, Self::Connect | Self::Disconnect)
+    }
+}
+
+impl TryFrom<u32> for ManagementCommandId {
+    type Error = PacketError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Connect),
+            1 => Ok(Self::Disconnect),
+            2 => Ok(Self::Reboot),
+            3 => Ok(Self::CheckIn),
+            4 => Ok(Self::RotateKeys),
+            5 => Ok(Self::GetStatus),
+            _ => Err(PacketError::InvalidValue("command_id")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ManagementCommand {
     pub command_id: ManagementCommandId,
 
-    // Ignored for SendChargeLog command
+    // Ignored for commands that don't target a connection
     pub connection_no: i32,
     pub connection_uuid: u128,
 }
 
-#[repr(C, packed)]
-#[derive(Debug, Clone, Copy)]
+impl ManagementCommand {
+    pub const SIZE: usize = 24;
+}
+
+impl WireFormat for ManagementCommand {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.command_id as u32).to_le_bytes());
+        out.extend_from_slice(&self.connection_no.to_le_bytes());
+        out.extend_from_slice(&self.connection_uuid.to_le_bytes());
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        Ok(Self {
+            command_id: ManagementCommandId::try_from(reader.u32()?)?,
+            connection_no: reader.i32()?,
+            connection_uuid: reader.u128()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ManagementPacketHeader {
     // 0x1234
     pub magic: u16,
@@ -56,6 +225,8 @@ pub struct ManagementPacketHeader {
 }
 
 impl ManagementPacketHeader {
+    pub const SIZE: usize = 8;
+
     /// Creates a new ManagementPacketHeader with the magic number set to 0x1234
     ///
     /// # Arguments
@@ -66,46 +237,198 @@ impl ManagementPacketHeader {
     /// * `p_type` - The packet type
     pub fn new(length: u16, seq_number: u16, version: u8, p_type: PacketType) -> Self {
         Self {
-            magic: 0x1234,
+            magic: MANAGEMENT_MAGIC,
             length,
             seq_number,
             version,
             p_type,
         }
     }
+
+    /// Reads only the sequence number, so packets that can't be decoded can still be answered.
+    pub fn peek_seq_number(data: &[u8]) -> Option<u16> {
+        let bytes = data.get(4..6)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn checks_length(&self) -> bool {
+        self.version >= LENGTH_CHECKED_VERSION
+    }
+
+    /// Reads the header and checks `length` against the rest of the packet.
+    fn read_checked(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        let header = Self::read(reader)?;
+        if header.checks_length() && header.length as usize != reader.remaining() {
+            return Err(PacketError::LengthMismatch {
+                length: header.length,
+                payload: reader.remaining(),
+            });
+        }
+
+        Ok(header)
+    }
+
+    /// Rejects bytes after the payload that `length` claims are part of it.
+    fn finish(&self, reader: &Reader<'_>) -> Result<(), PacketError> {
+        if self.checks_length() && reader.remaining() != 0 {
+            return Err(PacketError::LengthMismatch {
+                length: self.length,
+                payload: self.length as usize - reader.remaining(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl WireFormat for ManagementPacketHeader {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.magic.to_le_bytes());
+        out.extend_from_slice(&self.length.to_le_bytes());
+        out.extend_from_slice(&self.seq_number.to_le_bytes());
+        out.push(self.version);
+        out.push(self.p_type as u8);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        let magic = reader.u16()?;
+        let length = reader.u16()?;
+        let seq_number = reader.u16()?;
+        let version = reader.u8()?;
+        let p_type = reader.u8()?;
+        if magic != MANAGEMENT_MAGIC {
+            return Err(PacketError::InvalidMagic(magic));
+        }
+        let p_type =
+            PacketType::try_from(p_type).map_err(|_| PacketError::UnknownPacketType(p_type))?;
+
+        Ok(Self {
+            magic,
+            length,
+            seq_number,
+            version,
+            p_type,
+        })
+    }
 }
 
-#[repr(C, packed)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ManagementCommandPacket {
     pub header: ManagementPacketHeader,
     pub command: ManagementCommand,
 }
 
+impl ManagementCommandPacket {
+    pub fn new(command: ManagementCommand) -> Self {
+        Self {
+            header: ManagementPacketHeader::new(
+                ManagementCommand::SIZE as u16,
+                0,
+                PROTOCOL_VERSION,
+                PacketType::ManagementCommand,
+            ),
+            command,
+        }
+    }
+}
+
+impl WireFormat for ManagementCommandPacket {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.header.write(out);
+        self.command.write(out);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        let header = ManagementPacketHeader::read_checked(reader)?;
+        let command = ManagementCommand::read(reader)?;
+        header.finish(reader)?;
+
+        Ok(Self { header, command })
+    }
+}
+
+/// Sent by old firmwares from the port of a new connection. It has no header.
 #[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize)]
-#[repr(C, packed)]
 pub struct OldManagementResponse {
     pub charger_id: i32,
     pub connection_no: i32,
     pub connection_uuid: u128,
 }
 
+impl OldManagementResponse {
+    pub const SIZE: usize = 24;
+}
+
+impl WireFormat for OldManagementResponse {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.charger_id.to_le_bytes());
+        out.extend_from_slice(&self.connection_no.to_le_bytes());
+        out.extend_from_slice(&self.connection_uuid.to_le_bytes());
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        Ok(Self {
+            charger_id: reader.i32()?,
+            connection_no: reader.i32()?,
+            connection_uuid: reader.u128()?,
+        })
+    }
+}
+
 #[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize)]
-#[repr(C, packed)]
 pub struct ManagementResponseV2 {
     pub charger_id: u128,
     pub connection_no: i32,
     pub connection_uuid: u128,
 }
 
-#[repr(C, packed)]
+impl ManagementResponseV2 {
+    pub const SIZE: usize = 36;
+}
+
+impl WireFormat for ManagementResponseV2 {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.charger_id.to_le_bytes());
+        out.extend_from_slice(&self.connection_no.to_le_bytes());
+        out.extend_from_slice(&self.connection_uuid.to_le_bytes());
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        Ok(Self {
+            charger_id: reader.u128()?,
+            connection_no: reader.i32()?,
+            connection_uuid: reader.u128()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ManagementResponsePacket {
     pub header: ManagementPacketHeader,
     pub data: ManagementResponseV2,
 }
 
+impl ManagementResponsePacket {
+    pub const SIZE: usize = ManagementPacketHeader::SIZE + ManagementResponseV2::SIZE;
+}
+
+impl WireFormat for ManagementResponsePacket {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.header.write(out);
+        self.data.write(out);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        let header = ManagementPacketHeader::read_checked(reader)?;
+        let data = ManagementResponseV2::read(reader)?;
+        header.finish(reader)?;
+
+        Ok(Self { header, data })
+    }
+}
+
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub enum NackReason {
     Busy = 0,
     ToManyRequests = 1,
@@ -114,16 +437,33 @@ pub enum NackReason {
     Unauthorized = 4,
     InternalError = 5,
     AlreadySent = 6,
+    UnknownPacketType = 7,
+}
+
+impl TryFrom<u8> for NackReason {
+    type Error = PacketError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Busy),
+            1 => Ok(Self::ToManyRequests),
+            2 => Ok(Self::OngoingRequest),
+            3 => Ok(Self::Timeout),
+            4 => Ok(Self::Unauthorized),
+            5 => Ok(Self::InternalError),
+            6 => Ok(Self::AlreadySent),
+            7 => Ok(Self::UnknownPacketType),
+            _ => Err(PacketError::InvalidValue("reason")),
+        }
+    }
 }
 
-#[repr(C, packed)]
 #[derive(Debug)]
 pub struct ChargeLogSendRequestPacket {
     pub header: ManagementPacketHeader,
 }
 
-#[repr(C, packed)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct AckPacket {
     pub header: ManagementPacketHeader,
 }
@@ -131,7 +471,7 @@ pub struct AckPacket {
 impl Default for AckPacket {
     fn default() -> Self {
         Self {
-            header: ManagementPacketHeader::new(0, 0, 1, PacketType::Ack),
+            header: ManagementPacketHeader::new(0, 0, PROTOCOL_VERSION, PacketType::Ack),
         }
     }
 }
@@ -143,8 +483,137 @@ impl AckPacket {
     }
 }
 
-#[repr(C, packed)]
-#[derive(Debug)]
+impl WireFormat for AckPacket {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.header.write(out);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        let header = ManagementPacketHeader::read_checked(reader)?;
+        header.finish(reader)?;
+
+        Ok(Self { header })
+    }
+}
+
+/// Sent periodically to measure the round trip time. Devices answer with a
+/// [`PongPacket`] that carries the sequence number of the ping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PingPacket {
+    pub header: ManagementPacketHeader,
+}
+
+impl PingPacket {
+    pub fn new() -> Self {
+        Self {
+            header: ManagementPacketHeader::new(0, 0, PROTOCOL_VERSION, PacketType::Ping),
+        }
+    }
+}
+
+impl Default for PingPacket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WireFormat for PingPacket {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.header.write(out);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        let header = ManagementPacketHeader::read_checked(reader)?;
+        header.finish(reader)?;
+
+        Ok(Self { header })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PongPacket {
+    pub header: ManagementPacketHeader,
+}
+
+impl WireFormat for PongPacket {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.header.write(out);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        let header = ManagementPacketHeader::read_checked(reader)?;
+        header.finish(reader)?;
+
+        Ok(Self { header })
+    }
+}
+
+/// Short diagnostic status of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct DeviceStatus {
+    pub uptime_secs: u32,
+    /// Free heap memory in bytes.
+    pub free_heap: u32,
+    /// Signal strength in dBm. Not set if the device is not connected via WiFi.
+    pub wifi_rssi: Option<i8>,
+    /// Remote access connections that are open on the device.
+    pub open_connections: u8,
+}
+
+impl DeviceStatus {
+    pub const SIZE: usize = 10;
+}
+
+impl WireFormat for DeviceStatus {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.uptime_secs.to_le_bytes());
+        out.extend_from_slice(&self.free_heap.to_le_bytes());
+        // The RSSI is always negative, 0 means not connected.
+        out.push(self.wifi_rssi.unwrap_or(0) as u8);
+        out.push(self.open_connections);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        let uptime_secs = reader.u32()?;
+        let free_heap = reader.u32()?;
+        let wifi_rssi = match reader.u8()? as i8 {
+            0 => None,
+            rssi => Some(rssi),
+        };
+        let open_connections = reader.u8()?;
+
+        Ok(Self {
+            uptime_secs,
+            free_heap,
+            wifi_rssi,
+            open_connections,
+        })
+    }
+}
+
+/// Answer to [`ManagementCommandId::GetStatus`], carries the sequence number of the command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusPacket {
+    pub header: ManagementPacketHeader,
+    pub status: DeviceStatus,
+}
+
+impl WireFormat for StatusPacket {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.header.write(out);
+        self.status.write(out);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        let header = ManagementPacketHeader::read_checked(reader)?;
+        let status = DeviceStatus::read(reader)?;
+        header.finish(reader)?;
+
+        Ok(Self { header, status })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct NackPacket {
     pub header: ManagementPacketHeader,
     pub reason: NackReason,
@@ -154,42 +623,60 @@ impl NackPacket {
     /// Creates a new NackPacket with the specified reason
     pub fn new(reason: NackReason) -> Self {
         Self {
-            header: ManagementPacketHeader::new(0, 0, 1, PacketType::Nack),
+            header: ManagementPacketHeader::new(1, 0, PROTOCOL_VERSION, PacketType::Nack),
             reason,
         }
     }
 }
 
-#[derive(Debug)]
+impl WireFormat for NackPacket {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.header.write(out);
+        out.push(self.reason as u8);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        let header = ManagementPacketHeader::read_checked(reader)?;
+        let reason = NackReason::try_from(reader.u8()?)?;
+        header.finish(reader)?;
+
+        Ok(Self { header, reason })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RequestChargeLogSendPacket {
     pub header: ManagementPacketHeader,
     pub hash: [u8; 32], // SHA-256 hash
 }
 
-impl TryFrom<&[u8]> for RequestChargeLogSendPacket {
-    type Error = anyhow::Error;
+impl RequestChargeLogSendPacket {
+    pub const SIZE: usize = ManagementPacketHeader::SIZE + 32;
+}
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        // The packet must be at least 40 bytes to contain header (8) + hash (32)
-        let expected_size = std::mem::size_of::<ManagementPacketHeader>() + 32;
-        if value.len() < expected_size {
-            return Err(anyhow::anyhow!(
-                "Packet too short for RequestChargeLogSendPacket: expected at least {} bytes, got {}",
-                expected_size,
-                value.len()
-            ));
+impl WireFormat for RequestChargeLogSendPacket {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.header.write(out);
+        out.extend_from_slice(&self.hash);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        if reader.remaining() < Self::SIZE {
+            return Err(PacketError::TooShort);
         }
+        let header = ManagementPacketHeader::read_checked(reader)?;
+        let hash = reader.array()?;
+        header.finish(reader)?;
 
-        // Extract header
-        let header = unsafe { std::ptr::read(value.as_ptr() as *const ManagementPacketHeader) };
-        let header_size = std::mem::size_of::<ManagementPacketHeader>();
+        Ok(Self { header, hash })
+    }
+}
 
-        // Extract hash (32 bytes)
-        let hash_slice = &value[header_size..header_size + 32];
-        let mut hash = [0u8; 32];
-        hash.copy_from_slice(hash_slice);
+impl TryFrom<&[u8]> for RequestChargeLogSendPacket {
+    type Error = anyhow::Error;
 
-        Ok(Self { header, hash })
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Self::from_bytes(value)?)
     }
 }
 
@@ -205,7 +692,7 @@ impl TryFrom<&[u8]> for RequestChargeLogSendPacket {
 /// * `is_monthly_email` - Flag indicating if this is a monthly email (1 byte, 0 or 1)
 /// * `filename` - The actual filename of the charge log
 /// * `display_name` - Human-readable display name for the charge log
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChargeLogSendMetadata {
     pub user_uuid: u128,
     pub lang: String,
@@ -214,48 +701,53 @@ pub struct ChargeLogSendMetadata {
     pub display_name: String,
 }
 
-/// Parsed charge log metadata packet - not packed since it contains String fields
-#[derive(Debug)]
+/// Parsed charge log metadata packet
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChargeLogSendMetadataPacket {
     pub header: ManagementPacketHeader,
     pub data: ChargeLogSendMetadata,
 }
 
-impl TryFrom<&[u8]> for ChargeLogSendMetadataPacket {
-    type Error = anyhow::Error;
+impl ChargeLogSendMetadataPacket {
+    /// header (8) + user_uuid (16) + filename_length (2) + display_name_length (2) + lang (2) + is_monthly_email (1)
+    pub const MIN_SIZE: usize = 31;
+}
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        // The packet must be at least 31 bytes to be able to contain all fixed-size fields
-        // (header (8) + user_uuid (16) + filename_length (2) + display_name_length (2) + lang (2) + is_monthly_email (1))
-        if value.len() < 31 {
-            return Err(anyhow::anyhow!("Packet too short"));
-        }
-        let header = unsafe { std::ptr::read(value.as_ptr() as *const ManagementPacketHeader) };
-        let header_size = std::mem::size_of::<ManagementPacketHeader>();
-        let value = &value[header_size..];
-        let user_uuid = unsafe { std::ptr::read(value.as_ptr() as *const u128) };
-        let user_uuid = u128::from_be(user_uuid);
-        let value = &value[std::mem::size_of::<u128>()..];
-        let filename_length = unsafe { std::ptr::read(value.as_ptr() as *const u16) };
-        let value = &value[std::mem::size_of::<u16>()..];
-        let display_name_length = unsafe { std::ptr::read(value.as_ptr() as *const u16) };
-        let value = &value[std::mem::size_of::<u16>()..];
-        let lang_bytes = &value[..2];
-        let lang = String::from_utf8_lossy(lang_bytes).to_string();
-        let value = &value[2..];
-        let is_monthly_email = value[0] != 0;
-        let value = &value[1..];
-
-        // Check if there are enougth bytes for the filename and display name
-        if value.len() < (filename_length + display_name_length) as usize {
-            return Err(anyhow::anyhow!("Packet too short"));
+impl WireFormat for ChargeLogSendMetadataPacket {
+    fn write(&self, out: &mut Vec<u8>) {
+        let filename =
+            &self.data.filename.as_bytes()[..self.data.filename.len().min(u16::MAX as usize)];
+        let display_name = &self.data.display_name.as_bytes()
+            [..self.data.display_name.len().min(u16::MAX as usize)];
+        let mut lang = [0u8; 2];
+        for (dst, src) in lang.iter_mut().zip(self.data.lang.bytes()) {
+            *dst = src;
         }
-        let filename_bytes = &value[..filename_length as usize];
-        let filename = String::from_utf8_lossy(filename_bytes).to_string();
-        let value = &value[filename_length as usize..];
 
-        let display_name_bytes = &value[..display_name_length as usize];
-        let display_name = String::from_utf8_lossy(display_name_bytes).to_string();
+        self.header.write(out);
+        out.extend_from_slice(&self.data.user_uuid.to_be_bytes());
+        out.extend_from_slice(&(filename.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(display_name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&lang);
+        out.push(self.data.is_monthly_email as u8);
+        out.extend_from_slice(filename);
+        out.extend_from_slice(display_name);
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, PacketError> {
+        if reader.remaining() < Self::MIN_SIZE {
+            return Err(PacketError::TooShort);
+        }
+        let header = ManagementPacketHeader::read_checked(reader)?;
+        let user_uuid = u128::from_be_bytes(reader.array()?);
+        let filename_length = reader.u16()?;
+        let display_name_length = reader.u16()?;
+        let lang = String::from_utf8_lossy(reader.take(2)?).to_string();
+        let is_monthly_email = reader.u8()? != 0;
+        let filename = String::from_utf8_lossy(reader.take(filename_length as usize)?).to_string();
+        let display_name =
+            String::from_utf8_lossy(reader.take(display_name_length as usize)?).to_string();
+        header.finish(reader)?;
 
         let data = ChargeLogSendMetadata {
             user_uuid,
@@ -269,18 +761,31 @@ impl TryFrom<&[u8]> for ChargeLogSendMetadataPacket {
     }
 }
 
-#[repr(u8)]
+impl TryFrom<&[u8]> for ChargeLogSendMetadataPacket {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Self::from_bytes(value)?)
+    }
+}
+
+/// Packets sent by the server to a device.
 #[derive(Debug)]
 pub enum ManagementPacket {
     CommandPacket(ManagementCommandPacket),
     AckPacket(AckPacket),
     NackPacket(NackPacket),
+    PingPacket(PingPacket),
 }
 
 impl ManagementPacket {
-    pub fn as_bytes(self) -> Vec<u8> {
-        let bytes = as_u8_slice(&self);
-        bytes[1..].to_vec()
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::CommandPacket(p) => p.to_bytes(),
+            Self::AckPacket(p) => p.to_bytes(),
+            Self::NackPacket(p) => p.to_bytes(),
+            Self::PingPacket(p) => p.to_bytes(),
+        }
     }
 
     fn get_header(&mut self) -> &mut ManagementPacketHeader {
@@ -288,6 +793,7 @@ impl ManagementPacket {
             Self::CommandPacket(p) => &mut p.header,
             Self::AckPacket(p) => &mut p.header,
             Self::NackPacket(p) => &mut p.header,
+            Self::PingPacket(p) => &mut p.header,
         }
     }
 
@@ -297,6 +803,51 @@ impl ManagementPacket {
     }
 }
 
+/// Packets a device sends through its management tunnel.
+#[derive(Debug)]
+pub enum DevicePacket {
+    MetadataForChargeLog(ChargeLogSendMetadataPacket),
+    RequestChargeLogSend(RequestChargeLogSendPacket),
+    Ack(AckPacket),
+    Nack(NackPacket),
+    Pong(PongPacket),
+    Status(StatusPacket),
+}
+
+impl DevicePacket {
+    /// Decodes a packet of any type. Devices are answered with a Nack on
+    /// [`PacketError::UnknownPacketType`].
+    pub fn decode(data: &[u8]) -> Result<Self, PacketError> {
+        let header = ManagementPacketHeader::read(&mut Reader::new(data))?;
+        match header.p_type {
+            PacketType::MetadataForChargeLog => {
+                ChargeLogSendMetadataPacket::from_bytes(data).map(Self::MetadataForChargeLog)
+            }
+            PacketType::RequestChargeLogSend => {
+                RequestChargeLogSendPacket::from_bytes(data).map(Self::RequestChargeLogSend)
+            }
+            PacketType::Ack => AckPacket::from_bytes(data).map(Self::Ack),
+            PacketType::Nack => NackPacket::from_bytes(data).map(Self::Nack),
+            PacketType::Pong => PongPacket::from_bytes(data).map(Self::Pong),
+            PacketType::Status => StatusPacket::from_bytes(data).map(Self::Status),
+            PacketType::ManagementCommand | PacketType::Ping => {
+                Err(PacketError::UnknownPacketType(header.p_type as u8))
+            }
+        }
+    }
+
+    pub fn header(&self) -> &ManagementPacketHeader {
+        match self {
+            Self::MetadataForChargeLog(packet) => &packet.header,
+            Self::RequestChargeLogSend(packet) => &packet.header,
+            Self::Ack(packet) => &packet.header,
+            Self::Nack(packet) => &packet.header,
+            Self::Pong(packet) => &packet.header,
+            Self::Status(packet) => &packet.header,
+        }
+    }
+}
+
 /// Extracts and validates the management packet header from a byte slice
 ///
 /// # Arguments
@@ -312,54 +863,20 @@ impl ManagementPacket {
 ///
 /// - Packet must be at least 8 bytes (size of ManagementPacketHeader)
 /// - Magic number must be 0x1234
-/// - Protocol type (p_type) must be 0-4 (valid packet types)
+/// - Protocol type (p_type) must be 0-7 (valid packet types)
+/// - For version 2 and newer, `length` must match the size of the payload
 pub fn extract_management_packet_header(
     data: &[u8],
     id: uuid::Uuid,
 ) -> anyhow::Result<ManagementPacketHeader> {
-    let header_size = std::mem::size_of::<ManagementPacketHeader>();
-
-    // Check minimum packet size
-    if data.len() < header_size {
-        return Err(anyhow::anyhow!(
-            "Packet too short for device {}: expected at least {} bytes, got {}",
-            id,
-            header_size,
-            data.len()
-        ));
-    }
-
-    // Extract header
-    let header = unsafe { std::ptr::read(data.as_ptr() as *const ManagementPacketHeader) };
-
-    // Copy fields to local variables for validation (required for packed structs)
-    let magic = { header.magic };
-    let p_type = { header.p_type };
-
-    // Validate magic number
-    if magic != 0x1234 {
-        return Err(anyhow::anyhow!(
-            "Invalid magic number for device {}: expected 0x1234, got 0x{:04x}",
-            id,
-            magic
-        ));
-    }
-
-    // Validate packet type
-    let p_type_value = p_type as u8;
-    if p_type_value > 4 {
-        return Err(anyhow::anyhow!(
-            "Invalid packet type for device {}: expected 0-4, got {}",
-            id,
-            p_type_value
-        ));
-    }
-
-    Ok(header)
+    ManagementPacketHeader::read_checked(&mut Reader::new(data))
+        .map_err(|err| anyhow::anyhow!("{err} for device {id}"))
 }
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
     fn create_valid_packet(
@@ -468,6 +985,22 @@ mod tests {
             .contains("Invalid magic number"));
     }
 
+    #[test]
+    fn test_peek_seq_number() {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&0x1234u16.to_le_bytes()); // magic
+        packet.extend_from_slice(&0u16.to_le_bytes()); // length
+        packet.extend_from_slice(&0x5678u16.to_le_bytes()); // seq_number
+        packet.push(LENGTH_CHECKED_VERSION); // version
+        packet.push(0xff); // p_type - unknown
+
+        assert_eq!(
+            ManagementPacketHeader::peek_seq_number(&packet),
+            Some(0x5678)
+        );
+        assert_eq!(ManagementPacketHeader::peek_seq_number(&packet[..5]), None);
+    }
+
     #[test]
     fn test_extract_header_invalid_packet_type_too_high() {
         let mut packet = Vec::new();
@@ -475,7 +1008,7 @@ mod tests {
         packet.extend_from_slice(&100u16.to_ne_bytes()); // length
         packet.extend_from_slice(&42u16.to_ne_bytes()); // seq_number
         packet.push(1); // version
-        packet.push(5); // p_type - invalid (should be 0-4)
+        packet.push(8); // p_type - invalid (should be 0-7)
 
         let id = uuid::Uuid::nil();
         let result = extract_management_packet_header(&packet, id);
@@ -495,6 +1028,9 @@ mod tests {
             PacketType::MetadataForChargeLog,
             PacketType::RequestChargeLogSend,
             PacketType::Nack,
+            PacketType::Ping,
+            PacketType::Pong,
+            PacketType::Status,
         ];
 
         for p_type in valid_types {
@@ -692,11 +1228,11 @@ mod tests {
     fn test_header_fields_parsed_correctly() {
         let mut packet = Vec::new();
 
-        // Header with specific values
-        packet.extend_from_slice(&0xABCDu16.to_ne_bytes()); // magic
-        packet.extend_from_slice(&0x1234u16.to_ne_bytes()); // length
+        // Header with specific values. Version 1 packets may have any length.
+        packet.extend_from_slice(&0x1234u16.to_ne_bytes()); // magic
+        packet.extend_from_slice(&0xABCDu16.to_ne_bytes()); // length
         packet.extend_from_slice(&0x5678u16.to_ne_bytes()); // seq_number
-        packet.push(0x9A); // version
+        packet.push(1); // version
         packet.push(0x03); // p_type (MetadataForChargeLog)
 
         // user_uuid (16 bytes)
@@ -726,10 +1262,10 @@ mod tests {
         let version = { parsed.header.version };
         let p_type = { parsed.header.p_type };
 
-        assert_eq!(magic, 0xABCD);
-        assert_eq!(length, 0x1234);
+        assert_eq!(magic, 0x1234);
+        assert_eq!(length, 0xABCD);
         assert_eq!(seq_number, 0x5678);
-        assert_eq!(version, 0x9A);
+        assert_eq!(version, 1);
         assert_eq!(p_type, PacketType::MetadataForChargeLog);
     }
 
@@ -1083,11 +1619,11 @@ mod tests {
     fn test_request_charge_log_packet_header_preserved() {
         let mut packet = Vec::new();
 
-        // Header with specific values
-        packet.extend_from_slice(&0xABCDu16.to_ne_bytes()); // magic
-        packet.extend_from_slice(&0x1234u16.to_ne_bytes()); // length
+        // Header with specific values. Version 1 packets may have any length.
+        packet.extend_from_slice(&0x1234u16.to_ne_bytes()); // magic
+        packet.extend_from_slice(&0xABCDu16.to_ne_bytes()); // length
         packet.extend_from_slice(&0x5678u16.to_ne_bytes()); // seq_number
-        packet.push(0x9A); // version
+        packet.push(1); // version
         packet.push(PacketType::RequestChargeLogSend as u8); // p_type
 
         // hash (32 bytes)
@@ -1103,11 +1639,272 @@ mod tests {
         let version = { parsed.header.version };
         let p_type = { parsed.header.p_type };
 
-        assert_eq!(magic, 0xABCD);
-        assert_eq!(length, 0x1234);
+        assert_eq!(magic, 0x1234);
+        assert_eq!(length, 0xABCD);
         assert_eq!(seq_number, 0x5678);
-        assert_eq!(version, 0x9A);
+        assert_eq!(version, 1);
         assert_eq!(p_type, PacketType::RequestChargeLogSend);
         assert_eq!(parsed.hash, [0x42u8; 32]);
     }
+
+    fn arb_header(
+        p_type: PacketType,
+        length: usize,
+    ) -> impl Strategy<Value = ManagementPacketHeader> {
+        (any::<u16>(), 1..=LENGTH_CHECKED_VERSION).prop_map(move |(seq_number, version)| {
+            ManagementPacketHeader::new(length as u16, seq_number, version, p_type)
+        })
+    }
+
+    fn arb_command() -> impl Strategy<Value = ManagementCommand> {
+        let command_id = prop_oneof![
+            Just(ManagementCommandId::Connect),
+            Just(ManagementCommandId::Disconnect),
+            Just(ManagementCommandId::Reboot),
+            Just(ManagementCommandId::CheckIn),
+            Just(ManagementCommandId::RotateKeys),
+            Just(ManagementCommandId::GetStatus),
+        ];
+        (command_id, any::<i32>(), any::<u128>()).prop_map(
+            |(command_id, connection_no, connection_uuid)| ManagementCommand {
+                command_id,
+                connection_no,
+                connection_uuid,
+            },
+        )
+    }
+
+    fn arb_status() -> impl Strategy<Value = DeviceStatus> {
+        (
+            any::<u32>(),
+            any::<u32>(),
+            proptest::option::of(i8::MIN..0),
+            any::<u8>(),
+        )
+            .prop_map(|(uptime_secs, free_heap, wifi_rssi, open_connections)| {
+                DeviceStatus {
+                    uptime_secs,
+                    free_heap,
+                    wifi_rssi,
+                    open_connections,
+                }
+            })
+    }
+
+    fn arb_nack_reason() -> impl Strategy<Value = NackReason> {
+        (0u8..=7).prop_map(|reason| NackReason::try_from(reason).unwrap())
+    }
+
+    fn arb_metadata() -> impl Strategy<Value = ChargeLogSendMetadataPacket> {
+        (
+            any::<u128>(),
+            "[a-z]{2}",
+            any::<bool>(),
+            ".{0,64}",
+            ".{0,64}",
+        )
+            .prop_flat_map(
+                |(user_uuid, lang, is_monthly_email, filename, display_name)| {
+                    let length = ChargeLogSendMetadataPacket::MIN_SIZE
+                        - ManagementPacketHeader::SIZE
+                        + filename.len()
+                        + display_name.len();
+                    arb_header(PacketType::MetadataForChargeLog, length).prop_map(move |header| {
+                        ChargeLogSendMetadataPacket {
+                            header,
+                            data: ChargeLogSendMetadata {
+                                user_uuid,
+                                lang: lang.clone(),
+                                is_monthly_email,
+                                filename: filename.clone(),
+                                display_name: display_name.clone(),
+                            },
+                        }
+                    })
+                },
+            )
+    }
+
+    fn assert_round_trip<T: WireFormat + PartialEq + std::fmt::Debug>(
+        packet: T,
+    ) -> Result<(), TestCaseError> {
+        let bytes = packet.to_bytes();
+        prop_assert_eq!(T::from_bytes(&bytes), Ok(packet));
+
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip_header(header in arb_header(PacketType::Ack, 0)) {
+            assert_round_trip(header)?;
+        }
+
+        #[test]
+        fn test_round_trip_command(
+            header in arb_header(PacketType::ManagementCommand, ManagementCommand::SIZE),
+            command in arb_command(),
+        ) {
+            let packet = ManagementCommandPacket { header, command };
+            prop_assert_eq!(packet.to_bytes().len(), ManagementPacketHeader::SIZE + ManagementCommand::SIZE);
+            assert_round_trip(packet)?;
+        }
+
+        #[test]
+        fn test_round_trip_pong(header in arb_header(PacketType::Pong, 0)) {
+            assert_round_trip(PongPacket { header })?;
+        }
+
+        #[test]
+        fn test_round_trip_status(
+            header in arb_header(PacketType::Status, DeviceStatus::SIZE),
+            status in arb_status(),
+        ) {
+            let packet = StatusPacket { header, status };
+            prop_assert_eq!(packet.to_bytes().len(), ManagementPacketHeader::SIZE + DeviceStatus::SIZE);
+            assert_round_trip(packet)?;
+        }
+
+        #[test]
+        fn test_round_trip_ack(header in arb_header(PacketType::Ack, 0)) {
+            assert_round_trip(AckPacket { header })?;
+        }
+
+        #[test]
+        fn test_round_trip_nack(header in arb_header(PacketType::Nack, 1), reason in arb_nack_reason()) {
+            assert_round_trip(NackPacket { header, reason })?;
+        }
+
+        #[test]
+        fn test_round_trip_old_response(
+            charger_id in any::<i32>(),
+            connection_no in any::<i32>(),
+            connection_uuid in any::<u128>(),
+        ) {
+            let response = OldManagementResponse {
+                charger_id,
+                connection_no,
+                connection_uuid,
+            };
+            prop_assert_eq!(response.to_bytes().len(), OldManagementResponse::SIZE);
+            assert_round_trip(response)?;
+        }
+
+        #[test]
+        fn test_round_trip_response(
+            header in arb_header(PacketType::ManagementCommand, ManagementResponseV2::SIZE),
+            charger_id in any::<u128>(),
+            connection_no in any::<i32>(),
+            connection_uuid in any::<u128>(),
+        ) {
+            let packet = ManagementResponsePacket {
+                header,
+                data: ManagementResponseV2 {
+                    charger_id,
+                    connection_no,
+                    connection_uuid,
+                },
+            };
+            prop_assert_eq!(packet.to_bytes().len(), ManagementResponsePacket::SIZE);
+            assert_round_trip(packet)?;
+        }
+
+        #[test]
+        fn test_round_trip_request_charge_log(
+            header in arb_header(PacketType::RequestChargeLogSend, 32),
+            hash in any::<[u8; 32]>(),
+        ) {
+            assert_round_trip(RequestChargeLogSendPacket { header, hash })?;
+        }
+
+        #[test]
+        fn test_round_trip_metadata(packet in arb_metadata()) {
+            assert_round_trip(packet)?;
+        }
+
+        #[test]
+        fn test_decode_never_panics(data in proptest::collection::vec(any::<u8>(), 0..128)) {
+            let _ = DevicePacket::decode(&data);
+        }
+
+        /// A version 2 packet whose length does not match its payload is rejected.
+        #[test]
+        fn test_length_mismatch(packet in arb_metadata(), extra in 1usize..8) {
+            let mut packet = packet;
+            packet.header.version = LENGTH_CHECKED_VERSION;
+            let mut bytes = packet.to_bytes();
+            bytes.extend(vec![0u8; extra]);
+            prop_assert!(
+                matches!(
+                    ChargeLogSendMetadataPacket::from_bytes(&bytes),
+                    Err(PacketError::LengthMismatch { .. })
+                ),
+                "trailing bytes must be rejected"
+            );
+            bytes.truncate(bytes.len() - extra - 1);
+            prop_assert!(ChargeLogSendMetadataPacket::from_bytes(&bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn test_v2_length_must_match_payload() {
+        let mut packet = NackPacket::new(NackReason::Busy);
+        packet.header.version = LENGTH_CHECKED_VERSION;
+        assert!(NackPacket::from_bytes(&packet.to_bytes()).is_ok());
+
+        packet.header.length = 0;
+        assert_eq!(
+            NackPacket::from_bytes(&packet.to_bytes()),
+            Err(PacketError::LengthMismatch {
+                length: 0,
+                payload: 1
+            })
+        );
+
+        // Version 1 firmwares do not fill in the length.
+        packet.header.version = 1;
+        assert!(NackPacket::from_bytes(&packet.to_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_decode_unknown_packet_type() {
+        let mut data = AckPacket::new().to_bytes();
+        data[7] = 0x42;
+        assert!(matches!(
+            DevicePacket::decode(&data),
+            Err(PacketError::UnknownPacketType(0x42))
+        ));
+
+        let command = ManagementCommandPacket::new(ManagementCommand {
+            command_id: ManagementCommandId::Connect,
+            connection_no: 1,
+            connection_uuid: 2,
+        });
+        assert!(matches!(
+            DevicePacket::decode(&command.to_bytes()),
+            Err(PacketError::UnknownPacketType(0x00))
+        ));
+    }
+
+    #[test]
+    fn test_sent_packets_have_their_payload_length() {
+        let packets = [
+            ManagementPacket::AckPacket(AckPacket::new()),
+            ManagementPacket::NackPacket(NackPacket::new(NackReason::UnknownPacketType)),
+            ManagementPacket::PingPacket(PingPacket::new()),
+            ManagementPacket::CommandPacket(ManagementCommandPacket::new(ManagementCommand {
+                command_id: ManagementCommandId::Disconnect,
+                connection_no: 3,
+                connection_uuid: 4,
+            })),
+        ];
+        for packet in packets {
+            let bytes = packet.as_bytes();
+            let header = ManagementPacketHeader::from_bytes(&bytes).unwrap();
+            assert_eq!(
+                header.length as usize,
+                bytes.len() - ManagementPacketHeader::SIZE
+            );
+        }
+    }
 }
@@ -0,0 +1,402 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Finds the charger a WireGuard handshake initiation is meant for without trying
+//! to decrypt it with every key in the database.
+//!
+//! Every charger has its own server keypair, so the MAC1 field of a handshake
+//! initiation, which is keyed with the public key of the responder, is only valid
+//! for a single charger. Checking it is a single BLAKE2s run instead of the
+//! Diffie-Hellman operations needed for a full handshake.
+//!
+//! A MAC can only be checked with its key, not looked up, so the index remembers the
+//! address each charger last connected from. Chargers reconnecting from the same
+//! address are found with a single check and only new addresses are matched against
+//! every key.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use base64::prelude::*;
+use blake2::{
+    digest::{consts::U16, FixedOutput, KeyInit, Update},
+    Blake2s256, Blake2sMac, Digest,
+};
+use boringtun::x25519::{PublicKey, StaticSecret};
+use db_connector::Pool;
+use diesel::{prelude::*, PgConnection};
+
+const LABEL_MAC1: &[u8] = b"mac1----";
+const HANDSHAKE_INIT: u32 = 1;
+const HANDSHAKE_INIT_SIZE: usize = 148;
+/// The MAC1 covers everything in front of it.
+const MAC1_OFFSET: usize = 116;
+const MAC1_SIZE: usize = 16;
+
+struct IndexedPeer {
+    /// Kept to detect changed keys when refreshing.
+    management_private: String,
+    /// BLAKE2s state that already absorbed the MAC1 key, cloned for every check.
+    mac1: Blake2sMac<U16>,
+    /// Address of the last handshake that was matched to this charger.
+    last_ip: Option<IpAddr>,
+}
+
+impl IndexedPeer {
+    fn matches(&self, msg: &[u8], received_mac1: &[u8]) -> bool {
+        let mut mac = self.mac1.clone();
+        mac.update(msg);
+        let mac: [u8; MAC1_SIZE] = mac.finalize_fixed().into();
+        mac == received_mac1
+    }
+}
+
+#[derive(Default)]
+struct Peers {
+    by_id: HashMap<uuid::Uuid, IndexedPeer>,
+    by_ip: HashMap<IpAddr, uuid::Uuid>,
+}
+
+#[derive(Default)]
+pub struct PeerIndex {
+    peers: RwLock<Peers>,
+    refreshing: AtomicBool,
+}
+
+/// Returns true if the packet is a WireGuard handshake initiation.
+pub fn is_handshake_initiation(data: &[u8]) -> bool {
+    data.len() == HANDSHAKE_INIT_SIZE
+        && u32::from_le_bytes([data[0], data[1], data[2], data[3]]) == HANDSHAKE_INIT
+}
+
+fn mac1_key(public: &PublicKey) -> [u8; 32] {
+    let mut hasher = Blake2s256::new();
+    Digest::update(&mut hasher, LABEL_MAC1);
+    Digest::update(&mut hasher, public.as_bytes());
+    hasher.finalize().into()
+}
+
+fn indexed_peer(management_private: String) -> anyhow::Result<IndexedPeer> {
+    let private: [u8; 32] = match BASE64_STANDARD.decode(&management_private)?.try_into() {
+        Ok(v) => v,
+        Err(_) => {
+            return Err(anyhow::Error::msg(
+                "Somehow we got an invalid server private key in the database.",
+            ))
+        }
+    };
+    let public = PublicKey::from(&StaticSecret::from(private));
+    // The key always has a valid length.
+    let mac1 = Blake2sMac::<U16>::new_from_slice(&mac1_key(&public)).unwrap();
+
+    Ok(IndexedPeer {
+        management_private,
+        mac1,
+        last_ip: None,
+    })
+}
+
+impl PeerIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a charger with its base64 encoded server private key.
+    pub fn insert(&self, charger_id: uuid::Uuid, management_private: String) -> anyhow::Result<()> {
+        let peer = indexed_peer(management_private)?;
+        let mut peers = self.peers.write().unwrap();
+        if let Some(old) = peers.by_id.insert(charger_id, peer) {
+            if let Some(ip) = old.last_ip {
+                peers.by_ip.remove(&ip);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn contains(&self, charger_id: &uuid::Uuid) -> bool {
+        self.peers.read().unwrap().by_id.contains_key(charger_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.read().unwrap().by_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the chargers whose MAC1 matches the handshake initiation received from `ip`.
+    /// Anything that is not a handshake initiation matches nothing.
+    pub fn lookup(&self, data: &[u8], ip: IpAddr) -> Vec<uuid::Uuid> {
+        if !is_handshake_initiation(data) {
+            return Vec::new();
+        }
+
+        let (msg, rest) = data.split_at(MAC1_OFFSET);
+        let received_mac1 = &rest[..MAC1_SIZE];
+        let peers = self.peers.read().unwrap();
+        if let Some(id) = peers.by_ip.get(&ip) {
+            if let Some(peer) = peers.by_id.get(id) {
+                if peer.matches(msg, received_mac1) {
+                    return vec![*id];
+                }
+            }
+        }
+
+        peers
+            .by_id
+            .iter()
+            .filter(|(_, peer)| peer.matches(msg, received_mac1))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Remembers that the charger completed a handshake from `ip`, so it is checked first
+    /// the next time a handshake arrives from there.
+    pub fn connected_from(&self, charger_id: uuid::Uuid, ip: IpAddr) {
+        let mut peers = self.peers.write().unwrap();
+        let Some(peer) = peers.by_id.get_mut(&charger_id) else {
+            return;
+        };
+        let old_ip = peer.last_ip.replace(ip);
+        if let Some(old_ip) = old_ip {
+            if peers.by_ip.get(&old_ip) == Some(&charger_id) {
+                peers.by_ip.remove(&old_ip);
+            }
+        }
+        if let Some(previous) = peers.by_ip.insert(ip, charger_id) {
+            if previous != charger_id {
+                if let Some(peer) = peers.by_id.get_mut(&previous) {
+                    peer.last_ip = None;
+                }
+            }
+        }
+    }
+
+    /// Syncs the index with the chargers table.
+    /// Only keys that were added or changed since the last refresh are derived again.
+    pub fn refresh(&self, conn: &mut PgConnection) -> anyhow::Result<()> {
+        use db_connector::schema::chargers::dsl as chargers;
+
+        let rows: Vec<(uuid::Uuid, String)> = chargers::chargers
+            .select((chargers::id, chargers::management_private))
+            .load(conn)?;
+
+        let mut by_id = HashMap::with_capacity(rows.len());
+        {
+            let mut old_peers = self.peers.write().unwrap();
+            for (id, management_private) in rows.into_iter() {
+                let peer = match old_peers.by_id.remove(&id) {
+                    Some(peer) if peer.management_private == management_private => peer,
+                    _ => match indexed_peer(management_private) {
+                        Ok(peer) => peer,
+                        Err(err) => {
+                            log::error!("Failed to index charger {id}: {err}");
+                            continue;
+                        }
+                    },
+                };
+                by_id.insert(id, peer);
+            }
+            let by_ip = by_id
+                .iter()
+                .filter_map(|(id, peer)| peer.last_ip.map(|ip| (ip, *id)))
+                .collect();
+            *old_peers = Peers { by_id, by_ip };
+        }
+        log::debug!("Refreshed peer index with {} chargers", self.len());
+
+        Ok(())
+    }
+
+    /// Refreshes the index on its own thread, unless a refresh is already running.
+    /// Used when a handshake matched no charger, which might have been added since.
+    pub fn refresh_in_background(self: &Arc<Self>, pool: Pool) {
+        if self.refreshing.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let index = self.clone();
+        std::thread::spawn(move || {
+            let result = pool
+                .get()
+                .map_err(anyhow::Error::from)
+                .and_then(|mut conn| index.refresh(&mut conn));
+            index.refreshing.store(false, Ordering::SeqCst);
+            match result {
+                Ok(()) => log::info!("Indexed {} chargers", index.len()),
+                Err(err) => log::error!("Failed to refresh peer index: {err}"),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use boringtun::noise::{rate_limiter::RateLimiter, Tunn, TunnResult};
+    use rand_core::{OsRng, TryRngCore};
+
+    use super::*;
+    use crate::routes::user::tests::TestUser;
+
+    fn random_secret() -> StaticSecret {
+        let mut bytes = [0u8; 32];
+        OsRng.try_fill_bytes(&mut bytes).unwrap();
+        StaticSecret::from(bytes)
+    }
+
+    fn ip() -> IpAddr {
+        "10.0.0.1".parse().unwrap()
+    }
+
+    fn handshake_initiation(server_public: PublicKey) -> Vec<u8> {
+        let charger_private = random_secret();
+        let rate_limiter = Arc::new(RateLimiter::new(&PublicKey::from(&charger_private), 10));
+        let mut tunn = Tunn::new(
+            charger_private,
+            server_public,
+            None,
+            None,
+            0,
+            Some(rate_limiter),
+        );
+        let mut dst = vec![0u8; 2048];
+        match tunn.format_handshake_initiation(&mut dst, false) {
+            TunnResult::WriteToNetwork(packet) => packet.to_vec(),
+            _ => panic!("Failed to create handshake initiation"),
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let index = PeerIndex::new();
+        let mut keys = Vec::new();
+        for _ in 0..10 {
+            let id = uuid::Uuid::new_v4();
+            let secret = random_secret();
+            index
+                .insert(id, BASE64_STANDARD.encode(secret.as_bytes()))
+                .unwrap();
+            keys.push((id, PublicKey::from(&secret)));
+        }
+
+        for (id, public) in keys.iter() {
+            let packet = handshake_initiation(*public);
+            assert!(is_handshake_initiation(&packet));
+            assert_eq!(index.lookup(&packet, ip()), vec![*id]);
+        }
+
+        let unknown = handshake_initiation(PublicKey::from(&random_secret()));
+        assert!(index.lookup(&unknown, ip()).is_empty());
+    }
+
+    #[test]
+    fn test_lookup_by_address() {
+        let index = PeerIndex::new();
+        let mut keys = Vec::new();
+        for _ in 0..3 {
+            let id = uuid::Uuid::new_v4();
+            let secret = random_secret();
+            index
+                .insert(id, BASE64_STANDARD.encode(secret.as_bytes()))
+                .unwrap();
+            keys.push((id, PublicKey::from(&secret)));
+        }
+        let (first, first_public) = keys[0];
+        let (second, second_public) = keys[1];
+        let other_ip: IpAddr = "10.0.0.2".parse().unwrap();
+
+        index.connected_from(first, ip());
+        assert_eq!(
+            index.lookup(&handshake_initiation(first_public), ip()),
+            vec![first]
+        );
+        // Another charger behind the same address is still found.
+        assert_eq!(
+            index.lookup(&handshake_initiation(second_public), ip()),
+            vec![second]
+        );
+
+        index.connected_from(second, ip());
+        index.connected_from(first, other_ip);
+        {
+            let peers = index.peers.read().unwrap();
+            assert_eq!(peers.by_ip.len(), 2);
+            assert_eq!(peers.by_ip.get(&ip()), Some(&second));
+            assert_eq!(peers.by_ip.get(&other_ip), Some(&first));
+        }
+
+        // Changed keys forget the address.
+        index
+            .insert(first, BASE64_STANDARD.encode(random_secret().as_bytes()))
+            .unwrap();
+        assert!(index
+            .lookup(&handshake_initiation(first_public), other_ip)
+            .is_empty());
+        assert_eq!(index.peers.read().unwrap().by_ip.len(), 1);
+    }
+
+    #[test]
+    fn test_lookup_ignores_other_packets() {
+        let index = PeerIndex::new();
+        let secret = random_secret();
+        index
+            .insert(
+                uuid::Uuid::new_v4(),
+                BASE64_STANDARD.encode(secret.as_bytes()),
+            )
+            .unwrap();
+
+        let mut packet = handshake_initiation(PublicKey::from(&secret));
+        packet[0] = 4;
+        assert!(index.lookup(&packet, ip()).is_empty());
+        assert!(index.lookup(&[1, 0, 0, 0], ip()).is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_refresh() {
+        let (mut user, _) = TestUser::random().await;
+        user.login().await;
+        let charger = user.add_random_charger().await;
+        let charger_id = uuid::Uuid::parse_str(&charger.uuid).unwrap();
+
+        let index = PeerIndex::new();
+        let removed_id = uuid::Uuid::new_v4();
+        index
+            .insert(
+                removed_id,
+                BASE64_STANDARD.encode(random_secret().as_bytes()),
+            )
+            .unwrap();
+
+        let pool = db_connector::test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        index.refresh(&mut conn).unwrap();
+        assert!(index.contains(&charger_id));
+        assert!(!index.contains(&removed_id));
+    }
+}
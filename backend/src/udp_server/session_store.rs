@@ -0,0 +1,261 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Persists the state of the management connections across restarts so
+//! devices behind a stable address can be matched without searching for them.
+
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use actix_web::web;
+use chrono::Utc;
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+
+use crate::{BridgeState, DiscoveryCharger};
+
+/// Snapshots older than this are ignored since the devices have most likely moved on.
+const MAX_SNAPSHOT_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Entries that were not matched for this long are dropped.
+pub const KNOWN_DEVICE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeviceSession {
+    pub charger_id: uuid::Uuid,
+    pub remote_addr: SocketAddr,
+    pub out_sequence: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UndiscoveredDevice {
+    pub network: IpNetwork,
+    pub charger_id: uuid::Uuid,
+    pub age_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionSnapshot {
+    pub saved_at: i64,
+    pub sessions: Vec<DeviceSession>,
+    pub undiscovered_devices: Vec<UndiscoveredDevice>,
+}
+
+/// A device that was connected from this address before the restart.
+pub struct KnownDevice {
+    pub charger_id: uuid::Uuid,
+    pub out_sequence: u16,
+    pub since: Instant,
+}
+
+pub async fn take_snapshot(bridge_state: &web::Data<BridgeState<'_>>) -> SessionSnapshot {
+    let sockets: Vec<_> = bridge_state
+        .connections
+        .devices_by_id
+        .iter()
+        .map(|entry| entry.value().clone())
+        .collect();
+    let mut sessions = Vec::with_capacity(sockets.len());
+    for socket in sockets.into_iter() {
+        let socket = socket.lock().await;
+        sessions.push(socket.snapshot());
+    }
+
+    let undiscovered_devices = bridge_state
+        .connections
+        .undiscovered_devices
+        .iter()
+        .flat_map(|entry| {
+            let network = *entry.key();
+            entry
+                .value()
+                .iter()
+                .map(|d| UndiscoveredDevice {
+                    network,
+                    charger_id: d.id,
+                    age_secs: d.last_request.elapsed().as_secs(),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    SessionSnapshot {
+        saved_at: Utc::now().timestamp(),
+        sessions,
+        undiscovered_devices,
+    }
+}
+
+pub async fn save(bridge_state: &web::Data<BridgeState<'_>>, path: &Path) -> anyhow::Result<()> {
+    let snapshot = take_snapshot(bridge_state).await;
+    let data = serde_json::to_vec(&snapshot)?;
+
+    // Write to a temporary file first so a crash can not leave a half written snapshot behind.
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, data)?;
+    std::fs::rename(&tmp_path, path)?;
+    log::info!(
+        "Saved {} device sessions to {:?}",
+        snapshot.sessions.len(),
+        path
+    );
+
+    Ok(())
+}
+
+/// Reads and removes the snapshot so it is only used once.
+pub fn load(path: &Path) -> anyhow::Result<Option<SessionSnapshot>> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    std::fs::remove_file(path)?;
+
+    let snapshot: SessionSnapshot = serde_json::from_slice(&data)?;
+    let age = Utc::now().timestamp() - snapshot.saved_at;
+    if age < 0 || age as u64 > MAX_SNAPSHOT_AGE.as_secs() {
+        log::info!("Ignoring session snapshot that is {age} seconds old");
+        return Ok(None);
+    }
+
+    Ok(Some(snapshot))
+}
+
+pub async fn restore(bridge_state: &web::Data<BridgeState<'_>>, snapshot: SessionSnapshot) {
+    let now = Instant::now();
+    for session in snapshot.sessions.iter() {
+        bridge_state.known_devices.insert(
+            session.remote_addr,
+            KnownDevice {
+                charger_id: session.charger_id,
+                out_sequence: session.out_sequence,
+                since: now,
+            },
+        );
+    }
+
+    let downtime = Utc::now().timestamp() - snapshot.saved_at;
+    for device in snapshot.undiscovered_devices.into_iter() {
+        let age = Duration::from_secs(device.age_secs + downtime.max(0) as u64);
+        let Some(last_request) = now.checked_sub(age) else {
+            continue;
+        };
+        bridge_state
+            .connections
+            .undiscovered_devices
+            .entry(device.network)
+            .or_insert_with(HashSet::new)
+            .insert(DiscoveryCharger {
+                id: device.charger_id,
+                last_request,
+            });
+    }
+    log::info!(
+        "Restored {} device sessions from snapshot",
+        snapshot.sessions.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::create_test_bridge_state, udp_server::socket::ManagementSocket};
+
+    #[actix_web::test]
+    async fn test_save_and_restore() {
+        let bridge_state = create_test_bridge_state(None);
+        let charger_id = uuid::Uuid::new_v4();
+        let remote_addr: SocketAddr = "10.0.0.1:51820".parse().unwrap();
+        let network = IpNetwork::new("10.0.0.1".parse().unwrap(), 32).unwrap();
+        {
+            let mut socket = ManagementSocket::new_for_test(charger_id, remote_addr).await;
+            socket.set_out_sequence(42);
+            bridge_state
+                .connections
+                .insert_device(remote_addr, charger_id, socket);
+        }
+        {
+            let mut set = HashSet::new();
+            set.insert(DiscoveryCharger {
+                id: charger_id,
+                last_request: Instant::now(),
+            });
+            bridge_state
+                .connections
+                .undiscovered_devices
+                .insert(network, set);
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("session_state_{}.json", uuid::Uuid::new_v4()));
+        save(&bridge_state, &path).await.unwrap();
+
+        let snapshot = load(&path).unwrap().unwrap();
+        assert!(!path.exists());
+        assert_eq!(
+            snapshot.sessions,
+            vec![DeviceSession {
+                charger_id,
+                remote_addr,
+                out_sequence: 42,
+            }]
+        );
+
+        let restored_state = create_test_bridge_state(None);
+        restore(&restored_state, snapshot).await;
+        {
+            let device = restored_state.known_devices.get(&remote_addr).unwrap();
+            assert_eq!(device.charger_id, charger_id);
+            assert_eq!(device.out_sequence, 42);
+        }
+        let devices = restored_state
+            .connections
+            .undiscovered_devices
+            .get(&network)
+            .unwrap();
+        assert!(devices.iter().any(|d| d.id == charger_id));
+    }
+
+    #[test]
+    fn test_load_ignores_old_snapshot() {
+        let path =
+            std::env::temp_dir().join(format!("session_state_{}.json", uuid::Uuid::new_v4()));
+        let snapshot = SessionSnapshot {
+            saved_at: Utc::now().timestamp() - MAX_SNAPSHOT_AGE.as_secs() as i64 - 1,
+            sessions: Vec::new(),
+            undiscovered_devices: Vec::new(),
+        };
+        std::fs::write(&path, serde_json::to_vec(&snapshot).unwrap()).unwrap();
+
+        assert!(load(&path).unwrap().is_none());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let path =
+            std::env::temp_dir().join(format!("session_state_{}.json", uuid::Uuid::new_v4()));
+        assert!(load(&path).unwrap().is_none());
+    }
+}
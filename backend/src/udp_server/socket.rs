@@ -19,7 +19,7 @@
 
 use std::{
     future::poll_fn,
-    net::{Ipv4Addr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     sync::Arc,
     task::Poll,
     time::{Duration, Instant},
@@ -35,7 +35,17 @@ use smoltcp::{
 
 use crate::udp_server::packet::ChargeLogSendMetadata;
 
-use super::{device::ManagementDevice, packet::ManagementPacket, pcap_logger::PcapLogger};
+use super::{
+    commands::{CommandHandle, CommandOutcome, CommandTracker},
+    device::ManagementDevice,
+    health::{ConnectionHealth, HealthProbe},
+    packet::{
+        ManagementCommand, ManagementCommandPacket, ManagementPacket, PingPacket,
+        LENGTH_CHECKED_VERSION,
+    },
+    pcap_logger::PcapLogger,
+    session_store::DeviceSession,
+};
 
 pub struct ManagementSocket<'a> {
     charger_id: uuid::Uuid,
@@ -44,7 +54,7 @@ pub struct ManagementSocket<'a> {
     iface: iface::Interface,
     device: ManagementDevice,
     rate_limiter: Arc<RateLimiter>,
-    peer_ip: Ipv4Addr,
+    peer_ip: IpAddr,
     remote_addr: SocketAddr,
     udp_socket: Arc<UdpSocket>,
     last_seen: Instant,
@@ -52,6 +62,10 @@ pub struct ManagementSocket<'a> {
     tcp_socket: Option<SocketHandle>,
     pcap_logger: PcapLogger,
     sender: Option<tokio::sync::oneshot::Sender<ChargeLogSendMetadata>>,
+    commands: CommandTracker,
+    health: HealthProbe,
+    // Protocol version of the last packet from the device, 0 until it sent one.
+    protocol_version: u8,
 }
 
 impl std::fmt::Debug for ManagementSocket<'_> {
@@ -64,8 +78,8 @@ impl std::fmt::Debug for ManagementSocket<'_> {
 
 impl<'a> ManagementSocket<'a> {
     pub fn new(
-        self_ip: Ipv4Addr,
-        peer_ip: Ipv4Addr,
+        self_ip: IpAddr,
+        peer_ip: IpAddr,
         remote_addr: SocketAddr,
         tunn: Tunn,
         rate_limiter: Arc<RateLimiter>,
@@ -79,12 +93,13 @@ impl<'a> ManagementSocket<'a> {
         let mut config = Config::new(smoltcp::wire::HardwareAddress::Ip);
         config.random_seed = rand::random();
         let mut interface = Interface::new(config, &mut device, smoltcp::time::Instant::now());
+        let prefix_len = match self_ip {
+            IpAddr::V4(_) => 24,
+            IpAddr::V6(_) => 64,
+        };
         interface.update_ip_addrs(|ip_addrs| {
             log::debug!("listening on ip: {self_ip}");
-            let _ = ip_addrs.push(smoltcp::wire::IpCidr::new(
-                smoltcp::wire::IpAddress::Ipv4(self_ip),
-                24,
-            ));
+            let _ = ip_addrs.push(smoltcp::wire::IpCidr::new(self_ip.into(), prefix_len));
         });
 
         let rx_buf = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 32], vec![0; 65535]);
@@ -113,6 +128,9 @@ impl<'a> ManagementSocket<'a> {
             tcp_socket: None,
             pcap_logger,
             sender: None,
+            commands: CommandTracker::default(),
+            health: HealthProbe::default(),
+            protocol_version: 0,
         }
     }
 
@@ -121,7 +139,7 @@ impl<'a> ManagementSocket<'a> {
     }
 
     /// Construct a `ManagementSocket` suitable for unit/integration tests that
-    /// only need a populated entry in `device_management_map_with_id`. The
+    /// only need a populated entry in `connections.devices_by_id`. The
     /// underlying boringtun state is initialized with zeroed keys; the socket
     /// is never expected to send or receive real traffic, so the lack of a
     /// real handshake partner is irrelevant.
@@ -146,8 +164,8 @@ impl<'a> ManagementSocket<'a> {
                 .expect("failed to bind test UDP socket"),
         );
         Self::new(
-            Ipv4Addr::new(0, 0, 0, 0),
-            Ipv4Addr::new(0, 0, 0, 0),
+            Ipv4Addr::UNSPECIFIED.into(),
+            Ipv4Addr::UNSPECIFIED.into(),
             remote_addr,
             tunn,
             rate_limiter,
@@ -184,12 +202,106 @@ impl<'a> ManagementSocket<'a> {
         self.out_sequence = 1;
     }
 
-    pub fn send_packet(&mut self, mut packet: ManagementPacket) {
-        packet.set_seq_num(self.out_sequence);
-        self.out_sequence += 1;
+    pub fn set_out_sequence(&mut self, out_sequence: u16) {
+        self.out_sequence = out_sequence;
+    }
+
+    /// State needed to find the device again after a restart.
+    pub fn snapshot(&self) -> DeviceSession {
+        DeviceSession {
+            charger_id: self.charger_id,
+            remote_addr: self.remote_addr,
+            out_sequence: self.out_sequence,
+        }
+    }
 
+    /// Answers the packet of the device with `seq_number`. The answer carries the same
+    /// sequence number and is sent again if the device retransmits the packet.
+    pub fn send_reply(&mut self, seq_number: u16, mut packet: ManagementPacket) {
+        packet.set_seq_num(seq_number);
         let bytes = packet.as_bytes();
         self.encrypt_and_send_slice(&bytes);
+        self.commands.reply_sent(seq_number, bytes);
+    }
+
+    /// Sends the answer to a retransmitted packet again.
+    /// Returns false if the packet was not answered yet.
+    pub fn resend_reply(&mut self, seq_number: u16) -> bool {
+        let Some(bytes) = self.commands.reply_to(seq_number).map(|b| b.to_vec()) else {
+            return false;
+        };
+        self.encrypt_and_send_slice(&bytes);
+
+        true
+    }
+
+    /// Sends a command and retransmits it until the device answers it.
+    pub fn send_command(&mut self, command: ManagementCommand) -> CommandHandle {
+        let packet = ManagementPacket::CommandPacket(ManagementCommandPacket::new(command));
+        let (seq_number, bytes) = self.send_numbered(packet);
+        self.commands
+            .track(seq_number, command, bytes, Instant::now())
+    }
+
+    fn send_numbered(&mut self, mut packet: ManagementPacket) -> (u16, Vec<u8>) {
+        let seq_number = self.out_sequence;
+        packet.set_seq_num(seq_number);
+        self.out_sequence = self.out_sequence.wrapping_add(1);
+
+        let bytes = packet.as_bytes();
+        self.encrypt_and_send_slice(&bytes);
+        (seq_number, bytes)
+    }
+
+    /// Called with the Ack or Nack of the device for the command sent as `seq_number`.
+    pub fn command_answered(&mut self, seq_number: u16, outcome: CommandOutcome) -> bool {
+        self.commands.resolve(seq_number, outcome)
+    }
+
+    /// Called when the port of a connection was discovered, which answers its Connect.
+    pub fn connection_opened(&mut self, connection_uuid: u128) -> bool {
+        self.commands.connection_opened(connection_uuid)
+    }
+
+    /// Sends commands again that were not answered yet.
+    pub fn retransmit(&mut self) {
+        for bytes in self.commands.due(Instant::now()) {
+            self.encrypt_and_send_slice(&bytes);
+        }
+    }
+
+    pub fn send_ping(&mut self) {
+        let (seq_number, _) = self.send_numbered(ManagementPacket::PingPacket(PingPacket::new()));
+        self.health.ping_sent(seq_number, Instant::now());
+    }
+
+    /// Called with the protocol version of every packet the device sent.
+    pub fn version_announced(&mut self, version: u8) {
+        self.protocol_version = version;
+    }
+
+    /// Pings were added with the length checked protocol, older firmwares don't know them.
+    pub fn answers_pings(&self) -> bool {
+        self.protocol_version >= LENGTH_CHECKED_VERSION
+    }
+
+    pub fn pong_received(&mut self, seq_number: u16) -> bool {
+        self.health.pong_received(seq_number, Instant::now())
+    }
+
+    pub fn health(&mut self) -> ConnectionHealth {
+        ConnectionHealth {
+            last_handshake_secs: self
+                .device
+                .time_since_last_handshake()
+                .map(|age| age.as_secs()),
+            ..self.health.snapshot(Instant::now())
+        }
+    }
+
+    /// Returns false for packets of the device that were already received.
+    pub fn first_receipt(&mut self, seq_number: u16) -> bool {
+        self.commands.first_receipt(seq_number)
     }
 
     pub fn encrypt_and_send_slice(&mut self, data: &[u8]) {
@@ -226,7 +338,7 @@ impl<'a> ManagementSocket<'a> {
                 self.last_seen = Instant::now();
                 Ok(Vec::new())
             }
-            TunnResult::WriteToTunnelV4(data, _) => {
+            TunnResult::WriteToTunnelV4(data, _) | TunnResult::WriteToTunnelV6(data, _) => {
                 self.device.push_packet(data.to_owned());
                 self.poll();
                 self.last_seen = Instant::now();
@@ -236,9 +348,6 @@ impl<'a> ManagementSocket<'a> {
                     Ok(Vec::new())
                 }
             }
-            TunnResult::WriteToTunnelV6(_, _) => {
-                Err("Received an decryptable IPv6 packet, what is going on here?".to_string())
-            }
             TunnResult::Err(err) => Err(format!("{err:?}")),
             TunnResult::Done => {
                 self.last_seen = Instant::now();
@@ -35,7 +35,12 @@ use lettre::message::IntoBody;
 use lettre::{Message, Transport};
 use rand::RngExt;
 
-use crate::{error::Error, routes::charger::add::password_matches, AppState, BridgeState};
+use crate::{
+    error::Error,
+    routes::charger::add::password_matches,
+    webhooks::{self, WebhookEvent},
+    AppState, BridgeState,
+};
 
 pub fn get_connection(
     state: &web::Data<AppState>,
@@ -68,12 +73,6 @@ where
     }
 }
 
-pub fn as_u8_slice<T: Sized>(p: &T) -> &[u8] {
-    unsafe {
-        ::core::slice::from_raw_parts((p as *const T) as *const u8, ::core::mem::size_of::<T>())
-    }
-}
-
 pub fn parse_uuid(uuid: &str) -> actix_web::Result<uuid::Uuid> {
     match uuid::Uuid::from_str(uuid) {
         Ok(v) => Ok(v),
@@ -204,6 +203,14 @@ pub async fn validate_auth_token(
     })
     .await?;
 
+    webhooks::emit(
+        &state.pool,
+        vec![user_id],
+        WebhookEvent::AuthorizationTokenUsed,
+        serde_json::json!({ "token_id": token.id, "name": token.name }),
+    )
+    .await;
+
     Ok(())
 }
 
@@ -366,14 +373,12 @@ async fn notify_state_change(
         }
     };
 
-    let sessions: Vec<(uuid::Uuid, actix_ws::Session)> = {
-        let state_update_clients = bridge_state.state_update_clients.lock().await;
-        state_update_clients
-            .iter()
-            .filter(|(user_id, _)| affected_user_ids.contains(user_id))
-            .map(|(id, session)| (*id, session.clone()))
-            .collect()
-    };
+    let sessions: Vec<(uuid::Uuid, actix_ws::Session)> = bridge_state
+        .state_update_clients
+        .iter()
+        .filter(|entry| affected_user_ids.contains(entry.key()))
+        .map(|entry| (*entry.key(), entry.value().clone()))
+        .collect();
 
     // Send messages and track failures
     let mut to_remove = Vec::new();
@@ -403,11 +408,8 @@ async fn notify_state_change(
     }
 
     // Remove disconnected clients
-    if !to_remove.is_empty() {
-        let mut state_update_clients = bridge_state.state_update_clients.lock().await;
-        for user_id in to_remove {
-            state_update_clients.remove(&user_id);
-        }
+    for user_id in to_remove {
+        bridge_state.state_update_clients.remove(&user_id);
     }
 }
 
@@ -0,0 +1,749 @@
+/* esp32-remote-access
+ * Copyright (C) 2026 Frederic Henrichs <frederic@tinkerforge.com>
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Lesser General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public
+ * License along with this library; if not, write to the
+ * Free Software Foundation, Inc., 59 Temple Place - Suite 330,
+ * Boston, MA 02111-1307, USA.
+ */
+
+//! Webhooks notify integrations of events of a user. Every event is written to the
+//! `webhook_deliveries` outbox first and a worker posts it to the registered URLs, so
+//! deliveries survive restarts and failed ones are retried with exponential backoff.
+//!
+//! The body is signed with HMAC-SHA256 using the secret of the webhook. The signature
+//! covers `"{timestamp}.{body}"` and is sent as `X-Webhook-Signature: sha256=<hex>`
+//! together with the `X-Webhook-Timestamp` header.
+//!
+//! Webhooks can only reach public addresses. The host is resolved when the webhook is
+//! registered and again before every delivery, and redirects are not followed, so users
+//! can't make the server send requests into its own network.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use db_connector::{
+    models::{webhook_deliveries::WebhookDelivery, webhooks::Webhook},
+    Pool,
+};
+use diesel::prelude::*;
+use futures_util::StreamExt;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use utoipa::ToSchema;
+
+use crate::{error::Error, utils::web_block_unpacked};
+
+/// How often the outbox is checked for due deliveries.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const BATCH_SIZE: i64 = 100;
+/// Deliveries of a batch that are posted at the same time, so that a slow receiver
+/// doesn't hold up the webhooks of other users.
+const MAX_CONCURRENT_DELIVERIES: usize = 10;
+/// A delivery that is being sent is not picked up by other servers for this long. It is
+/// twice the time a batch can take when every receiver runs into the timeout.
+const DELIVERY_LEASE: TimeDelta = TimeDelta::seconds(
+    2 * (BATCH_SIZE as u64).div_ceil(MAX_CONCURRENT_DELIVERIES as u64) as i64
+        * DELIVERY_TIMEOUT.as_secs() as i64,
+);
+const RETRY_INITIAL: TimeDelta = TimeDelta::seconds(30);
+const RETRY_MAX: TimeDelta = TimeDelta::hours(6);
+/// Deliveries that failed this often are given up.
+pub const MAX_ATTEMPTS: i32 = 12;
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    DeviceConnected,
+    DeviceDisconnected,
+    ChargeLogGenerated,
+    AccessGranted,
+    AccessRevoked,
+    AuthorizationTokenUsed,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::DeviceConnected => "device_connected",
+            Self::DeviceDisconnected => "device_disconnected",
+            Self::ChargeLogGenerated => "charge_log_generated",
+            Self::AccessGranted => "access_granted",
+            Self::AccessRevoked => "access_revoked",
+            Self::AuthorizationTokenUsed => "authorization_token_used",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WebhookPayload {
+    /// Id of the delivery. Retries keep it so receivers can drop duplicates.
+    pub id: uuid::Uuid,
+    pub event: WebhookEvent,
+    pub created_at: i64,
+    pub data: serde_json::Value,
+}
+
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Whether webhooks may be sent to the address. Loopback, private, link-local, unique
+/// local and other special-purpose addresses belong to the network of the server.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_v4(ip);
+            }
+            let segments = ip.segments();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // Unique local fc00::/7
+                || (segments[0] & 0xfe00) == 0xfc00
+                // Link-local fe80::/10
+                || (segments[0] & 0xffc0) == 0xfe80
+                // Documentation 2001:db8::/32
+                || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+        }
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // Shared address space 100.64.0.0/10
+        || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+        // This network 0.0.0.0/8 and reserved 240.0.0.0/4
+        || octets[0] == 0
+        || octets[0] >= 240)
+}
+
+/// Resolves the host of the URL and fails unless all of its addresses are public.
+pub async fn check_destination(url: &str) -> Result<(), String> {
+    let uri = url
+        .parse::<awc::http::Uri>()
+        .map_err(|err| err.to_string())?;
+    let Some(host) = uri.host() else {
+        return Err("URL has no host".to_string());
+    };
+    // IPv6 literals keep their brackets in the URI.
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+        Some("http") => 80,
+        _ => 443,
+    });
+
+    let addrs: Vec<_> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|err| format!("Failed to resolve {host}: {err}"))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{host} has no addresses"));
+    }
+    if let Some(addr) = addrs.iter().find(|addr| !is_public_address(addr.ip())) {
+        return Err(format!(
+            "{host} resolves to the non-public address {}",
+            addr.ip()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Delay before the next attempt after `attempts` failed ones.
+pub fn backoff(attempts: i32) -> TimeDelta {
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+    (RETRY_INITIAL * 2i32.pow(exponent)).min(RETRY_MAX)
+}
+
+/// Users that have access to the charger.
+pub async fn charger_users(pool: &Pool, charger_id: uuid::Uuid) -> Vec<uuid::Uuid> {
+    let Ok(mut conn) = pool.get() else {
+        log::error!("Failed to get database connection to load users of charger {charger_id}");
+        return Vec::new();
+    };
+    let res = web_block_unpacked(move || {
+        use db_connector::schema::allowed_users::dsl as allowed_users;
+
+        match allowed_users::allowed_users
+            .filter(allowed_users::charger_id.eq(charger_id))
+            .filter(allowed_users::valid.eq(true))
+            .select(allowed_users::user_id)
+            .load(&mut conn)
+        {
+            Ok(users) => Ok(users),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await;
+
+    res.unwrap_or_else(|_| {
+        log::error!("Failed to load users of charger {charger_id}");
+        Vec::new()
+    })
+}
+
+/// Queues `event` for all webhooks of `users` that subscribed to it.
+pub async fn emit(
+    pool: &Pool,
+    users: Vec<uuid::Uuid>,
+    event: WebhookEvent,
+    data: serde_json::Value,
+) {
+    if users.is_empty() {
+        return;
+    }
+    let Ok(mut conn) = pool.get() else {
+        log::error!("Failed to get database connection to queue {event:?} webhooks");
+        return;
+    };
+    let res = web_block_unpacked(move || {
+        use db_connector::schema::webhook_deliveries::dsl as webhook_deliveries;
+        use db_connector::schema::webhooks::dsl as webhooks;
+
+        let hooks: Vec<Webhook> = match webhooks::webhooks
+            .filter(webhooks::user_id.eq_any(users))
+            .select(Webhook::as_select())
+            .load(&mut conn)
+        {
+            Ok(hooks) => hooks,
+            Err(_err) => return Err(Error::InternalError),
+        };
+
+        let now = Utc::now().naive_utc();
+        let mut deliveries = Vec::new();
+        for hook in hooks {
+            if !hook
+                .events
+                .iter()
+                .any(|e| e.as_deref() == Some(event.as_str()))
+            {
+                continue;
+            }
+            let id = uuid::Uuid::new_v4();
+            let payload = WebhookPayload {
+                id,
+                event,
+                created_at: now.and_utc().timestamp(),
+                data: data.clone(),
+            };
+            let Ok(payload) = serde_json::to_string(&payload) else {
+                return Err(Error::InternalError);
+            };
+            deliveries.push(WebhookDelivery {
+                id,
+                webhook_id: hook.id,
+                event: event.as_str().to_string(),
+                payload,
+                created_at: now,
+                attempts: 0,
+                next_attempt_at: now,
+                delivered_at: None,
+                last_error: None,
+            });
+        }
+        if deliveries.is_empty() {
+            return Ok(());
+        }
+
+        match diesel::insert_into(webhook_deliveries::webhook_deliveries)
+            .values(&deliveries)
+            .execute(&mut conn)
+        {
+            Ok(_) => Ok(()),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await;
+    if res.is_err() {
+        log::error!("Failed to queue {event:?} webhooks");
+    }
+}
+
+/// Queues `event` for the webhooks of all users that have access to the charger.
+pub async fn emit_for_charger(
+    pool: &Pool,
+    charger_id: uuid::Uuid,
+    event: WebhookEvent,
+    data: serde_json::Value,
+) {
+    let users = charger_users(pool, charger_id).await;
+    emit(pool, users, event, data).await;
+}
+
+/// Posts the due deliveries of the outbox. Returns how many were delivered.
+/// `allow_private_targets` skips the address check, so that tests can post to a local
+/// receiver.
+pub async fn deliver_due(
+    pool: &Pool,
+    client: &awc::Client,
+    now: NaiveDateTime,
+    allow_private_targets: bool,
+) -> usize {
+    let Ok(mut conn) = pool.get() else {
+        log::error!("Failed to get database connection to deliver webhooks");
+        return 0;
+    };
+    let due = web_block_unpacked(move || {
+        use db_connector::schema::webhook_deliveries::dsl as webhook_deliveries;
+        use db_connector::schema::webhooks::dsl as webhooks;
+
+        let due: Vec<(WebhookDelivery, String, String)> =
+            match webhook_deliveries::webhook_deliveries
+                .inner_join(webhooks::webhooks)
+                .filter(webhook_deliveries::delivered_at.is_null())
+                .filter(webhook_deliveries::attempts.lt(MAX_ATTEMPTS))
+                .filter(webhook_deliveries::next_attempt_at.le(now))
+                .order(webhook_deliveries::next_attempt_at.asc())
+                .limit(BATCH_SIZE)
+                .select((
+                    WebhookDelivery::as_select(),
+                    webhooks::url,
+                    webhooks::secret,
+                ))
+                .load(&mut conn)
+            {
+                Ok(due) => due,
+                Err(_err) => return Err(Error::InternalError),
+            };
+
+        // Claim the deliveries so other servers don't send them at the same time.
+        let mut claimed = Vec::new();
+        for (delivery, url, secret) in due {
+            match diesel::update(webhook_deliveries::webhook_deliveries)
+                .filter(webhook_deliveries::id.eq(delivery.id))
+                .filter(webhook_deliveries::next_attempt_at.eq(delivery.next_attempt_at))
+                .set(webhook_deliveries::next_attempt_at.eq(now + DELIVERY_LEASE))
+                .execute(&mut conn)
+            {
+                Ok(1) => claimed.push((delivery, url, secret)),
+                Ok(_) => (),
+                Err(_err) => return Err(Error::InternalError),
+            }
+        }
+
+        Ok(claimed)
+    })
+    .await;
+    let Ok(due) = due else {
+        log::error!("Failed to load due webhook deliveries");
+        return 0;
+    };
+
+    futures_util::stream::iter(due)
+        .map(|(delivery, url, secret)| async move {
+            let result = match actix_web::rt::time::timeout(
+                DELIVERY_TIMEOUT,
+                post(client, &delivery, &url, &secret, allow_private_targets),
+            )
+            .await
+            {
+                Ok(result) => result,
+                Err(_elapsed) => Err("Timed out".to_string()),
+            };
+            let delivered = result.is_ok();
+            record_attempt(pool, &delivery, result, now).await;
+            delivered
+        })
+        .buffer_unordered(MAX_CONCURRENT_DELIVERIES)
+        .filter(|delivered| std::future::ready(*delivered))
+        .count()
+        .await
+}
+
+async fn post(
+    client: &awc::Client,
+    delivery: &WebhookDelivery,
+    url: &str,
+    secret: &str,
+    allow_private_targets: bool,
+) -> Result<(), String> {
+    // Checked again because the DNS records can change after the webhook was created.
+    if !allow_private_targets {
+        check_destination(url).await?;
+    }
+
+    let timestamp = Utc::now().timestamp();
+    let signature = sign(secret, timestamp, &delivery.payload);
+    let resp = client
+        .post(url)
+        .insert_header(("Content-Type", "application/json"))
+        .insert_header(("X-Webhook-Id", delivery.id.to_string()))
+        .insert_header(("X-Webhook-Event", delivery.event.as_str()))
+        .insert_header(("X-Webhook-Timestamp", timestamp.to_string()))
+        .insert_header(("X-Webhook-Signature", format!("sha256={signature}")))
+        .send_body(delivery.payload.clone())
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("Receiver answered with {}", resp.status()))
+    }
+}
+
+async fn record_attempt(
+    pool: &Pool,
+    delivery: &WebhookDelivery,
+    result: Result<(), String>,
+    now: NaiveDateTime,
+) {
+    let Ok(mut conn) = pool.get() else {
+        log::error!("Failed to get database connection to record webhook delivery");
+        return;
+    };
+    let delivery_id = delivery.id;
+    let lease_until = now + DELIVERY_LEASE;
+    let attempts = delivery.attempts + 1;
+    if let Err(err) = &result {
+        if attempts >= MAX_ATTEMPTS {
+            log::warn!("Giving up webhook delivery {delivery_id}: {err}");
+        } else {
+            log::info!("Webhook delivery {delivery_id} failed: {err}");
+        }
+    }
+    let res = web_block_unpacked(move || {
+        use db_connector::schema::webhook_deliveries::dsl as webhook_deliveries;
+
+        // Only record the attempt while the claim holds. Once the lease ran out another
+        // server may be sending the delivery and records its own attempt.
+        let query = diesel::update(webhook_deliveries::webhook_deliveries)
+            .filter(webhook_deliveries::id.eq(delivery_id))
+            .filter(webhook_deliveries::next_attempt_at.eq(lease_until))
+            .filter(webhook_deliveries::delivered_at.is_null());
+        let res = match result {
+            Ok(()) => query
+                .set((
+                    webhook_deliveries::attempts.eq(attempts),
+                    webhook_deliveries::delivered_at.eq(Some(now)),
+                ))
+                .execute(&mut conn),
+            Err(err) => query
+                .set((
+                    webhook_deliveries::attempts.eq(attempts),
+                    webhook_deliveries::next_attempt_at.eq(now + backoff(attempts)),
+                    webhook_deliveries::last_error.eq(Some(err)),
+                ))
+                .execute(&mut conn),
+        };
+        match res {
+            Ok(updated) => Ok(updated == 1),
+            Err(_err) => Err(Error::InternalError),
+        }
+    })
+    .await;
+    match res {
+        Ok(true) => (),
+        Ok(false) => log::warn!("Lease of webhook delivery {delivery_id} expired while sending"),
+        Err(_err) => log::error!("Failed to record webhook delivery {delivery_id}"),
+    }
+}
+
+pub async fn run_delivery_worker(pool: Pool) {
+    // A redirect could lead to an address that was not checked.
+    let client = awc::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .max_redirects(0)
+        .finish();
+    loop {
+        actix_web::rt::time::sleep(POLL_INTERVAL).await;
+        deliver_due(&pool, &client, Utc::now().naive_utc(), false).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use db_connector::test_connection_pool;
+
+    use super::*;
+    use crate::routes::user::{me::tests::get_test_user, tests::TestUser};
+
+    type Received = Mutex<Vec<(String, String, String)>>;
+
+    /// Stands in for the server of an integration.
+    async fn receive(
+        req: HttpRequest,
+        body: String,
+        received: web::Data<Received>,
+    ) -> HttpResponse {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        received.lock().unwrap().push((
+            header("X-Webhook-Timestamp"),
+            header("X-Webhook-Signature"),
+            body,
+        ));
+
+        HttpResponse::Ok().finish()
+    }
+
+    fn insert_webhook(user_id: uuid::Uuid, url: String) -> uuid::Uuid {
+        use db_connector::schema::webhooks::dsl::webhooks;
+
+        let webhook = Webhook {
+            id: uuid::Uuid::new_v4(),
+            user_id,
+            url,
+            secret: "secret".to_string(),
+            events: vec![Some(WebhookEvent::DeviceConnected.as_str().to_string())],
+            created_at: Utc::now().naive_utc(),
+        };
+        let webhook_id = webhook.id;
+
+        let pool = test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        diesel::insert_into(webhooks)
+            .values(webhook)
+            .execute(&mut conn)
+            .unwrap();
+
+        webhook_id
+    }
+
+    fn delivery_of(hook: uuid::Uuid) -> WebhookDelivery {
+        use db_connector::schema::webhook_deliveries::dsl::*;
+
+        let pool = test_connection_pool();
+        let mut conn = pool.get().unwrap();
+        webhook_deliveries
+            .filter(webhook_id.eq(hook))
+            .select(WebhookDelivery::as_select())
+            .get_result(&mut conn)
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_delivery() {
+        let received = web::Data::new(Received::default());
+        let data = received.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route("/ok", web::post().to(receive))
+                .route(
+                    "/fail",
+                    web::post().to(|| async { HttpResponse::InternalServerError().finish() }),
+                )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        let (mut user, mail) = TestUser::random().await;
+        user.login().await;
+        let uid = get_test_user(&mail).id;
+        let ok = insert_webhook(uid, format!("http://{addr}/ok"));
+        let fail = insert_webhook(uid, format!("http://{addr}/fail"));
+
+        let pool = test_connection_pool();
+        // Not subscribed, so nothing is queued.
+        emit(
+            &pool,
+            vec![uid],
+            WebhookEvent::AccessGranted,
+            serde_json::json!({}),
+        )
+        .await;
+        emit(
+            &pool,
+            vec![uid],
+            WebhookEvent::DeviceConnected,
+            serde_json::json!({ "charger_id": "abc" }),
+        )
+        .await;
+
+        let client = awc::Client::builder()
+            .timeout(Duration::from_secs(2))
+            .max_redirects(0)
+            .finish();
+        let now = Utc::now().naive_utc();
+        deliver_due(&pool, &client, now, true).await;
+
+        let (timestamp, signature, body) = received.lock().unwrap().pop().unwrap();
+        assert!(received.lock().unwrap().is_empty());
+        let timestamp: i64 = timestamp.parse().unwrap();
+        assert_eq!(
+            signature,
+            format!("sha256={}", sign("secret", timestamp, &body))
+        );
+        let payload: WebhookPayload = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload.event, WebhookEvent::DeviceConnected);
+        assert_eq!(payload.data["charger_id"], "abc");
+
+        let delivered = delivery_of(ok);
+        assert_eq!(delivered.id, payload.id);
+        assert_eq!(delivered.attempts, 1);
+        assert!(delivered.delivered_at.is_some());
+
+        let failed = delivery_of(fail);
+        assert_eq!(failed.attempts, 1);
+        assert!(failed.delivered_at.is_none());
+        assert!(failed.last_error.is_some());
+        assert!(failed.next_attempt_at > now + backoff(1) - TimeDelta::seconds(1));
+
+        // The failed delivery is not due yet.
+        deliver_due(&pool, &client, now + TimeDelta::seconds(1), true).await;
+        assert_eq!(delivery_of(fail).attempts, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_delivery_to_private_address() {
+        let received = web::Data::new(Received::default());
+        let data = received.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route("/ok", web::post().to(receive))
+                .route(
+                    "/redirect",
+                    web::post().to(|| async {
+                        HttpResponse::TemporaryRedirect()
+                            .insert_header(("Location", "/ok"))
+                            .finish()
+                    }),
+                )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        let (mut user, mail) = TestUser::random().await;
+        user.login().await;
+        let uid = get_test_user(&mail).id;
+        let private = insert_webhook(uid, format!("http://{addr}/ok"));
+        let redirect = insert_webhook(uid, format!("http://{addr}/redirect"));
+
+        let pool = test_connection_pool();
+        emit(
+            &pool,
+            vec![uid],
+            WebhookEvent::DeviceConnected,
+            serde_json::json!({}),
+        )
+        .await;
+
+        let client = awc::Client::builder()
+            .timeout(Duration::from_secs(2))
+            .max_redirects(0)
+            .finish();
+        let now = Utc::now().naive_utc();
+        deliver_due(&pool, &client, now, false).await;
+
+        // The loopback address is refused before anything is sent.
+        let failed = delivery_of(private);
+        assert_eq!(failed.attempts, 1);
+        assert!(failed.delivered_at.is_none());
+        assert!(failed.last_error.unwrap().contains("non-public"));
+
+        // Redirects are not followed.
+        deliver_due(&pool, &client, now + backoff(1), true).await;
+        let failed = delivery_of(redirect);
+        assert_eq!(failed.attempts, 2);
+        assert!(failed.delivered_at.is_none());
+        assert!(delivery_of(private).delivered_at.is_some());
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_is_public_address() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.178.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["1.1.1.1", "93.184.215.14", "2606:4700:4700::1111"] {
+            assert!(is_public_address(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_check_destination() {
+        for url in [
+            "https://127.0.0.1/hook",
+            "https://localhost:8443/hook",
+            "https://169.254.169.254/latest/meta-data",
+            "https://[::1]/hook",
+            "https://[fd00::1]:8443/hook",
+        ] {
+            assert!(check_destination(url).await.is_err(), "{url}");
+        }
+        assert!(check_destination("https://1.1.1.1/hook").await.is_ok());
+    }
+
+    #[test]
+    fn test_sign() {
+        // echo -n '1700000000.{}' | openssl dgst -sha256 -hmac secret
+        assert_eq!(
+            sign("secret", 1_700_000_000, "{}"),
+            "b8569b78799ff9e3cbff0fc2d63a33a2b57f3282abd07c37ae5e8e7d79a5f163"
+        );
+        assert_ne!(
+            sign("secret", 1_700_000_001, "{}"),
+            sign("secret", 1_700_000_000, "{}")
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), TimeDelta::seconds(30));
+        assert_eq!(backoff(2), TimeDelta::seconds(60));
+        assert_eq!(backoff(5), TimeDelta::minutes(8));
+        assert_eq!(backoff(MAX_ATTEMPTS), RETRY_MAX);
+    }
+}
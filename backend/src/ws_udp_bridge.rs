@@ -23,24 +23,27 @@ use actix_web::rt::pin;
 use actix_web::web::Bytes;
 use actix_web::{get, rt, web, HttpRequest, HttpResponse};
 use actix_web_validator::Query;
-use actix_ws::{AggregatedMessage, Session};
-use db_connector::models::wg_keys::WgKey;
+use actix_ws::{AggregatedMessage, CloseCode, CloseReason, Session};
+use dashmap::{mapref::entry::Entry, DashMap};
+use db_connector::models::{access_sessions::AccessSession, wg_keys::WgKey};
 use diesel::prelude::*;
-use futures_util::future::Either;
+use futures_util::future::{self, Either};
 use futures_util::lock::Mutex;
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 use validator::{Validate, ValidationError};
 
+use crate::bandwidth::{DeviceTraffic, SessionTraffic, Traffic};
+use crate::logging::{self, LogContext};
+use crate::relay_bus::RelayMessage;
+use crate::udp_server::commands::CommandHandle;
 use crate::udp_server::management::RemoteConnMeta;
-use crate::udp_server::packet::{
-    ManagementCommand, ManagementCommandId, ManagementCommandPacket, ManagementPacket,
-    ManagementPacketHeader, ManagementResponseV2, PacketType,
-};
+use crate::udp_server::packet::{ManagementCommand, ManagementCommandId, ManagementResponseV2};
 use crate::udp_server::socket::ManagementSocket;
 use crate::{
     error::Error,
@@ -50,6 +53,12 @@ use crate::{
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(15);
+/// Datagrams waiting for a browser. Further ones are dropped.
+const OUTBOUND_QUEUE_LEN: usize = 256;
+/// A browser that does not read for this long is disconnected.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+/// Datagrams from a browser waiting for the bandwidth limit. Further ones are dropped.
+const INBOUND_QUEUE_LEN: usize = 256;
 
 #[derive(Deserialize, Serialize, Validate)]
 struct WsQuery {
@@ -64,44 +73,199 @@ fn validate_key_id(key_id: &str) -> Result<(), ValidationError> {
     }
 }
 
-pub struct WebClient<'a> {
+/// A browser connected to this instance.
+///
+/// Data for the browser goes through a bounded queue that is drained by its own task,
+/// so a slow browser only loses its own datagrams instead of stalling the relaying
+/// for everyone else.
+#[derive(Clone)]
+pub struct WebClientHandle {
+    pub session: Session,
+    pub traffic: Arc<SessionTraffic>,
+    outbound: mpsc::Sender<Bytes>,
+}
+
+impl WebClientHandle {
+    pub fn new(session: Session, traffic: Arc<SessionTraffic>) -> Self {
+        let (outbound, queue) = mpsc::channel(OUTBOUND_QUEUE_LEN);
+        rt::spawn(logging::scope(
+            logging::current(),
+            forward_to_browser(session.clone(), queue),
+        ));
+
+        Self {
+            session,
+            traffic,
+            outbound,
+        }
+    }
+
+    /// Queues data for the browser without waiting.
+    /// Returns false if it was dropped because the queue is full or the session is gone.
+    pub fn send(&self, data: Bytes) -> bool {
+        self.outbound.try_send(data).is_ok()
+    }
+}
+
+/// Runs until every handle of the session was dropped or the browser stopped reading.
+async fn forward_to_browser(mut session: Session, mut queue: mpsc::Receiver<Bytes>) {
+    while let Some(data) = queue.recv().await {
+        match rt::time::timeout(SEND_TIMEOUT, session.binary(data)).await {
+            Ok(Ok(())) => (),
+            Ok(Err(_closed)) => return,
+            Err(_elapsed) => {
+                log::warn!("Closing websocket that did not read for {SEND_TIMEOUT:?}");
+                let reason = CloseReason {
+                    code: CloseCode::Policy,
+                    description: Some("Client is too slow".to_string()),
+                };
+                session.close(Some(reason)).await.ok();
+                return;
+            }
+        }
+    }
+}
+
+/// Sends the data of a browser to its device once the bandwidth limits allow it.
+///
+/// Waiting here instead of in the websocket loop keeps pings and the close of the
+/// session flowing while the browser is throttled.
+async fn forward_to_device(
+    bridge_state: web::Data<BridgeState<'static>>,
+    meta: RemoteConnMeta,
+    remote_owner: Option<uuid::Uuid>,
+    traffic: Arc<SessionTraffic>,
+    mut queue: mpsc::Receiver<Bytes>,
+) {
+    while let Some(msg) = queue.recv().await {
+        let delay = traffic.add_to_device(msg.len());
+        if !delay.is_zero() {
+            rt::time::sleep(delay).await;
+        }
+
+        if let (Some(owner), Some(relay)) = (remote_owner, &bridge_state.relay) {
+            relay.send_to_device(owner, &meta, &msg);
+            continue;
+        }
+
+        let peer_sock_addr = match bridge_state.connections.device_connections.get(&meta) {
+            Some(addr) => *addr,
+            None => continue,
+        };
+
+        match bridge_state.socket.send_to(&msg, peer_sock_addr).await {
+            Ok(s) => {
+                if s < msg.len() {
+                    log::error!("Sent incomplete message to charger '{}'", meta.charger_id);
+                }
+            }
+            Err(_err) => {
+                log::error!(
+                    "Failed to send message to charger '{}': {}",
+                    meta.charger_id,
+                    _err
+                );
+            }
+        }
+    }
+}
+
+/// Why a remote access session ended. Stored in the access log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEndReason {
+    ClientClosed,
+    Disconnected,
+    Timeout,
+    Error,
+    ServerShutdown,
+    /// The charger refused the connection or never answered the Connect.
+    Rejected,
+}
+
+impl SessionEndReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ClientClosed => "client_closed",
+            Self::Disconnected => "disconnected",
+            Self::Timeout => "timeout",
+            Self::Error => "error",
+            Self::ServerShutdown => "server_shutdown",
+            Self::Rejected => "rejected",
+        }
+    }
+}
+
+pub struct WebClient {
     key_id: uuid::Uuid,
     charger_id: uuid::Uuid,
     app_state: web::Data<AppState>,
-    bridge_state: web::Data<BridgeState<'a>>,
+    bridge_state: web::Data<BridgeState<'static>>,
     conn_no: i32,
     session: Session,
+    // Instance owning the device in case it is not connected to this one.
+    remote_owner: Option<uuid::Uuid>,
+    traffic: Arc<SessionTraffic>,
+    // Data for the device, sent by the task in `to_device_task`.
+    to_device: mpsc::Sender<Bytes>,
+    to_device_task: rt::task::JoinHandle<()>,
+    // Row in the access log, None if it could not be written.
+    access_session: Option<uuid::Uuid>,
 }
 
-impl<'a> WebClient<'a> {
+impl WebClient {
     pub async fn new(
         key_id: uuid::Uuid,
         charger_id: uuid::Uuid,
         app_state: web::Data<AppState>,
-        bridge_state: web::Data<BridgeState<'a>>,
+        bridge_state: web::Data<BridgeState<'static>>,
         conn_no: i32,
         session: Session,
+        remote_owner: Option<uuid::Uuid>,
+        access_session: Option<uuid::Uuid>,
     ) -> Self {
         let meta = RemoteConnMeta {
             charger_id,
             conn_no,
         };
-
-        {
-            let map = bridge_state.device_remote_conn_map.lock().await;
-            match map.get(&meta) {
-                Some(addr) => {
-                    let mut client_map = bridge_state.web_client_map.lock().await;
-                    client_map.insert(*addr, session.clone());
-                }
-                None => {
-                    drop(map);
-                    let mut map = bridge_state.undiscovered_clients.lock().await;
-                    map.insert(meta, session.clone());
-                }
-            }
+        let device_traffic = {
+            let mut entry = bridge_state
+                .device_traffic
+                .entry(charger_id)
+                .or_insert_with(|| DeviceTraffic {
+                    traffic: Arc::new(Traffic::new(&app_state.config.bandwidth.device)),
+                    sessions: 0,
+                });
+            entry.sessions += 1;
+            entry.traffic.clone()
+        };
+        let traffic = Arc::new(SessionTraffic::new(
+            &app_state.config.bandwidth,
+            device_traffic,
+        ));
+        bridge_state
+            .session_traffic
+            .insert(meta.clone(), traffic.clone());
+
+        let handle = WebClientHandle::new(session.clone(), traffic.clone());
+        if let (Some(owner), Some(relay)) = (remote_owner, &bridge_state.relay) {
+            let mut map = relay.remote_devices.lock().await;
+            map.insert(meta.clone(), (owner, handle));
+        } else {
+            bridge_state.connections.add_client(meta.clone(), handle);
         }
 
+        let (to_device, queue) = mpsc::channel(INBOUND_QUEUE_LEN);
+        let to_device_task = rt::spawn(logging::scope(
+            logging::current(),
+            forward_to_device(
+                bridge_state.clone(),
+                meta,
+                remote_owner,
+                traffic.clone(),
+                queue,
+            ),
+        ));
+
         Self {
             key_id,
             charger_id,
@@ -109,6 +273,11 @@ impl<'a> WebClient<'a> {
             bridge_state,
             conn_no,
             session,
+            remote_owner,
+            traffic,
+            to_device,
+            to_device_task,
+            access_session,
         }
     }
 
@@ -122,42 +291,29 @@ impl<'a> WebClient<'a> {
                 *last_heartbeat = Instant::now();
             }
             AggregatedMessage::Binary(msg) => {
-                let peer_sock_addr = {
-                    let meta = RemoteConnMeta {
-                        charger_id: self.charger_id,
-                        conn_no: self.conn_no,
-                    };
-                    let map = self.bridge_state.device_remote_conn_map.lock().await;
-                    match map.get(&meta) {
-                        Some(addr) => addr.to_owned(),
-                        None => {
-                            return;
-                        }
-                    }
-                };
-
-                match self.bridge_state.socket.send_to(&msg, peer_sock_addr).await {
-                    Ok(s) => {
-                        if s < msg.len() {
-                            log::error!("Sent incomplete message to charger '{}'", self.charger_id);
-                        }
-                    }
-                    Err(_err) => {
-                        log::error!(
-                            "Failed to send message to charger '{}': {}",
-                            self.charger_id,
-                            _err
-                        );
-                    }
+                // The browser retransmits whatever gets lost while it is throttled.
+                if self.to_device.try_send(msg).is_err() {
+                    log::debug!("Dropping message for charger '{}'", self.charger_id);
                 }
             }
             msg => log::info!("/ws got other msg: {msg:?}"),
         }
     }
 
-    pub async fn stop(self) {
+    pub async fn stop(self, reason: SessionEndReason) {
         log::debug!("Closed connection to charger '{}'", self.charger_id);
 
+        let reason = if self.bridge_state.shutting_down.load(Ordering::SeqCst) {
+            SessionEndReason::ServerShutdown
+        } else {
+            reason
+        };
+        if let Some(id) = self.access_session {
+            if let Err(err) = end_access_session(&self.app_state, id, &self.traffic, reason).await {
+                log::error!("Failed to finish access log entry '{id}': {err}");
+            }
+        }
+
         {
             let mut keys_in_use = self.app_state.keys_in_use.lock().await;
             keys_in_use.remove(&self.key_id);
@@ -167,48 +323,120 @@ impl<'a> WebClient<'a> {
             charger_id: self.charger_id,
             conn_no: self.conn_no,
         };
+        // Nothing queued may reach the device after the Disconnect.
+        self.to_device_task.abort();
+        self.bridge_state.session_traffic.remove(&meta);
+        if let Entry::Occupied(mut entry) = self.bridge_state.device_traffic.entry(self.charger_id)
         {
-            let mut map = self.bridge_state.device_remote_conn_map.lock().await;
-            if let Some(addr) = map.get(&meta) {
-                let mut map = self.bridge_state.web_client_map.lock().await;
-                map.remove(addr);
+            entry.get_mut().sessions -= 1;
+            if entry.get().sessions == 0 {
+                entry.remove();
             }
-
-            map.remove(&meta);
         }
 
-        {
-            let mut lost_map = self.bridge_state.lost_connections.lock().await;
-            let _ = lost_map.remove(&self.charger_id);
+        if let (Some(owner), Some(relay)) = (self.remote_owner, &self.bridge_state.relay) {
+            {
+                let mut map = relay.remote_devices.lock().await;
+                map.remove(&meta);
+            }
+            relay.send(owner, &meta, RelayMessage::CloseConnection);
+            self.session.close(None).await.ok();
+            return;
         }
+
+        self.bridge_state.connections.remove_client(&meta);
+        self.bridge_state
+            .connections
+            .lost_connections
+            .remove(&self.charger_id);
+
+        send_disconnect(&self.bridge_state, self.charger_id, self.conn_no).await;
+
+        self.session.close(None).await.ok();
+    }
+}
+
+/// Writes the start of a remote access session to the access log.
+async fn start_access_session(
+    state: &web::Data<AppState>,
+    user_id: uuid::Uuid,
+    keys: &WgKey,
+) -> actix_web::Result<uuid::Uuid> {
+    use db_connector::schema::access_sessions::dsl as access_sessions;
+
+    let session = AccessSession {
+        id: uuid::Uuid::new_v4(),
+        user_id: Some(user_id),
+        charger_id: keys.charger_id,
+        key_id: keys.id,
+        connection_no: keys.connection_no,
+        started_at: chrono::Utc::now().naive_utc(),
+        ended_at: None,
+        bytes_to_device: 0,
+        bytes_from_device: 0,
+        close_reason: None,
+    };
+    let session_id = session.id;
+
+    let mut conn = get_connection(state)?;
+    web_block_unpacked(move || {
+        match diesel::insert_into(access_sessions::access_sessions)
+            .values(session)
+            .execute(&mut conn)
         {
-            let mut map = self.bridge_state.undiscovered_clients.lock().await;
-            let _ = map.remove(&meta);
+            Ok(_) => Ok(()),
+            Err(_err) => Err(Error::InternalError),
         }
+    })
+    .await?;
 
+    Ok(session_id)
+}
+
+async fn end_access_session(
+    state: &web::Data<AppState>,
+    session_id: uuid::Uuid,
+    traffic: &SessionTraffic,
+    reason: SessionEndReason,
+) -> actix_web::Result<()> {
+    use db_connector::schema::access_sessions::dsl as access_sessions;
+
+    let to_device = traffic.session.to_device() as i64;
+    let from_device = traffic.session.from_device() as i64;
+
+    let mut conn = get_connection(state)?;
+    web_block_unpacked(move || {
+        match diesel::update(access_sessions::access_sessions.find(session_id))
+            .set((
+                access_sessions::ended_at.eq(chrono::Utc::now().naive_utc()),
+                access_sessions::bytes_to_device.eq(to_device),
+                access_sessions::bytes_from_device.eq(from_device),
+                access_sessions::close_reason.eq(reason.as_str()),
+            ))
+            .execute(&mut conn)
         {
-            let command = ManagementCommand {
-                command_id: ManagementCommandId::Disconnect,
-                connection_no: self.conn_no,
-                connection_uuid: uuid::Uuid::new_v4().as_u128(),
-            };
-            let header = ManagementPacketHeader {
-                magic: 0x1234,
-                length: std::mem::size_of::<ManagementCommand>() as u16,
-                seq_number: 0,
-                version: 1,
-                p_type: PacketType::ManagementCommand,
-            };
-
-            let packet = ManagementCommandPacket { header, command };
-            let map = self.bridge_state.device_management_map_with_id.lock().await;
-            if let Some(sock) = map.get(&self.charger_id) {
-                let mut sock = sock.lock().await;
-                sock.send_packet(ManagementPacket::CommandPacket(packet));
-            }
+            Ok(_) => Ok(()),
+            Err(_err) => Err(Error::InternalError),
         }
+    })
+    .await
+}
 
-        self.session.close(None).await.ok();
+/// Tells the device to close the connection with the given number.
+pub async fn send_disconnect(
+    bridge_state: &web::Data<BridgeState<'_>>,
+    charger_id: uuid::Uuid,
+    conn_no: i32,
+) {
+    let command = ManagementCommand {
+        command_id: ManagementCommandId::Disconnect,
+        connection_no: conn_no,
+        connection_uuid: uuid::Uuid::new_v4().as_u128(),
+    };
+    if let Some(sock) = bridge_state.connections.device(&charger_id) {
+        let mut sock = sock.lock().await;
+        // Nobody waits for the disconnect, it is only retransmitted until the device answers.
+        let _ = sock.send_command(command);
     }
 }
 
@@ -216,12 +444,14 @@ impl<'a> WebClient<'a> {
 #[rtype(result = "()")]
 pub struct Message(pub Bytes);
 
+/// Asks the device to open a connection. The returned handle resolves once the device
+/// acknowledged or refused it.
 pub async fn open_connection(
     conn_no: i32,
     charger_id: uuid::Uuid,
     management_sock: Arc<Mutex<ManagementSocket<'_>>>,
-    port_discovery: Arc<Mutex<HashMap<ManagementResponseV2, Instant>>>,
-) -> Result<(), Error> {
+    port_discovery: &DashMap<ManagementResponseV2, Instant>,
+) -> Result<CommandHandle, Error> {
     let conn_uuid = uuid::Uuid::new_v4();
     let command = ManagementCommand {
         command_id: ManagementCommandId::Connect,
@@ -234,21 +464,11 @@ pub async fn open_connection(
         connection_uuid: conn_uuid.as_u128(),
     };
 
-    let header = ManagementPacketHeader {
-        magic: 0x1234,
-        length: std::mem::size_of::<ManagementCommand>() as u16,
-        seq_number: 0,
-        version: 1,
-        p_type: PacketType::ManagementCommand,
-    };
-
-    let packet = ManagementCommandPacket { header, command };
     let mut sock = management_sock.lock().await;
-    sock.send_packet(ManagementPacket::CommandPacket(packet));
-    let mut set = port_discovery.lock().await;
-    set.insert(response, Instant::now());
+    // The answer might arrive before this task runs again.
+    port_discovery.insert(response, Instant::now());
 
-    Ok(())
+    Ok(sock.send_command(command))
 }
 
 #[get("/ws")]
@@ -262,6 +482,13 @@ async fn start_ws(
 ) -> Result<HttpResponse, actix_web::Error> {
     use db_connector::schema::wg_keys::dsl as wg_keys;
 
+    if bridge_state
+        .shutting_down
+        .load(std::sync::atomic::Ordering::SeqCst)
+    {
+        return Err(Error::ServerShuttingDown.into());
+    }
+
     let key_uuid = uuid::Uuid::from_str(&key_id.key_id).map_err(|_| Error::WgKeysDoNotExist)?;
 
     let mut keys_in_use = state.keys_in_use.lock().await;
@@ -289,23 +516,31 @@ async fn start_ws(
         return Err(Error::Unauthorized.into());
     }
 
-    let management_sock = {
-        let map = bridge_state.device_management_map_with_id.lock().await;
-        let management_sock = match map.get(&keys.charger_id) {
-            Some(sock) => sock.clone(),
-            None => return Err(Error::ChargerDisconnected.into()),
-        };
-        management_sock
+    let management_sock = bridge_state.connections.device(&keys.charger_id);
+
+    let (remote_owner, opened) = match (management_sock, &bridge_state.relay) {
+        (Some(management_sock), _) => {
+            let opened = open_connection(
+                keys.connection_no,
+                keys.charger_id,
+                management_sock,
+                &bridge_state.connections.port_discovery,
+            )
+            .await?;
+            (None, Some(opened))
+        }
+        // The device might be connected to another instance.
+        (None, Some(relay)) => (
+            Some(
+                relay
+                    .open_remote_connection(&state.pool, keys.charger_id, keys.connection_no)
+                    .await?,
+            ),
+            None,
+        ),
+        (None, None) => return Err(Error::ChargerDisconnected.into()),
     };
 
-    open_connection(
-        keys.connection_no,
-        keys.charger_id,
-        management_sock,
-        bridge_state.port_discovery.clone(),
-    )
-    .await?;
-
     let (resp, mut session, stream) = actix_ws::handle(&req, stream)?;
     let mut stream = stream
         .aggregate_continuations()
@@ -316,7 +551,21 @@ async fn start_ws(
     }
     drop(keys_in_use);
 
-    rt::spawn(async move {
+    let access_session = match start_access_session(&state, user_id, &keys).await {
+        Ok(id) => Some(id),
+        Err(err) => {
+            log::error!("Failed to write access log entry: {err}");
+            None
+        }
+    };
+
+    // Everything logged during the session can be traced back to the request that opened it.
+    let log_context = LogContext {
+        charger_id: Some(keys.charger_id),
+        conn_no: Some(keys.connection_no),
+        ..logging::current()
+    };
+    rt::spawn(logging::scope(log_context, async move {
         let mut client = WebClient::new(
             keys.id,
             keys.charger_id,
@@ -324,45 +573,128 @@ async fn start_ws(
             bridge_state,
             keys.connection_no,
             session.clone(),
+            remote_owner,
+            access_session,
         )
         .await;
 
+        // Only resolves if the charger refused the connection.
+        let rejected = async move {
+            match opened {
+                Some(opened) => match opened.await {
+                    outcome if outcome.is_failure() => outcome,
+                    _ => future::pending().await,
+                },
+                None => future::pending().await,
+            }
+        };
+        pin!(rejected);
+
         let mut last_heartbeat = Instant::now();
         let mut interval = interval(HEARTBEAT_INTERVAL);
-        loop {
+        let reason = loop {
             let tick = interval.tick();
             pin!(tick);
+            let timers = future::select(tick, &mut rejected);
 
-            match futures_util::future::select(stream.next(), tick).await {
-                Either::Left((Some(Ok(AggregatedMessage::Close(_))), _)) => break,
+            match future::select(stream.next(), timers).await {
+                Either::Left((Some(Ok(AggregatedMessage::Close(_))), _)) => {
+                    break SessionEndReason::ClientClosed
+                }
                 Either::Left((Some(Ok(msg)), _)) => {
                     client.handle_message(msg, &mut last_heartbeat).await
                 }
                 Either::Left((Some(err), _)) => {
                     log::error!("Websocket Error during connection: {err:?}");
-                    break;
+                    break SessionEndReason::Error;
+                }
+                Either::Left((None, _)) => break SessionEndReason::Disconnected,
+                Either::Right((Either::Right((outcome, _)), _)) => {
+                    log::warn!("Charger did not accept the connection: {outcome:?}");
+                    let reason = CloseReason {
+                        code: CloseCode::Again,
+                        description: Some("Charger did not accept the connection".to_string()),
+                    };
+                    session.clone().close(Some(reason)).await.ok();
+                    break SessionEndReason::Rejected;
                 }
-                Either::Left((None, _)) => break,
-                Either::Right(_) => {
+                Either::Right((Either::Left(_), _)) => {
                     if Instant::now().duration_since(last_heartbeat) > CLIENT_TIMEOUT {
                         log::debug!("Client quietly quit.");
-                        break;
+                        break SessionEndReason::Timeout;
                     }
                     let _ = session.ping(b"").await;
                 }
             }
-        }
-        client.stop().await;
-    });
+        };
+        client.stop(reason).await;
+    }));
 
     Ok(resp)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::pin::Pin;
+
+    use actix_web::body::{BoxBody, MessageBody};
+    use futures_util::future::poll_fn;
+
     use super::*;
+    use crate::tests::create_test_session;
     use uuid::Uuid;
 
+    /// Reads until the session is gone and returns the number of bytes received.
+    async fn read_all(mut body: BoxBody) -> usize {
+        let mut received = 0;
+        while let Some(Ok(chunk)) = poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await {
+            received += chunk.len();
+        }
+
+        received
+    }
+
+    /// Many browsers are served at the same time and one of them stops reading.
+    #[actix_web::test]
+    async fn test_slow_browser() {
+        const SESSIONS: usize = 50;
+        const DATAGRAMS: usize = 1000;
+        let payload = Bytes::from(vec![0u8; 100]);
+
+        let mut readers = Vec::new();
+        let mut clients = Vec::new();
+        for _ in 0..SESSIONS {
+            let (body, session) = create_test_session().await;
+            readers.push(rt::spawn(read_all(body)));
+            clients.push(WebClientHandle::new(session, Arc::default()));
+        }
+        let (_unread_body, session) = create_test_session().await;
+        let slow_client = WebClientHandle::new(session, Arc::default());
+
+        let start = Instant::now();
+        let mut dropped = 0;
+        for _ in 0..DATAGRAMS {
+            for client in clients.iter() {
+                assert!(client.send(payload.clone()));
+            }
+            if !slow_client.send(payload.clone()) {
+                dropped += 1;
+            }
+            rt::task::yield_now().await;
+        }
+
+        // Sending never waits for the slow browser, it only loses its own datagrams.
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(dropped > 0);
+        assert!(dropped < DATAGRAMS);
+
+        drop(clients);
+        for reader in readers {
+            // Every datagram arrived, each with a two byte frame header.
+            assert!(reader.await.unwrap() >= DATAGRAMS * (payload.len() + 2));
+        }
+    }
+
     #[test]
     fn test_validate_key_id_valid() {
         let valid_uuid = Uuid::new_v4().to_string();
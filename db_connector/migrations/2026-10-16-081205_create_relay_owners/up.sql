@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE "relay_owners"(
+    "charger_id" UUID PRIMARY KEY REFERENCES chargers(id) ON DELETE CASCADE,
+    "instance_id" UUID NOT NULL,
+    "updated_at" TIMESTAMP NOT NULL DEFAULT NOW()
+);
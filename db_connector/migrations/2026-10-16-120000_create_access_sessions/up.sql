@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE "access_sessions"(
+    "id" UUID PRIMARY KEY,
+    "user_id" UUID REFERENCES users(id) ON DELETE SET NULL,
+    "charger_id" UUID NOT NULL REFERENCES chargers(id) ON DELETE CASCADE,
+    "key_id" UUID NOT NULL,
+    "connection_no" INTEGER NOT NULL,
+    "started_at" TIMESTAMP NOT NULL DEFAULT NOW(),
+    "ended_at" TIMESTAMP,
+    "bytes_to_device" BIGINT NOT NULL DEFAULT 0,
+    "bytes_from_device" BIGINT NOT NULL DEFAULT 0,
+    "close_reason" VARCHAR
+);
+
+CREATE INDEX "access_sessions_charger_id_started_at_idx" ON "access_sessions"("charger_id", "started_at" DESC);
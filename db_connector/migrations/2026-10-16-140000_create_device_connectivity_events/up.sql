@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE "device_connectivity_events"(
+    "id" UUID PRIMARY KEY,
+    "charger_id" UUID NOT NULL REFERENCES chargers(id) ON DELETE CASCADE,
+    "connected_at" TIMESTAMP NOT NULL DEFAULT NOW(),
+    "disconnected_at" TIMESTAMP,
+    "disconnect_reason" VARCHAR
+);
+
+CREATE INDEX "device_connectivity_events_charger_id_connected_at_idx" ON "device_connectivity_events"("charger_id", "connected_at" DESC);
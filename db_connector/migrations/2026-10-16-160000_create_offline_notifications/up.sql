@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE "offline_notifications"(
+    "id" UUID PRIMARY KEY,
+    "user_id" UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    "charger_id" UUID NOT NULL REFERENCES chargers(id) ON DELETE CASCADE,
+    "lang" VARCHAR NOT NULL,
+    "notified_at" TIMESTAMP,
+    UNIQUE ("user_id", "charger_id")
+);
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+CREATE TABLE "webhooks"(
+    "id" UUID PRIMARY KEY,
+    "user_id" UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    "url" VARCHAR NOT NULL,
+    "secret" VARCHAR NOT NULL,
+    "events" TEXT[] NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE "webhook_deliveries"(
+    "id" UUID PRIMARY KEY,
+    "webhook_id" UUID NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    "event" VARCHAR NOT NULL,
+    "payload" TEXT NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW(),
+    "attempts" INTEGER NOT NULL DEFAULT 0,
+    "next_attempt_at" TIMESTAMP NOT NULL DEFAULT NOW(),
+    "delivered_at" TIMESTAMP,
+    "last_error" VARCHAR
+);
+
+CREATE INDEX "webhook_deliveries_pending_idx" ON "webhook_deliveries"("next_attempt_at") WHERE "delivered_at" IS NULL;
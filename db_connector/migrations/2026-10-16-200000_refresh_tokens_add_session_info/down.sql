@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "refresh_tokens" DROP COLUMN "created_at";
+ALTER TABLE "refresh_tokens" DROP COLUMN "last_used_at";
+ALTER TABLE "refresh_tokens" DROP COLUMN "user_agent";
+ALTER TABLE "refresh_tokens" DROP COLUMN "ip";
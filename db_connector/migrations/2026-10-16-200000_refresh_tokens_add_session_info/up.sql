@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE "refresh_tokens" ADD COLUMN "created_at" TIMESTAMP NOT NULL DEFAULT NOW();
+ALTER TABLE "refresh_tokens" ADD COLUMN "last_used_at" TIMESTAMP NOT NULL DEFAULT NOW();
+ALTER TABLE "refresh_tokens" ADD COLUMN "user_agent" VARCHAR;
+ALTER TABLE "refresh_tokens" ADD COLUMN "ip" VARCHAR;
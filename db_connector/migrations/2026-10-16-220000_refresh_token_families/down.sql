@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM "refresh_tokens" WHERE "rotated_at" IS NOT NULL;
+ALTER TABLE "refresh_tokens" DROP COLUMN "family_id";
+ALTER TABLE "refresh_tokens" DROP COLUMN "rotated_at";
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE "refresh_tokens" ADD COLUMN "family_id" UUID;
+UPDATE "refresh_tokens" SET "family_id" = "id";
+ALTER TABLE "refresh_tokens" ALTER COLUMN "family_id" SET NOT NULL;
+ALTER TABLE "refresh_tokens" ADD COLUMN "rotated_at" TIMESTAMP;
+
+CREATE INDEX "refresh_tokens_family_id_idx" ON "refresh_tokens"("family_id");
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE "users" ADD COLUMN "token_generation" INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "pre_auth_tokens";
+DROP TABLE "totp_backup_codes";
+DROP TABLE "totp_secrets";
@@ -0,0 +1,26 @@
+-- Your SQL goes here
+CREATE TABLE "totp_secrets"(
+    "user_id" UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    "secret" BYTEA NOT NULL,
+    "confirmed" BOOLEAN NOT NULL DEFAULT FALSE,
+    "last_used_step" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW(),
+    "failed_attempts" INTEGER NOT NULL DEFAULT 0,
+    "locked_until" TIMESTAMP
+);
+
+CREATE TABLE "totp_backup_codes"(
+    "id" UUID PRIMARY KEY,
+    "user_id" UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    "code_hash" VARCHAR NOT NULL,
+    "used_at" TIMESTAMP
+);
+
+CREATE INDEX "totp_backup_codes_user_id_idx" ON "totp_backup_codes"("user_id");
+
+CREATE TABLE "pre_auth_tokens"(
+    "id" UUID PRIMARY KEY,
+    "user_id" UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    "expires_at" TIMESTAMP NOT NULL,
+    "attempts" INTEGER NOT NULL DEFAULT 0
+);
@@ -19,9 +19,7 @@ pub fn run_migrations(
 /**
  * Create db connection pool
  */
-pub fn get_connection_pool() -> Pool {
-    dotenvy::dotenv().ok();
-    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+pub fn get_connection_pool(url: &str) -> Pool {
     let manager = diesel::r2d2::ConnectionManager::<PgConnection>::new(url);
     Pool::builder()
         .max_size(90)
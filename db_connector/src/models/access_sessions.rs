@@ -0,0 +1,24 @@
+use super::chargers::Charger;
+use super::users::User;
+use diesel::prelude::*;
+
+#[derive(
+    Debug, Clone, Queryable, Selectable, Insertable, Identifiable, Associations, PartialEq,
+)]
+#[diesel(belongs_to(User))]
+#[diesel(belongs_to(Charger))]
+#[diesel(table_name = crate::schema::access_sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AccessSession {
+    pub id: uuid::Uuid,
+    /// Not set once the user deleted their account.
+    pub user_id: Option<uuid::Uuid>,
+    pub charger_id: uuid::Uuid,
+    pub key_id: uuid::Uuid,
+    pub connection_no: i32,
+    pub started_at: chrono::NaiveDateTime,
+    pub ended_at: Option<chrono::NaiveDateTime>,
+    pub bytes_to_device: i64,
+    pub bytes_from_device: i64,
+    pub close_reason: Option<String>,
+}
@@ -0,0 +1,16 @@
+use super::chargers::Charger;
+use diesel::prelude::*;
+
+#[derive(
+    Debug, Clone, Queryable, Selectable, Insertable, Identifiable, Associations, PartialEq,
+)]
+#[diesel(belongs_to(Charger))]
+#[diesel(table_name = crate::schema::device_connectivity_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ConnectivityEvent {
+    pub id: uuid::Uuid,
+    pub charger_id: uuid::Uuid,
+    pub connected_at: chrono::NaiveDateTime,
+    pub disconnected_at: Option<chrono::NaiveDateTime>,
+    pub disconnect_reason: Option<String>,
+}
@@ -1,10 +1,19 @@
+pub mod access_sessions;
 pub mod allowed_users;
 pub mod authorization_tokens;
 pub mod chargers;
+pub mod device_connectivity_events;
 pub mod device_grouping_members;
 pub mod device_groupings;
+pub mod offline_notifications;
+pub mod pre_auth_tokens;
 pub mod recovery_tokens;
 pub mod refresh_tokens;
+pub mod relay_owners;
+pub mod totp_backup_codes;
+pub mod totp_secrets;
 pub mod users;
 pub mod verification;
+pub mod webhook_deliveries;
+pub mod webhooks;
 pub mod wg_keys;
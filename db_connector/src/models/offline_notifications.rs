@@ -0,0 +1,19 @@
+use super::chargers::Charger;
+use super::users::User;
+use diesel::prelude::*;
+
+#[derive(
+    Debug, Clone, Queryable, Selectable, Insertable, Identifiable, Associations, PartialEq,
+)]
+#[diesel(belongs_to(User))]
+#[diesel(belongs_to(Charger))]
+#[diesel(table_name = crate::schema::offline_notifications)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OfflineNotification {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub charger_id: uuid::Uuid,
+    pub lang: String,
+    /// Set while the user was told that the charger is offline.
+    pub notified_at: Option<chrono::NaiveDateTime>,
+}
@@ -0,0 +1,16 @@
+use super::users::User;
+use diesel::prelude::*;
+
+#[derive(
+    Debug, Clone, Queryable, Selectable, Insertable, Identifiable, Associations, PartialEq,
+)]
+#[diesel(belongs_to(User))]
+#[diesel(table_name = crate::schema::pre_auth_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PreAuthToken {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub expires_at: chrono::NaiveDateTime,
+    /// Number of wrong second factor codes entered with this token.
+    pub attempts: i32,
+}
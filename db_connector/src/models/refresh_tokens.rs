@@ -9,4 +9,13 @@ pub struct RefreshToken {
     pub id: uuid::Uuid,
     pub user_id: uuid::Uuid,
     pub expiration: i64,
+    /// When the user logged in. Refreshing the token keeps it.
+    pub created_at: chrono::NaiveDateTime,
+    pub last_used_at: chrono::NaiveDateTime,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// All tokens that were issued for the same login share the family.
+    pub family_id: uuid::Uuid,
+    /// Set once the token was exchanged for a new one. Presenting it again revokes the family.
+    pub rotated_at: Option<chrono::NaiveDateTime>,
 }
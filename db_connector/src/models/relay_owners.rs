@@ -0,0 +1,15 @@
+use super::chargers::Charger;
+use diesel::prelude::*;
+
+#[derive(
+    Debug, Clone, Queryable, Selectable, Insertable, Identifiable, Associations, PartialEq,
+)]
+#[diesel(primary_key(charger_id))]
+#[diesel(belongs_to(Charger, foreign_key = charger_id))]
+#[diesel(table_name = crate::schema::relay_owners)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RelayOwner {
+    pub charger_id: uuid::Uuid,
+    pub instance_id: uuid::Uuid,
+    pub updated_at: chrono::NaiveDateTime,
+}
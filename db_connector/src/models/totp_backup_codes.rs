@@ -0,0 +1,16 @@
+use super::users::User;
+use diesel::prelude::*;
+
+#[derive(
+    Debug, Clone, Queryable, Selectable, Insertable, Identifiable, Associations, PartialEq,
+)]
+#[diesel(belongs_to(User))]
+#[diesel(table_name = crate::schema::totp_backup_codes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TotpBackupCode {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    /// Hex encoded SHA-256 of the normalized code.
+    pub code_hash: String,
+    pub used_at: Option<chrono::NaiveDateTime>,
+}
@@ -0,0 +1,22 @@
+use super::users::User;
+use diesel::prelude::*;
+
+#[derive(
+    Debug, Clone, Queryable, Selectable, Insertable, Identifiable, Associations, PartialEq,
+)]
+#[diesel(belongs_to(User))]
+#[diesel(primary_key(user_id))]
+#[diesel(table_name = crate::schema::totp_secrets)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TotpSecret {
+    pub user_id: uuid::Uuid,
+    pub secret: Vec<u8>,
+    /// Only confirmed secrets are required for login.
+    pub confirmed: bool,
+    /// Time step of the last accepted code. Codes can't be used twice.
+    pub last_used_step: i64,
+    pub created_at: chrono::NaiveDateTime,
+    /// Wrong codes since the last valid one, counted for login and sensitive routes.
+    pub failed_attempts: i32,
+    pub locked_until: Option<chrono::NaiveDateTime>,
+}
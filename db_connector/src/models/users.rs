@@ -16,4 +16,6 @@ pub struct User {
     pub delivery_email: Option<String>,
     pub old_email: Option<String>,
     pub old_delivery_email: Option<String>,
+    /// Access tokens carrying an older generation are rejected.
+    pub token_generation: i32,
 }
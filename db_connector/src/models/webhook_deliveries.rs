@@ -0,0 +1,20 @@
+use super::webhooks::Webhook;
+use diesel::prelude::*;
+
+#[derive(
+    Debug, Clone, Queryable, Selectable, Insertable, Identifiable, Associations, PartialEq,
+)]
+#[diesel(belongs_to(Webhook))]
+#[diesel(table_name = crate::schema::webhook_deliveries)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WebhookDelivery {
+    pub id: uuid::Uuid,
+    pub webhook_id: uuid::Uuid,
+    pub event: String,
+    pub payload: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub attempts: i32,
+    pub next_attempt_at: chrono::NaiveDateTime,
+    pub delivered_at: Option<chrono::NaiveDateTime>,
+    pub last_error: Option<String>,
+}
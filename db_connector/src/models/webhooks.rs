@@ -0,0 +1,18 @@
+use super::users::User;
+use diesel::prelude::*;
+
+#[derive(
+    Debug, Clone, Queryable, Selectable, Insertable, Identifiable, Associations, PartialEq,
+)]
+#[diesel(belongs_to(User))]
+#[diesel(table_name = crate::schema::webhooks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Webhook {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub url: String,
+    /// Key for the HMAC signature of the deliveries.
+    pub secret: String,
+    pub events: Vec<Option<String>>,
+    pub created_at: chrono::NaiveDateTime,
+}
@@ -1,5 +1,20 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    access_sessions (id) {
+        id -> Uuid,
+        user_id -> Nullable<Uuid>,
+        charger_id -> Uuid,
+        key_id -> Uuid,
+        connection_no -> Int4,
+        started_at -> Timestamp,
+        ended_at -> Nullable<Timestamp>,
+        bytes_to_device -> Int8,
+        bytes_from_device -> Int8,
+        close_reason -> Nullable<Varchar>,
+    }
+}
+
 diesel::table! {
     allowed_users (id) {
         id -> Uuid,
@@ -44,6 +59,16 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    device_connectivity_events (id) {
+        id -> Uuid,
+        charger_id -> Uuid,
+        connected_at -> Timestamp,
+        disconnected_at -> Nullable<Timestamp>,
+        disconnect_reason -> Nullable<Varchar>,
+    }
+}
+
 diesel::table! {
     device_grouping_members (id) {
         id -> Uuid,
@@ -62,6 +87,25 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    offline_notifications (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        charger_id -> Uuid,
+        lang -> Varchar,
+        notified_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    pre_auth_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        expires_at -> Timestamp,
+        attempts -> Int4,
+    }
+}
+
 diesel::table! {
     recovery_tokens (id) {
         id -> Uuid,
@@ -75,6 +119,41 @@ diesel::table! {
         id -> Uuid,
         user_id -> Uuid,
         expiration -> Int8,
+        created_at -> Timestamp,
+        last_used_at -> Timestamp,
+        user_agent -> Nullable<Varchar>,
+        ip -> Nullable<Varchar>,
+        family_id -> Uuid,
+        rotated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    relay_owners (charger_id) {
+        charger_id -> Uuid,
+        instance_id -> Uuid,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    totp_backup_codes (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        code_hash -> Varchar,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    totp_secrets (user_id) {
+        user_id -> Uuid,
+        secret -> Bytea,
+        confirmed -> Bool,
+        last_used_step -> Int8,
+        created_at -> Timestamp,
+        failed_attempts -> Int4,
+        locked_until -> Nullable<Timestamp>,
     }
 }
 
@@ -95,6 +174,7 @@ diesel::table! {
         delivery_email -> Nullable<Varchar>,
         old_email -> Nullable<Varchar>,
         old_delivery_email -> Nullable<Varchar>,
+        token_generation -> Int4,
     }
 }
 
@@ -106,6 +186,31 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Uuid,
+        webhook_id -> Uuid,
+        event -> Varchar,
+        payload -> Text,
+        created_at -> Timestamp,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
+        last_error -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    webhooks (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        url -> Varchar,
+        secret -> Varchar,
+        events -> Array<Nullable<Text>>,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     wg_keys (id) {
         id -> Uuid,
@@ -120,27 +225,47 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(access_sessions -> chargers (charger_id));
+diesel::joinable!(access_sessions -> users (user_id));
 diesel::joinable!(allowed_users -> chargers (charger_id));
 diesel::joinable!(allowed_users -> users (user_id));
 diesel::joinable!(authorization_tokens -> users (user_id));
+diesel::joinable!(device_connectivity_events -> chargers (charger_id));
 diesel::joinable!(device_grouping_members -> chargers (charger_id));
 diesel::joinable!(device_grouping_members -> device_groupings (grouping_id));
 diesel::joinable!(device_groupings -> users (user_id));
+diesel::joinable!(offline_notifications -> chargers (charger_id));
+diesel::joinable!(offline_notifications -> users (user_id));
+diesel::joinable!(pre_auth_tokens -> users (user_id));
 diesel::joinable!(recovery_tokens -> users (user_id));
 diesel::joinable!(refresh_tokens -> users (user_id));
+diesel::joinable!(relay_owners -> chargers (charger_id));
+diesel::joinable!(totp_backup_codes -> users (user_id));
+diesel::joinable!(totp_secrets -> users (user_id));
 diesel::joinable!(verification -> users (user));
+diesel::joinable!(webhook_deliveries -> webhooks (webhook_id));
+diesel::joinable!(webhooks -> users (user_id));
 diesel::joinable!(wg_keys -> chargers (charger_id));
 diesel::joinable!(wg_keys -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    access_sessions,
     allowed_users,
     authorization_tokens,
     chargers,
+    device_connectivity_events,
     device_grouping_members,
     device_groupings,
+    offline_notifications,
+    pre_auth_tokens,
     recovery_tokens,
     refresh_tokens,
+    relay_owners,
+    totp_backup_codes,
+    totp_secrets,
     users,
     verification,
+    webhook_deliveries,
+    webhooks,
     wg_keys,
 );
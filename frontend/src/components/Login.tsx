@@ -20,6 +20,9 @@ interface LoginState {
     password: string,
     show_modal: boolean,
     credentials_wrong: boolean,
+    second_factor_required: boolean,
+    code: string,
+    code_wrong: boolean,
 }
 
 export class Login extends Component<Record<string, never>, LoginState> {
@@ -30,6 +33,9 @@ export class Login extends Component<Record<string, never>, LoginState> {
             password: "",
             show_modal: false,
             credentials_wrong: false,
+            second_factor_required: false,
+            code: "",
+            code_wrong: false,
         };
     }
 
@@ -59,7 +65,7 @@ export class Login extends Component<Record<string, never>, LoginState> {
         };
 
         {
-            const {error, response} = await fetchClient.POST("/auth/login", {body: login_schema, credentials: "same-origin"});
+            const {data, error, response} = await fetchClient.POST("/auth/login", {body: login_schema, credentials: "same-origin"});
             if (response.status === 403) {
                 showAlert(i18n.t("login.verify_before_login"), "danger", "login", i18n.t("login.verify_before_login_heading"));
                 return;
@@ -67,8 +73,34 @@ export class Login extends Component<Record<string, never>, LoginState> {
                 this.setState({credentials_wrong: true});
                 return;
             }
+
+            // The session cookies are only set once the second factor was checked at /auth/login_totp.
+            if (data?.second_factor_required) {
+                this.setState({credentials_wrong: false, second_factor_required: true});
+                return;
+            }
         }
 
+        await this.finishLogin();
+    }
+
+    async onSubmitCode(e: SubmitEvent) {
+        e.preventDefault();
+
+        const {response} = await fetchClient.POST("/auth/login_totp", {body: {code: this.state.code}, credentials: "same-origin"});
+        if (response.status === 429) {
+            showAlert(i18n.t("login.too_many_attempts"), "danger", "login", i18n.t("login.too_many_attempts_heading"));
+            this.setState({second_factor_required: false, code: ""});
+            return;
+        } else if (response.status !== 200) {
+            this.setState({code_wrong: true});
+            return;
+        }
+
+        await this.finishLogin();
+    }
+
+    async finishLogin() {
         const {data, response, error} = await fetchClient.GET("/user/get_secret", {credentials: "same-origin"});
         if (200 !== response.status || !data) {
             const text = `Failed with status ${response.status}: ${error}`;
@@ -123,6 +155,23 @@ export class Login extends Component<Record<string, never>, LoginState> {
                 </Form>
             </Modal>
 
+            {this.state.second_factor_required ?
+            <Form onSubmit={async (e: SubmitEvent) => this.onSubmitCode(e)}>
+                <Form.Group className="mb-3" controlId="loginCode">
+                    <Form.Label>{t("code")}</Form.Label>
+                    <Form.Control autoComplete="one-time-code" isInvalid={this.state.code_wrong} type="text" placeholder={t("code_placeholder")} value={this.state.code} onChange={(e) => {
+                        this.setState({code: (e.target as HTMLInputElement).value});
+                    }} />
+                    <Form.Text>{this.state.code_wrong ? t("wrong_code") : t("code_help")}</Form.Text>
+                </Form.Group>
+                <Button variant="primary" type="submit" id="loginCodeSubmit">
+                    {t("login")}
+                </Button>
+                <a className="col mb-3 ms-3" href="" onClick={(e) => {
+                        e.preventDefault();
+                        this.setState({second_factor_required: false, code: "", code_wrong: false});
+                    }}>{t("back")}</a>
+            </Form> :
             <Form onSubmit={async (e: SubmitEvent) => this.onSubmit(e)}>
                 <Form.Group className="mb-3" controlId="loginEmail">
                     <Form.Label>{t("email")}</Form.Label>
@@ -148,7 +197,7 @@ export class Login extends Component<Record<string, never>, LoginState> {
                         e.preventDefault();
                         this.setState({show_modal: true});
                     }}>{t("password_recovery")}</a>
-            </Form>
+            </Form>}
         </>)
     }
 }
@@ -104,6 +104,57 @@ describe('Login Component', () => {
     });
   });
 
+  it('asks for the second factor instead of finishing the login', async () => {
+    mockUtils.fetchClient.POST.mockResolvedValueOnce({
+      data: { second_factor_required: true },
+      response: { status: 200 },
+      error: null,
+    });
+    fillAndSubmit();
+
+    await waitFor(() => {
+      expect(screen.getByRole('textbox', { name: 'code' })).toBeTruthy();
+    });
+    expect(mockUtils.fetchClient.GET).not.toHaveBeenCalled();
+    expect(mockUtils.storeSecretKeyInServiceWorker).not.toHaveBeenCalled();
+  });
+
+  async function submitCode(code: string) {
+    mockUtils.fetchClient.POST.mockResolvedValueOnce({
+      data: { second_factor_required: true },
+      response: { status: 200 },
+      error: null,
+    });
+    fillAndSubmit();
+    const codeInput = await screen.findByRole('textbox', { name: 'code' });
+    fireEvent.change(codeInput, { target: { value: code } });
+    fireEvent.click(screen.getByRole('button', { name: 'login' }));
+  }
+
+  it('finishes the login after a valid second factor', async () => {
+    await submitCode('123456');
+
+    await waitFor(() => {
+      expect(mockUtils.fetchClient.POST).toHaveBeenLastCalledWith('/auth/login_totp', {
+        body: { code: '123456' },
+        credentials: 'same-origin',
+      });
+      expect(mockUtils.storeSecretKeyInServiceWorker).toHaveBeenCalledWith('encoded');
+      expect(mockUtils.loggedIn.value).toBe(mockUtils.AppState.LoggedIn);
+      expect(mockUtils.bc.postMessage).toHaveBeenCalledWith('login');
+    });
+  });
+
+  it('marks the code wrong when the second factor is rejected', async () => {
+    mockUtils.fetchClient.POST.mockResolvedValue({ response: { status: 401 }, error: 'wrong' });
+    await submitCode('000000');
+
+    await waitFor(() => {
+      expect(screen.getByRole('textbox', { name: 'code' })).toHaveClass('invalid');
+    });
+    expect(mockUtils.fetchClient.GET).not.toHaveBeenCalled();
+  });
+
   it('opens and submits password recovery modal success', async () => {
     render(<Login />);
     fireEvent.click(screen.getByText('password_recovery'));
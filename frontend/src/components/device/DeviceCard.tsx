@@ -5,6 +5,7 @@ import { Edit, Monitor, Trash2 } from "react-feather";
 import * as Base58 from "base58";
 import { Circle } from "../Circle";
 import { StateDevice, Grouping, ConnectVia } from "./types";
+import { DeviceTraffic } from "./DeviceTraffic";
 
 interface DeviceCardProps {
     device: StateDevice;
@@ -88,6 +89,7 @@ export function DeviceCard({
                             ))}
                         </div>
                     )}
+                    <DeviceTraffic device={device} />
                 </Col>
                 <Col className="d-flex justify-content-end">
                     {showConnectMenu ? (
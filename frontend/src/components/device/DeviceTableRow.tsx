@@ -5,6 +5,7 @@ import { Edit } from "react-feather";
 import * as Base58 from "base58";
 import { Circle } from "../Circle";
 import { StateDevice, Grouping, ConnectVia } from "./types";
+import { DeviceTraffic } from "./DeviceTraffic";
 
 interface DeviceTableRowProps {
     device: StateDevice;
@@ -67,6 +68,7 @@ export function DeviceTableRow({
                             ))}
                         </div>
                     )}
+                    <DeviceTraffic device={device} />
                 </div>
             </td>
             <td class="align-middle">
@@ -0,0 +1,32 @@
+import { useTranslation } from "react-i18next";
+import { StateDevice } from "./types";
+
+export function formatBytes(bytes: number): string {
+    const units = ["B", "kB", "MB", "GB"];
+    let value = bytes;
+    let unit = 0;
+    while (value >= 1000 && unit < units.length - 1) {
+        value /= 1000;
+        unit++;
+    }
+    return `${unit === 0 ? value : value.toFixed(1)} ${units[unit]}`;
+}
+
+// Shows the traffic of the open remote access sessions of a device.
+export function DeviceTraffic({ device }: { device: StateDevice }) {
+    const { t } = useTranslation("", { useSuspense: false, keyPrefix: "chargers" });
+    if (!device.traffic) {
+        return null;
+    }
+
+    const { sessions, traffic } = device.traffic;
+    return (
+        <div className="text-muted" style={{ fontSize: "0.8rem" }}>
+            {t("remote_traffic", {
+                sessions,
+                to_device: formatBytes(traffic.to_device),
+                from_device: formatBytes(traffic.from_device),
+            })}
+        </div>
+    );
+}
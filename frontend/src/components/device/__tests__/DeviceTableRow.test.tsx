@@ -179,6 +179,24 @@ describe('DeviceTableRow', () => {
     expect(screen.queryByText('Another Group')).not.toBeInTheDocument();
   });
 
+  it('shows the traffic of open remote access sessions', () => {
+    const deviceWithTraffic: StateDevice = {
+      ...mockDevice,
+      traffic: {
+        id: '1',
+        sessions: 2,
+        traffic: { to_device: 1500, from_device: 20000, dropped_from_device: 0 },
+      },
+    };
+    render(<table><tbody><DeviceTableRow {...defaultProps} device={deviceWithTraffic} /></tbody></table>);
+    expect(screen.getByText('remote_traffic')).toBeInTheDocument();
+  });
+
+  it('does not show traffic without open sessions', () => {
+    render(<table><tbody><DeviceTableRow {...defaultProps} /></tbody></table>);
+    expect(screen.queryByText('remote_traffic')).not.toBeInTheDocument();
+  });
+
   it('renders multiple grouping badges', () => {
     const manyGroupings: Grouping[] = [
       { id: 'g1', name: 'Group 1', device_ids: ['1'], is_default: false },
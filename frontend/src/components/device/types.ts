@@ -20,6 +20,8 @@ export interface StateDevice {
     valid: boolean,
     last_state_change?: number | null,
     firmware_version: string,
+    // Set while the device has open remote access sessions.
+    traffic?: ChargerTraffic,
     // Hostname/IP of the device on the local network. Set for two kinds of
     // devices, and is the sole marker that a device is reachable on the LAN:
     //   1. Standalone local devices that are not yet paired with the cloud
@@ -33,6 +35,18 @@ export interface StateDevice {
     host?: string,
 }
 
+// Counters of the open remote access sessions of a device, sent with the
+// `traffic` state update for every device that has at least one.
+export interface ChargerTraffic {
+    id: string,
+    sessions: number,
+    traffic: {
+        to_device: number,
+        from_device: number,
+        dropped_from_device: number,
+    },
+}
+
 export type SortColumn = "name" | "uid" | "status" | "none" | "note" | "last_state_change" | "firmware_version";
 
 // Selects how to reach a device. `"default"` keeps the legacy behavior of
@@ -72,4 +86,6 @@ export interface DeviceListState {
     // (instead of re-deriving them from the already-merged `devices` list).
     cloudDevices: StateDevice[],
     groupByEnabled: boolean,
+    // Latest `traffic` state update keyed by device id.
+    traffic: Record<string, ChargerTraffic>,
 }
@@ -120,6 +120,7 @@ export const de ={
         "local": "Lokal",
         "connect_locally": "Lokal verbinden",
         "connect_via_cloud": "Über Fernzugriff verbinden",
+        "remote_traffic": "Offene Verbindungen: {{sessions}}, gesendet {{to_device}}, empfangen {{from_device}}",
         "manage_groupings": "Gruppen verwalten",
         "groupings": "Gruppen",
         "create_grouping": "Gruppe erstellen",
@@ -204,7 +205,14 @@ export const de ={
         "verify_before_login": "Bitte bestätige deine E-Mail-Adresse, bevor du dich anmeldest",
         "verify_before_login_heading": "E-Mail-Adresse nicht bestätigt",
         "verify_success": "Dein Konto wurde bestätigt. Du kannst dich jetzt anmelden.",
-        "verify_success_heading": "Konto aktiviert"
+        "verify_success_heading": "Konto aktiviert",
+        "code": "Code der Zwei-Faktor-Authentifizierung",
+        "code_placeholder": "123456",
+        "code_help": "Gib den Code deiner Authenticator-App oder einen deiner Backup-Codes ein.",
+        "wrong_code": "Der Code ist falsch.",
+        "back": "Zurück",
+        "too_many_attempts": "Es wurden zu viele falsche Codes eingegeben. Bitte melde dich später erneut an.",
+        "too_many_attempts_heading": "Anmeldung gesperrt"
     },
     "password_strength": {
         "strength": "Stärke",
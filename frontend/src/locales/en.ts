@@ -120,6 +120,7 @@ export const en = {
         "local": "Local",
         "connect_locally": "Connect locally",
         "connect_via_cloud": "Connect via remote-access",
+        "remote_traffic": "Open sessions: {{sessions}}, sent {{to_device}}, received {{from_device}}",
         "manage_groupings": "Manage Groups",
         "groupings": "Groups",
         "create_grouping": "Create Group",
@@ -204,7 +205,14 @@ export const en = {
         "verify_before_login": "Please verify your email address before logging in",
         "verify_before_login_heading": "Email not verified",
         "verify_success": "Your account has been verified. You can now log in.",
-        "verify_success_heading": "Account activated"
+        "verify_success_heading": "Account activated",
+        "code": "Two-factor authentication code",
+        "code_placeholder": "123456",
+        "code_help": "Enter the code of your authenticator app or one of your backup codes.",
+        "wrong_code": "The code is wrong.",
+        "back": "Back",
+        "too_many_attempts": "Too many wrong codes were entered. Please log in again later.",
+        "too_many_attempts_heading": "Login blocked"
     },
     "password_strength": {
         "strength": "Strength",
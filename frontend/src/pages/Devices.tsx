@@ -8,7 +8,7 @@ import { fetchClient, get_decrypted_secret, pub_key, secret } from "../utils";
 import { Button, Container, Spinner } from "react-bootstrap";
 import i18n from "../i18n";
 import { useLocation } from "preact-iso";
-import { Device, StateDevice, SortColumn, DeviceListState, Grouping, ConnectVia } from "../components/device/types";
+import { Device, StateDevice, SortColumn, DeviceListState, Grouping, ConnectVia, ChargerTraffic } from "../components/device/types";
 import { DeviceTable } from "../components/device/DeviceTable";
 import { DeviceMobileView } from "../components/device/DeviceMobileView";
 import { DeleteDeviceModal } from "../components/device/DeleteDeviceModal";
@@ -54,6 +54,7 @@ export class DeviceList extends Component<Record<string, never>, DeviceListState
             localDevices: [],
             cloudDevices: [],
             groupByEnabled: window.localStorage.getItem("groupByEnabled") !== "false",
+            traffic: {},
         };
 
         this.stateUpdateWs = null;
@@ -95,6 +96,18 @@ export class DeviceList extends Component<Record<string, never>, DeviceListState
                     if (message.type === 'state_change' && Array.isArray(message.chargers)) {
                         this.processChargers(message.chargers as Device[]);
                     }
+                    // Devices without open remote access sessions are not part of the update
+                    else if (message.type === 'traffic' && Array.isArray(message.chargers)) {
+                        const traffic: Record<string, ChargerTraffic> = {};
+                        for (const charger of message.chargers as ChargerTraffic[]) {
+                            traffic[charger.id] = charger;
+                        }
+                        this.setState({ traffic });
+                    }
+                    // Connection health of the chargers is meant for monitoring and not shown here
+                    else if (message.type === 'health') {
+                        return;
+                    }
                     // Handle initial charger list (array without type wrapper)
                     else if (Array.isArray(message)) {
                         this.processChargers(message as Device[]);
@@ -610,9 +623,10 @@ export class DeviceList extends Component<Record<string, never>, DeviceListState
         }
 
         // Apply filtering: if search term or grouping filter is active, show filtered devices
-        const devices = (this.state.filteredDevices.length > 0 || this.state.searchTerm || this.state.selectedGroupingId)
+        const devices = ((this.state.filteredDevices.length > 0 || this.state.searchTerm || this.state.selectedGroupingId)
             ? this.state.filteredDevices
-            : this.state.devices;
+            : this.state.devices
+        ).map(device => ({ ...device, traffic: this.state.traffic[device.id] }));
 
         const handleConnect = async (device: StateDevice, via: ConnectVia = "default") => {
             await this.connect_to_charger(device, route, via);
@@ -106,6 +106,23 @@ export interface paths {
         patch?: never;
         trace?: never;
     };
+    "/auth/login_totp": {
+        parameters: {
+            query?: never;
+            header?: never;
+            path?: never;
+            cookie?: never;
+        };
+        get?: never;
+        put?: never;
+        /** Finish a login with a two-factor authentication code */
+        post: operations["login_totp"];
+        delete?: never;
+        options?: never;
+        head?: never;
+        patch?: never;
+        trace?: never;
+    };
     "/auth/recovery": {
         parameters: {
             query?: never;
@@ -781,10 +798,19 @@ export interface components {
             web_address: string;
             web_private: number[];
         };
+        LoginResponseSchema: {
+            /** @description The login has to be completed at /auth/login_totp with the pre_auth_token cookie
+             *     that was set instead of the session cookies. */
+            second_factor_required: boolean;
+        };
         LoginSchema: {
             email: string;
             login_key: number[];
         };
+        LoginTotpSchema: {
+            /** @description Code of the authenticator app or one of the backup codes. */
+            code: string;
+        };
         ManagementDataVersion: {
             V1: components["schemas"]["ManagementDataVersion1"];
         } | {
@@ -1050,6 +1076,15 @@ export interface operations {
                     "text/plain": string;
                 };
             };
+            /** @description The refresh token was just exchanged by a concurrent request. The cookies of that request stay valid. */
+            409: {
+                headers: {
+                    [name: string]: unknown;
+                };
+                content: {
+                    "text/plain": string;
+                };
+            };
         };
     };
     login: {
@@ -1065,12 +1100,14 @@ export interface operations {
             };
         };
         responses: {
-            /** @description Login was successful */
+            /** @description Login was successful or needs a second factor */
             200: {
                 headers: {
                     [name: string]: unknown;
                 };
-                content?: never;
+                content: {
+                    "application/json": components["schemas"]["LoginResponseSchema"];
+                };
             };
             /** @description Credentials were incorrect */
             401: {
@@ -1088,6 +1125,42 @@ export interface operations {
             };
         };
     };
+    login_totp: {
+        parameters: {
+            query?: never;
+            header?: never;
+            path?: never;
+            cookie?: never;
+        };
+        requestBody: {
+            content: {
+                "application/json": components["schemas"]["LoginTotpSchema"];
+            };
+        };
+        responses: {
+            /** @description Login was successful */
+            200: {
+                headers: {
+                    [name: string]: unknown;
+                };
+                content?: never;
+            };
+            /** @description The code was wrong or the login has to be started again */
+            401: {
+                headers: {
+                    [name: string]: unknown;
+                };
+                content?: never;
+            };
+            /** @description Too many wrong two-factor authentication codes */
+            429: {
+                headers: {
+                    [name: string]: unknown;
+                };
+                content?: never;
+            };
+        };
+    };
     recovery: {
         parameters: {
             query?: never;
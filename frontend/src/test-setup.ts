@@ -358,6 +358,8 @@ vi.mock('react-bootstrap/Form', () => {
   };
   Form.Label = ({ children, controlId }: { children?: ComponentChildren; controlId?: string }) =>
     h('label', { htmlFor: controlId }, children);
+  Form.Text = ({ children }: { children?: ComponentChildren }) =>
+    h('div', { 'data-testid': 'form-text' }, children);
   Form.Control = ({
     type,
     value,
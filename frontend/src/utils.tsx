@@ -82,6 +82,12 @@ const AuthMiddleware: Middleware = {
             return undefined;
         }
 
+        // A 401 of the second login step means a wrong code. Retrying would count as another attempt
+        // and the failing refresh would drop the login salt stored by the first step.
+        if (request.url.indexOf("/auth/login_totp") !== -1) {
+            return undefined;
+        }
+
         if (response.status === 401 && !auth_already_failed) {
             await refresh_access_token();
             return await fetch(request);
@@ -104,7 +110,8 @@ export async function refresh_access_token() {
             return resp;
         });
 
-        if (!error || response.status === 502) {
+        // A 409 means a concurrent refresh of this session already got new cookies.
+        if (!error || response.status === 502 || response.status === 409) {
             const hasLoginSalt = localStorage.getItem("loginSalt");
             const hasSecret = await getSecretKeyFromServiceWorker();
             if (!hasLoginSalt || !hasSecret) {